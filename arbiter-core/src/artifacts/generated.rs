@@ -0,0 +1,53 @@
+//! This file is generated by `arbiter bind --artifacts`. Do not edit it by
+//! hand.
+
+use super::Artifact;
+use crate::bindings::*;
+
+/// The contracts embedded in `arbiter-core`.
+pub static ARTIFACTS: [Artifact; 4] = [
+    Artifact {
+        name: "ArbiterMath",
+        bytecode: &arbiter_math::ARBITERMATH_BYTECODE,
+        deployed_bytecode: &arbiter_math::ARBITERMATH_DEPLOYED_BYTECODE,
+        abi: &arbiter_math::ARBITERMATH_ABI,
+        bytecode_size: 4448,
+        bytecode_hash: "0xeb9f946b836a7b73d02c99438e4af5e2077255c44e342458d26ad61ed2c2c78f",
+        deployed_bytecode_size: 4345,
+        deployed_bytecode_hash:
+            "0xd78c2d47059f9d71aaa52799dcbbd9d3571a0d3d6846346db841146f0f88cc9d",
+    },
+    Artifact {
+        name: "ArbiterToken",
+        bytecode: &arbiter_token::ARBITERTOKEN_BYTECODE,
+        deployed_bytecode: &arbiter_token::ARBITERTOKEN_DEPLOYED_BYTECODE,
+        abi: &arbiter_token::ARBITERTOKEN_ABI,
+        bytecode_size: 4948,
+        bytecode_hash: "0xac2123e5fd944dfb28f3b73f316d115e70df5f9b254125e9285a3eed39fbde5f",
+        deployed_bytecode_size: 3205,
+        deployed_bytecode_hash:
+            "0x50f9f6144e6a4b3874e7b167ff8f6199fbb3f772838274d6765f001469247706",
+    },
+    Artifact {
+        name: "LiquidExchange",
+        bytecode: &liquid_exchange::LIQUIDEXCHANGE_BYTECODE,
+        deployed_bytecode: &liquid_exchange::LIQUIDEXCHANGE_DEPLOYED_BYTECODE,
+        abi: &liquid_exchange::LIQUIDEXCHANGE_ABI,
+        bytecode_size: 1948,
+        bytecode_hash: "0x05ef346845657c74159e19c9b8c4374cd43eca40bace9d000f1a8e656853d484",
+        deployed_bytecode_size: 1583,
+        deployed_bytecode_hash:
+            "0x882f1b19c28c5a7bffce88d9b8c2de03d4f220ea8fdf7f59f3a6a82772cc0cb1",
+    },
+    Artifact {
+        name: "WETH",
+        bytecode: &weth::WETH_BYTECODE,
+        deployed_bytecode: &weth::WETH_DEPLOYED_BYTECODE,
+        abi: &weth::WETH_ABI,
+        bytecode_size: 4826,
+        bytecode_hash: "0xa56ad2ab10ec29f5516703d14062b2e4920d0003d799c5111717d5c1077fcb39",
+        deployed_bytecode_size: 3590,
+        deployed_bytecode_hash:
+            "0xf82c9e3828f0930e7b1807041b4189ac4f76c09059ffcec4321a79a09baf64ce",
+    },
+];
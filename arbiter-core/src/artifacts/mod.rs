@@ -0,0 +1,84 @@
+//! The `artifacts` module lists the contracts whose bytecode is embedded in
+//! `arbiter-core` through the [`bindings`](crate::bindings), along with the
+//! size and `keccak256` hash of that bytecode.
+//!
+//! The list itself lives in `generated.rs` and is written by running
+//! `arbiter bind --artifacts` in the `arbiter-core` directory after the
+//! contracts are compiled with `forge build`. Adding a built-in contract is
+//! then a matter of adding it to `contracts/` and regenerating both the
+//! bindings and the artifacts. Because the hashes are checked in, a change to
+//! the embedded bytecode shows up in review, and [`Artifact::verify`] catches
+//! bindings that were regenerated without the artifacts.
+
+#![warn(missing_docs)]
+
+use ethers::{
+    abi::Abi,
+    contract::Lazy,
+    types::Bytes,
+    utils::{hex, keccak256},
+};
+
+mod generated;
+
+pub use generated::ARTIFACTS;
+
+/// The bytecode and ABI of a contract embedded in `arbiter-core`.
+#[derive(Debug)]
+pub struct Artifact {
+    /// The name of the contract.
+    pub name: &'static str,
+
+    /// The init code the contract is deployed with.
+    pub bytecode: &'static Bytes,
+
+    /// The code of the contract once it is deployed.
+    pub deployed_bytecode: &'static Bytes,
+
+    /// The ABI of the contract.
+    pub abi: &'static Lazy<Abi>,
+
+    /// The size of [`Artifact::bytecode`] in bytes when it was generated.
+    pub bytecode_size: usize,
+
+    /// The `keccak256` hash of [`Artifact::bytecode`] when it was generated.
+    pub bytecode_hash: &'static str,
+
+    /// The size of [`Artifact::deployed_bytecode`] in bytes when it was
+    /// generated.
+    pub deployed_bytecode_size: usize,
+
+    /// The `keccak256` hash of [`Artifact::deployed_bytecode`] when it was
+    /// generated.
+    pub deployed_bytecode_hash: &'static str,
+}
+
+impl Artifact {
+    /// Returns whether the embedded bytecode still has the size and hash it was
+    /// generated with.
+    pub fn verify(&self) -> bool {
+        self.bytecode.len() == self.bytecode_size
+            && hash(self.bytecode) == self.bytecode_hash
+            && self.deployed_bytecode.len() == self.deployed_bytecode_size
+            && hash(self.deployed_bytecode) == self.deployed_bytecode_hash
+    }
+}
+
+/// Returns the artifact of the contract with the given `name`, if it is
+/// embedded in `arbiter-core`.
+pub fn artifact(name: &str) -> Option<&'static Artifact> {
+    ARTIFACTS.iter().find(|artifact| artifact.name == name)
+}
+
+/// Returns the number of bytes of bytecode, both init and deployed, that the
+/// embedded contracts add to a build.
+pub fn embedded_size() -> usize {
+    ARTIFACTS
+        .iter()
+        .map(|artifact| artifact.bytecode_size + artifact.deployed_bytecode_size)
+        .sum()
+}
+
+fn hash(bytecode: &Bytes) -> String {
+    format!("0x{}", hex::encode(keccak256(bytecode)))
+}
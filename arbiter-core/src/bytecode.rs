@@ -0,0 +1,321 @@
+//! The `bytecode` module provides utilities for inspecting deployed EVM
+//! bytecode, such as the code of an unknown contract pulled in from a fork.
+//!
+//! The code of an account is fetched with
+//! [`Middleware::get_code`](ethers::providers::Middleware::get_code) on a
+//! [`RevmMiddleware`] and can then be:
+//! - disassembled into [`Opcode`]s with [`disassemble`],
+//! - searched for its valid jump destinations with [`jump_destinations`],
+//! - searched for the function selectors of its dispatcher with
+//!   [`function_selectors`].
+
+#![warn(missing_docs)]
+
+use std::fmt;
+
+use ethers::types::Bytes;
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(doc, doc(hidden))]
+#[cfg_attr(doc, allow(unused_imports))]
+#[cfg(doc)]
+use crate::middleware::RevmMiddleware;
+
+const PUSH1: u8 = 0x60;
+const PUSH4: u8 = 0x63;
+const PUSH32: u8 = 0x7f;
+const DUP1: u8 = 0x80;
+const DUP2: u8 = 0x81;
+const EQ: u8 = 0x14;
+const JUMPI: u8 = 0x57;
+const JUMPDEST: u8 = 0x5b;
+
+/// A single disassembled instruction.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Opcode {
+    /// The offset of the instruction in the bytecode.
+    pub pc: usize,
+
+    /// The byte of the instruction.
+    pub opcode: u8,
+
+    /// The mnemonic of the instruction or `None` if the byte is not a known
+    /// instruction, e.g., when disassembling the metadata appended by the
+    /// compiler.
+    pub name: Option<&'static str>,
+
+    /// The immediate data of a `PUSH` instruction. This is shorter than the
+    /// push size if the bytecode ends in the middle of the data.
+    pub push_data: Option<Bytes>,
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#06x}: ", self.pc)?;
+        match self.name {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "INVALID({:#04x})", self.opcode)?,
+        }
+        if let Some(data) = &self.push_data {
+            write!(f, " {}", data)?;
+        }
+        Ok(())
+    }
+}
+
+/// An entry of a contract's function dispatcher: the selector that is
+/// compared against the calldata and where execution jumps to on a match.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelectorEntry {
+    /// The 4 byte function selector.
+    pub selector: [u8; 4],
+
+    /// The offset of the code that handles the function.
+    pub jump_destination: usize,
+}
+
+/// Disassembles `code` into its [`Opcode`]s in order.
+pub fn disassemble(code: &[u8]) -> Vec<Opcode> {
+    let mut opcodes = vec![];
+    let mut pc = 0;
+    while pc < code.len() {
+        let opcode = code[pc];
+        let push_data = match opcode {
+            PUSH1..=PUSH32 => {
+                let start = (pc + 1).min(code.len());
+                let end = (pc + 1 + push_size(opcode)).min(code.len());
+                Some(Bytes::from(code[start..end].to_vec()))
+            }
+            _ => None,
+        };
+        opcodes.push(Opcode {
+            pc,
+            opcode,
+            name: opcode_name(opcode),
+            push_data,
+        });
+        pc += 1 + match opcode {
+            PUSH1..=PUSH32 => push_size(opcode),
+            _ => 0,
+        };
+    }
+    opcodes
+}
+
+/// Returns the offsets of every `JUMPDEST` in `code`, skipping over bytes that
+/// are the data of a `PUSH`.
+pub fn jump_destinations(code: &[u8]) -> Vec<usize> {
+    disassemble(code)
+        .into_iter()
+        .filter(|opcode| opcode.opcode == JUMPDEST)
+        .map(|opcode| opcode.pc)
+        .collect()
+}
+
+/// Extracts the entries of the function dispatcher of `code`, in the order
+/// they appear.
+///
+/// This looks for the pattern the Solidity and Vyper compilers emit for
+/// each external function, i.e., `PUSH4 <selector>` followed by an `EQ`
+/// (optionally behind a `DUP1` or `DUP2`) and a `PUSH <destination> JUMPI`.
+/// Only entries that jump to a valid `JUMPDEST` are returned. Contracts that
+/// dispatch differently, e.g., hand-written or heavily optimized ones, may
+/// have selectors that are not found.
+pub fn function_selectors(code: &[u8]) -> Vec<SelectorEntry> {
+    let opcodes = disassemble(code);
+    let jump_destinations = jump_destinations(code);
+    let mut entries: Vec<SelectorEntry> = vec![];
+    for (index, opcode) in opcodes.iter().enumerate() {
+        let selector = match (&opcode.push_data, opcode.opcode) {
+            (Some(data), PUSH4) if data.len() == 4 => [data[0], data[1], data[2], data[3]],
+            _ => continue,
+        };
+        let mut rest = opcodes[index + 1..].iter();
+        let mut next = rest.next();
+        if matches!(next, Some(opcode) if opcode.opcode == DUP1 || opcode.opcode == DUP2) {
+            next = rest.next();
+        }
+        if !matches!(next, Some(opcode) if opcode.opcode == EQ) {
+            continue;
+        }
+        let jump_destination = match (rest.next(), rest.next()) {
+            (Some(push), Some(jumpi)) if jumpi.opcode == JUMPI => match &push.push_data {
+                Some(data) if data.len() <= 8 => data.iter().fold(0usize, |destination, byte| {
+                    (destination << 8) | *byte as usize
+                }),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        if jump_destinations.binary_search(&jump_destination).is_ok()
+            && !entries.iter().any(|entry| entry.selector == selector)
+        {
+            entries.push(SelectorEntry {
+                selector,
+                jump_destination,
+            });
+        }
+    }
+    entries
+}
+
+/// The number of bytes of immediate data of a `PUSH` instruction.
+fn push_size(opcode: u8) -> usize {
+    (opcode - PUSH1) as usize + 1
+}
+
+/// The mnemonic of an instruction as of the Cancun hard fork.
+pub fn opcode_name(opcode: u8) -> Option<&'static str> {
+    Some(match opcode {
+        0x00 => "STOP",
+        0x01 => "ADD",
+        0x02 => "MUL",
+        0x03 => "SUB",
+        0x04 => "DIV",
+        0x05 => "SDIV",
+        0x06 => "MOD",
+        0x07 => "SMOD",
+        0x08 => "ADDMOD",
+        0x09 => "MULMOD",
+        0x0a => "EXP",
+        0x0b => "SIGNEXTEND",
+        0x10 => "LT",
+        0x11 => "GT",
+        0x12 => "SLT",
+        0x13 => "SGT",
+        0x14 => "EQ",
+        0x15 => "ISZERO",
+        0x16 => "AND",
+        0x17 => "OR",
+        0x18 => "XOR",
+        0x19 => "NOT",
+        0x1a => "BYTE",
+        0x1b => "SHL",
+        0x1c => "SHR",
+        0x1d => "SAR",
+        0x20 => "KECCAK256",
+        0x30 => "ADDRESS",
+        0x31 => "BALANCE",
+        0x32 => "ORIGIN",
+        0x33 => "CALLER",
+        0x34 => "CALLVALUE",
+        0x35 => "CALLDATALOAD",
+        0x36 => "CALLDATASIZE",
+        0x37 => "CALLDATACOPY",
+        0x38 => "CODESIZE",
+        0x39 => "CODECOPY",
+        0x3a => "GASPRICE",
+        0x3b => "EXTCODESIZE",
+        0x3c => "EXTCODECOPY",
+        0x3d => "RETURNDATASIZE",
+        0x3e => "RETURNDATACOPY",
+        0x3f => "EXTCODEHASH",
+        0x40 => "BLOCKHASH",
+        0x41 => "COINBASE",
+        0x42 => "TIMESTAMP",
+        0x43 => "NUMBER",
+        0x44 => "PREVRANDAO",
+        0x45 => "GASLIMIT",
+        0x46 => "CHAINID",
+        0x47 => "SELFBALANCE",
+        0x48 => "BASEFEE",
+        0x49 => "BLOBHASH",
+        0x4a => "BLOBBASEFEE",
+        0x50 => "POP",
+        0x51 => "MLOAD",
+        0x52 => "MSTORE",
+        0x53 => "MSTORE8",
+        0x54 => "SLOAD",
+        0x55 => "SSTORE",
+        0x56 => "JUMP",
+        0x57 => "JUMPI",
+        0x58 => "PC",
+        0x59 => "MSIZE",
+        0x5a => "GAS",
+        0x5b => "JUMPDEST",
+        0x5c => "TLOAD",
+        0x5d => "TSTORE",
+        0x5e => "MCOPY",
+        0x5f => "PUSH0",
+        0x60 => "PUSH1",
+        0x61 => "PUSH2",
+        0x62 => "PUSH3",
+        0x63 => "PUSH4",
+        0x64 => "PUSH5",
+        0x65 => "PUSH6",
+        0x66 => "PUSH7",
+        0x67 => "PUSH8",
+        0x68 => "PUSH9",
+        0x69 => "PUSH10",
+        0x6a => "PUSH11",
+        0x6b => "PUSH12",
+        0x6c => "PUSH13",
+        0x6d => "PUSH14",
+        0x6e => "PUSH15",
+        0x6f => "PUSH16",
+        0x70 => "PUSH17",
+        0x71 => "PUSH18",
+        0x72 => "PUSH19",
+        0x73 => "PUSH20",
+        0x74 => "PUSH21",
+        0x75 => "PUSH22",
+        0x76 => "PUSH23",
+        0x77 => "PUSH24",
+        0x78 => "PUSH25",
+        0x79 => "PUSH26",
+        0x7a => "PUSH27",
+        0x7b => "PUSH28",
+        0x7c => "PUSH29",
+        0x7d => "PUSH30",
+        0x7e => "PUSH31",
+        0x7f => "PUSH32",
+        0x80 => "DUP1",
+        0x81 => "DUP2",
+        0x82 => "DUP3",
+        0x83 => "DUP4",
+        0x84 => "DUP5",
+        0x85 => "DUP6",
+        0x86 => "DUP7",
+        0x87 => "DUP8",
+        0x88 => "DUP9",
+        0x89 => "DUP10",
+        0x8a => "DUP11",
+        0x8b => "DUP12",
+        0x8c => "DUP13",
+        0x8d => "DUP14",
+        0x8e => "DUP15",
+        0x8f => "DUP16",
+        0x90 => "SWAP1",
+        0x91 => "SWAP2",
+        0x92 => "SWAP3",
+        0x93 => "SWAP4",
+        0x94 => "SWAP5",
+        0x95 => "SWAP6",
+        0x96 => "SWAP7",
+        0x97 => "SWAP8",
+        0x98 => "SWAP9",
+        0x99 => "SWAP10",
+        0x9a => "SWAP11",
+        0x9b => "SWAP12",
+        0x9c => "SWAP13",
+        0x9d => "SWAP14",
+        0x9e => "SWAP15",
+        0x9f => "SWAP16",
+        0xa0 => "LOG0",
+        0xa1 => "LOG1",
+        0xa2 => "LOG2",
+        0xa3 => "LOG3",
+        0xa4 => "LOG4",
+        0xf0 => "CREATE",
+        0xf1 => "CALL",
+        0xf2 => "CALLCODE",
+        0xf3 => "RETURN",
+        0xf4 => "DELEGATECALL",
+        0xf5 => "CREATE2",
+        0xfa => "STATICCALL",
+        0xfd => "REVERT",
+        0xfe => "INVALID",
+        0xff => "SELFDESTRUCT",
+        _ => return None,
+    })
+}
@@ -6,8 +6,12 @@
 //! It also optionally contains a path where the event logs will be stored.
 //!
 //! This module also provides the implementation of the `EventLogger` struct,
-//! including methods for constructing a new `EventLogger`, adding an event to
-//! the `EventLogger`, and writing the event logs to a file.
+//! including methods for constructing a new `EventLogger`, adding an event,
+//! the transaction receipts, or the portfolios recorded by an `Accountant` to
+//! the `EventLogger`, and writing the event logs
+//! to files in an `OutputFormat` (CSV, JSON lines, or, with the `parquet`
+//! feature, Parquet). The `parquet` feature also provides `record_batch` to
+//! turn records into Arrow record batches directly.
 //!
 //! # Type Parameters
 //!
@@ -17,18 +21,45 @@
 //!   `Sync`, and has a static lifetime.
 //! * `E` - Type that implements the `EthLogDecode`, `Debug`, `Serialize`
 //!   traits, and has a static lifetime.
-use std::{collections::BTreeMap, env::current_dir, fmt::Debug, sync::Arc};
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    env::current_dir,
+    fmt::Debug,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
+#[cfg(feature = "parquet")]
+use arrow::{
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    error::ArrowError,
+    json::{reader::infer_json_schema_from_iterator, ReaderBuilder},
+    record_batch::RecordBatch,
+};
 use ethers::{
     contract::{builders::Event, EthLogDecode},
-    providers::StreamExt as ProviderStreamExt,
+    providers::{Middleware, ProviderError, StreamExt as ProviderStreamExt},
+    types::{Address, TransactionReceipt, H256, U256, U64},
 };
-use serde::Serialize;
+#[cfg(feature = "parquet")]
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tokio::io::AsyncWriteExt;
-use tracing::info;
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{watch, Mutex},
+};
+use tracing::{info, warn};
 
-use crate::middleware::{errors::RevmMiddlewareError, RevmMiddleware};
+#[cfg_attr(doc, doc(hidden))]
+#[cfg_attr(doc, allow(unused_imports))]
+#[cfg(doc)]
+use crate::environment::{builder::EnvironmentBuilder, Environment};
+use crate::{
+    metrics::Accountant,
+    middleware::{errors::RevmMiddlewareError, RevmMiddleware},
+};
 
 /// `EventLogger` is a struct that logs events from the Ethereum network.
 ///
@@ -45,8 +76,11 @@ use crate::middleware::{errors::RevmMiddlewareError, RevmMiddleware};
 /// * `E` - Type that implements the `EthLogDecode`, `Debug`, `Serialize`
 ///   traits, and has a static lifetime.
 pub struct EventLogger {
-    events: tokio::task::JoinSet<()>,
+    events: tokio::task::JoinSet<Result<(), RevmMiddlewareError>>,
+    stop: watch::Sender<bool>,
     path: Option<String>,
+    format: OutputFormat,
+    metadata: Option<Value>,
 }
 
 impl EventLogger {
@@ -59,12 +93,18 @@ impl EventLogger {
     pub fn builder() -> Self {
         Self {
             events: tokio::task::JoinSet::new(),
+            stop: watch::channel(false).0,
             path: None,
+            format: OutputFormat::default(),
+            metadata: None,
         }
     }
 
     /// Adds an event to the `EventLogger`.
     ///
+    /// The events are written in the [`OutputFormat`] and to the path set on
+    /// the `EventLogger` at the time they are added, so set those first.
+    ///
     /// # Arguments
     ///
     /// * `event` - The event to be added.
@@ -78,73 +118,170 @@ impl EventLogger {
         event: Event<Arc<RevmMiddleware>, RevmMiddleware, E>,
         name: S,
     ) -> Self {
-        let name = name.into();
-        let event_dir = current_dir()
-            .unwrap()
-            .join(self.path.clone().unwrap_or("events".into()))
-            .join(name);
-        std::fs::create_dir_all(&event_dir).unwrap();
+        let event_dir = self.output_dir().join(name.into());
+        let format = self.format;
+        let mut stop = self.stop.subscribe();
         self.events.spawn(async move {
-            let mut stream = event.stream().await.unwrap();
-            let mut files: BTreeMap<String, tokio::fs::File> = BTreeMap::new();
-            let mut columns_written: BTreeMap<String, bool> = BTreeMap::new();
-            while let Some(Ok(log)) = stream.next().await {
-                let serialized = serde_json::to_string(&log).unwrap();
-                let deserialized: BTreeMap<String, Value> =
-                    serde_json::from_str(&serialized).unwrap();
-                let (key, value) = deserialized.iter().next().unwrap();
-                let file_name = event_dir.join(format!("{}.csv", key));
-                let file_key = file_name.to_str().unwrap();
-                let file_value = files.get(file_key);
-                let toggle_written_columns = columns_written.get(file_key).unwrap_or(&false);
-                if file_value.is_none() {
-                    files.insert(
-                        file_key.into(),
-                        tokio::fs::OpenOptions::new()
-                            .write(true)
-                            .create(true)
-                            .truncate(true)
-                            .open(&file_name)
-                            .await
-                            .unwrap(),
-                    );
+            std::fs::create_dir_all(&event_dir)?;
+            let mut stream = event.stream().await.map_err(|e| {
+                RevmMiddlewareError::Provider(ProviderError::CustomError(e.to_string()))
+            })?;
+            let mut writers: BTreeMap<String, RecordWriter> = BTreeMap::new();
+            let result = async {
+                loop {
+                    let log = tokio::select! {
+                        Some(Ok(log)) = stream.next() => log,
+                        _ = stop.changed() => break,
+                        else => break,
+                    };
+                    let serialized =
+                        serde_json::to_value(&log).map_err(RevmMiddlewareError::Json)?;
+                    let (key, value) = serialized
+                        .as_object()
+                        .and_then(|fields| fields.iter().next())
+                        .ok_or_else(|| {
+                            RevmMiddlewareError::MissingData(format!(
+                                "The event {:?} is not keyed by its name!",
+                                log
+                            ))
+                        })?;
+                    let file_name = event_dir.join(format!("{}.{}", key, format.extension()));
+                    let writer = match writers.entry(key.clone()) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            entry.insert(RecordWriter::create(&file_name, format).await?)
+                        }
+                    };
+                    writer.write(value).await?;
                 }
+                Ok::<_, RevmMiddlewareError>(())
+            }
+            .await;
+            // What was written so far is finished even if writing more failed.
+            for writer in writers.into_values() {
+                writer.finish().await?;
+            }
+            result
+        });
+        self
+    }
 
-                let file = files.get_mut(file_key).unwrap();
+    /// Adds the receipts of every transaction sent to the [`Environment`]
+    /// the `client` is connected to. The receipts of a block are written once
+    /// the [`Environment`] has moved on to the next block, and those of the
+    /// last block once the logging stops. This requires the
+    /// [`Environment`] to be built with
+    /// [`EnvironmentBuilder::store_receipts`].
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - A client connected to the [`Environment`].
+    /// * `name` - The name to write the receipts under.
+    ///
+    /// # Returns
+    ///
+    /// The `EventLogger` instance with the added receipts.
+    pub fn add_receipts<S: Into<String>>(mut self, client: Arc<RevmMiddleware>, name: S) -> Self {
+        let receipts_dir = self.output_dir().join(name.into());
+        let file_name = receipts_dir.join(format!("receipts.{}", self.format.extension()));
+        let format = self.format;
+        let mut stop = self.stop.subscribe();
+        self.events.spawn(async move {
+            std::fs::create_dir_all(&receipts_dir)?;
+            let mut blocks = client.watch_blocks().await?;
+            let mut writer = RecordWriter::create(&file_name, format).await?;
+            let result = async {
+                let mut next_block = client.get_block_number().await?.as_u64();
+                loop {
+                    let stopped = tokio::select! {
+                        Some(_) = blocks.next() => false,
+                        _ = stop.changed() => true,
+                        else => true,
+                    };
+                    // Once the logging stops, the block in progress is the last one,
+                    // so its receipts are written as well.
+                    let current_block = client.get_block_number().await?.as_u64();
+                    let end_block = if stopped {
+                        current_block + 1
+                    } else {
+                        current_block
+                    };
+                    for block in next_block..end_block {
+                        for receipt in client.get_block_receipts(block).await? {
+                            let record = serde_json::to_value(ReceiptRecord::from(receipt))
+                                .map_err(RevmMiddlewareError::Json)?;
+                            writer.write(&record).await?;
+                        }
+                    }
+                    next_block = next_block.max(end_block);
+                    if stopped {
+                        break;
+                    }
+                }
+                Ok::<_, RevmMiddlewareError>(())
+            }
+            .await;
+            // What was written so far is finished even if writing more failed.
+            writer.finish().await?;
+            result
+        });
+        self
+    }
 
-                if toggle_written_columns == &true {
-                    let values = value
-                        .as_object()
-                        .unwrap()
-                        .values()
-                        .map(|x| x.to_string())
-                        .collect::<Vec<String>>()
-                        .join(",");
-                    file.write_all(values.as_bytes()).await.unwrap();
-                    file.write_all("\n".as_bytes()).await.unwrap();
-                } else {
-                    columns_written.entry(file_key.into()).or_insert(true);
-                    let columns = value
-                        .as_object()
-                        .unwrap()
-                        .keys()
-                        .map(|x| x.to_string())
-                        .collect::<Vec<String>>()
-                        .join(",");
-                    file.write_all(columns.as_bytes()).await.unwrap();
-                    file.write_all("\n".as_bytes()).await.unwrap();
-                    let values = value
-                        .as_object()
-                        .unwrap()
-                        .values()
-                        .map(|x| x.to_string())
-                        .collect::<Vec<String>>()
-                        .join(",");
-                    file.write_all(values.as_bytes()).await.unwrap();
-                    file.write_all("\n".as_bytes()).await.unwrap();
+    /// Adds the portfolios recorded by an [`Accountant`], one row per account
+    /// per [`Snapshot`](crate::metrics::Snapshot) as in
+    /// [`Accountant::records`]. Snapshots recorded since the last block are
+    /// written whenever the [`Environment`] moves on to the next block, and
+    /// the rest once the logging stops.
+    ///
+    /// # Arguments
+    ///
+    /// * `accountant` - The [`Accountant`] the simulation records snapshots
+    ///   with.
+    /// * `name` - The name to write the portfolios under.
+    ///
+    /// # Returns
+    ///
+    /// The `EventLogger` instance with the added portfolios.
+    pub fn add_metrics<S: Into<String>>(
+        mut self,
+        accountant: Arc<Mutex<Accountant>>,
+        name: S,
+    ) -> Self {
+        let metrics_dir = self.output_dir().join(name.into());
+        let file_name = metrics_dir.join(format!("portfolios.{}", self.format.extension()));
+        let format = self.format;
+        let mut stop = self.stop.subscribe();
+        self.events.spawn(async move {
+            std::fs::create_dir_all(&metrics_dir)?;
+            let client = accountant.lock().await.client().clone();
+            let mut blocks = client.watch_blocks().await?;
+            let mut writer = RecordWriter::create(&file_name, format).await?;
+            let result = async {
+                let mut written = 0;
+                loop {
+                    let stopped = tokio::select! {
+                        Some(_) = blocks.next() => false,
+                        _ = stop.changed() => true,
+                        else => true,
+                    };
+                    let accountant = accountant.lock().await;
+                    for record in accountant.records_since(written) {
+                        let record =
+                            serde_json::to_value(record).map_err(RevmMiddlewareError::Json)?;
+                        writer.write(&record).await?;
+                    }
+                    written = accountant.history().len();
+                    if stopped {
+                        break;
+                    }
                 }
-                continue;
+                Ok::<_, RevmMiddlewareError>(())
             }
+            .await;
+            // What was written so far is finished even if writing more failed.
+            writer.finish().await?;
+            result
         });
         self
     }
@@ -163,13 +300,52 @@ impl EventLogger {
         self
     }
 
+    /// Sets the [`OutputFormat`] the event logs are written in. Defaults to
+    /// [`OutputFormat::Csv`].
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format to write the event logs in.
+    ///
+    /// # Returns
+    ///
+    /// The `EventLogger` instance with the specified format.
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets metadata to be written alongside the event logs.
+    ///
+    /// This is useful for recording what produced the logs, e.g., the
+    /// [`EnvironmentParameters`](crate::environment::builder::EnvironmentParameters)
+    /// which include the seed that makes the run reproducible.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - Anything serializable to be written to `metadata.json`.
+    ///
+    /// # Returns
+    ///
+    /// The `EventLogger` instance with the specified metadata.
+    pub fn metadata(mut self, metadata: impl Serialize) -> Self {
+        self.metadata = Some(serde_json::to_value(metadata).unwrap());
+        self
+    }
+
     /// Executes the `EventLogger`.
     ///
     /// This function starts the event logging process. It first deletes the
     /// existing events directory, then creates a new directory for each
-    /// event. For each event, it creates a new CSV file and writes
-    /// the event data into the file. If the file already exists, it appends the
-    /// new data to the file.
+    /// event. For each event, it creates a new file in the [`OutputFormat`]
+    /// and writes the event data into the file. If the file already exists, it
+    /// appends the new data to the file. Any metadata is written to
+    /// `metadata.json` in the events directory.
+    ///
+    /// The logging runs for as long as the program does. Parquet files are
+    /// only complete once the logging is stopped, so use
+    /// [`EventLogger::run_until`] for those. Failures to log events are only
+    /// reported in the logs, as there is no one left to return them to.
     ///
     /// # Returns
     ///
@@ -184,12 +360,312 @@ impl EventLogger {
     /// This function will return an error if there is a problem creating the
     /// directories or files, or writing to the files.
     pub fn run(self) -> Result<(), RevmMiddlewareError> {
+        self.write_metadata()?;
         tokio::spawn(async move {
+            // Holding on to the sender keeps the logging going.
+            let _stop = self.stop;
             let mut set = self.events;
             while let Some(res) = set.join_next().await {
-                info!("task completed: {:?}", res);
+                match res {
+                    Ok(Err(e)) => warn!("task failed: {}", e),
+                    res => info!("task completed: {:?}", res),
+                }
             }
         });
         Ok(())
     }
+
+    /// Executes the `EventLogger` like [`EventLogger::run`] until `stop`
+    /// completes, e.g., once the simulation is done. All files are then
+    /// flushed and finished before this returns.
+    ///
+    /// # Arguments
+    ///
+    /// * `stop` - A future that completes when the logging should stop.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there is a problem creating the
+    /// directories or files, or writing to the files. If any of the events,
+    /// receipts, or metrics failed to be logged, the first of their errors is
+    /// returned once the rest are finished.
+    pub async fn run_until(
+        self,
+        stop: impl Future<Output = ()>,
+    ) -> Result<(), RevmMiddlewareError> {
+        self.write_metadata()?;
+        stop.await;
+        // Sending only fails if every task already finished.
+        let _ = self.stop.send(true);
+        let mut set = self.events;
+        let mut result = Ok(());
+        while let Some(res) = set.join_next().await {
+            info!("task completed: {:?}", res);
+            if let Ok(Err(e)) = res {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    /// Writes the metadata, if any, to `metadata.json`.
+    fn write_metadata(&self) -> Result<(), RevmMiddlewareError> {
+        if let Some(metadata) = &self.metadata {
+            let events_dir = self.output_dir();
+            std::fs::create_dir_all(&events_dir)?;
+            std::fs::write(
+                events_dir.join("metadata.json"),
+                serde_json::to_string_pretty(metadata).map_err(RevmMiddlewareError::Json)?,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The directory the event logs are written to.
+    fn output_dir(&self) -> PathBuf {
+        current_dir()
+            .unwrap()
+            .join(self.path.clone().unwrap_or("events".into()))
+    }
+}
+
+/// The formats the [`EventLogger`] can write event logs in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputFormat {
+    /// Comma separated values with a header row, written to `.csv` files.
+    #[default]
+    Csv,
+
+    /// One JSON object per line, written to `.jsonl` files.
+    JsonLines,
+
+    /// Apache Parquet, written to `.parquet` files in row groups of
+    /// [`PARQUET_BATCH_SIZE`] records. The files are only complete once the
+    /// [`EventLogger`] is stopped with [`EventLogger::run_until`].
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl OutputFormat {
+    /// The file extension for the format.
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::JsonLines => "jsonl",
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Writes records to a file in an [`OutputFormat`].
+enum RecordWriter {
+    /// Writes each record as a line of text as soon as it comes in.
+    Text {
+        file: tokio::fs::File,
+        format: OutputFormat,
+        header_written: bool,
+    },
+
+    /// Buffers records and writes them as Parquet row groups.
+    #[cfg(feature = "parquet")]
+    Parquet(ParquetWriter),
+}
+
+impl RecordWriter {
+    async fn create(path: &Path, format: OutputFormat) -> std::io::Result<Self> {
+        #[cfg(feature = "parquet")]
+        {
+            if format == OutputFormat::Parquet {
+                return Ok(Self::Parquet(ParquetWriter::create(path)?));
+            }
+        }
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .await?;
+        Ok(Self::Text {
+            file,
+            format,
+            header_written: false,
+        })
+    }
+
+    async fn write(&mut self, record: &Value) -> std::io::Result<()> {
+        let (file, format, header_written) = match self {
+            Self::Text {
+                file,
+                format,
+                header_written,
+            } => (file, format, header_written),
+            #[cfg(feature = "parquet")]
+            Self::Parquet(writer) => return writer.write(record),
+        };
+        let mut lines = String::new();
+        match format {
+            OutputFormat::Csv => {
+                let fields = record.as_object().unwrap();
+                if !*header_written {
+                    let columns = fields.keys().cloned().collect::<Vec<String>>().join(",");
+                    lines.push_str(&columns);
+                    lines.push('\n');
+                    *header_written = true;
+                }
+                let values = fields
+                    .values()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<String>>()
+                    .join(",");
+                lines.push_str(&values);
+            }
+            OutputFormat::JsonLines => lines.push_str(&record.to_string()),
+            #[cfg(feature = "parquet")]
+            OutputFormat::Parquet => unreachable!("Parquet is never written as text"),
+        }
+        lines.push('\n');
+        file.write_all(lines.as_bytes()).await
+    }
+
+    /// Writes out anything still buffered and completes the file.
+    async fn finish(self) -> std::io::Result<()> {
+        match self {
+            Self::Text { mut file, .. } => file.flush().await,
+            #[cfg(feature = "parquet")]
+            Self::Parquet(writer) => writer.finish(),
+        }
+    }
+}
+
+/// The number of records written to each row group of a Parquet file.
+#[cfg(feature = "parquet")]
+pub const PARQUET_BATCH_SIZE: usize = 8192;
+
+/// Converts JSON records, such as the serialized events written by the
+/// [`EventLogger`], into an Arrow [`RecordBatch`]. The schema is inferred from
+/// the records, with columns that are always `null` typed as strings.
+#[cfg(feature = "parquet")]
+pub fn record_batch(records: &[Value]) -> Result<RecordBatch, ArrowError> {
+    let schema = infer_schema(records)?;
+    decode(records, schema)
+}
+
+#[cfg(feature = "parquet")]
+fn infer_schema(records: &[Value]) -> Result<SchemaRef, ArrowError> {
+    let schema = infer_json_schema_from_iterator(records.iter().map(Ok))?;
+    Ok(Arc::new(Schema::new(
+        schema
+            .fields()
+            .iter()
+            .map(|field| match field.data_type() {
+                DataType::Null => Field::new(field.name(), DataType::Utf8, true),
+                _ => field.as_ref().clone(),
+            })
+            .collect::<Vec<_>>(),
+    )))
+}
+
+#[cfg(feature = "parquet")]
+fn decode(records: &[Value], schema: SchemaRef) -> Result<RecordBatch, ArrowError> {
+    let mut decoder = ReaderBuilder::new(schema.clone())
+        .with_batch_size(records.len().max(1))
+        .build_decoder()?;
+    decoder.serialize(records)?;
+    Ok(decoder
+        .flush()?
+        .unwrap_or_else(|| RecordBatch::new_empty(schema)))
+}
+
+/// Buffers records and writes them to a Parquet file in row groups. The
+/// schema is inferred from the first row group, and the file is only created
+/// once there is a row group to write.
+#[cfg(feature = "parquet")]
+struct ParquetWriter {
+    path: PathBuf,
+    writer: Option<(SchemaRef, ArrowWriter<std::fs::File>)>,
+    buffer: Vec<Value>,
+}
+
+#[cfg(feature = "parquet")]
+impl ParquetWriter {
+    fn create(path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: None,
+            buffer: vec![],
+        })
+    }
+
+    fn write(&mut self, record: &Value) -> std::io::Result<()> {
+        self.buffer.push(record.clone());
+        if self.buffer.len() >= PARQUET_BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        if self.writer.is_none() {
+            let schema = infer_schema(&self.buffer).map_err(to_io_error)?;
+            let file = std::fs::File::create(&self.path)?;
+            let writer = ArrowWriter::try_new(file, schema.clone(), None).map_err(to_io_error)?;
+            self.writer = Some((schema, writer));
+        }
+        let (schema, writer) = self.writer.as_mut().unwrap();
+        let batch = decode(&self.buffer, schema.clone()).map_err(to_io_error)?;
+        writer.write(&batch).map_err(to_io_error)?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        self.flush()?;
+        if let Some((_, writer)) = self.writer {
+            writer.close().map_err(to_io_error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet")]
+fn to_io_error(error: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, error.to_string())
+}
+
+/// The fields of a [`TransactionReceipt`] that the [`EventLogger`] writes.
+/// The logs are left out as they are written as events.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReceiptRecord {
+    transaction_hash: H256,
+    block_number: Option<U64>,
+    transaction_index: U64,
+    from: Address,
+    to: Option<Address>,
+    contract_address: Option<Address>,
+    gas_used: Option<U256>,
+    cumulative_gas_used: U256,
+    effective_gas_price: Option<U256>,
+    status: Option<U64>,
+}
+
+impl From<TransactionReceipt> for ReceiptRecord {
+    fn from(receipt: TransactionReceipt) -> Self {
+        Self {
+            transaction_hash: receipt.transaction_hash,
+            block_number: receipt.block_number,
+            transaction_index: receipt.transaction_index,
+            from: receipt.from,
+            to: receipt.to,
+            contract_address: receipt.contract_address,
+            gas_used: receipt.gas_used,
+            cumulative_gas_used: receipt.cumulative_gas_used,
+            effective_gas_price: receipt.effective_gas_price,
+            status: receipt.status,
+        }
+    }
 }
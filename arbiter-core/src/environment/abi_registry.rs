@@ -0,0 +1,415 @@
+//! The `abi_registry` module contains the [`AbiRegistry`] an [`Environment`]
+//! shares with all of its clients.
+//!
+//! ABIs are registered by the address of the contract they describe with
+//! [`Environment::register_abi`] or
+//! [`RevmMiddleware::register_abi`](crate::middleware::RevmMiddleware::register_abi).
+//! Contracts deployed through the [`RevmMiddleware`] from the bytecode of a
+//! known binding, e.g., one registered with [`AbiRegistry::register_binding`],
+//! are registered automatically under the name of the binding.
+//!
+//! When a call or transaction reverts, the middleware decodes any custom error
+//! in the revert data with the ABI of the contract that was called, falling
+//! back to the other registered ABIs for errors bubbled up from contracts
+//! further down the call. The registry also renders calls, logs, receipts, and
+//! the traces of a [`CallTracer`] with the names of the functions and events
+//! involved.
+
+#![warn(missing_docs)]
+
+use std::{
+    fmt::{self, Display},
+    sync::RwLock,
+};
+
+use ethers::{
+    abi::{Abi, RawLog, Token},
+    types::{Address, Bytes, TransactionReceipt},
+};
+
+use super::{trace::*, *};
+use crate::middleware::revert::RevertError;
+
+/// The ABI of a contract along with the name it is known by, if any.
+#[derive(Clone, Debug)]
+struct Registered {
+    name: Option<String>,
+    abi: Abi,
+}
+
+/// The bytecode and ABI of a binding that contracts can be deployed from.
+#[derive(Clone, Debug)]
+struct Binding {
+    name: String,
+    bytecode: Bytes,
+    abi: Abi,
+}
+
+/// Everything held by an [`AbiRegistry`].
+#[derive(Debug, Default)]
+struct Registry {
+    contracts: BTreeMap<Address, Registered>,
+    bindings: Vec<Binding>,
+}
+
+/// The ABIs of the contracts in an [`Environment`], keyed by the address the
+/// contracts are deployed at. Clones share the same ABIs.
+#[derive(Clone, Debug, Default)]
+pub struct AbiRegistry(Arc<RwLock<Registry>>);
+
+impl AbiRegistry {
+    /// Creates a registry that knows the bindings that ship with
+    /// `arbiter-core` when the `contracts` feature is enabled, and is empty
+    /// otherwise.
+    pub fn new() -> Self {
+        let registry = Self::default();
+        #[cfg(feature = "contracts")]
+        for artifact in crate::artifacts::ARTIFACTS.iter() {
+            registry.register_binding(
+                artifact.name,
+                artifact.bytecode.clone(),
+                (*artifact.abi).clone(),
+            );
+        }
+        registry
+    }
+
+    /// Registers the `abi` of the contract at `address`, replacing the one
+    /// registered before, if any.
+    pub fn register(&self, address: Address, abi: Abi) {
+        self.0
+            .write()
+            .unwrap()
+            .contracts
+            .insert(address, Registered { name: None, abi });
+    }
+
+    /// Registers the `abi` of the contract at `address` along with the `name`
+    /// it is rendered with.
+    pub fn register_named(&self, address: Address, name: impl Into<String>, abi: Abi) {
+        let registered = Registered {
+            name: Some(name.into()),
+            abi,
+        };
+        self.0
+            .write()
+            .unwrap()
+            .contracts
+            .insert(address, registered);
+    }
+
+    /// Registers a binding, e.g., the `*_BYTECODE` and `*_ABI` generated by
+    /// `abigen`. Contracts deployed through the [`RevmMiddleware`] with this
+    /// `bytecode` are then registered with the `abi` under the `name`.
+    pub fn register_binding(&self, name: impl Into<String>, bytecode: Bytes, abi: Abi) {
+        self.0.write().unwrap().bindings.push(Binding {
+            name: name.into(),
+            bytecode,
+            abi,
+        });
+    }
+
+    /// Registers the contract deployed at `address` with the `init_code` if
+    /// the init code is the bytecode of a known binding, possibly followed by
+    /// constructor arguments. Returns whether it was registered.
+    pub(crate) fn register_deployment(&self, address: Address, init_code: &[u8]) -> bool {
+        let binding = self
+            .0
+            .read()
+            .unwrap()
+            .bindings
+            .iter()
+            .rev()
+            .find(|binding| {
+                !binding.bytecode.is_empty() && init_code.starts_with(&binding.bytecode)
+            })
+            .cloned();
+        match binding {
+            Some(binding) => {
+                self.register_named(address, binding.name, binding.abi);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The ABI registered for the contract at `address`.
+    pub fn get(&self, address: Address) -> Option<Abi> {
+        self.0
+            .read()
+            .unwrap()
+            .contracts
+            .get(&address)
+            .map(|registered| registered.abi.clone())
+    }
+
+    /// The name the contract at `address` was registered under, if any.
+    pub fn name(&self, address: Address) -> Option<String> {
+        self.0
+            .read()
+            .unwrap()
+            .contracts
+            .get(&address)
+            .and_then(|registered| registered.name.clone())
+    }
+
+    /// The addresses that have an ABI registered, in order.
+    pub fn addresses(&self) -> Vec<Address> {
+        self.0.read().unwrap().contracts.keys().copied().collect()
+    }
+
+    /// Finds the first registered ABI that `f` returns a value for, trying the
+    /// ABI of the contract at `address` before all others.
+    pub fn find_map<T>(
+        &self,
+        address: Option<Address>,
+        mut f: impl FnMut(&Abi) -> Option<T>,
+    ) -> Option<T> {
+        let registry = self.0.read().unwrap();
+        address
+            .and_then(|address| registry.contracts.get(&address))
+            .and_then(|registered| f(&registered.abi))
+            .or_else(|| {
+                registry
+                    .contracts
+                    .values()
+                    .find_map(|registered| f(&registered.abi))
+            })
+    }
+
+    /// Decodes a call with `data` to the contract at `address` with the
+    /// contract's ABI.
+    pub fn decode_call(&self, address: Address, data: &[u8]) -> Option<DecodedCall> {
+        let selector = data.get(..4)?;
+        let abi = self.get(address)?;
+        let function = abi
+            .functions()
+            .find(|function| function.short_signature() == selector)?;
+        let args = function.decode_input(&data[4..]).ok()?;
+        Some(DecodedCall {
+            contract: self.label(address),
+            function: function.name.clone(),
+            args: named_args(function.inputs.iter().map(|input| &input.name), &args),
+        })
+    }
+
+    /// Decodes a `log` with the ABI of the contract that emitted it, or else
+    /// with the first other registered ABI that declares the event.
+    pub fn decode_log(&self, log: &ethers::types::Log) -> Option<DecodedLog> {
+        let topic = log.topics.first()?;
+        let (name, params) = self.find_map(Some(log.address), |abi| {
+            let event = abi
+                .events()
+                .find(|event| !event.anonymous && event.signature() == *topic)?;
+            let parsed = event
+                .parse_log(RawLog {
+                    topics: log.topics.clone(),
+                    data: log.data.to_vec(),
+                })
+                .ok()?;
+            Some((event.name.clone(), parsed.params))
+        })?;
+        Some(DecodedLog {
+            contract: self.label(log.address),
+            event: name,
+            params: params
+                .into_iter()
+                .map(|param| (param.name, format_token(&param.value)))
+                .collect(),
+        })
+    }
+
+    /// Renders a `receipt` with the name of the contract that was called or
+    /// created and the names of the events that were emitted.
+    pub fn render_receipt(&self, receipt: &TransactionReceipt) -> String {
+        let mut rendered = format!(
+            "{:?} in block {}: {}, gas used {}\n",
+            receipt.transaction_hash,
+            receipt.block_number.unwrap_or_default(),
+            match receipt.status.map(|status| status.as_u64()) {
+                Some(0) => "failed",
+                _ => "succeeded",
+            },
+            receipt.gas_used.unwrap_or_default(),
+        );
+        if let Some(address) = receipt.contract_address {
+            rendered.push_str(&format!("  created {}\n", self.label(address)));
+        } else if let Some(to) = receipt.to {
+            rendered.push_str(&format!("  to {}\n", self.label(to)));
+        }
+        for log in &receipt.logs {
+            match self.decode_log(log) {
+                Some(decoded) => rendered.push_str(&format!("  emit {}\n", decoded)),
+                None => rendered.push_str(&format!(
+                    "  emit {} topics {:?} data {}\n",
+                    self.label(log.address),
+                    log.topics,
+                    log.data
+                )),
+            }
+        }
+        rendered
+    }
+
+    /// Renders a `trace` recorded by a [`CallTracer`] as a call tree, in the
+    /// style of Foundry's traces.
+    pub fn render_trace(&self, trace: &[CallFrame]) -> String {
+        let mut rendered = String::new();
+        for frame in trace {
+            let indent = "  ".repeat(frame.depth);
+            let call = match (frame.kind, frame.to) {
+                (CallKind::Create, Some(to)) => format!("new {}", self.label(to)),
+                (CallKind::Create, None) => "new <unknown>".to_string(),
+                (_, Some(to)) => match self.decode_call(to, &frame.input) {
+                    Some(decoded) => decoded.to_string(),
+                    None => format!("{}::{}", self.label(to), frame.input),
+                },
+                (_, None) => frame.input.to_string(),
+            };
+            let kind = match frame.kind {
+                CallKind::StaticCall => " [staticcall]",
+                CallKind::DelegateCall => " [delegatecall]",
+                CallKind::CallCode => " [callcode]",
+                CallKind::Call | CallKind::Create => "",
+            };
+            rendered.push_str(&format!(
+                "{}[{}] {}{}\n",
+                indent, frame.gas_used, call, kind
+            ));
+            let outcome = match (frame.success, frame.kind, frame.to) {
+                (true, CallKind::Create, _) => format!("{} bytes of code", frame.output.len()),
+                (true, _, Some(to)) => self
+                    .decode_output(to, &frame.input, &frame.output)
+                    .unwrap_or_else(|| frame.output.to_string()),
+                (true, _, None) => frame.output.to_string(),
+                (false, _, to) => {
+                    RevertError::decode_with_registry(&frame.output, self, to).to_string()
+                }
+            };
+            rendered.push_str(&format!("{}  └─ ← {}\n", indent, outcome));
+        }
+        rendered
+    }
+
+    /// Decodes the `output` of a call with `input` to the contract at
+    /// `address`.
+    fn decode_output(&self, address: Address, input: &[u8], output: &[u8]) -> Option<String> {
+        let selector = input.get(..4)?;
+        let abi = self.get(address)?;
+        let function = abi
+            .functions()
+            .find(|function| function.short_signature() == selector)?;
+        let tokens = function.decode_output(output).ok()?;
+        Some(format!(
+            "({})",
+            tokens
+                .iter()
+                .map(format_token)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    }
+
+    /// The name of the contract at `address` if it has one and its address
+    /// otherwise.
+    fn label(&self, address: Address) -> String {
+        match self.name(address) {
+            Some(name) => format!("{}@{:?}", name, address),
+            None => format!("{:?}", address),
+        }
+    }
+}
+
+/// A call decoded with an [`AbiRegistry`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedCall {
+    /// The name of the contract that was called, or its address.
+    pub contract: String,
+
+    /// The name of the function that was called.
+    pub function: String,
+
+    /// The name and rendered value of each argument.
+    pub args: Vec<(String, String)>,
+}
+
+impl Display for DecodedCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}::{}({})",
+            self.contract,
+            self.function,
+            render_args(&self.args)
+        )
+    }
+}
+
+/// A log decoded with an [`AbiRegistry`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedLog {
+    /// The name of the contract that emitted the log, or its address.
+    pub contract: String,
+
+    /// The name of the event.
+    pub event: String,
+
+    /// The name and rendered value of each parameter of the event.
+    pub params: Vec<(String, String)>,
+}
+
+impl Display for DecodedLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}::{}({})",
+            self.contract,
+            self.event,
+            render_args(&self.params)
+        )
+    }
+}
+
+/// Pairs each of the `tokens` with its name, rendering the tokens.
+fn named_args<'a>(
+    names: impl Iterator<Item = &'a String>,
+    tokens: &[Token],
+) -> Vec<(String, String)> {
+    names
+        .zip(tokens)
+        .map(|(name, token)| (name.clone(), format_token(token)))
+        .collect()
+}
+
+/// Renders arguments as `name: value`, leaving out empty names.
+fn render_args(args: &[(String, String)]) -> String {
+    args.iter()
+        .map(|(name, value)| match name.is_empty() {
+            true => value.clone(),
+            false => format!("{}: {}", name, value),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Renders a `token` the way it would be written in Solidity, e.g., integers
+/// in decimal and addresses checksummed.
+pub fn format_token(token: &Token) -> String {
+    let join = |tokens: &[Token]| {
+        tokens
+            .iter()
+            .map(format_token)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match token {
+        Token::Address(address) => ethers::utils::to_checksum(address, None),
+        Token::Uint(value) => value.to_string(),
+        Token::Int(value) => ethers::types::I256::from_raw(*value).to_string(),
+        Token::Bool(value) => value.to_string(),
+        Token::String(value) => format!("{:?}", value),
+        Token::Bytes(bytes) | Token::FixedBytes(bytes) => Bytes::from(bytes.clone()).to_string(),
+        Token::Array(tokens) | Token::FixedArray(tokens) => format!("[{}]", join(tokens)),
+        Token::Tuple(tokens) => format!("({})", join(tokens)),
+    }
+}
@@ -0,0 +1,168 @@
+//! The `anvil` module moves state between an [`Environment`] and
+//! [Anvil](https://book.getfoundry.sh/anvil/) in the JSON format of
+//! `anvil --dump-state` and `anvil --load-state`.
+//!
+//! [`Environment::dump_state`] writes the accounts of an [`Environment`] to
+//! an [`AnvilState`] file that Anvil can start from, and
+//! [`EnvironmentBuilder::load_state`](super::builder::EnvironmentBuilder::load_state)
+//! starts an [`Environment`] from a file that Anvil dumped.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use ethers::types::{Address, Bytes, U256 as EthersU256};
+
+use super::*;
+
+/// The state of a chain as it is dumped and loaded by Anvil.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnvilState {
+    /// The block the state is at, as Anvil dumps it. Only its number and
+    /// timestamp are read when the state is loaded, and it is left out when
+    /// the state is dumped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block: Option<AnvilBlock>,
+
+    /// The accounts of the chain, keyed by their address.
+    pub accounts: BTreeMap<Address, AnvilAccount>,
+
+    /// The number of the latest block.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub best_block_number: Option<u64>,
+}
+
+/// The parts of a block in an [`AnvilState`] that an [`Environment`] starts
+/// from.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnvilBlock {
+    /// The number of the block.
+    #[serde(default)]
+    pub number: Option<EthersU256>,
+
+    /// The timestamp of the block.
+    #[serde(default)]
+    pub timestamp: Option<EthersU256>,
+}
+
+/// An account in an [`AnvilState`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AnvilAccount {
+    /// The nonce of the account.
+    pub nonce: u64,
+
+    /// The balance of the account.
+    pub balance: EthersU256,
+
+    /// The deployed code of the account, which is empty for externally owned
+    /// accounts.
+    pub code: Bytes,
+
+    /// The populated storage slots of the account.
+    pub storage: BTreeMap<EthersU256, EthersU256>,
+}
+
+impl AnvilState {
+    /// Reads an [`AnvilState`] from the JSON file at `path`, e.g., one written
+    /// by `anvil --dump-state`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, EnvironmentError> {
+        let data = fs::read_to_string(path).map_err(|e| EnvironmentError::State(e.to_string()))?;
+        serde_json::from_str(&data).map_err(|e| EnvironmentError::State(e.to_string()))
+    }
+
+    /// Writes the [`AnvilState`] as JSON to the file at `path`, where
+    /// `anvil --load-state` can read it.
+    pub fn to_file(&self, path: impl AsRef<Path>) -> Result<(), EnvironmentError> {
+        let data =
+            serde_json::to_string(self).map_err(|e| EnvironmentError::State(e.to_string()))?;
+        fs::write(path, data).map_err(|e| EnvironmentError::State(e.to_string()))
+    }
+
+    /// Collects the accounts held in `db` at the block `block`. Accounts of a
+    /// forked database that were never touched are not held in it and so are
+    /// left out.
+    pub(crate) fn from_db(db: &CacheDB<ExternalDb>, block: &BlockEnv) -> Self {
+        let accounts = db
+            .accounts
+            .iter()
+            .filter(|(_, account)| !matches!(account.account_state, AccountState::NotExisting))
+            .map(|(address, account)| {
+                let code = account
+                    .info
+                    .code
+                    .as_ref()
+                    .map(|code| Bytes::from(code.original_bytes().0))
+                    .unwrap_or_default();
+                let storage = account
+                    .storage
+                    .iter()
+                    .filter(|(_, value)| **value != U256::ZERO)
+                    .map(|(slot, value)| {
+                        (
+                            EthersU256::from(slot.to_be_bytes()),
+                            EthersU256::from(value.to_be_bytes()),
+                        )
+                    })
+                    .collect();
+                (
+                    Address::from(address.into_array()),
+                    AnvilAccount {
+                        nonce: account.info.nonce,
+                        balance: EthersU256::from(account.info.balance.to_be_bytes()),
+                        code,
+                        storage,
+                    },
+                )
+            })
+            .collect();
+        Self {
+            block: None,
+            accounts,
+            best_block_number: Some(block.number.as_limbs()[0]),
+        }
+    }
+
+    /// Builds a database holding the accounts of the [`AnvilState`].
+    pub(crate) fn to_db(&self) -> CacheDB<ExternalDb> {
+        let mut db = CacheDB::new(ExternalDb::default());
+        for (address, account) in &self.accounts {
+            let address = revm::primitives::Address::from(address.as_fixed_bytes());
+            let mut info = AccountInfo {
+                nonce: account.nonce,
+                balance: U256::from_limbs(account.balance.0),
+                ..Default::default()
+            };
+            if !account.code.is_empty() {
+                let code = Bytecode::new_raw(revm::primitives::Bytes(account.code.0.clone()));
+                info.code_hash = code.hash_slow();
+                info.code = Some(code);
+            }
+            db.insert_account_info(address, info);
+            let db_account = db.accounts.get_mut(&address).unwrap();
+            for (slot, value) in &account.storage {
+                db_account
+                    .storage
+                    .insert(U256::from_limbs(slot.0), U256::from_limbs(value.0));
+            }
+        }
+        db
+    }
+
+    /// The block an [`Environment`] loading the [`AnvilState`] starts at. This
+    /// is the block in the state, or the latest block if only that is known.
+    pub(crate) fn block_env(&self) -> Option<BlockEnv> {
+        let number = self
+            .block
+            .as_ref()
+            .and_then(|block| block.number)
+            .or(self.best_block_number.map(EthersU256::from))?;
+        let timestamp = self
+            .block
+            .as_ref()
+            .and_then(|block| block.timestamp)
+            .unwrap_or_default();
+        Some(BlockEnv {
+            number: U256::from_limbs(number.0),
+            timestamp: U256::from_limbs(timestamp.0),
+            ..Default::default()
+        })
+    }
+}
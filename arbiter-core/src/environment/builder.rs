@@ -5,7 +5,31 @@
 
 #![warn(missing_docs)]
 
+pub use revm::primitives::SpecId;
+
 use super::*;
+#[cfg(feature = "persistent-history")]
+use crate::environment::history::PersistentHistory;
+use crate::environment::precompile::Precompile;
+
+/// The address of the canonical CREATE2 deployer, which is deployed at the
+/// same address on most live chains. Contracts deployed through it with the
+/// same salt and init code land at the same address everywhere.
+pub const CREATE2_DEPLOYER: ethers::types::Address = ethers::types::H160([
+    0x4e, 0x59, 0xb4, 0x48, 0x47, 0xb3, 0x79, 0x57, 0x85, 0x88, 0x92, 0x0c, 0xa7, 0x8f, 0xbf, 0x26,
+    0xc0, 0xb4, 0x95, 0x6c,
+]);
+
+/// The runtime code of the [`CREATE2_DEPLOYER`]. It takes a 32-byte salt
+/// followed by the init code of a contract, deploys the contract with
+/// `CREATE2`, and returns its address.
+pub const CREATE2_DEPLOYER_CODE: &[u8] = &[
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xe0, 0x36, 0x01, 0x60, 0x00, 0x81, 0x60, 0x20, 0x82, 0x37, 0x80, 0x35, 0x82, 0x82, 0x34, 0xf5,
+    0x80, 0x15, 0x15, 0x60, 0x39, 0x57, 0x81, 0x82, 0xfd, 0x5b, 0x80, 0x82, 0x52, 0x50, 0x50, 0x50,
+    0x60, 0x14, 0x60, 0x0c, 0xf3,
+];
 
 /// Parameters necessary for creating or modifying an `Environment`.
 ///
@@ -38,6 +62,34 @@ pub struct EnvironmentParameters {
     /// By default, [`GasSettings::UserControlled`] begins with a gas price of
     /// 0.
     pub gas_settings: GasSettings,
+
+    /// The maximum amount of gas that the transactions in a single block can
+    /// use. Transactions that would exceed it spill into the next block.
+    /// There is no limit when this is `None`.
+    pub block_gas_limit: Option<u64>,
+
+    /// The seed used for all of the randomness in the [`Environment`], e.g.,
+    /// the block sizes of [`BlockSettings::RandomlySampled`]. When set, it
+    /// takes precedence over the seed given in the [`BlockSettings`].
+    pub seed: Option<u64>,
+
+    /// The L1 data fee charged to every transaction on top of its execution
+    /// gas, as on a rollup. No fee is charged when this is `None`.
+    pub l1_fee: Option<L1FeeSettings>,
+
+    /// The hardfork that transactions are executed under, which decides
+    /// whether opcodes such as `PUSH0` or `TSTORE` are available. The latest
+    /// hardfork `revm` supports is used when this is `None`.
+    pub spec_id: Option<SpecId>,
+
+    /// The chain ID reported to clients and returned by the `CHAINID` opcode.
+    /// Mainnet's chain ID of 1 is used when this is `None`.
+    pub chain_id: Option<u64>,
+
+    /// The accounts that are funded, deployed, and given storage before the
+    /// first block, keyed by their address.
+    #[serde(default)]
+    pub genesis: std::collections::HashMap<ethers::types::Address, GenesisAccount>,
 }
 
 /// A builder for creating an `Environment`.
@@ -73,9 +125,66 @@ pub struct EnvironmentBuilder {
     /// 0.
     pub gas_settings: GasSettings,
 
+    /// An optional limit on the gas the transactions in a single block can
+    /// use.
+    pub block_gas_limit: Option<u64>,
+
+    /// An optional seed used for all of the randomness in the `Environment`.
+    pub seed: Option<u64>,
+
+    /// An optional L1 data fee charged to every transaction.
+    pub l1_fee: Option<L1FeeSettings>,
+
+    /// An optional hardfork to execute transactions under.
+    pub spec_id: Option<SpecId>,
+
+    /// An optional chain ID for the `Environment`.
+    pub chain_id: Option<u64>,
+
+    /// The accounts set up before the first block.
+    pub genesis: std::collections::HashMap<ethers::types::Address, GenesisAccount>,
+
+    /// Whether the `Environment` records the kind of every instruction it
+    /// receives.
+    pub record_instructions: bool,
+
+    /// Whether the transactions and receipts of every block are kept so they
+    /// can be retrieved with `eth_getBlockReceipts`, `eth_getLogs`, or by
+    /// hash.
+    pub store_receipts: bool,
+
+    /// Whether the state diff of every transaction is kept so it can be
+    /// retrieved by the hash of the transaction.
+    pub record_state_diffs: bool,
+
+    /// Where the history of the `Environment` is kept on disk, if it is not
+    /// kept in memory.
+    #[cfg(feature = "persistent-history")]
+    pub(crate) persistent_history: Option<PersistentHistory>,
+
+    /// Whether the `Environment` tracks the calls its transactions make to
+    /// precompiles.
+    pub track_precompiles: bool,
+
+    /// Whether the `Environment` tracks the gas used by the calls to each
+    /// contract function.
+    pub profile_gas: bool,
+
+    /// The inspectors run on every transaction in the `Environment`.
+    pub(crate) inspectors: Inspectors,
+
+    /// The precompiles implemented in Rust that are added to the
+    /// `Environment`.
+    pub(crate) precompiles: CustomPrecompiles,
+
     /// The database to be loaded into the `Environment`.
-    /// This can come from a [`fork::Fork`] or otherwise.
-    pub db: Option<CacheDB<EmptyDB>>,
+    /// This can come from a [`fork::Fork`], a [`fork::ForkedDb`], or
+    /// otherwise.
+    pub db: Option<CacheDB<ExternalDb>>,
+
+    /// The block the `Environment` starts at, when it is branched from another
+    /// one.
+    pub(crate) block: Option<BlockEnv>,
 }
 
 /// The `EnvironmentBuilder` is a builder pattern for creating an
@@ -91,7 +200,23 @@ impl EnvironmentBuilder {
             label: None,
             block_settings: BlockSettings::UserControlled,
             gas_settings: GasSettings::UserControlled,
+            block_gas_limit: None,
+            seed: None,
+            l1_fee: None,
+            spec_id: None,
+            chain_id: None,
+            genesis: std::collections::HashMap::new(),
+            record_instructions: false,
+            store_receipts: false,
+            record_state_diffs: false,
+            #[cfg(feature = "persistent-history")]
+            persistent_history: None,
+            track_precompiles: false,
+            profile_gas: false,
+            inspectors: Inspectors::default(),
+            precompiles: CustomPrecompiles::default(),
             db: None,
+            block: None,
         }
     }
 
@@ -110,6 +235,181 @@ impl EnvironmentBuilder {
         self
     }
 
+    /// Sets the `block_gas_limit` for the `EnvironmentBuilder`.
+    /// Once the transactions in a block have used this much gas, further
+    /// transactions are included in the next block.
+    pub fn block_gas_limit(mut self, block_gas_limit: u64) -> Self {
+        self.block_gas_limit = Some(block_gas_limit);
+        self
+    }
+
+    /// Sets the `seed` for the `EnvironmentBuilder`.
+    /// This makes the block sizes and gas prices sampled by the
+    /// [`Environment`] reproducible across runs and overrides the seed given
+    /// in [`BlockSettings::RandomlySampled`].
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Sets the `spec_id` for the `EnvironmentBuilder`.
+    /// This is the hardfork the [`Environment`] executes transactions under,
+    /// e.g., [`SpecId::SHANGHAI`] to disallow the transient storage of
+    /// Cancun or [`SpecId::MERGE`] to simulate a chain without `PUSH0`.
+    pub fn spec_id(mut self, spec_id: SpecId) -> Self {
+        self.spec_id = Some(spec_id);
+        self
+    }
+
+    /// Sets the `chain_id` for the `EnvironmentBuilder`.
+    /// This is the chain ID returned by the `CHAINID` opcode, and thereby used
+    /// in EIP-712 domain separators, as well as the one the clients of the
+    /// [`Environment`] sign transactions for and report via `get_chainid`.
+    pub fn chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = Some(chain_id);
+        self
+    }
+
+    /// Sets up the accounts in `genesis` before the first block, e.g., to
+    /// fund the agents of a simulation or to deploy contracts from their
+    /// runtime bytecode, without a `Deal` or `Etch` cheatcode for each of them.
+    /// The accounts are set up on top of any `db`, and the ones given here
+    /// replace those given by an earlier call.
+    pub fn with_genesis(
+        mut self,
+        genesis: std::collections::HashMap<ethers::types::Address, GenesisAccount>,
+    ) -> Self {
+        self.genesis.extend(genesis);
+        self
+    }
+
+    /// Deploys the canonical CREATE2 deployer at [`CREATE2_DEPLOYER`] before
+    /// the first block, as it is on most live chains, so contracts can be
+    /// deployed with
+    /// [`RevmMiddleware::deploy_create2`](crate::middleware::RevmMiddleware::deploy_create2)
+    /// at the addresses they
+    /// have in production.
+    pub fn create2_deployer(mut self) -> Self {
+        self.genesis.insert(
+            CREATE2_DEPLOYER,
+            GenesisAccount {
+                code: Some(ethers::types::Bytes::from_static(CREATE2_DEPLOYER_CODE)),
+                ..Default::default()
+            },
+        );
+        self
+    }
+
+    /// Makes the [`Environment`] record the [`InstructionKind`] of every
+    /// instruction it receives. The record can be read with
+    /// [`Environment::recorded_instructions`], which is useful for asserting
+    /// on the instructions a piece of code sends in tests.
+    pub fn record_instructions(mut self) -> Self {
+        self.record_instructions = true;
+        self
+    }
+
+    /// Makes the [`Environment`] keep every transaction sent through a
+    /// [`RevmMiddleware`] along with its receipt so that all the receipts of a
+    /// block can be retrieved at once via `get_block_receipts` and past
+    /// transactions can be looked up by hash via `get_transaction` and
+    /// `get_transaction_receipt`. Past logs can be queried with `get_logs`.
+    /// They are kept for the lifetime of the [`Environment`].
+    pub fn store_receipts(mut self) -> Self {
+        self.store_receipts = true;
+        self
+    }
+
+    /// Makes the [`Environment`] keep the [`StateDiff`](state_diff::StateDiff)
+    /// of every transaction it executes, i.e., the balances, nonces, and
+    /// storage slots it changed. The diff of a transaction can be retrieved by
+    /// its hash via [`RevmMiddleware::get_state_diff`]. They are kept in memory
+    /// for the lifetime of the [`Environment`].
+    pub fn record_state_diffs(mut self) -> Self {
+        self.record_state_diffs = true;
+        self
+    }
+
+    /// Makes the [`Environment`] keep its history, i.e., its blocks and the
+    /// transactions and receipts that [`EnvironmentBuilder::store_receipts`]
+    /// keeps, in an embedded database at `path` instead of in memory. This
+    /// is meant for runs whose history outgrows memory and answers the same
+    /// queries. Any history kept at `path` by an earlier run is cleared,
+    /// while the history of this run is left there after it ends.
+    ///
+    /// Implies [`EnvironmentBuilder::store_receipts`]. Errors if the database
+    /// cannot be opened.
+    #[cfg(feature = "persistent-history")]
+    pub fn with_persistent_history(
+        mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Self, EnvironmentError> {
+        let history =
+            PersistentHistory::open(path).map_err(|e| EnvironmentError::History(e.to_string()))?;
+        self.persistent_history = Some(history);
+        self.store_receipts = true;
+        Ok(self)
+    }
+
+    /// Makes the [`Environment`] count the calls its transactions make to each
+    /// precompile and the gas those calls use. The totals can be read with
+    /// [`Environment::precompile_usage`].
+    pub fn track_precompiles(mut self) -> Self {
+        self.track_precompiles = true;
+        self
+    }
+
+    /// Makes the [`Environment`] record the gas used by every call to each
+    /// contract function, keyed by the contract's address and the function's
+    /// selector. Calls made by contracts are included. The totals can be read
+    /// with [`Environment::gas_report`].
+    pub fn profile_gas(mut self) -> Self {
+        self.profile_gas = true;
+        self
+    }
+
+    /// Adds a [`revm::Inspector`] that is run on every transaction in the
+    /// [`Environment`], e.g., to log storage accesses, count opcodes, or trace
+    /// calls. Inspectors run in the order they were added and are not run for
+    /// calls.
+    ///
+    /// The inspector is moved onto the [`Environment`]'s thread, so whatever it
+    /// collects should be shared through something like an `Arc<Mutex<_>>`.
+    /// A transaction that spills into the next block because of the
+    /// [`EnvironmentBuilder::block_gas_limit`] is inspected each time it is
+    /// executed. Clones of the builder share the inspector.
+    pub fn with_inspector(
+        mut self,
+        inspector: impl revm::Inspector<CacheDB<ExternalDb>> + Send + 'static,
+    ) -> Self {
+        self.inspectors.push(Box::new(inspector));
+        self
+    }
+
+    /// Adds a [`Precompile`] implemented in Rust at `address`, replacing the
+    /// one added there before, if any. Every call to `address` made in the
+    /// [`Environment`], including calls made by contracts, is carried out by
+    /// the `precompile` instead of by code at the address, which makes it
+    /// cheap to stand in for contracts such as math libraries or proof
+    /// verifiers. Clones of the builder share the precompile.
+    pub fn with_precompile(
+        mut self,
+        address: ethers::types::Address,
+        precompile: impl Precompile + 'static,
+    ) -> Self {
+        self.precompiles
+            .insert(address.to_fixed_bytes().into(), Box::new(precompile));
+        self
+    }
+
+    /// Makes the [`Environment`] charge every transaction an L1 data fee on
+    /// top of its execution gas, as rollups do for posting the transaction's
+    /// data to L1. The fee is reported on the transaction's receipt.
+    pub fn l1_fee(mut self, l1_fee: L1FeeSettings) -> Self {
+        self.l1_fee = Some(l1_fee);
+        self
+    }
+
     /// Sets the `label` for the `EnvironmentBuilder`.
     /// This is an optional string that can be used to identify the
     /// [`Environment`].
@@ -120,22 +420,98 @@ impl EnvironmentBuilder {
 
     /// Sets the `db` for the `EnvironmentBuilder`.
     /// This is an optional [`fork::Fork`] that can be loaded into the
-    /// [`Environment`].
-    pub fn db(mut self, db: impl Into<CacheDB<EmptyDB>>) -> Self {
+    /// [`Environment`] or a [`fork::ForkedDb`] that lazily fetches state from a
+    /// remote node.
+    pub fn db(mut self, db: impl Into<CacheDB<ExternalDb>>) -> Self {
         self.db = Some(db.into());
         self
     }
 
+    /// Starts the [`Environment`] from the state in the file at `path`, e.g.,
+    /// one written by `anvil --dump-state` or [`Environment::dump_state`]. The
+    /// accounts in the file replace any `db` set before, and the
+    /// [`Environment`] starts at the block the file is at, if it says.
+    /// Errors if the file cannot be read or is not an
+    /// [`AnvilState`](super::anvil::AnvilState).
+    pub fn load_state(
+        mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Self, EnvironmentError> {
+        let state = super::anvil::AnvilState::from_file(path)?;
+        self.db = Some(state.to_db());
+        self.block = state.block_env();
+        Ok(self)
+    }
+
     /// Builds the `Environment` from the `EnvironmentBuilder`.
-    /// This consumes the `EnvironmentBuilder` and returns an [`Environment`].
+    /// This consumes the `EnvironmentBuilder` and returns an [`Environment`]
+    /// whose [`Executor`] runs on a thread of its own.
     pub fn build(self) -> Environment {
+        let mut env = self.configure();
+        env.run();
+        env
+    }
+
+    /// Builds the `Environment` from the `EnvironmentBuilder` without
+    /// starting it. The [`Executor`] that carries out the instructions sent
+    /// to the [`Environment`] is returned alongside it and has to be driven
+    /// with [`Executor::run`] or [`Executor::poll`] by the caller, e.g., on a
+    /// runtime other than a dedicated thread.
+    pub fn build_detached(self) -> (Environment, Executor) {
+        let mut env = self.configure();
+        let executor = Executor::new(&mut env);
+        (env, executor)
+    }
+
+    /// Creates the [`Environment`] with everything the builder configured.
+    fn configure(self) -> Environment {
         let parameters = EnvironmentParameters {
             label: self.label,
             block_settings: self.block_settings,
             gas_settings: self.gas_settings,
+            block_gas_limit: self.block_gas_limit,
+            seed: self.seed,
+            l1_fee: self.l1_fee,
+            spec_id: self.spec_id,
+            chain_id: self.chain_id,
+            genesis: self.genesis,
         };
-        let mut env = Environment::new(parameters, self.db);
-        env.run();
+        let db = match parameters.genesis.is_empty() {
+            true => self.db,
+            false => {
+                let mut db = self
+                    .db
+                    .unwrap_or_else(|| CacheDB::new(ExternalDb::default()));
+                for (address, account) in &parameters.genesis {
+                    account.apply(*address, &mut db);
+                }
+                Some(db)
+            }
+        };
+        let mut env = Environment::new(parameters, db);
+        env.block = self.block;
+        if self.record_instructions {
+            env.instruction_record = Some(Arc::new(Mutex::new(Vec::new())));
+        }
+        if self.store_receipts {
+            env.socket.receipts = Some(ReceiptStore::default());
+        }
+        if self.record_state_diffs {
+            env.socket.state_diffs = Some(StateDiffStore::default());
+        }
+        #[cfg(feature = "persistent-history")]
+        if let Some(history) = self.persistent_history {
+            env.socket.blocks = BlockStore::Persistent(history.clone());
+            env.socket.receipts = Some(ReceiptStore::Persistent(history));
+        }
+        if self.track_precompiles {
+            env.profiler.precompiles = Some(Arc::new(Mutex::new(Default::default())));
+        }
+        if self.profile_gas {
+            env.profiler.functions = Some(Arc::new(Mutex::new(Default::default())));
+        }
+        env.profiler.inspectors = self.inspectors;
+        env.profiler.custom_precompiles = self.precompiles;
         env
     }
 }
@@ -174,6 +550,55 @@ pub enum BlockSettings {
     },
 }
 
+/// An account that is set up before the first block of an [`Environment`].
+/// In a config, the balance and the storage are given as hex strings, e.g.,
+/// `balance = "0xde0b6b3a7640000"` for one ether.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct GenesisAccount {
+    /// The balance of the account in wei.
+    #[serde(default)]
+    pub balance: ethers::types::U256,
+
+    /// The nonce of the account.
+    #[serde(default)]
+    pub nonce: u64,
+
+    /// The runtime bytecode deployed to the account, if it is a contract.
+    #[serde(default)]
+    pub code: Option<ethers::types::Bytes>,
+
+    /// The storage slots of the account and their values. Slots that are not
+    /// given keep what the `db` of the [`Environment`] holds for them, if
+    /// anything.
+    #[serde(default)]
+    pub storage: std::collections::HashMap<ethers::types::U256, ethers::types::U256>,
+}
+
+impl GenesisAccount {
+    /// Sets up the account at `address` in `db`. Its balance, nonce, and code
+    /// replace whatever `db` holds while its storage is patched slot by slot.
+    fn apply(&self, address: ethers::types::Address, db: &mut CacheDB<ExternalDb>) {
+        let address = revm::primitives::Address::from(address.as_fixed_bytes());
+        let mut info = AccountInfo {
+            balance: U256::from_limbs(self.balance.0),
+            nonce: self.nonce,
+            ..Default::default()
+        };
+        if let Some(code) = &self.code {
+            let bytecode = Bytecode::new_raw(revm::primitives::Bytes(code.0.clone()));
+            info.code_hash = bytecode.hash_slow();
+            info.code = Some(bytecode);
+        }
+        db.insert_account_info(address, info);
+        let account = db.accounts.get_mut(&address).unwrap();
+        for (slot, value) in &self.storage {
+            account
+                .storage
+                .insert(U256::from_limbs(slot.0), U256::from_limbs(value.0));
+        }
+    }
+}
+
 /// Provides a means of deciding how the gas price of the
 /// [`EVM`] will be chosen.
 /// This can either be a [`GasSettings::UserControlled`],
@@ -15,6 +15,30 @@ pub enum Cheatcodes {
         /// The amount to increase the balance of the account by.
         amount: ethers::types::U256,
     },
+    /// A `DealToken` sets the ERC-20 balance of a holder by overwriting the
+    /// storage slot the token reads it from. The slot is found by trying the
+    /// balance mapping at each of the first 128 storage slots of the token,
+    /// with both the Solidity and the Vyper layout, until `balanceOf` reports
+    /// the value written there. The total supply of the token is left as is.
+    DealToken {
+        /// The address of the ERC-20 token.
+        token: ethers::types::Address,
+
+        /// The address of the holder whose balance is set.
+        holder: ethers::types::Address,
+
+        /// The balance to give the holder, in the smallest unit of the token.
+        amount: ethers::types::U256,
+    },
+    /// Sets the nonce of an account, which also determines the address of
+    /// the next contract it deploys.
+    SetNonce {
+        /// The address of the account to set the nonce of.
+        address: ethers::types::Address,
+
+        /// The nonce to give the account.
+        nonce: u64,
+    },
     /// Fetches the value of a storage slot of an account.
     Load {
         /// The address of the account to fetch the storage slot from.
@@ -36,6 +60,104 @@ pub enum Cheatcodes {
         /// The value to overwrite the storage slot with.
         value: ethers::types::H256,
     },
+    /// Fetches the populated (nonzero) storage slots of an account, ordered by
+    /// slot. For a forked database, only the slots that have been fetched or
+    /// loaded so far are known.
+    Storage {
+        /// The address of the account to fetch the storage slots of.
+        account: ethers::types::Address,
+        /// The number of slots to skip, for paginating through large storage.
+        offset: usize,
+        /// The maximum number of slots to return. All remaining slots are
+        /// returned if this is `None`.
+        limit: Option<usize>,
+    },
+    /// Fetches the deployed code of an account. Accounts without code return
+    /// empty bytes.
+    Code {
+        /// The address of the account to fetch the code of.
+        account: ethers::types::Address,
+    },
+    /// Fetches a summary of every account in the [`EVM`]'s database that
+    /// passes the given filter, ordered by address.
+    Accounts {
+        /// Which accounts to include.
+        filter: AccountFilter,
+    },
+    /// Sets the timestamp of the current block. Unlike a block update, the
+    /// block number stays the same, the gas used by the block so far is kept,
+    /// and no new block is broadcast.
+    Warp {
+        /// The timestamp to move to.
+        timestamp: ethers::types::U256,
+    },
+    /// Sets the number of the current block. Unlike a block update, the
+    /// timestamp stays the same, the gas used by the block so far is kept, and
+    /// no new block is broadcast.
+    Roll {
+        /// The block number to move to.
+        block_number: ethers::types::U256,
+    },
+    /// Sets the base fee of the current block, which is what `BASEFEE`
+    /// returns. The base fee carries over to later blocks until it is set
+    /// again. Transactions with a gas price below it are rejected, while calls
+    /// are run with a zero base fee like `eth_call` does.
+    SetBaseFee {
+        /// The base fee in wei.
+        base_fee: ethers::types::U256,
+    },
+    /// Sets the coinbase of the current block, which is what `COINBASE`
+    /// returns. The coinbase carries over to later blocks until it is set
+    /// again.
+    SetCoinbase {
+        /// The address of the coinbase.
+        coinbase: ethers::types::Address,
+    },
+    /// Sets the randomness of the current block, which is what `PREVRANDAO`
+    /// returns. The randomness carries over to later blocks until it is set
+    /// again.
+    SetPrevRandao {
+        /// The value to return from `PREVRANDAO`.
+        prev_randao: ethers::types::H256,
+    },
+}
+
+/// Selects which accounts are returned by [`Cheatcodes::Accounts`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum AccountFilter {
+    /// Every account.
+    #[default]
+    All,
+    /// Only accounts that have code deployed to them.
+    Contracts,
+    /// Only externally owned accounts, i.e., accounts without code.
+    Eoas,
+}
+
+/// A summary of an account in the [`EVM`]'s database.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct AccountSummary {
+    /// The address of the account.
+    pub address: ethers::types::Address,
+    /// The balance of the account.
+    pub balance: ethers::types::U256,
+    /// The nonce of the account.
+    pub nonce: u64,
+    /// The hash of the code of the account. This is the hash of empty code for
+    /// externally owned accounts.
+    pub code_hash: ethers::types::H256,
+    /// The number of populated (nonzero) storage slots of the account. For a
+    /// forked database, only the slots that have been fetched or loaded so far
+    /// are counted.
+    pub storage_slots: usize,
+}
+
+impl AccountSummary {
+    /// Whether the account has code deployed to it.
+    pub fn is_contract(&self) -> bool {
+        self.code_hash != ethers::types::H256::from(revm::primitives::KECCAK_EMPTY.0)
+            && !self.code_hash.is_zero()
+    }
 }
 
 /// Return values of applying cheatcodes.
@@ -48,6 +170,36 @@ pub enum CheatcodesReturn {
     },
     /// A `Store` returns nothing.
     Store,
+    /// A `Storage` returns the populated storage slots of an account.
+    Storage {
+        /// The slots and their values, ordered by slot.
+        slots: Vec<(revm::primitives::U256, revm::primitives::U256)>,
+    },
+    /// A `Code` returns the deployed code of an account.
+    Code {
+        /// The code of the account.
+        code: ethers::types::Bytes,
+    },
+    /// An `Accounts` returns a summary of the accounts that passed the filter,
+    /// ordered by address.
+    Accounts {
+        /// The summaries of the accounts.
+        accounts: Vec<AccountSummary>,
+    },
     /// A `Deal` returns nothing.
     Deal,
+    /// A `DealToken` returns nothing.
+    DealToken,
+    /// A `SetNonce` returns nothing.
+    SetNonce,
+    /// A `Warp` returns nothing.
+    Warp,
+    /// A `Roll` returns nothing.
+    Roll,
+    /// A `SetBaseFee` returns nothing.
+    SetBaseFee,
+    /// A `SetCoinbase` returns nothing.
+    SetCoinbase,
+    /// A `SetPrevRandao` returns nothing.
+    SetPrevRandao,
 }
@@ -22,7 +22,7 @@ pub enum EnvironmentError {
     /// revert or halt, this is likely an error in `revm`. Please report
     /// this type of error.
     #[error("execution error! the source error is: {0:?}")]
-    Execution(#[from] EVMError<Infallible>),
+    Execution(#[from] EVMError<DatabaseError>),
 
     /// [`EnvironmentError::Transaction`] is thrown when a transaction fails
     /// to be processed by the [`EVM`]. This could be due to a insufficient
@@ -86,4 +86,35 @@ pub enum EnvironmentError {
     /// [`BlockSettings::RandomlySampled`].
     #[error("error in the environment! attempted to set a gas price via a multiplier when the `BlockSettings` is not `BlockSettings::RandomlySampled`.")]
     NotRandomlySampledBlockSettings,
+
+    /// [`EnvironmentError::Fork`] is thrown when a [`fork::ForkedDb`] cannot
+    /// be set up, e.g., due to a malformed provider URL or an unreadable
+    /// cache file.
+    #[error("error setting up the fork! due to: {0}")]
+    Fork(String),
+
+    /// [`EnvironmentError::History`] is thrown when the database that keeps
+    /// the history of the [`Environment`] on disk cannot be opened, e.g.,
+    /// because another process holds it.
+    #[error("error opening the history database! due to: {0}")]
+    History(String),
+
+    /// [`EnvironmentError::State`] is thrown when the state of an
+    /// [`Environment`] cannot be dumped to or loaded from an
+    /// [`anvil::AnvilState`] file, e.g., because the file is malformed.
+    #[error("error dumping or loading state! due to: {0}")]
+    State(String),
+}
+
+/// Errors that can occur when the [`CacheDB`] of the [`Environment`] has to
+/// request state it does not yet hold from the [`fork::ExternalDb`]
+/// underneath it.
+#[derive(Error, Debug, Clone)]
+pub enum DatabaseError {
+    /// [`DatabaseError::Fetch`] is thrown when a [`fork::ForkedDb`] fails to
+    /// fetch an account, storage slot, code, or block hash from the remote
+    /// node. This is typically due to the node being unreachable or rate
+    /// limiting requests.
+    #[error("error fetching remote state! due to: {0}")]
+    Fetch(String),
 }
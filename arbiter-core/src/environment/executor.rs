@@ -0,0 +1,874 @@
+//! The `executor` module contains the [`Executor`], which owns the [`EVM`] of
+//! an [`Environment`] and carries out the instructions its clients send.
+//!
+//! The [`Executor`] does no I/O of its own: it only reacts to the
+//! instructions it is handed, so it can be driven by whatever runtime the
+//! [`Environment`] is embedded in. By default, [`EnvironmentBuilder::build`]
+//! moves it onto a dedicated thread that blocks on incoming instructions with
+//! [`Executor::run`]. [`EnvironmentBuilder::build_detached`] instead hands it
+//! back so that it can be driven with [`Executor::poll`], e.g., from a
+//! single-threaded WASM host, an `async-std` task, or a deterministic test
+//! executor.
+
+#![warn(missing_docs)]
+
+use std::ops::ControlFlow;
+
+use crossbeam_channel::TryRecvError;
+use revm::primitives::{Output, TransactTo};
+
+use super::*;
+
+/// Carries out the instructions sent to an [`Environment`] against its
+/// [`EVM`].
+pub struct Executor {
+    /// The [`EVM`] the instructions are carried out against.
+    evm: EVM<CacheDB<ExternalDb>>,
+
+    /// Where the instructions sent to the [`Environment`] arrive.
+    instruction_receiver: InstructionReceiver,
+
+    /// The broadcaster of logs and new blocks to the clients.
+    event_broadcaster: Arc<Mutex<EventBroadcaster>>,
+
+    /// How blocks are moved forward.
+    block_settings: BlockSettings,
+
+    /// The distribution block sizes are sampled from when using
+    /// [`BlockSettings::RandomlySampled`].
+    seeded_poisson: Option<Arc<Mutex<SeededPoisson>>>,
+
+    /// How gas prices are set.
+    gas_settings: GasSettings,
+
+    /// The L1 data fee charged to every transaction, if any.
+    l1_fee: Option<L1FeeSettings>,
+
+    /// What is tracked about the execution of transactions.
+    profiler: Profiler,
+
+    /// The kinds of the instructions received so far, if they are recorded.
+    instruction_record: Option<Arc<Mutex<Vec<InstructionKind>>>>,
+
+    /// How far along the current block the [`Environment`] is.
+    block_progress: BlockProgress,
+
+    /// Whether the first block has been set up.
+    started: bool,
+}
+
+impl Debug for Executor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Executor")
+            .field("block_settings", &self.block_settings)
+            .field("gas_settings", &self.gas_settings)
+            .field("started", &self.started)
+            .finish()
+    }
+}
+
+impl Executor {
+    /// Creates the [`Executor`] of the `environment`, taking its database.
+    pub(crate) fn new(environment: &mut Environment) -> Self {
+        let mut evm = EVM::new();
+        evm.database(
+            environment
+                .db
+                .take()
+                .unwrap_or_else(|| CacheDB::new(ExternalDb::default())),
+        );
+
+        if let Some(block) = environment.block.take() {
+            evm.env.block = block;
+        }
+
+        // Choose extra large code size and gas limit
+        evm.env.cfg.limit_contract_code_size = Some(0x100000);
+        evm.env.cfg.spec_id = environment.spec_id();
+        evm.env.cfg.chain_id = environment.chain_id();
+        evm.env.block.gas_limit = U256::MAX;
+
+        let parameters = &environment.parameters;
+        let seeded_poisson = match parameters.block_settings {
+            BlockSettings::RandomlySampled {
+                block_rate,
+                block_time,
+                seed,
+            } => Some(Arc::new(Mutex::new(SeededPoisson::new(
+                block_rate,
+                block_time,
+                parameters.seed.unwrap_or(seed),
+            )))),
+            BlockSettings::UserControlled => None,
+        };
+        // Get the first amount of transactions per block from the distribution and set
+        // the initial counter.
+        let block_progress = BlockProgress {
+            transaction_index: 0,
+            cumulative_gas_per_block: U256::ZERO,
+            block_gas_limit: parameters.block_gas_limit,
+            transactions_per_block: seeded_poisson
+                .clone()
+                .map(|distribution| distribution.lock().unwrap().sample()),
+            blocks: environment.socket.blocks.clone(),
+            state_diffs: environment.socket.state_diffs.clone(),
+        };
+        Self {
+            evm,
+            instruction_receiver: environment.socket.instruction_receiver.clone(),
+            event_broadcaster: environment.socket.event_broadcaster.clone(),
+            block_settings: parameters.block_settings.clone(),
+            seeded_poisson,
+            gas_settings: parameters.gas_settings.clone(),
+            l1_fee: parameters.l1_fee.clone(),
+            profiler: environment.profiler.clone(),
+            instruction_record: environment.instruction_record.clone(),
+            block_progress,
+            started: false,
+        }
+    }
+
+    /// Carries out instructions as they arrive, blocking the current thread
+    /// while there are none, until the [`Environment`] is stopped or all of
+    /// its clients are gone.
+    pub fn run(mut self) -> Result<(), EnvironmentError> {
+        self.start()?;
+        while let Ok(instruction) = self.instruction_receiver.recv() {
+            if self.step(instruction)?.is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Carries out the instructions that have arrived so far without blocking.
+    /// Returns [`ControlFlow::Break`] once the [`Environment`] is stopped or
+    /// all of its clients are gone, after which there is nothing left to
+    /// poll.
+    ///
+    /// Note that the [`RevmMiddleware`] blocks until its instruction is
+    /// carried out, so a runtime that polls the [`Executor`] must not do so
+    /// from the same thread as the clients it is waiting on.
+    pub fn poll(&mut self) -> Result<ControlFlow<()>, EnvironmentError> {
+        self.start()?;
+        loop {
+            match self.instruction_receiver.try_recv() {
+                Ok(instruction) => {
+                    if self.step(instruction)?.is_break() {
+                        return Ok(ControlFlow::Break(()));
+                    }
+                }
+                Err(TryRecvError::Empty) => return Ok(ControlFlow::Continue(())),
+                Err(TryRecvError::Disconnected) => return Ok(ControlFlow::Break(())),
+            }
+        }
+    }
+
+    /// Sets up the first block and gas price before the first instruction is
+    /// carried out.
+    fn start(&mut self) -> Result<(), EnvironmentError> {
+        if self.started {
+            return Ok(());
+        }
+        if let GasSettings::RandomlySampled { multiplier: _ } = self.gas_settings {
+            if self.seeded_poisson.is_none() {
+                return Err(EnvironmentError::NotRandomlySampledBlockSettings);
+            }
+        }
+        self.block_progress.start_block(&self.evm.env.block)?;
+        match self.gas_settings {
+            GasSettings::UserControlled => {
+                self.evm.env.tx.gas_price = U256::from(0);
+            }
+            GasSettings::RandomlySampled { multiplier } => {
+                let gas_price = (self
+                    .block_progress
+                    .transactions_per_block
+                    .ok_or(EnvironmentError::NotRandomlySampledBlockSettings)?
+                    as f64)
+                    * multiplier;
+                self.evm.env.tx.gas_price = U256::from(gas_price as u128);
+            }
+            GasSettings::Constant(gas_price) => {
+                self.evm.env.tx.gas_price = U256::from(gas_price);
+            }
+        }
+        self.started = true;
+        Ok(())
+    }
+
+    /// Carries out a single `instruction`. Returns [`ControlFlow::Break`] if
+    /// the instruction stopped the [`Environment`].
+    fn step(&mut self, instruction: Instruction) -> Result<ControlFlow<()>, EnvironmentError> {
+        if let Some(instruction_record) = &self.instruction_record {
+            instruction_record
+                .lock()
+                .map_err(|e| EnvironmentError::Communication(e.to_string()))?
+                .push(instruction.kind());
+        }
+        match instruction {
+            Instruction::AddAccount {
+                address,
+                outcome_sender,
+            } => {
+                let db = self.evm.db.as_mut().unwrap();
+                let recast_address = revm::primitives::Address::from(address.as_fixed_bytes());
+                let account = revm::db::DbAccount {
+                    info: AccountInfo::default(),
+                    account_state: revm::db::AccountState::None,
+                    storage: HashMap::new(),
+                };
+                // An account that was only looked up and found to not exist is fine to
+                // overwrite.
+                match db.accounts.get(&recast_address) {
+                    None
+                    | Some(revm::db::DbAccount {
+                        account_state: AccountState::NotExisting,
+                        ..
+                    }) => {
+                        db.accounts.insert(recast_address, account);
+                        outcome_sender
+                            .send(Ok(Outcome::AddAccountCompleted))
+                            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                    }
+                    Some(_) => {
+                        outcome_sender
+                            .send(Err(EnvironmentError::Account(
+                                "Account already exists!".to_string(),
+                            )))
+                            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                    }
+                }
+            }
+            Instruction::BlockUpdate {
+                block_number,
+                block_timestamp,
+                outcome_sender,
+            } => {
+                if self.block_settings != BlockSettings::UserControlled {
+                    outcome_sender
+                        .send(Err(EnvironmentError::NotUserControlledBlockSettings))
+                        .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                    return Ok(ControlFlow::Continue(()));
+                }
+                // Update the block number and timestamp
+                self.evm.env.block.number = block_number;
+                self.evm.env.block.timestamp = block_timestamp;
+                self.block_progress.transaction_index = 0;
+                self.block_progress.cumulative_gas_per_block = U256::ZERO;
+                self.block_progress.start_block(&self.evm.env.block)?;
+                self.event_broadcaster
+                    .lock()
+                    .map_err(|e| EnvironmentError::Communication(e.to_string()))?
+                    .broadcast(Broadcast::NewBlock {
+                        number: block_number,
+                        timestamp: block_timestamp,
+                    });
+
+                let receipt_data = ReceiptData {
+                    block_number: convert_uint_to_u64(self.evm.env.block.number).unwrap(),
+                    transaction_index: U64::from(0), /* replace with actual
+                                                      * value */
+                    nonce: 0,
+                    cumulative_gas_per_block: U256::from(0),
+                    l1_fee: None,
+                };
+                outcome_sender
+                    .send(Ok(Outcome::BlockUpdateCompleted(receipt_data)))
+                    .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+            }
+            Instruction::Cheatcode {
+                cheatcode,
+                outcome_sender,
+            } => match cheatcode {
+                Cheatcodes::Load {
+                    account,
+                    key,
+                    block: _,
+                } => {
+                    // Get the underlying database.
+                    let db = self.evm.db.as_mut().unwrap();
+
+                    // Cast the ethers-rs cheatcode arguments into revm types.
+                    let recast_address = revm::primitives::Address::from(account.as_fixed_bytes());
+                    let recast_key = revm::primitives::B256::from(key.as_fixed_bytes());
+
+                    // Get the account storage value at the key in the db. Going through
+                    // the `Database` methods lets a forked db fetch state it does not
+                    // hold yet.
+                    let outcome = match db.basic(recast_address) {
+                        Ok(Some(_)) => {
+                            // Returns zero if the slot is missing.
+                            db.storage(recast_address, recast_key.into())
+                                .map(|value| {
+                                    Outcome::CheatcodeReturn(CheatcodesReturn::Load { value })
+                                })
+                                .map_err(|e| EnvironmentError::Execution(EVMError::Database(e)))
+                        }
+                        Ok(None) => {
+                            Err(EnvironmentError::Account("Account is missing!".to_string()))
+                        }
+                        Err(e) => Err(EnvironmentError::Execution(EVMError::Database(e))),
+                    };
+
+                    // Sends the revm::primitives::U256 storage value back to the
+                    // sender via CheatcodeReturn(revm::primitives::U256).
+                    outcome_sender
+                        .send(outcome)
+                        .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                }
+                Cheatcodes::Store {
+                    account,
+                    key,
+                    value,
+                } => {
+                    // Get the underlying database
+                    let db = self.evm.db.as_mut().unwrap();
+
+                    // Cast the ethers-rs types passed in the cheatcode arguments into revm
+                    // primitive types
+                    let recast_address = revm::primitives::Address::from(account.as_fixed_bytes());
+                    let recast_key = revm::primitives::B256::from(key.as_fixed_bytes());
+                    let recast_value = revm::primitives::B256::from(value.as_fixed_bytes());
+
+                    // Mutate the db by inserting the new key-value pair into the account's
+                    // storage and send the successful
+                    // CheatcodeCompleted outcome.
+                    match db.accounts.get_mut(&recast_address) {
+                        Some(account) => {
+                            account
+                                .storage
+                                .insert(recast_key.into(), recast_value.into());
+
+                            outcome_sender
+                                .send(Ok(Outcome::CheatcodeReturn(CheatcodesReturn::Store)))
+                                .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                        }
+                        None => {
+                            outcome_sender
+                                .send(Err(EnvironmentError::Account(
+                                    "Account is missing!".to_string(),
+                                )))
+                                .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                        }
+                    };
+                }
+                Cheatcodes::Storage {
+                    account,
+                    offset,
+                    limit,
+                } => {
+                    let db = self.evm.db.as_mut().unwrap();
+                    let recast_address = revm::primitives::Address::from(account.as_fixed_bytes());
+                    let outcome = match db.accounts.get(&recast_address) {
+                        Some(account) => {
+                            let mut slots = account
+                                .storage
+                                .iter()
+                                .filter(|(_, value)| **value != U256::ZERO)
+                                .map(|(slot, value)| (*slot, *value))
+                                .collect::<Vec<_>>();
+                            slots.sort_unstable_by_key(|(slot, _)| *slot);
+                            let slots = slots
+                                .into_iter()
+                                .skip(offset)
+                                .take(limit.unwrap_or(usize::MAX))
+                                .collect();
+                            Ok(Outcome::CheatcodeReturn(CheatcodesReturn::Storage {
+                                slots,
+                            }))
+                        }
+                        None => Err(EnvironmentError::Account("Account is missing!".to_string())),
+                    };
+                    outcome_sender
+                        .send(outcome)
+                        .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                }
+                Cheatcodes::Code { account } => {
+                    let db = self.evm.db.as_mut().unwrap();
+                    let recast_address = revm::primitives::Address::from(account.as_fixed_bytes());
+                    let outcome = db
+                        .basic(recast_address)
+                        .and_then(|info| match info {
+                            Some(AccountInfo {
+                                code: Some(code), ..
+                            }) => Ok(code),
+                            Some(info) => db.code_by_hash(info.code_hash),
+                            None => Ok(Bytecode::new()),
+                        })
+                        .map(|code| {
+                            Outcome::CheatcodeReturn(CheatcodesReturn::Code {
+                                code: ethers::types::Bytes::from(code.original_bytes().0),
+                            })
+                        })
+                        .map_err(|e| EnvironmentError::Execution(EVMError::Database(e)));
+                    outcome_sender
+                        .send(outcome)
+                        .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                }
+                Cheatcodes::Accounts { filter } => {
+                    let db = self.evm.db.as_mut().unwrap();
+                    let mut accounts = db
+                        .accounts
+                        .iter()
+                        .filter(|(_, account)| {
+                            !matches!(account.account_state, AccountState::NotExisting)
+                        })
+                        .map(|(address, account)| AccountSummary {
+                            address: ethers::types::Address::from(address.into_array()),
+                            balance: ethers::types::U256::from(account.info.balance.to_be_bytes()),
+                            nonce: account.info.nonce,
+                            code_hash: ethers::types::H256::from(account.info.code_hash.0),
+                            storage_slots: account
+                                .storage
+                                .values()
+                                .filter(|value| **value != U256::ZERO)
+                                .count(),
+                        })
+                        .filter(|summary| match filter {
+                            AccountFilter::All => true,
+                            AccountFilter::Contracts => summary.is_contract(),
+                            AccountFilter::Eoas => !summary.is_contract(),
+                        })
+                        .collect::<Vec<_>>();
+                    accounts.sort_unstable_by_key(|summary| summary.address);
+                    outcome_sender
+                        .send(Ok(Outcome::CheatcodeReturn(CheatcodesReturn::Accounts {
+                            accounts,
+                        })))
+                        .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                }
+                Cheatcodes::Deal { address, amount } => {
+                    let db = self.evm.db.as_mut().unwrap();
+                    let recast_address = revm::primitives::Address::from(address.as_fixed_bytes());
+                    match db.accounts.get_mut(&recast_address) {
+                        Some(account) => {
+                            account.info.balance += U256::from_limbs(amount.0);
+                            outcome_sender
+                                .send(Ok(Outcome::CheatcodeReturn(CheatcodesReturn::Deal)))
+                                .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                        }
+                        None => {
+                            outcome_sender
+                                .send(Err(EnvironmentError::Account(
+                                    "Account is missing!".to_string(),
+                                )))
+                                .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                        }
+                    };
+                }
+                Cheatcodes::DealToken {
+                    token,
+                    holder,
+                    amount,
+                } => {
+                    let outcome = deal_token(&mut self.evm, token, holder, amount)
+                        .map(|()| Outcome::CheatcodeReturn(CheatcodesReturn::DealToken));
+                    outcome_sender
+                        .send(outcome)
+                        .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                }
+                Cheatcodes::SetNonce { address, nonce } => {
+                    let db = self.evm.db.as_mut().unwrap();
+                    let recast_address = revm::primitives::Address::from(address.as_fixed_bytes());
+                    let outcome = match db.load_account(recast_address) {
+                        Ok(account)
+                            if !matches!(account.account_state, AccountState::NotExisting) =>
+                        {
+                            account.info.nonce = nonce;
+                            Ok(Outcome::CheatcodeReturn(CheatcodesReturn::SetNonce))
+                        }
+                        Ok(_) => Err(EnvironmentError::Account("Account is missing!".to_string())),
+                        Err(e) => Err(EnvironmentError::Execution(EVMError::Database(e))),
+                    };
+                    outcome_sender
+                        .send(outcome)
+                        .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                }
+                Cheatcodes::Warp { timestamp } => {
+                    self.evm.env.block.timestamp = U256::from_limbs(timestamp.0);
+                    outcome_sender
+                        .send(Ok(Outcome::CheatcodeReturn(CheatcodesReturn::Warp)))
+                        .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                }
+                Cheatcodes::Roll { block_number } => {
+                    self.evm.env.block.number = U256::from_limbs(block_number.0);
+                    outcome_sender
+                        .send(Ok(Outcome::CheatcodeReturn(CheatcodesReturn::Roll)))
+                        .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                }
+                Cheatcodes::SetBaseFee { base_fee } => {
+                    self.evm.env.block.basefee = U256::from_limbs(base_fee.0);
+                    outcome_sender
+                        .send(Ok(Outcome::CheatcodeReturn(CheatcodesReturn::SetBaseFee)))
+                        .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                }
+                Cheatcodes::SetCoinbase { coinbase } => {
+                    self.evm.env.block.coinbase =
+                        revm::primitives::Address::from(coinbase.as_fixed_bytes());
+                    outcome_sender
+                        .send(Ok(Outcome::CheatcodeReturn(CheatcodesReturn::SetCoinbase)))
+                        .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                }
+                Cheatcodes::SetPrevRandao { prev_randao } => {
+                    self.evm.env.block.prevrandao =
+                        Some(revm::primitives::B256::from(prev_randao.as_fixed_bytes()));
+                    outcome_sender
+                        .send(Ok(Outcome::CheatcodeReturn(
+                            CheatcodesReturn::SetPrevRandao,
+                        )))
+                        .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                }
+            },
+            // A `Call` is not state changing and will not create events.
+            Instruction::Call {
+                tx_env,
+                state_overrides,
+                outcome_sender,
+            } => {
+                // Set the tx_env and prepare to process it
+                self.evm.env.tx = tx_env;
+
+                // Errors, such as a forked database failing to fetch, are the
+                // caller's to handle and must not bring the `Environment` down.
+                let outcome = match state_overrides {
+                    None => transact_call(&mut self.evm, &self.profiler),
+                    Some(state_overrides) => {
+                        // Run the call against a throwaway copy of the db so that the
+                        // overrides never touch the actual worldstate.
+                        let mut db = self.evm.db.clone().unwrap();
+                        apply_state_overrides(&mut db, state_overrides).and_then(|()| {
+                            let mut overlay = EVM::new();
+                            overlay.env = self.evm.env.clone();
+                            overlay.database(db);
+                            transact_call(&mut overlay, &self.profiler)
+                        })
+                    }
+                };
+                outcome_sender
+                    .send(outcome.map(Outcome::CallCompleted))
+                    .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+            }
+            Instruction::BatchCall {
+                tx_envs,
+                outcome_sender,
+            } => {
+                let outcome = tx_envs
+                    .into_iter()
+                    .map(|tx_env| {
+                        self.evm.env.tx = tx_env;
+                        transact_call(&mut self.evm, &self.profiler)
+                    })
+                    .collect::<Result<Vec<_>, _>>();
+                outcome_sender
+                    .send(outcome.map(Outcome::BatchCallCompleted))
+                    .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+            }
+            Instruction::SetGasPrice {
+                gas_price,
+                outcome_sender,
+            } => {
+                if GasSettings::UserControlled != self.gas_settings {
+                    outcome_sender
+                        .send(Err(EnvironmentError::NotUserControlledGasSettings))
+                        .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+                    return Ok(ControlFlow::Continue(()));
+                }
+                self.evm.env.tx.gas_price = U256::from_limbs(gas_price.0);
+                outcome_sender
+                    .send(Ok(Outcome::SetGasPriceCompleted))
+                    .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+            }
+
+            // A `Transaction` is state changing and will create events.
+            Instruction::Transaction {
+                tx_env,
+                outcome_sender,
+            } => {
+                let outcome = execute_transaction(
+                    &mut self.evm,
+                    tx_env,
+                    &mut self.block_progress,
+                    &self.seeded_poisson,
+                    &self.gas_settings,
+                    &self.l1_fee,
+                    &self.event_broadcaster,
+                    &self.profiler,
+                )?
+                .map(|(execution_result, receipt_data)| {
+                    Outcome::TransactionCompleted(execution_result, receipt_data)
+                });
+                outcome_sender
+                    .send(outcome)
+                    .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+            }
+            // A `BatchTransaction` is a sequence of `Transaction`s processed in order
+            // and answered with a single outcome.
+            Instruction::BatchTransaction {
+                tx_envs,
+                outcome_sender,
+            } => {
+                let mut execution_results = Vec::with_capacity(tx_envs.len());
+                let mut outcome = Ok(());
+                for tx_env in tx_envs {
+                    match execute_transaction(
+                        &mut self.evm,
+                        tx_env,
+                        &mut self.block_progress,
+                        &self.seeded_poisson,
+                        &self.gas_settings,
+                        &self.l1_fee,
+                        &self.event_broadcaster,
+                        &self.profiler,
+                    )? {
+                        Ok((execution_result, _)) => execution_results.push(execution_result),
+                        Err(e) => {
+                            outcome = Err(e);
+                            break;
+                        }
+                    }
+                }
+                outcome_sender
+                    .send(outcome.map(|_| Outcome::BatchTransactionCompleted(execution_results)))
+                    .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+            }
+            // A `SimulateBundle` is processed like a `BatchTransaction`, but against
+            // a throwaway copy of the db so that nothing is committed or broadcast.
+            Instruction::SimulateBundle {
+                tx_envs,
+                outcome_sender,
+            } => {
+                let outcome = simulate_bundle(&self.evm, tx_envs, &self.profiler);
+                outcome_sender
+                    .send(outcome)
+                    .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+            }
+            Instruction::Query {
+                environment_data,
+                outcome_sender,
+            } => {
+                let outcome = match environment_data {
+                    EnvironmentData::BlockNumber => {
+                        Ok(Outcome::QueryReturn(self.evm.env.block.number.to_string()))
+                    }
+                    EnvironmentData::BlockTimestamp => Ok(Outcome::QueryReturn(
+                        self.evm.env.block.timestamp.to_string(),
+                    )),
+                    EnvironmentData::GasPrice => {
+                        Ok(Outcome::QueryReturn(self.evm.env.tx.gas_price.to_string()))
+                    }
+                    EnvironmentData::Balance(address) => {
+                        // This unwrap should never fail.
+                        let db = self.evm.db().unwrap();
+                        match db.basic(address.as_fixed_bytes().into()) {
+                            Ok(Some(info)) => Ok(Outcome::QueryReturn(info.balance.to_string())),
+                            Ok(None) => {
+                                Err(EnvironmentError::Account("Account is missing!".to_string()))
+                            }
+                            Err(e) => Err(EnvironmentError::Execution(EVMError::Database(e))),
+                        }
+                    }
+
+                    EnvironmentData::TransactionCount(address) => {
+                        let db = self.evm.db().unwrap();
+                        match db.basic(address.as_fixed_bytes().into()) {
+                            Ok(Some(info)) => Ok(Outcome::QueryReturn(info.nonce.to_string())),
+                            Ok(None) => {
+                                Err(EnvironmentError::Account("Account is missing!".to_string()))
+                            }
+                            Err(e) => Err(EnvironmentError::Execution(EVMError::Database(e))),
+                        }
+                    }
+                };
+                outcome_sender
+                    .send(outcome)
+                    .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+            }
+            Instruction::Snapshot(snapshot_sender) => {
+                snapshot_sender
+                    .send((self.evm.db.clone().unwrap(), self.evm.env.block.clone()))
+                    .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+            }
+            Instruction::Stop(outcome_sender) => {
+                // The `Environment` does not wait on the outcome when the executor is
+                // detached, so there may be no one left to send it to.
+                outcome_sender.send(Ok(Outcome::StopCompleted)).ok();
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+}
+
+/// Runs the call in the transaction environment of the `evm` without
+/// committing it. The call is inspected only when there are custom
+/// precompiles that may have to carry it out.
+///
+/// As with `eth_call`, a call that pays no gas is run with a zero base fee so
+/// that it is not rejected once the base fee has been set.
+fn transact_call(
+    evm: &mut EVM<CacheDB<ExternalDb>>,
+    profiler: &Profiler,
+) -> Result<ExecutionResult, EnvironmentError> {
+    let base_fee = evm.env.block.basefee;
+    if evm.env.tx.gas_price == U256::ZERO {
+        evm.env.block.basefee = U256::ZERO;
+    }
+    let result = if profiler.custom_precompiles.is_empty() {
+        evm.transact()
+    } else {
+        let mut no_inspectors: Vec<BoxedInspector> = vec![];
+        let mut inspector = profiler.inspector(&mut no_inspectors);
+        evm.inspect(&mut inspector)
+    };
+    evm.env.block.basefee = base_fee;
+    Ok(result?.result)
+}
+
+/// The selector of the ERC-20 `balanceOf(address)` function.
+const BALANCE_OF_SELECTOR: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+
+/// How many of the first storage slots of a token are tried as the slot of
+/// its balance mapping by [`Cheatcodes::DealToken`].
+const BALANCE_SLOT_SEARCH_DEPTH: u64 = 128;
+
+/// Sets the balance of the `holder` in the `token` to `amount`. The slot the
+/// balance lives in is found by writing a marker value to each candidate slot
+/// in a throwaway copy of the database and calling `balanceOf` until the
+/// marker comes back.
+fn deal_token(
+    evm: &mut EVM<CacheDB<ExternalDb>>,
+    token: ethers::types::Address,
+    holder: ethers::types::Address,
+    amount: ethers::types::U256,
+) -> Result<(), EnvironmentError> {
+    let token_address = revm::primitives::Address::from(token.as_fixed_bytes());
+    let mut holder_word = [0_u8; 32];
+    holder_word[12..].copy_from_slice(holder.as_bytes());
+
+    let mut overlay = EVM::new();
+    overlay.env = evm.env.clone();
+    // The probes pay no gas, whatever the current block asks for.
+    overlay.env.block.basefee = U256::ZERO;
+    overlay.env.block.gas_limit = U256::MAX;
+    overlay.env.tx = TxEnv {
+        caller: revm::primitives::Address::ZERO,
+        gas_limit: u64::MAX,
+        gas_price: U256::ZERO,
+        transact_to: TransactTo::Call(token_address),
+        data: revm::primitives::Bytes(bytes::Bytes::from(
+            [BALANCE_OF_SELECTOR.as_slice(), &holder_word].concat(),
+        )),
+        ..Default::default()
+    };
+    overlay.database(evm.db.clone().unwrap());
+
+    let marker = U256::from_be_bytes(revm::primitives::keccak256(b"arbiter.deal_token").0);
+    for index in 0..BALANCE_SLOT_SEARCH_DEPTH {
+        let index = U256::from(index).to_be_bytes::<32>();
+        // Solidity hashes the key before the slot of the mapping, Vyper after.
+        for preimage in [[holder_word, index].concat(), [index, holder_word].concat()] {
+            let slot = U256::from_be_bytes(revm::primitives::keccak256(preimage).0);
+            let db = overlay.db.as_mut().unwrap();
+            let original = db
+                .storage(token_address, slot)
+                .map_err(|e| EnvironmentError::Execution(EVMError::Database(e)))?;
+            db.insert_account_storage(token_address, slot, marker)
+                .map_err(|e| EnvironmentError::Execution(EVMError::Database(e)))?;
+            let result = overlay.transact()?.result;
+            overlay
+                .db
+                .as_mut()
+                .unwrap()
+                .insert_account_storage(token_address, slot, original)
+                .map_err(|e| EnvironmentError::Execution(EVMError::Database(e)))?;
+            let ExecutionResult::Success {
+                output: Output::Call(output),
+                ..
+            } = result
+            else {
+                continue;
+            };
+            if output.len() >= 32 && U256::from_be_slice(&output[..32]) == marker {
+                return evm
+                    .db
+                    .as_mut()
+                    .unwrap()
+                    .insert_account_storage(token_address, slot, U256::from_limbs(amount.0))
+                    .map_err(|e| EnvironmentError::Execution(EVMError::Database(e)));
+            }
+        }
+    }
+    Err(EnvironmentError::Account(format!(
+        "no balance slot for {:?} found in token {:?}!",
+        holder, token
+    )))
+}
+
+/// Runs the `tx_envs` one after the other against a throwaway copy of the
+/// database of the `evm` and reports their results along with the ether
+/// balance of their sender before and after.
+fn simulate_bundle(
+    evm: &EVM<CacheDB<ExternalDb>>,
+    tx_envs: Vec<TxEnv>,
+    profiler: &Profiler,
+) -> Result<Outcome, EnvironmentError> {
+    let mut overlay = EVM::new();
+    overlay.env = evm.env.clone();
+    overlay.database(evm.db.clone().unwrap());
+    let sender = tx_envs.first().map(|tx_env| tx_env.caller);
+    let balance_before = sender_balance(&mut overlay, sender)?;
+    let mut execution_results = Vec::with_capacity(tx_envs.len());
+    for tx_env in tx_envs {
+        overlay.env.tx = tx_env;
+        execution_results.push(transact_uncommitted(&mut overlay, profiler)?);
+    }
+    Ok(Outcome::SimulateBundleCompleted {
+        execution_results,
+        balance_before,
+        balance_after: sender_balance(&mut overlay, sender)?,
+    })
+}
+
+/// The ether balance of the `sender` in the database of the `evm`, or zero if
+/// there is no `sender`.
+fn sender_balance(
+    evm: &mut EVM<CacheDB<ExternalDb>>,
+    sender: Option<revm::primitives::Address>,
+) -> Result<U256, EnvironmentError> {
+    let Some(sender) = sender else {
+        return Ok(U256::ZERO);
+    };
+    evm.db
+        .as_mut()
+        .unwrap()
+        .basic(sender)
+        .map(|info| info.map(|info| info.balance).unwrap_or_default())
+        .map_err(|e| EnvironmentError::Execution(EVMError::Database(e)))
+}
+
+/// Runs the transaction in the transaction environment of the `evm` and
+/// commits it to the `evm`'s own database only. Nothing is broadcast and the
+/// block does not move on, so this is meant for an `evm` over a throwaway copy
+/// of the database.
+fn transact_uncommitted(
+    evm: &mut EVM<CacheDB<ExternalDb>>,
+    profiler: &Profiler,
+) -> Result<ExecutionResult, EnvironmentError> {
+    let result_and_state = if profiler.custom_precompiles.is_empty() {
+        evm.transact()
+    } else {
+        let mut no_inspectors: Vec<BoxedInspector> = vec![];
+        let mut inspector = profiler.inspector(&mut no_inspectors);
+        evm.inspect(&mut inspector)
+    };
+    let ResultAndState { result, state } = match result_and_state {
+        Ok(result_and_state) => result_and_state,
+        Err(EVMError::Transaction(invalid_transaction)) => {
+            return Err(EnvironmentError::Transaction(invalid_transaction))
+        }
+        Err(e) => return Err(EnvironmentError::Execution(e)),
+    };
+    evm.db.as_mut().unwrap().commit(state);
+    Ok(result)
+}
@@ -3,13 +3,35 @@
 //! [`Fork`] contains a [`CacheDB`] and [`ContractMetadata`] so
 //! that the [`Environment`] can be initialized with a forked database and the
 //! end-user still has access to the relevant metadata.
+//! The metadata records the EIP-1967 proxies among the forked contracts in
+//! [`ProxyMetadata`], the [`TokenMetadata`] of the forked ERC-20 tokens, and
+//! the ABIs of the contracts, which can be registered for decoding with
+//! [`Fork::register_abis`].
+//!
+//! For state that should not be fetched ahead of time, a [`ForkedDb`] can be
+//! given to the [`Environment`] instead. It lazily pulls accounts and storage
+//! from a remote node the first time they are touched.
 
 use std::{collections::HashMap, env, fs};
 
-use ethers::types::Address;
+use ethers::{
+    abi::Abi,
+    types::{Address, H256},
+};
+use revm::{
+    primitives::{Bytecode, B256},
+    DatabaseRef,
+};
 
 use super::*;
 
+#[cfg(feature = "fork")]
+mod forked_db;
+#[cfg(feature = "fork")]
+pub(crate) use forked_db::ForkCache;
+#[cfg(feature = "fork")]
+pub use forked_db::{CachePolicy, ForkedDb};
+
 /// A [`ContractMetadata`] is used to store the metadata of a contract that will
 /// be loaded into a [`Fork`].
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -20,8 +42,134 @@ pub struct ContractMetadata {
     /// The path to the contract artifacts.
     pub artifacts_path: String,
 
-    /// The mappings that are part of the contract's storage.
+    /// The name of the contract in the artifacts. This is only needed when
+    /// the artifacts hold more than one contract, e.g., the standard JSON
+    /// output of `solc`.
+    #[serde(default)]
+    pub contract_name: Option<String>,
+
+    /// The mappings that are part of the contract's storage. Each entry is
+    /// the label of a mapping along with the keys to pull in. Keys of nested
+    /// mappings are separated by commas.
     pub mappings: HashMap<String, Vec<String>>,
+
+    /// The hash of the code the contract is expected to have. When it is set,
+    /// forking fails if the fetched code hashes differently, e.g., because a
+    /// proxy was upgraded since the config was written.
+    #[serde(default)]
+    pub code_hash: Option<H256>,
+
+    /// The path to the artifacts of the proxy itself when the contract is a
+    /// proxy. Its ABI is registered alongside the ABI in `artifacts_path`,
+    /// which should be the implementation's.
+    #[serde(default)]
+    pub proxy_artifacts_path: Option<String>,
+
+    /// The ABI read from the artifacts when the contract was forked.
+    #[serde(default)]
+    pub abi: Option<Abi>,
+
+    /// The proxy found at the contract's address when it was forked, if the
+    /// contract is one.
+    #[serde(default)]
+    pub proxy: Option<ProxyMetadata>,
+
+    /// The metadata of the contract if it was found to be an ERC-20 token
+    /// when it was forked.
+    #[serde(default)]
+    pub token: Option<TokenMetadata>,
+
+    /// The holders of the contract, if it is an ERC-20 token, whose balances
+    /// are forked along with it. Holders found by `holder_scan` are added
+    /// here when the contract is forked.
+    #[serde(default)]
+    pub holders: Vec<Address>,
+
+    /// The spenders whose allowances from each of the `holders` are forked
+    /// along with the token, e.g., a router the agents trade through.
+    #[serde(default)]
+    pub spenders: Vec<Address>,
+
+    /// A range of blocks whose `Transfer` logs are scanned for more holders
+    /// of the token.
+    #[serde(default)]
+    pub holder_scan: Option<HolderScan>,
+}
+
+/// A range of blocks whose `Transfer` logs are scanned for the holders of an
+/// ERC-20 token when it is forked. Every sender and recipient of a transfer
+/// in the range is taken to be a holder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HolderScan {
+    /// The first block that is scanned.
+    pub from_block: u64,
+
+    /// The last block that is scanned. Defaults to the block that is forked.
+    #[serde(default)]
+    pub to_block: Option<u64>,
+}
+
+/// The metadata of an ERC-20 token, captured when it was forked so amounts
+/// can be rendered without asking a node.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenMetadata {
+    /// The name of the token. Tokens without a `name` use their symbol.
+    pub name: String,
+
+    /// The symbol of the token.
+    pub symbol: String,
+
+    /// The number of decimals of the token.
+    pub decimals: u8,
+}
+
+impl TokenMetadata {
+    /// Renders an `amount` of the token in whole units followed by its
+    /// symbol, e.g., `1.5 WETH`.
+    pub fn format_amount(&self, amount: ethers::types::U256) -> String {
+        let units = ethers::utils::format_units(amount, self.decimals as u32)
+            .unwrap_or_else(|_| amount.to_string());
+        let units = match units.contains('.') {
+            true => units.trim_end_matches('0').trim_end_matches('.'),
+            false => units.as_str(),
+        };
+        format!("{} {}", units, self.symbol)
+    }
+}
+
+/// The kinds of proxies that are detected when forking. All of them keep
+/// their state in the slots set out by EIP-1967.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProxyKind {
+    /// A transparent proxy, which keeps its admin in the proxy.
+    Transparent,
+
+    /// A UUPS proxy, which leaves upgrades to its implementation and so has no
+    /// admin.
+    Uups,
+
+    /// A beacon proxy, which asks its beacon for the implementation.
+    Beacon,
+}
+
+/// What was found out about a proxy when it was forked. The code of its
+/// implementation, and that of its beacon, is forked along with it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProxyMetadata {
+    /// The kind of the proxy.
+    pub kind: ProxyKind,
+
+    /// The address of the implementation the proxy delegates to.
+    pub implementation: Address,
+
+    /// The address of the beacon of a [`ProxyKind::Beacon`] proxy.
+    pub beacon: Option<Address>,
+
+    /// The address of the admin of a [`ProxyKind::Transparent`] proxy.
+    pub admin: Option<Address>,
+
+    /// The ABI of the proxy itself, read from the `proxy_artifacts_path`.
+    pub abi: Option<Abi>,
 }
 
 /// A [`Fork`] is used to store the data that will be loaded into an
@@ -32,7 +180,7 @@ pub struct ContractMetadata {
 #[derive(Clone, Debug)]
 pub struct Fork {
     /// The [`CacheDB`] that will be loaded into the [`Environment`].
-    pub db: CacheDB<EmptyDB>,
+    pub db: CacheDB<ExternalDb>,
 
     /// The [`HashMap`] of [`ContractMetadata`] that will be used by the
     /// end-user.
@@ -40,51 +188,140 @@ pub struct Fork {
 }
 
 impl Fork {
+    /// The metadata of the forked ERC-20 token at `address`, if there is one.
+    pub fn token(&self, address: Address) -> Option<&TokenMetadata> {
+        self.contracts_meta
+            .values()
+            .find(|contract| contract.address == address)
+            .and_then(|contract| contract.token.as_ref())
+    }
+
+    /// The metadata of every forked ERC-20 token, keyed by its address.
+    pub fn tokens(&self) -> HashMap<Address, TokenMetadata> {
+        self.contracts_meta
+            .values()
+            .filter_map(|contract| Some((contract.address, contract.token.clone()?)))
+            .collect()
+    }
+
+    /// Registers the ABIs of the forked contracts with the `registry`, e.g.,
+    /// [`Environment::abi_registry`], so that their reverts are decoded.
+    /// Proxies are registered with their implementation's ABI merged with
+    /// their own, and their implementations with the implementation's ABI.
+    pub fn register_abis(&self, registry: &AbiRegistry) {
+        for contract in self.contracts_meta.values() {
+            let mut abi = contract.abi.clone();
+            if let Some(proxy) = &contract.proxy {
+                if let Some(abi) = &contract.abi {
+                    registry.register(proxy.implementation, abi.clone());
+                }
+                if let Some(proxy_abi) = &proxy.abi {
+                    abi = Some(match abi {
+                        Some(abi) => merge_abis(abi, proxy_abi),
+                        None => proxy_abi.clone(),
+                    });
+                }
+            }
+            if let Some(abi) = abi {
+                registry.register(contract.address, abi);
+            }
+        }
+    }
+
     /// Creates a new [`Fork`] from serialized [`DiskData`] stored on disk.
     pub fn from_disk(path: &str) -> Result<Self, EnvironmentError> {
-        // Read the file
-        let mut cwd = env::current_dir().unwrap();
-        cwd.push(path);
-        println!("Reading db from: {:?}", cwd);
-        let data = fs::read_to_string(cwd).unwrap();
+        let disk_data = read_disk_data(path);
+        Ok(Self {
+            db: raw_to_db(disk_data.raw),
+            contracts_meta: disk_data.meta,
+        })
+    }
 
-        // Deserialize the JSON data to your OutputData type
-        let disk_data: DiskData = serde_json::from_str(&data).unwrap();
+    /// Creates a new [`Fork`] from the snapshot with the given `label` in
+    /// serialized [`DiskData`] stored on disk. This allows for running against
+    /// several historical states that were all written out at once.
+    pub fn from_disk_at(path: &str, label: &str) -> Result<Self, EnvironmentError> {
+        let mut disk_data = read_disk_data(path);
+        let raw = disk_data.snapshots.remove(label).ok_or_else(|| {
+            EnvironmentError::Fork(format!("no snapshot labeled `{}` in {}", label, path))
+        })?;
+        Ok(Self {
+            db: raw_to_db(raw),
+            contracts_meta: disk_data.meta,
+        })
+    }
+}
 
-        // Create a CacheDB instance
-        let mut db = CacheDB::new(EmptyDB::default());
+/// Adds the functions, events, and errors of `other` that `abi` lacks to it.
+fn merge_abis(mut abi: Abi, other: &Abi) -> Abi {
+    fn merge<T: Clone + PartialEq>(
+        into: &mut BTreeMap<String, Vec<T>>,
+        from: &BTreeMap<String, Vec<T>>,
+    ) {
+        for (name, items) in from {
+            let entry = into.entry(name.clone()).or_default();
+            for item in items {
+                if !entry.contains(item) {
+                    entry.push(item.clone());
+                }
+            }
+        }
+    }
+    merge(&mut abi.functions, &other.functions);
+    merge(&mut abi.events, &other.events);
+    merge(&mut abi.errors, &other.errors);
+    abi.receive |= other.receive;
+    abi.fallback |= other.fallback;
+    abi
+}
 
-        // Populate the CacheDB from the OutputData
-        for (address, (info, storage_map)) in disk_data.raw {
-            // Convert the string address back to its original type
-            let address = address.as_fixed_bytes().into(); // You'd need to define this
+fn read_disk_data(path: &str) -> DiskData {
+    // Read the file
+    let mut cwd = env::current_dir().unwrap();
+    cwd.push(path);
+    println!("Reading db from: {:?}", cwd);
+    let data = fs::read_to_string(cwd).unwrap();
 
-            // Insert account info into the DB
-            db.insert_account_info(address, info);
+    // Deserialize the JSON data to your OutputData type
+    serde_json::from_str(&data).unwrap()
+}
 
-            // Insert storage data into the DB
-            for (key_str, value_str) in storage_map {
-                let key = revm::primitives::U256::from_str_radix(&key_str, 10).unwrap();
-                let value = revm::primitives::U256::from_str_radix(&value_str, 10).unwrap();
+fn raw_to_db(raw: RawState) -> CacheDB<ExternalDb> {
+    // Create a CacheDB instance
+    let mut db = CacheDB::new(ExternalDb::default());
 
-                db.insert_account_storage(address, key, value).unwrap();
-            }
-        }
+    // Populate the CacheDB from the OutputData
+    for (address, (info, storage_map)) in raw {
+        // Convert the string address back to its original type
+        let address = address.as_fixed_bytes().into(); // You'd need to define this
 
-        Ok(Self {
-            db,
-            contracts_meta: disk_data.meta,
-        })
+        // Insert account info into the DB
+        db.insert_account_info(address, info);
+
+        // Insert storage data into the DB
+        for (key_str, value_str) in storage_map {
+            let key = revm::primitives::U256::from_str_radix(&key_str, 10).unwrap();
+            let value = revm::primitives::U256::from_str_radix(&value_str, 10).unwrap();
+
+            db.insert_account_storage(address, key, value).unwrap();
+        }
     }
+    db
 }
 
-impl From<Fork> for CacheDB<EmptyDB> {
+impl From<Fork> for CacheDB<ExternalDb> {
     fn from(val: Fork) -> Self {
         val.db
     }
 }
 
-type Storage = HashMap<String, String>;
+/// The storage of an account as written to disk, mapping decimal slot strings
+/// to decimal value strings.
+pub type Storage = HashMap<String, String>;
+
+/// The account info and storage of every account in a snapshot as written to
+/// disk.
+pub type RawState = HashMap<Address, (AccountInfo, Storage)>;
 
 /// This is the data that will be written to and loaded from disk to generate a
 /// [`Fork`].
@@ -95,5 +332,83 @@ pub struct DiskData {
     pub meta: HashMap<String, ContractMetadata>,
 
     /// This is the raw data that will be loaded into the [`Fork`].
-    pub raw: HashMap<Address, (AccountInfo, Storage)>,
+    pub raw: RawState,
+
+    /// Additional snapshots of the raw data at other blocks, keyed by label.
+    /// These are loaded with [`Fork::from_disk_at`].
+    #[serde(default)]
+    pub snapshots: HashMap<String, RawState>,
+
+    /// The block number the raw data was fetched at. This is missing for
+    /// forks written out before it was recorded.
+    #[serde(default)]
+    pub block_number: Option<u64>,
+}
+
+/// The database that sits underneath the [`CacheDB`] of an [`Environment`].
+/// Any account, storage slot, or block hash that the [`CacheDB`] does not hold
+/// is requested from here.
+#[derive(Clone, Debug)]
+pub enum ExternalDb {
+    /// Nothing is stored underneath the [`CacheDB`] so that missing state is
+    /// treated as empty. This is the default for an [`Environment`].
+    Empty(EmptyDB),
+
+    /// Missing state is lazily fetched from a remote node through a
+    /// [`ForkedDb`].
+    #[cfg(feature = "fork")]
+    Forked(ForkedDb),
+}
+
+impl Default for ExternalDb {
+    fn default() -> Self {
+        Self::Empty(EmptyDB::default())
+    }
+}
+
+impl DatabaseRef for ExternalDb {
+    type Error = DatabaseError;
+
+    fn basic_ref(
+        &self,
+        address: revm::primitives::Address,
+    ) -> Result<Option<AccountInfo>, Self::Error> {
+        match self {
+            Self::Empty(db) => db.basic_ref(address).map_err(|never| match never {}),
+            #[cfg(feature = "fork")]
+            Self::Forked(db) => db.basic_ref(address),
+        }
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        match self {
+            Self::Empty(db) => db
+                .code_by_hash_ref(code_hash)
+                .map_err(|never| match never {}),
+            #[cfg(feature = "fork")]
+            Self::Forked(db) => db.code_by_hash_ref(code_hash),
+        }
+    }
+
+    fn storage_ref(
+        &self,
+        address: revm::primitives::Address,
+        index: U256,
+    ) -> Result<U256, Self::Error> {
+        match self {
+            Self::Empty(db) => db
+                .storage_ref(address, index)
+                .map_err(|never| match never {}),
+            #[cfg(feature = "fork")]
+            Self::Forked(db) => db.storage_ref(address, index),
+        }
+    }
+
+    fn block_hash_ref(&self, number: U256) -> Result<B256, Self::Error> {
+        match self {
+            Self::Empty(db) => db.block_hash_ref(number).map_err(|never| match never {}),
+            #[cfg(feature = "fork")]
+            Self::Forked(db) => db.block_hash_ref(number),
+        }
+    }
 }
@@ -0,0 +1,565 @@
+//! The [`ForkedDb`] that lazily fetches state from a remote node. This is only
+//! available with the `fork` feature.
+
+use std::{
+    collections::HashSet,
+    fmt::Formatter,
+    future::Future,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use ethers::{
+    providers::{
+        Http, HttpRateLimitRetryPolicy, Middleware, Provider, RetryClient, RetryClientBuilder,
+    },
+    types::{BlockId, BlockNumber, H256},
+};
+
+use super::*;
+
+/// A [`ForkedDb`] lazily fetches accounts and storage from a remote node the
+/// first time the [`Environment`] asks for them.
+/// Fetched state can be persisted to a cache file on disk so that repeated
+/// runs against the same block do not hit the node again.
+///
+/// The cache file is loaded when [`ForkedDb::cache`] is called and written
+/// back once the last handle to the [`ForkedDb`] is dropped (e.g., when the
+/// [`Environment`] is stopped).
+///
+/// State can also be shared between forks through a cache directory given
+/// with [`ForkedDb::cache_dir`], where it is kept per chain, block, and
+/// account. A [`CachePolicy`] decides whether the directory is only read from,
+/// e.g., so that runs in CI never hit the node, or filled in again.
+#[derive(Clone)]
+pub struct ForkedDb {
+    /// The provider that fetches state from the remote node.
+    provider: Arc<Provider<RetryClient<Http>>>,
+
+    /// The runtime that requests to the remote node are run on.
+    runtime: Arc<ForkRuntime>,
+
+    /// The URL of the remote node.
+    provider_url: String,
+
+    /// The block number that the [`ForkedDb`] is pinned to.
+    block_number: u64,
+
+    /// The id of the chain the remote node is on, once it is known.
+    chain_id: Option<u64>,
+
+    /// All of the state that has been fetched so far.
+    cache: Arc<Mutex<ForkCache>>,
+}
+
+/// How many times a request to the node that times out or is rate limited is
+/// retried when a [`ForkedDb`] is created with [`ForkedDb::new`].
+const DEFAULT_RETRIES: u32 = 5;
+
+/// The file in a cache directory that maps the URLs of nodes to the ids of the
+/// chains they are on, so the chain can be found without asking the node.
+const CHAIN_IDS_FILE: &str = "chain_ids.json";
+
+/// How a [`ForkedDb`] uses the cache directory given with
+/// [`ForkedDb::cache_dir`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CachePolicy {
+    /// State in the cache is served from it. Everything else is fetched from
+    /// the node and added to the cache.
+    #[default]
+    Reuse,
+
+    /// Only state in the cache is served. Anything else fails to load instead
+    /// of being fetched, so the node is never contacted.
+    Offline,
+
+    /// Everything is fetched from the node again and replaces what the cache
+    /// holds.
+    Refresh,
+}
+
+impl ForkedDb {
+    /// Creates a new [`ForkedDb`] that fetches state from the node at
+    /// `provider_url` as of the given `block_number`.
+    pub fn new(provider_url: &str, block_number: u64) -> Result<Self, EnvironmentError> {
+        Self::with_retries(provider_url, block_number, DEFAULT_RETRIES)
+    }
+
+    /// Creates a new [`ForkedDb`] like [`ForkedDb::new`] that retries a
+    /// request to the node that times out or is rate limited up to `retries`
+    /// times.
+    pub fn with_retries(
+        provider_url: &str,
+        block_number: u64,
+        retries: u32,
+    ) -> Result<Self, EnvironmentError> {
+        let http = provider_url
+            .parse::<Http>()
+            .map_err(|e| EnvironmentError::Fork(e.to_string()))?;
+        let client = RetryClientBuilder::default()
+            .rate_limit_retries(retries)
+            .timeout_retries(retries)
+            .initial_backoff(Duration::from_millis(500))
+            .build(http, Box::<HttpRateLimitRetryPolicy>::default());
+        let provider = Arc::new(Provider::new(client));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| EnvironmentError::Fork(e.to_string()))?;
+        Ok(Self {
+            provider,
+            runtime: Arc::new(ForkRuntime(Some(runtime))),
+            provider_url: provider_url.to_string(),
+            block_number,
+            chain_id: None,
+            cache: Arc::new(Mutex::new(ForkCache::new(block_number))),
+        })
+    }
+
+    /// Persists all fetched state to the file at `path`. If the file already
+    /// holds state for the same block number, that state is loaded and will be
+    /// served without contacting the remote node.
+    pub fn cache(self, path: impl AsRef<Path>) -> Result<Self, EnvironmentError> {
+        let path = path.as_ref().to_path_buf();
+        let mut cache = if path.is_file() {
+            let data =
+                fs::read_to_string(&path).map_err(|e| EnvironmentError::Fork(e.to_string()))?;
+            let cache: ForkCache =
+                serde_json::from_str(&data).map_err(|e| EnvironmentError::Fork(e.to_string()))?;
+            if cache.block_number == self.block_number {
+                cache
+            } else {
+                warn!(
+                    "Fork cache at {:?} is for block {} and not block {}. Ignoring it.",
+                    path, cache.block_number, self.block_number
+                );
+                ForkCache::new(self.block_number)
+            }
+        } else {
+            ForkCache::new(self.block_number)
+        };
+        cache.path = Some(path);
+        let mut current = self.cache.lock().unwrap();
+        cache.directory = current.directory.take();
+        cache.policy = current.policy;
+        *current = cache;
+        drop(current);
+        Ok(self)
+    }
+
+    /// Keeps all fetched state in the directory at `path`, where each account
+    /// is stored under the id of its chain and the block number, e.g.,
+    /// `<path>/1/18228556/0xc02a...6cc2.json`. Accounts are loaded from the
+    /// directory the first time they are touched, so forks of the same block
+    /// share what they fetched. The `policy` decides whether the node is asked
+    /// for state that is missing from the directory, or for all of it again.
+    ///
+    /// The id of the chain is asked of the node once and remembered in the
+    /// directory, so a [`CachePolicy::Offline`] [`ForkedDb`] only needs a
+    /// node that has been cached from before.
+    pub fn cache_dir(
+        mut self,
+        path: impl AsRef<Path>,
+        policy: CachePolicy,
+    ) -> Result<Self, EnvironmentError> {
+        let path = path.as_ref().to_path_buf();
+        let chain_ids_path = path.join(CHAIN_IDS_FILE);
+        let mut chain_ids: HashMap<String, u64> = match chain_ids_path.is_file() {
+            true => fs::read_to_string(&chain_ids_path)
+                .map_err(|e| e.to_string())
+                .and_then(|data| serde_json::from_str(&data).map_err(|e| e.to_string()))
+                .map_err(EnvironmentError::Fork)?,
+            false => HashMap::new(),
+        };
+        let chain_id = match (chain_ids.get(&self.provider_url), policy) {
+            (Some(chain_id), CachePolicy::Reuse | CachePolicy::Offline) => *chain_id,
+            (None, CachePolicy::Offline) => {
+                return Err(EnvironmentError::Fork(format!(
+                    "the chain of {} is not in the fork cache at {:?}! Fork it once without going offline first.",
+                    self.provider_url, path
+                )))
+            }
+            _ => {
+                let provider = self.provider.clone();
+                let chain_id = self
+                    .block_on(async move { provider.get_chainid().await })
+                    .map_err(|e| EnvironmentError::Fork(e.to_string()))?
+                    .map_err(|e| EnvironmentError::Fork(e.to_string()))?
+                    .as_u64();
+                chain_ids.insert(self.provider_url.clone(), chain_id);
+                fs::create_dir_all(&path)
+                    .and_then(|_| {
+                        fs::write(&chain_ids_path, serde_json::to_string_pretty(&chain_ids)?)
+                    })
+                    .map_err(|e| EnvironmentError::Fork(e.to_string()))?;
+                chain_id
+            }
+        };
+        self.chain_id = Some(chain_id);
+        {
+            let mut cache = self.cache.lock().unwrap();
+            cache.directory = Some(
+                path.join(chain_id.to_string())
+                    .join(self.block_number.to_string()),
+            );
+            cache.policy = policy;
+        }
+        Ok(self)
+    }
+
+    /// The block number that the [`ForkedDb`] is pinned to.
+    pub fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    /// The id of the chain the remote node is on. This is only known once a
+    /// cache directory is given with [`ForkedDb::cache_dir`].
+    pub fn chain_id(&self) -> Option<u64> {
+        self.chain_id
+    }
+
+    /// The block the [`ForkedDb`] is pinned to, as the node is asked for it.
+    fn block_id(&self) -> Option<BlockId> {
+        Some(BlockId::Number(BlockNumber::Number(
+            self.block_number.into(),
+        )))
+    }
+
+    /// Fetches the nonce, balance, and code of the account at `address` from
+    /// the node.
+    fn fetch_account(&self, address: Address) -> Result<AccountInfo, DatabaseError> {
+        let block = self.block_id();
+        let (nonce, balance, code) = self
+            .block_on(async {
+                futures_util::try_join!(
+                    self.provider.get_transaction_count(address, block),
+                    self.provider.get_balance(address, block),
+                    self.provider.get_code(address, block),
+                )
+            })?
+            .map_err(|e| DatabaseError::Fetch(e.to_string()))?;
+        let code = Bytecode::new_raw(revm::primitives::Bytes(code.0));
+        Ok(AccountInfo {
+            balance: U256::from_limbs(balance.0),
+            nonce: nonce.as_u64(),
+            code_hash: code.hash_slow(),
+            code: Some(code),
+        })
+    }
+
+    /// Fetches the storage slot `index` of the account at `address` from the
+    /// node.
+    fn fetch_storage(&self, address: Address, index: U256) -> Result<U256, DatabaseError> {
+        let slot = H256::from(index.to_be_bytes());
+        let value = self
+            .block_on(self.provider.get_storage_at(address, slot, self.block_id()))?
+            .map_err(|e| DatabaseError::Fetch(e.to_string()))?;
+        Ok(U256::from_be_bytes(value.to_fixed_bytes()))
+    }
+
+    /// Runs a `future` that talks to the node to completion on the runtime of
+    /// the [`ForkedDb`]. The runtime is driven from a separate thread so that
+    /// this works both when called from within another runtime and from the
+    /// plain thread the [`Environment`] executes on.
+    fn block_on<F>(&self, future: F) -> Result<F::Output, DatabaseError>
+    where
+        F: Future + Send,
+        F::Output: Send,
+    {
+        // This unwrap should never fail, the runtime is only taken on drop.
+        let runtime = self.runtime.0.as_ref().unwrap();
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| runtime.block_on(future))
+                .join()
+                .map_err(|_| {
+                    DatabaseError::Fetch("the thread talking to the node panicked".to_string())
+                })
+        })
+    }
+}
+
+/// The runtime a [`ForkedDb`] and all of its clones share. It is shut down
+/// without waiting on it when dropped, as the last clone may be dropped from
+/// within another runtime, where blocking is not allowed.
+struct ForkRuntime(Option<tokio::runtime::Runtime>);
+
+impl Drop for ForkRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl Debug for ForkedDb {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let cache = self.cache.lock().unwrap();
+        f.debug_struct("ForkedDb")
+            .field("block_number", &self.block_number)
+            .field("chain_id", &self.chain_id)
+            .field("cache", &cache.path)
+            .field("cache_directory", &cache.directory)
+            .field("cache_policy", &cache.policy)
+            .finish()
+    }
+}
+
+impl DatabaseRef for ForkedDb {
+    type Error = DatabaseError;
+
+    fn basic_ref(
+        &self,
+        address: revm::primitives::Address,
+    ) -> Result<Option<AccountInfo>, Self::Error> {
+        let recast_address = Address::from(address.into_array());
+        {
+            let mut cache = self.cache.lock().unwrap();
+            cache.load(recast_address);
+            if let Some(info) = cache.accounts.get(&recast_address) {
+                return Ok(Some(info.clone()));
+            }
+            cache.check_online(recast_address)?;
+        }
+        let info = self.fetch_account(recast_address)?;
+        let mut cache = self.cache.lock().unwrap();
+        cache.accounts.insert(recast_address, info.clone());
+        cache.fetched.insert(recast_address);
+        cache.dirty = true;
+        Ok(Some(info))
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        // Code is fetched alongside the account info, so it can only be found
+        // for accounts that have already been loaded.
+        self.cache
+            .lock()
+            .unwrap()
+            .accounts
+            .values()
+            .find(|info| info.code_hash == code_hash)
+            .and_then(|info| info.code.clone())
+            .ok_or(DatabaseError::Fetch(format!(
+                "no code found for hash {:?}",
+                code_hash
+            )))
+    }
+
+    fn storage_ref(
+        &self,
+        address: revm::primitives::Address,
+        index: U256,
+    ) -> Result<U256, Self::Error> {
+        let recast_address = Address::from(address.into_array());
+        {
+            let mut cache = self.cache.lock().unwrap();
+            cache.load(recast_address);
+            if let Some(value) = cache
+                .storage
+                .get(&recast_address)
+                .and_then(|storage| storage.get(&index.to_string()))
+            {
+                return U256::from_str_radix(value, 10)
+                    .map_err(|e| DatabaseError::Fetch(e.to_string()));
+            }
+            cache.check_online(recast_address)?;
+        }
+        let value = self.fetch_storage(recast_address, index)?;
+        let mut cache = self.cache.lock().unwrap();
+        cache
+            .storage
+            .entry(recast_address)
+            .or_default()
+            .insert(index.to_string(), value.to_string());
+        cache.fetched.insert(recast_address);
+        cache.dirty = true;
+        Ok(value)
+    }
+
+    fn block_hash_ref(&self, number: U256) -> Result<B256, Self::Error> {
+        if self.cache.lock().unwrap().policy == CachePolicy::Offline {
+            return Err(DatabaseError::Fetch(format!(
+                "the hash of block {} is not cached and the fork is offline",
+                number
+            )));
+        }
+        let number = u64::try_from(number).map_err(|e| DatabaseError::Fetch(e.to_string()))?;
+        self.block_on(self.provider.get_block(number))?
+            .map_err(|e| DatabaseError::Fetch(e.to_string()))?
+            .and_then(|block| block.hash)
+            .map(|hash| B256::from(hash.to_fixed_bytes()))
+            .ok_or(DatabaseError::Fetch(format!(
+                "block {} was not found on the node",
+                number
+            )))
+    }
+}
+
+impl From<ForkedDb> for CacheDB<ExternalDb> {
+    fn from(val: ForkedDb) -> Self {
+        CacheDB::new(ExternalDb::Forked(val))
+    }
+}
+
+/// The state fetched by a [`ForkedDb`] as it is stored on disk. Storage is
+/// kept in the same format as in [`DiskData`].
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ForkCache {
+    /// The block number the state was fetched at.
+    pub(crate) block_number: u64,
+
+    /// The account info fetched so far.
+    pub(crate) accounts: HashMap<Address, AccountInfo>,
+
+    /// The storage slots fetched so far.
+    pub(crate) storage: HashMap<Address, Storage>,
+
+    /// Where the cache is written to, if anywhere.
+    #[serde(skip)]
+    pub(crate) path: Option<PathBuf>,
+
+    /// Whether anything has been fetched since the cache was loaded.
+    #[serde(skip)]
+    pub(crate) dirty: bool,
+
+    /// The directory accounts are kept in, one file each, if anywhere. This
+    /// is already specific to the chain and the block.
+    #[serde(skip)]
+    pub(crate) directory: Option<PathBuf>,
+
+    /// How the `directory` is used.
+    #[serde(skip)]
+    pub(crate) policy: CachePolicy,
+
+    /// The accounts that have been looked up in the `directory`.
+    #[serde(skip)]
+    pub(crate) loaded: HashSet<Address>,
+
+    /// The accounts that had state fetched from the node.
+    #[serde(skip)]
+    pub(crate) fetched: HashSet<Address>,
+}
+
+/// The state of a single account as it is kept in a cache directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CachedAccount {
+    /// The account info, if it has been fetched.
+    info: Option<AccountInfo>,
+
+    /// The storage slots fetched so far.
+    storage: Storage,
+}
+
+impl ForkCache {
+    pub(crate) fn new(block_number: u64) -> Self {
+        Self {
+            block_number,
+            accounts: HashMap::new(),
+            storage: HashMap::new(),
+            path: None,
+            dirty: false,
+            directory: None,
+            policy: CachePolicy::default(),
+            loaded: HashSet::new(),
+            fetched: HashSet::new(),
+        }
+    }
+
+    /// The file the state of `address` is kept in within the cache directory.
+    fn account_path(&self, address: Address) -> Option<PathBuf> {
+        self.directory
+            .as_ref()
+            .map(|directory| directory.join(format!("{:?}.json", address)))
+    }
+
+    /// Loads what the cache directory holds for `address` the first time the
+    /// account is touched. Nothing is loaded when the cache is refreshed.
+    fn load(&mut self, address: Address) {
+        if self.policy == CachePolicy::Refresh || !self.loaded.insert(address) {
+            return;
+        }
+        let Some(path) = self.account_path(address) else {
+            return;
+        };
+        let Some(cached) = read_cached_account(&path) else {
+            return;
+        };
+        if let Some(info) = cached.info {
+            self.accounts.entry(address).or_insert(info);
+        }
+        let storage = self.storage.entry(address).or_default();
+        for (slot, value) in cached.storage {
+            storage.entry(slot).or_insert(value);
+        }
+    }
+
+    /// Fails if state of `address` that is not cached would have to be
+    /// fetched while the cache is offline.
+    fn check_online(&self, address: Address) -> Result<(), DatabaseError> {
+        match self.policy {
+            CachePolicy::Offline => Err(DatabaseError::Fetch(format!(
+                "state of {:?} is not cached and the fork is offline",
+                address
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the accounts that had state fetched to the cache directory,
+    /// merged with what the directory already holds for them.
+    fn write_directory(&self) -> Result<(), String> {
+        let Some(directory) = &self.directory else {
+            return Ok(());
+        };
+        if self.fetched.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+        for address in &self.fetched {
+            let path = self.account_path(*address).unwrap();
+            let mut cached = read_cached_account(&path).unwrap_or_default();
+            if let Some(info) = self.accounts.get(address) {
+                cached.info = Some(info.clone());
+            }
+            if let Some(storage) = self.storage.get(address) {
+                cached.storage.extend(storage.clone());
+            }
+            let data = serde_json::to_string(&cached).map_err(|e| e.to_string())?;
+            fs::write(&path, data).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the state of an account kept in a cache directory, if there is any.
+fn read_cached_account(path: &Path) -> Option<CachedAccount> {
+    let data = fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&data) {
+        Ok(cached) => Some(cached),
+        Err(e) => {
+            warn!("Ignoring the corrupt fork cache at {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+impl Drop for ForkCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.write_directory() {
+            error!(
+                "Failed to write the fork cache to {:?}: {}",
+                self.directory, e
+            );
+        }
+        let Some(path) = &self.path else { return };
+        if !self.dirty {
+            return;
+        }
+        let result = serde_json::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|data| fs::write(path, data).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Failed to write the fork cache to {:?}: {}", path, e);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! The `gas_report` module contains the [`GasReport`] an [`Environment`] built
+//! with [`EnvironmentBuilder::profile_gas`] produces on request.
+//!
+//! The report aggregates the gas used by every call to each contract function
+//! over the course of a simulation, including calls made by other contracts,
+//! and is displayed as a table in the style of Foundry's `forge test
+//! --gas-report`.
+//!
+//! The report also serializes to JSON with one flat object per function so
+//! that it can be loaded straight into a dataframe:
+//!
+//! ```json
+//! {
+//!   "functions": [
+//!     {
+//!       "contract": "0x...",
+//!       "selector": "0xa9059cbb",
+//!       "function": "transfer",
+//!       "calls": 3,
+//!       "min": 29470,
+//!       "mean": 33745,
+//!       "median": 29470,
+//!       "max": 42295
+//!     }
+//!   ],
+//!   "precompiles": { "sha256": { "calls": 1, "gas": 72 } }
+//! }
+//! ```
+//!
+//! `selector` is `null` for calls without one and `function` is `null` until
+//! it is resolved with [`GasReport::with_abi`].
+
+#![warn(missing_docs)]
+
+use std::fmt;
+
+use ethers::{
+    abi::Abi,
+    types::{Address, Bytes},
+};
+
+use super::*;
+
+/// The gas used by the calls to a single contract function.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionGas {
+    /// The address of the contract.
+    pub contract: Address,
+
+    /// The selector the function was called with or `None` for calls with
+    /// less than 4 bytes of calldata. Serialized as a hex string.
+    #[serde(with = "selector_hex")]
+    pub selector: Option<[u8; 4]>,
+
+    /// The name of the function, if it was resolved with
+    /// [`GasReport::with_abi`].
+    pub function: Option<String>,
+
+    /// The number of calls to the function.
+    pub calls: u64,
+
+    /// The least gas used by a call.
+    pub min: u64,
+
+    /// The mean gas used by the calls.
+    pub mean: u64,
+
+    /// The median gas used by the calls.
+    pub median: u64,
+
+    /// The most gas used by a call.
+    pub max: u64,
+}
+
+impl FunctionGas {
+    /// The name of the function if it is known and otherwise its selector in
+    /// hex, or `fallback` if it was called without one.
+    pub fn label(&self) -> String {
+        match (&self.function, self.selector) {
+            (Some(function), _) => function.clone(),
+            (None, Some(selector)) => Bytes::from(selector.to_vec()).to_string(),
+            (None, None) => "fallback".to_string(),
+        }
+    }
+}
+
+/// (De)serializes a selector as a `0x` prefixed hex string rather than an
+/// array of bytes.
+mod selector_hex {
+    use ethers::types::Bytes;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        selector: &Option<[u8; 4]>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        selector
+            .map(|selector| Bytes::from(selector.to_vec()))
+            .serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<[u8; 4]>, D::Error> {
+        Option::<Bytes>::deserialize(deserializer)?
+            .map(|bytes| {
+                <[u8; 4]>::try_from(bytes.as_ref())
+                    .map_err(|_| D::Error::custom("a selector has to be 4 bytes long"))
+            })
+            .transpose()
+    }
+}
+
+/// A report of the gas used by the transactions in an [`Environment`], per
+/// contract function and per precompile.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GasReport {
+    /// The gas used by each contract function, ordered by contract address
+    /// and selector.
+    pub functions: Vec<FunctionGas>,
+
+    /// The usage of each precompile, which is empty unless the
+    /// [`Environment`] was also built with
+    /// [`EnvironmentBuilder::track_precompiles`].
+    pub precompiles: BTreeMap<String, PrecompileUsage>,
+}
+
+impl GasReport {
+    /// Builds the report from the gas used by every call to each function.
+    pub(crate) fn new(
+        functions: &BTreeMap<FunctionKey, Vec<u64>>,
+        precompiles: BTreeMap<String, PrecompileUsage>,
+    ) -> Self {
+        let functions = functions
+            .iter()
+            .filter(|(_, gas)| !gas.is_empty())
+            .map(|((contract, selector), gas)| {
+                let mut gas = gas.clone();
+                gas.sort_unstable();
+                let calls = gas.len() as u64;
+                FunctionGas {
+                    contract: Address::from(contract.into_array()),
+                    selector: *selector,
+                    function: None,
+                    calls,
+                    min: gas[0],
+                    mean: (gas.iter().map(|gas| *gas as u128).sum::<u128>() / calls as u128) as u64,
+                    median: gas[gas.len() / 2],
+                    max: gas[gas.len() - 1],
+                }
+            })
+            .collect();
+        Self {
+            functions,
+            precompiles,
+        }
+    }
+
+    /// Names the functions of the contract at `contract` after the functions
+    /// in its `abi`. Selectors that are not in the `abi` are left unnamed.
+    pub fn with_abi(mut self, contract: Address, abi: &Abi) -> Self {
+        for entry in self
+            .functions
+            .iter_mut()
+            .filter(|entry| entry.contract == contract)
+        {
+            if let Some(selector) = entry.selector {
+                entry.function = abi
+                    .functions()
+                    .find(|function| function.short_signature() == selector)
+                    .map(|function| function.name.clone());
+            }
+        }
+        self
+    }
+
+    /// The entries for the functions of the contract at `contract`.
+    pub fn contract(&self, contract: Address) -> impl Iterator<Item = &FunctionGas> {
+        self.functions
+            .iter()
+            .filter(move |entry| entry.contract == contract)
+    }
+}
+
+impl fmt::Display for GasReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut contract = None;
+        for entry in &self.functions {
+            if contract != Some(entry.contract) {
+                if contract.is_some() {
+                    writeln!(f)?;
+                }
+                contract = Some(entry.contract);
+                writeln!(f, "| {:?} |", entry.contract)?;
+                writeln!(
+                    f,
+                    "| {:<24} | {:>10} | {:>10} | {:>10} | {:>10} | {:>8} |",
+                    "Function Name", "min", "avg", "median", "max", "# calls"
+                )?;
+            }
+            writeln!(
+                f,
+                "| {:<24} | {:>10} | {:>10} | {:>10} | {:>10} | {:>8} |",
+                entry.label(),
+                entry.min,
+                entry.mean,
+                entry.median,
+                entry.max,
+                entry.calls
+            )?;
+        }
+        if !self.precompiles.is_empty() {
+            if contract.is_some() {
+                writeln!(f)?;
+            }
+            writeln!(
+                f,
+                "| {:<24} | {:>10} | {:>8} |",
+                "Precompile", "gas", "# calls"
+            )?;
+            for (name, usage) in &self.precompiles {
+                writeln!(
+                    f,
+                    "| {:<24} | {:>10} | {:>8} |",
+                    name, usage.gas, usage.calls
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
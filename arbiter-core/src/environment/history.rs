@@ -0,0 +1,415 @@
+//! The `history` module keeps what an [`Environment`] has done so that its
+//! clients can look back on it: a [`BlockRecord`] of every block it moved
+//! through and, if enabled, the transactions it executed along with their
+//! receipts.
+//!
+//! History is kept in memory by default. For runs whose history does not fit
+//! in memory, [`EnvironmentBuilder::with_persistent_history`] keeps it in an
+//! embedded [`sled`](https://docs.rs/sled) database on disk instead. Both
+//! answer the same queries, so clients do not see a difference.
+
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+
+use ethers::types::{Bloom, Transaction, TransactionReceipt, H256};
+
+use super::*;
+
+/// The ways reading from or writing to the history of an [`Environment`] can
+/// fail.
+#[derive(Debug, Error)]
+pub(crate) enum HistoryError {
+    /// The lock around the history kept in memory was poisoned.
+    #[error("the history lock was poisoned: {0}")]
+    Poisoned(String),
+
+    /// The history kept on disk could not be read or written.
+    #[cfg(feature = "persistent-history")]
+    #[error("the history database failed: {0}")]
+    Database(#[from] sled::Error),
+
+    /// A value kept on disk could not be serialized or deserialized.
+    #[error("a history entry could not be (de)serialized: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+impl<T> From<std::sync::PoisonError<T>> for HistoryError {
+    fn from(e: std::sync::PoisonError<T>) -> Self {
+        Self::Poisoned(e.to_string())
+    }
+}
+
+/// The transactions executed by an [`Environment`] along with their receipts,
+/// when kept in memory.
+#[derive(Debug, Default)]
+pub(crate) struct Receipts {
+    /// The receipts of transactions keyed by the number of the block they were
+    /// included in.
+    blocks: BTreeMap<u64, Vec<TransactionReceipt>>,
+
+    /// The transactions and their receipts keyed by the hash of the
+    /// transaction.
+    transactions: HashMap<H256, (Transaction, TransactionReceipt)>,
+
+    /// The bloom filter of each block, accrued from the blooms of its
+    /// receipts, so that log queries over wide block ranges can skip the
+    /// blocks that cannot hold a matching log without looking at their
+    /// receipts.
+    blooms: BTreeMap<u64, Bloom>,
+}
+
+/// The transactions executed by an [`Environment`] along with their receipts.
+/// These are added by the clients that sent the transactions so they are
+/// shared by all clients of an [`Environment`].
+#[derive(Clone, Debug)]
+pub(crate) enum ReceiptStore {
+    /// The receipts are kept in memory.
+    Memory(Arc<Mutex<Receipts>>),
+
+    /// The receipts are kept on disk.
+    #[cfg(feature = "persistent-history")]
+    Persistent(PersistentHistory),
+}
+
+impl Default for ReceiptStore {
+    fn default() -> Self {
+        Self::Memory(Arc::default())
+    }
+}
+
+impl ReceiptStore {
+    /// Keeps a `transaction` and its `receipt` for the block with the given
+    /// `number`.
+    pub(crate) fn insert(
+        &self,
+        number: u64,
+        transaction: Transaction,
+        receipt: TransactionReceipt,
+    ) -> Result<(), HistoryError> {
+        match self {
+            Self::Memory(receipts) => {
+                let mut receipts = receipts.lock()?;
+                receipts
+                    .blocks
+                    .entry(number)
+                    .or_default()
+                    .push(receipt.clone());
+                receipts
+                    .blooms
+                    .entry(number)
+                    .or_default()
+                    .accrue_bloom(&receipt.logs_bloom);
+                receipts
+                    .transactions
+                    .insert(receipt.transaction_hash, (transaction, receipt));
+            }
+            #[cfg(feature = "persistent-history")]
+            Self::Persistent(history) => history.insert_receipt(number, transaction, receipt)?,
+        }
+        Ok(())
+    }
+
+    /// The number of the last block that has a receipt, if any.
+    pub(crate) fn latest_block(&self) -> Result<Option<u64>, HistoryError> {
+        match self {
+            Self::Memory(receipts) => Ok(receipts.lock()?.blocks.keys().next_back().copied()),
+            #[cfg(feature = "persistent-history")]
+            Self::Persistent(history) => {
+                Ok(history.blooms.last()?.map(|(key, _)| key_to_number(&key)))
+            }
+        }
+    }
+
+    /// The receipts of the block with the given `number`, ordered by their
+    /// index in the block.
+    pub(crate) fn block_receipts(
+        &self,
+        number: u64,
+    ) -> Result<Vec<TransactionReceipt>, HistoryError> {
+        let mut block_receipts = match self {
+            Self::Memory(receipts) => receipts
+                .lock()?
+                .blocks
+                .get(&number)
+                .cloned()
+                .unwrap_or_default(),
+            #[cfg(feature = "persistent-history")]
+            Self::Persistent(history) => history
+                .receipts
+                .scan_prefix(number.to_be_bytes())
+                .values()
+                .map(|value| Ok(serde_json::from_slice(&value?)?))
+                .collect::<Result<Vec<_>, HistoryError>>()?,
+        };
+        block_receipts.sort_by_key(|receipt| receipt.transaction_index);
+        Ok(block_receipts)
+    }
+
+    /// The transaction with the given `hash` and its receipt, if it was kept.
+    pub(crate) fn transaction(
+        &self,
+        hash: H256,
+    ) -> Result<Option<(Transaction, TransactionReceipt)>, HistoryError> {
+        match self {
+            Self::Memory(receipts) => Ok(receipts.lock()?.transactions.get(&hash).cloned()),
+            #[cfg(feature = "persistent-history")]
+            Self::Persistent(history) => history
+                .transactions
+                .get(hash.as_bytes())?
+                .map(|value| Ok(serde_json::from_slice(&value)?))
+                .transpose(),
+        }
+    }
+
+    /// The bloom filters of the blocks from `from_block` to `to_block`
+    /// (inclusive) that have receipts, in the order of their blocks.
+    pub(crate) fn blooms(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<(u64, Bloom)>, HistoryError> {
+        match self {
+            Self::Memory(receipts) => Ok(receipts
+                .lock()?
+                .blooms
+                .range(from_block..=to_block)
+                .map(|(number, bloom)| (*number, *bloom))
+                .collect()),
+            #[cfg(feature = "persistent-history")]
+            Self::Persistent(history) => history
+                .blooms
+                .range(from_block.to_be_bytes()..=to_block.to_be_bytes())
+                .map(|entry| {
+                    let (key, value) = entry?;
+                    Ok((key_to_number(&key), Bloom::from_slice(&value)))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// What the [`Environment`] keeps about each block it moves through so that
+/// clients can answer for blocks with `get_block`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct BlockRecord {
+    /// The timestamp of the block.
+    pub(crate) timestamp: U256,
+
+    /// The base fee of the block.
+    pub(crate) base_fee: U256,
+
+    /// The gas limit of the block.
+    pub(crate) gas_limit: U256,
+
+    /// The total gas used by the transactions in the block.
+    pub(crate) gas_used: U256,
+
+    /// The hashes of the transactions in the block, in the order they were
+    /// executed.
+    pub(crate) transactions: Vec<H256>,
+}
+
+/// The [`BlockRecord`]s of an [`Environment`] keyed by the number of their
+/// block. Unlike the [`ReceiptStore`], blocks are recorded by the
+/// [`Environment`] itself and always kept.
+#[derive(Clone, Debug)]
+pub(crate) enum BlockStore {
+    /// The blocks are kept in memory.
+    Memory(Arc<Mutex<BTreeMap<u64, BlockRecord>>>),
+
+    /// The blocks are kept on disk.
+    #[cfg(feature = "persistent-history")]
+    Persistent(PersistentHistory),
+}
+
+impl Default for BlockStore {
+    fn default() -> Self {
+        Self::Memory(Arc::default())
+    }
+}
+
+impl BlockStore {
+    /// Keeps the `record` of the block with the given `number`, replacing the
+    /// one kept before, if any.
+    pub(crate) fn insert(&self, number: u64, record: BlockRecord) -> Result<(), HistoryError> {
+        match self {
+            Self::Memory(blocks) => {
+                blocks.lock()?.insert(number, record);
+            }
+            #[cfg(feature = "persistent-history")]
+            Self::Persistent(history) => {
+                history
+                    .blocks
+                    .insert(number.to_be_bytes(), serde_json::to_vec(&record)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a transaction with the given `hash` that used `gas_used` to the
+    /// record of the block with the given `number`. The block is recorded
+    /// as `record` first if it was not recorded yet.
+    pub(crate) fn add_transaction(
+        &self,
+        number: u64,
+        record: BlockRecord,
+        hash: H256,
+        gas_used: u64,
+    ) -> Result<(), HistoryError> {
+        match self {
+            Self::Memory(blocks) => {
+                let mut blocks = blocks.lock()?;
+                let record = blocks.entry(number).or_insert(record);
+                record.transactions.push(hash);
+                record.gas_used += U256::from(gas_used);
+                Ok(())
+            }
+            // Blocks are only written by the executor, so reading the record and writing it
+            // back cannot race with another write.
+            #[cfg(feature = "persistent-history")]
+            Self::Persistent(_) => {
+                let mut record = self.get(number)?.unwrap_or(record);
+                record.transactions.push(hash);
+                record.gas_used += U256::from(gas_used);
+                self.insert(number, record)
+            }
+        }
+    }
+
+    /// The record of the block with the given `number`, if it was reached.
+    pub(crate) fn get(&self, number: u64) -> Result<Option<BlockRecord>, HistoryError> {
+        match self {
+            Self::Memory(blocks) => Ok(blocks.lock()?.get(&number).cloned()),
+            #[cfg(feature = "persistent-history")]
+            Self::Persistent(history) => history
+                .blocks
+                .get(number.to_be_bytes())?
+                .map(|value| Ok(serde_json::from_slice(&value)?))
+                .transpose(),
+        }
+    }
+
+    /// The number of the first block that was recorded, if any.
+    pub(crate) fn first(&self) -> Result<Option<u64>, HistoryError> {
+        match self {
+            Self::Memory(blocks) => Ok(blocks.lock()?.keys().next().copied()),
+            #[cfg(feature = "persistent-history")]
+            Self::Persistent(history) => {
+                Ok(history.blocks.first()?.map(|(key, _)| key_to_number(&key)))
+            }
+        }
+    }
+
+    /// The number of the last block that was recorded, if any.
+    pub(crate) fn last(&self) -> Result<Option<u64>, HistoryError> {
+        match self {
+            Self::Memory(blocks) => Ok(blocks.lock()?.keys().next_back().copied()),
+            #[cfg(feature = "persistent-history")]
+            Self::Persistent(history) => {
+                Ok(history.blocks.last()?.map(|(key, _)| key_to_number(&key)))
+            }
+        }
+    }
+
+    /// The number of the first recorded block that matches the `predicate`,
+    /// if any.
+    pub(crate) fn find(
+        &self,
+        predicate: impl Fn(u64) -> bool,
+    ) -> Result<Option<u64>, HistoryError> {
+        match self {
+            Self::Memory(blocks) => Ok(blocks
+                .lock()?
+                .keys()
+                .copied()
+                .find(|number| predicate(*number))),
+            #[cfg(feature = "persistent-history")]
+            Self::Persistent(history) => {
+                for key in history.blocks.iter().keys() {
+                    let number = key_to_number(&key?);
+                    if predicate(number) {
+                        return Ok(Some(number));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// The history of an [`Environment`] kept in an embedded
+/// [`sled`](https://docs.rs/sled) database so that it is not bound by memory.
+/// Block numbers are stored big-endian so that the trees keep them in order.
+#[cfg(feature = "persistent-history")]
+#[derive(Clone, Debug)]
+pub(crate) struct PersistentHistory {
+    /// The receipts keyed by the number of their block followed by their
+    /// index in the block.
+    receipts: sled::Tree,
+
+    /// The transactions and their receipts keyed by the hash of the
+    /// transaction.
+    transactions: sled::Tree,
+
+    /// The bloom filter of each block that has receipts.
+    blooms: sled::Tree,
+
+    /// The [`BlockRecord`] of each block.
+    blocks: sled::Tree,
+}
+
+#[cfg(feature = "persistent-history")]
+impl PersistentHistory {
+    /// Opens the database at `path`, creating it if it does not exist. The
+    /// history of an earlier run kept there is cleared so that it is not
+    /// mixed with the history of this one.
+    pub(crate) fn open(path: impl AsRef<std::path::Path>) -> Result<Self, HistoryError> {
+        let db = sled::open(path)?;
+        let open_tree = |name: &str| -> Result<sled::Tree, HistoryError> {
+            let tree = db.open_tree(name)?;
+            tree.clear()?;
+            Ok(tree)
+        };
+        Ok(Self {
+            receipts: open_tree("receipts")?,
+            transactions: open_tree("transactions")?,
+            blooms: open_tree("blooms")?,
+            blocks: open_tree("blocks")?,
+        })
+    }
+
+    /// Keeps a `transaction` and its `receipt` for the block with the given
+    /// `number`.
+    fn insert_receipt(
+        &self,
+        number: u64,
+        transaction: Transaction,
+        receipt: TransactionReceipt,
+    ) -> Result<(), HistoryError> {
+        let mut key = number.to_be_bytes().to_vec();
+        key.extend(receipt.transaction_index.as_u64().to_be_bytes());
+        self.receipts.insert(key, serde_json::to_vec(&receipt)?)?;
+        // Clients store their receipts concurrently, so the bloom of the block is
+        // updated atomically.
+        self.blooms
+            .update_and_fetch(number.to_be_bytes(), |bloom| {
+                let mut bloom = bloom.map(Bloom::from_slice).unwrap_or_default();
+                bloom.accrue_bloom(&receipt.logs_bloom);
+                Some(bloom.as_bytes().to_vec())
+            })?;
+        self.transactions.insert(
+            receipt.transaction_hash.as_bytes(),
+            serde_json::to_vec(&(transaction, receipt))?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Reads the block number a key of a [`PersistentHistory`] starts with.
+#[cfg(feature = "persistent-history")]
+fn key_to_number(key: &[u8]) -> u64 {
+    let mut number = [0; 8];
+    number.copy_from_slice(&key[..8]);
+    u64::from_be_bytes(number)
+}
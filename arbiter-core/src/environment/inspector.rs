@@ -0,0 +1,336 @@
+//! The [`ArbiterInspector`] is the [`Inspector`] every transaction in the
+//! [`Environment`] is run with. It collects the statistics the [`Profiler`]
+//! was set up to track, such as the use of precompiles or the gas used by
+//! each contract function, and hands every hook on to the inspectors given to
+//! [`EnvironmentBuilder::with_inspector`]. Calls to the precompiles given to
+//! [`EnvironmentBuilder::with_precompile`] are carried out here as well.
+
+#![warn(missing_docs)]
+
+use std::{collections::BTreeMap, sync::MutexGuard};
+
+use revm::{
+    interpreter::{CallInputs, CreateInputs, Gas, InstructionResult, Interpreter},
+    primitives::{Address, Bytes, B256},
+    EVMData, Inspector,
+};
+
+use super::*;
+
+/// The names of the precompiles by their address, as of the Cancun hard fork.
+const PRECOMPILES: [&str; 10] = [
+    "ecrecover",
+    "sha256",
+    "ripemd160",
+    "identity",
+    "modexp",
+    "ecadd",
+    "ecmul",
+    "ecpairing",
+    "blake2f",
+    "point_evaluation",
+];
+
+/// The name of the precompile at `address`, if there is one.
+fn precompile_name(address: &Address) -> Option<&'static str> {
+    let (prefix, last) = address.as_slice().split_at(19);
+    match last[0] {
+        index @ 1..=10 if prefix.iter().all(|byte| *byte == 0) => {
+            Some(PRECOMPILES[index as usize - 1])
+        }
+        _ => None,
+    }
+}
+
+/// How often a precompile was called by the transactions in an
+/// [`Environment`] and how much gas those calls used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrecompileUsage {
+    /// The number of calls to the precompile.
+    pub calls: u64,
+
+    /// The total gas used by the calls to the precompile.
+    pub gas: u64,
+}
+
+/// Alias for the usage of each precompile keyed by its name.
+pub(crate) type PrecompileRecord = Arc<Mutex<BTreeMap<String, PrecompileUsage>>>;
+
+/// A contract function identified by the address of the contract and the
+/// selector it was called with. Calls without a selector, i.e., to the
+/// `receive` or `fallback` function, have no selector.
+pub(crate) type FunctionKey = (Address, Option<[u8; 4]>);
+
+/// Alias for the gas used by every call to each contract function.
+pub(crate) type FunctionGasRecord = Arc<Mutex<BTreeMap<FunctionKey, Vec<u64>>>>;
+
+/// A user supplied [`Inspector`] that is run on every transaction in an
+/// [`Environment`].
+pub type BoxedInspector = Box<dyn Inspector<CacheDB<ExternalDb>> + Send>;
+
+/// The inspectors given to [`EnvironmentBuilder::with_inspector`], in the
+/// order they were given.
+#[derive(Clone, Default)]
+pub(crate) struct Inspectors(Arc<Mutex<Vec<BoxedInspector>>>);
+
+impl Inspectors {
+    /// Adds an inspector after the ones already held.
+    pub(crate) fn push(&mut self, inspector: BoxedInspector) {
+        self.0.lock().unwrap().push(inspector);
+    }
+
+    /// Locks the inspectors for the duration of a transaction.
+    pub(crate) fn lock(&self) -> Result<MutexGuard<'_, Vec<BoxedInspector>>, EnvironmentError> {
+        self.0
+            .lock()
+            .map_err(|e| EnvironmentError::Communication(e.to_string()))
+    }
+}
+
+impl Debug for Inspectors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let count = self.0.lock().map_or(0, |inspectors| inspectors.len());
+        f.debug_tuple("Inspectors").field(&count).finish()
+    }
+}
+
+/// Holds what the [`Environment`] was built to track about the execution of
+/// its transactions.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Profiler {
+    /// The usage of the precompiles, if it is tracked.
+    pub(crate) precompiles: Option<PrecompileRecord>,
+
+    /// The gas used by the calls to each contract function, if it is tracked.
+    pub(crate) functions: Option<FunctionGasRecord>,
+
+    /// The inspectors supplied by the user.
+    pub(crate) inspectors: Inspectors,
+
+    /// The precompiles supplied by the user.
+    pub(crate) custom_precompiles: CustomPrecompiles,
+}
+
+impl Profiler {
+    /// Creates an [`ArbiterInspector`] to run a transaction with that also
+    /// runs the user's `inspectors`, which are obtained with
+    /// [`Inspectors::lock`].
+    pub(crate) fn inspector<'a>(
+        &self,
+        inspectors: &'a mut [BoxedInspector],
+    ) -> ArbiterInspector<'a> {
+        ArbiterInspector {
+            inspectors,
+            custom_precompiles: self.custom_precompiles.clone(),
+            track_precompiles: self.precompiles.is_some(),
+            precompile_calls: vec![],
+            track_functions: self.functions.is_some(),
+            call_stack: vec![],
+            function_calls: vec![],
+        }
+    }
+
+    /// Adds what the `inspector` collected to the totals. This should only be
+    /// called for transactions that were committed.
+    pub(crate) fn record(&self, inspector: ArbiterInspector<'_>) -> Result<(), EnvironmentError> {
+        if let Some(precompiles) = &self.precompiles {
+            let mut precompiles = precompiles
+                .lock()
+                .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+            for (name, gas) in inspector.precompile_calls {
+                let usage = precompiles.entry(name.to_owned()).or_default();
+                usage.calls += 1;
+                usage.gas += gas;
+            }
+        }
+        if let Some(functions) = &self.functions {
+            let mut functions = functions
+                .lock()
+                .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+            for (function, gas) in inspector.function_calls {
+                functions.entry(function).or_default().push(gas);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collects statistics on the execution of a single transaction.
+pub(crate) struct ArbiterInspector<'a> {
+    /// The inspectors supplied by the user.
+    inspectors: &'a mut [BoxedInspector],
+
+    /// The precompiles supplied by the user, which calls to their address are
+    /// handed to.
+    custom_precompiles: CustomPrecompiles,
+
+    /// Whether calls to precompiles are collected.
+    track_precompiles: bool,
+
+    /// The name of each precompile called and the gas the call used.
+    precompile_calls: Vec<(&'static str, u64)>,
+
+    /// Whether the gas used by calls to contract functions is collected.
+    track_functions: bool,
+
+    /// The contract function of each call that has not ended yet, or `None`
+    /// for calls that are not collected.
+    call_stack: Vec<Option<FunctionKey>>,
+
+    /// The contract function of each call that ended and the gas the call
+    /// used, including the gas used by the calls it made.
+    function_calls: Vec<(FunctionKey, u64)>,
+}
+
+// Hooks that can stop execution return the first result a user inspector
+// gives other than `InstructionResult::Continue`, while the hooks that end a
+// call or creation pass the result through every user inspector in turn.
+impl Inspector<CacheDB<ExternalDb>> for &mut ArbiterInspector<'_> {
+    fn initialize_interp(
+        &mut self,
+        interp: &mut Interpreter,
+        data: &mut EVMData<'_, CacheDB<ExternalDb>>,
+    ) -> InstructionResult {
+        for inspector in self.inspectors.iter_mut() {
+            let result = inspector.initialize_interp(interp, data);
+            if result != InstructionResult::Continue {
+                return result;
+            }
+        }
+        InstructionResult::Continue
+    }
+
+    fn step(
+        &mut self,
+        interp: &mut Interpreter,
+        data: &mut EVMData<'_, CacheDB<ExternalDb>>,
+    ) -> InstructionResult {
+        for inspector in self.inspectors.iter_mut() {
+            let result = inspector.step(interp, data);
+            if result != InstructionResult::Continue {
+                return result;
+            }
+        }
+        InstructionResult::Continue
+    }
+
+    fn log(
+        &mut self,
+        evm_data: &mut EVMData<'_, CacheDB<ExternalDb>>,
+        address: &Address,
+        topics: &[B256],
+        data: &Bytes,
+    ) {
+        for inspector in self.inspectors.iter_mut() {
+            inspector.log(evm_data, address, topics, data);
+        }
+    }
+
+    fn step_end(
+        &mut self,
+        interp: &mut Interpreter,
+        data: &mut EVMData<'_, CacheDB<ExternalDb>>,
+    ) -> InstructionResult {
+        for inspector in self.inspectors.iter_mut() {
+            let result = inspector.step_end(interp, data);
+            if result != InstructionResult::Continue {
+                return result;
+            }
+        }
+        InstructionResult::Continue
+    }
+
+    fn call(
+        &mut self,
+        data: &mut EVMData<'_, CacheDB<ExternalDb>>,
+        inputs: &mut CallInputs,
+    ) -> (InstructionResult, Gas, Bytes) {
+        if self.track_functions {
+            let is_precompile = precompile_name(&inputs.contract).is_some()
+                || self.custom_precompiles.contains(&inputs.contract);
+            let function = match is_precompile {
+                true => None,
+                false => Some((
+                    inputs.contract,
+                    inputs
+                        .input
+                        .get(..4)
+                        .map(|selector| [selector[0], selector[1], selector[2], selector[3]]),
+                )),
+            };
+            self.call_stack.push(function);
+        }
+        for inspector in self.inspectors.iter_mut() {
+            let outcome = inspector.call(data, inputs);
+            if outcome.0 != InstructionResult::Continue {
+                return outcome;
+            }
+        }
+        if let Some(outcome) = self.custom_precompiles.call(inputs) {
+            return outcome;
+        }
+        (InstructionResult::Continue, Gas::new(0), Bytes::new())
+    }
+
+    fn call_end(
+        &mut self,
+        data: &mut EVMData<'_, CacheDB<ExternalDb>>,
+        inputs: &CallInputs,
+        remaining_gas: Gas,
+        ret: InstructionResult,
+        out: Bytes,
+    ) -> (InstructionResult, Gas, Bytes) {
+        let gas_used = inputs.gas_limit.saturating_sub(remaining_gas.remaining());
+        if let Some(Some(function)) = self.call_stack.pop() {
+            self.function_calls.push((function, gas_used));
+        }
+        if self.track_precompiles {
+            if let Some(name) = precompile_name(&inputs.contract) {
+                self.precompile_calls.push((name, gas_used));
+            }
+        }
+        self.inspectors.iter_mut().fold(
+            (ret, remaining_gas, out),
+            |(ret, remaining_gas, out), inspector| {
+                inspector.call_end(data, inputs, remaining_gas, ret, out)
+            },
+        )
+    }
+
+    fn create(
+        &mut self,
+        data: &mut EVMData<'_, CacheDB<ExternalDb>>,
+        inputs: &mut CreateInputs,
+    ) -> (InstructionResult, Option<Address>, Gas, Bytes) {
+        for inspector in self.inspectors.iter_mut() {
+            let outcome = inspector.create(data, inputs);
+            if outcome.0 != InstructionResult::Continue {
+                return outcome;
+            }
+        }
+        (InstructionResult::Continue, None, Gas::new(0), Bytes::new())
+    }
+
+    fn create_end(
+        &mut self,
+        data: &mut EVMData<'_, CacheDB<ExternalDb>>,
+        inputs: &CreateInputs,
+        ret: InstructionResult,
+        address: Option<Address>,
+        remaining_gas: Gas,
+        out: Bytes,
+    ) -> (InstructionResult, Option<Address>, Gas, Bytes) {
+        self.inspectors.iter_mut().fold(
+            (ret, address, remaining_gas, out),
+            |(ret, address, remaining_gas, out), inspector| {
+                inspector.create_end(data, inputs, ret, address, remaining_gas, out)
+            },
+        )
+    }
+
+    fn selfdestruct(&mut self, contract: Address, target: Address, value: U256) {
+        for inspector in self.inspectors.iter_mut() {
+            inspector.selfdestruct(contract, target, value);
+        }
+    }
+}
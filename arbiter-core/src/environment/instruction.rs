@@ -6,17 +6,21 @@ use super::*;
 /// [`Socket`].
 /// These instructions can be:
 /// - [`Instruction::AddAccount`],
+/// - [`Instruction::BatchCall`],
+/// - [`Instruction::BatchTransaction`],
 /// - [`Instruction::BlockUpdate`],
 /// - [`Instruction::Call`],
 /// - [`Instruction::Cheatcode`],
 /// - [`Instruction::Query`].
 /// - [`Instruction::SetGasPrice`],
+/// - [`Instruction::SimulateBundle`],
+/// - [`Instruction::Snapshot`],
 /// - [`Instruction::Stop`],
 /// - [`Instruction::Transaction`],
 
 /// The [`Instruction`]s are sent to the [`Environment`] via the
 /// [`Socket::instruction_sender`] and the results are received via the
-/// [`crate::middleware::Connection::outcome_receiver`].
+/// receiver of the [`OutcomeSender`] sent along with each of them.
 #[derive(Debug, Clone)]
 pub(crate) enum Instruction {
     /// An `AddAccount` is used to add a default/unfunded account to the
@@ -43,12 +47,41 @@ pub(crate) enum Instruction {
         outcome_sender: OutcomeSender,
     },
 
+    /// A `BatchCall` is a sequence of calls that are processed by the [`EVM`]
+    /// against the same state and answered with a single outcome. Like a
+    /// `Call`, none of them are state changing or create events.
+    BatchCall {
+        /// The transaction environments for the calls, in the order their
+        /// results are returned.
+        tx_envs: Vec<TxEnv>,
+
+        /// The sender used to to send the outcome of the calls back to.
+        outcome_sender: OutcomeSender,
+    },
+
+    /// A `BatchTransaction` is a sequence of transactions that are processed
+    /// by the [`EVM`] in order and answered with a single outcome. This saves
+    /// on the round trips through the channels when sending many transactions
+    /// at once.
+    BatchTransaction {
+        /// The transaction environments for the transactions, in the order they
+        /// will be executed.
+        tx_envs: Vec<TxEnv>,
+
+        /// The sender used to to send the outcome of the transactions back to.
+        outcome_sender: OutcomeSender,
+    },
+
     /// A `Call` is processed by the [`EVM`] but will not be state changing and
     /// will not create events.
     Call {
         /// The transaction environment for the call.
         tx_env: TxEnv,
 
+        /// Overrides of account state that the call is executed against. These
+        /// are applied to a temporary copy of the database and never persist.
+        state_overrides: Option<std::collections::HashMap<ethers::types::Address, spoof::Account>>,
+
         /// The sender used to to send the outcome of the call back to.
         outcome_sender: OutcomeSender,
     },
@@ -82,6 +115,24 @@ pub(crate) enum Instruction {
         outcome_sender: OutcomeSender,
     },
 
+    /// A `SimulateBundle` is a sequence of transactions that are processed by
+    /// the [`EVM`] in order against a temporary copy of the database. Each
+    /// transaction sees the state left by the ones before it, but none of them
+    /// are committed to the [`Environment`] and they create no events.
+    SimulateBundle {
+        /// The transaction environments for the transactions, in the order they
+        /// will be executed.
+        tx_envs: Vec<TxEnv>,
+
+        /// The sender used to to send the outcome of the bundle back to.
+        outcome_sender: OutcomeSender,
+    },
+
+    /// A `Snapshot` is used to copy the database and the current block of the
+    /// [`EVM`] so that another [`Environment`] can be started from them. The
+    /// copy is sent back over the given sender instead of as an [`Outcome`].
+    Snapshot(SnapshotSender),
+
     /// A `Stop` is used to stop the [`Environment`].
     Stop(OutcomeSender),
 
@@ -96,6 +147,75 @@ pub(crate) enum Instruction {
     },
 }
 
+impl Instruction {
+    /// The [`InstructionKind`] of this [`Instruction`].
+    pub(crate) fn kind(&self) -> InstructionKind {
+        match self {
+            Instruction::AddAccount { .. } => InstructionKind::AddAccount,
+            Instruction::BlockUpdate { .. } => InstructionKind::BlockUpdate,
+            Instruction::BatchCall { tx_envs, .. } => InstructionKind::BatchCall(tx_envs.len()),
+            Instruction::BatchTransaction { tx_envs, .. } => {
+                InstructionKind::BatchTransaction(tx_envs.len())
+            }
+            Instruction::Call { .. } => InstructionKind::Call,
+            Instruction::Cheatcode { .. } => InstructionKind::Cheatcode,
+            Instruction::Query { .. } => InstructionKind::Query,
+            Instruction::SetGasPrice { .. } => InstructionKind::SetGasPrice,
+            Instruction::SimulateBundle { tx_envs, .. } => {
+                InstructionKind::SimulateBundle(tx_envs.len())
+            }
+            Instruction::Snapshot(_) => InstructionKind::Snapshot,
+            Instruction::Stop(_) => InstructionKind::Stop,
+            Instruction::Transaction { .. } => InstructionKind::Transaction,
+        }
+    }
+}
+
+/// The kind of an [`Instruction`] received by the [`Environment`] without any
+/// of its contents. These are what the [`Environment`] records when built
+/// with [`EnvironmentBuilder::record_instructions`] so that tests can assert
+/// on the sequence of instructions a piece of code emits.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum InstructionKind {
+    /// An [`Instruction::AddAccount`].
+    AddAccount,
+
+    /// An [`Instruction::BlockUpdate`].
+    BlockUpdate,
+
+    /// An [`Instruction::BatchCall`] holding the given number of calls.
+    BatchCall(usize),
+
+    /// An [`Instruction::BatchTransaction`] holding the given number of
+    /// transactions.
+    BatchTransaction(usize),
+
+    /// An [`Instruction::Call`].
+    Call,
+
+    /// An [`Instruction::Cheatcode`].
+    Cheatcode,
+
+    /// An [`Instruction::Query`].
+    Query,
+
+    /// An [`Instruction::SetGasPrice`].
+    SetGasPrice,
+
+    /// An [`Instruction::SimulateBundle`] holding the given number of
+    /// transactions.
+    SimulateBundle(usize),
+
+    /// An [`Instruction::Snapshot`].
+    Snapshot,
+
+    /// An [`Instruction::Stop`].
+    Stop,
+
+    /// An [`Instruction::Transaction`].
+    Transaction,
+}
+
 /// [`Outcome`]s that can be sent back to the the client via the
 /// [`Socket`].
 /// These outcomes can be from `Call`, `Transaction`, or `BlockUpdate`
@@ -119,6 +239,10 @@ pub(crate) enum Outcome {
     /// of some [`EVM`] computation to the client.
     CallCompleted(ExecutionResult),
 
+    /// The outcome of a `BatchCall` instruction that carries the
+    /// [`ExecutionResult`] of each call in the order they were sent.
+    BatchCallCompleted(Vec<ExecutionResult>),
+
     /// The outcome of a [`Instruction::SetGasPrice`] instruction that is used
     /// to signify that the gas price was set successfully.
     SetGasPriceCompleted,
@@ -128,6 +252,24 @@ pub(crate) enum Outcome {
     /// `TransactionReceipt` in the `Middleware`.
     TransactionCompleted(ExecutionResult, ReceiptData),
 
+    /// The outcome of a `BatchTransaction` instruction that carries the
+    /// [`ExecutionResult`] of each transaction in the order they were sent.
+    BatchTransactionCompleted(Vec<ExecutionResult>),
+
+    /// The outcome of a `SimulateBundle` instruction that carries the
+    /// [`ExecutionResult`] of each transaction in the order they were sent,
+    /// along with the ether balance of the sender before and after the bundle.
+    SimulateBundleCompleted {
+        /// The result of each transaction.
+        execution_results: Vec<ExecutionResult>,
+
+        /// The balance of the sender before the first transaction.
+        balance_before: U256,
+
+        /// The balance of the sender after the last transaction.
+        balance_after: U256,
+    },
+
     /// The outcome of a `Query` instruction that carries a `String`
     /// representation of the data. Currently this may carry the block
     /// number, block timestamp, gas price, or balance of an account.
@@ -161,7 +303,8 @@ pub(crate) enum EnvironmentData {
 }
 
 /// [`ReceiptData`] is a structure that holds the block number, transaction
-/// index, and cumulative gas used per block for a transaction.
+/// index, sender nonce, cumulative gas used per block, and L1 data fee for a
+/// transaction.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ReceiptData {
     /// `block_number` is the number of the block in which the transaction was
@@ -170,7 +313,13 @@ pub struct ReceiptData {
     /// `transaction_index` is the index position of the transaction in the
     /// block.
     pub(crate) transaction_index: U64,
+    /// `nonce` is the nonce of the sender that the transaction used.
+    #[serde(default)]
+    pub(crate) nonce: u64,
     /// [`cumulative_gas_per_block`] is the total amount of gas used in the
     /// block up until and including the transaction.
     pub(crate) cumulative_gas_per_block: U256,
+    /// `l1_fee` is the L1 data fee charged for the transaction when the
+    /// [`Environment`] was built with one.
+    pub(crate) l1_fee: Option<L1DataFee>,
 }
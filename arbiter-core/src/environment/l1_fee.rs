@@ -0,0 +1,213 @@
+//! The `l1_fee` module models the L1 data fee that rollups charge on top of
+//! execution gas for posting a transaction's data to L1.
+//!
+//! An [`Environment`] built with [`EnvironmentBuilder::l1_fee`] charges every
+//! transaction the fee given by its [`L1FeeSettings`] from the sender's
+//! balance after the transaction is executed. The fee is burned. It is
+//! reported on the transaction's receipt under the `l1Fee`, `l1GasUsed`, and
+//! `l1GasPrice` fields used by OP Stack chains, which can be read with
+//! [`L1DataFee::from_receipt`].
+
+#![warn(missing_docs)]
+
+use ethers::types::{OtherFields, TransactionReceipt};
+
+use super::*;
+
+/// The L1 gas charged per byte of compressed data.
+const L1_GAS_PER_BYTE: u64 = 16;
+
+/// The L1 gas charged per zero byte of uncompressed calldata, following
+/// EIP-2028.
+const L1_GAS_PER_ZERO_BYTE: u64 = 4;
+
+/// How the size of a transaction's data is estimated before it is priced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CalldataCompression {
+    /// The data is priced as L1 calldata, i.e., 16 gas per non-zero byte and
+    /// 4 gas per zero byte. This is how OP Stack chains priced data before
+    /// the Fjord upgrade.
+    #[default]
+    None,
+
+    /// The data is priced at 16 gas per byte of its FastLZ compressed size,
+    /// which approximates chains that post compressed batches.
+    FastLz,
+}
+
+/// The configuration of the L1 data fee charged by an [`Environment`].
+///
+/// The fee of a transaction is `(l1_gas + overhead) * l1_gas_price * scalar`
+/// where `l1_gas` is the L1 gas of its data under the chosen
+/// [`CalldataCompression`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct L1FeeSettings {
+    /// The price of L1 gas in wei.
+    pub l1_gas_price: u128,
+
+    /// Fixed L1 gas added to every transaction, e.g., for its signature and
+    /// envelope.
+    pub overhead: u64,
+
+    /// A multiplier applied to the fee.
+    pub scalar: f64,
+
+    /// How the size of the data is estimated.
+    pub compression: CalldataCompression,
+}
+
+impl Default for L1FeeSettings {
+    fn default() -> Self {
+        Self {
+            l1_gas_price: 0,
+            overhead: 0,
+            scalar: 1.0,
+            compression: CalldataCompression::default(),
+        }
+    }
+}
+
+impl L1FeeSettings {
+    /// The L1 gas used to post `data`, including the overhead.
+    pub fn l1_gas_used(&self, data: &[u8]) -> u64 {
+        let l1_gas = match self.compression {
+            CalldataCompression::None => data
+                .iter()
+                .map(|byte| match byte {
+                    0 => L1_GAS_PER_ZERO_BYTE,
+                    _ => L1_GAS_PER_BYTE,
+                })
+                .sum(),
+            CalldataCompression::FastLz => flz_compress_len(data) as u64 * L1_GAS_PER_BYTE,
+        };
+        l1_gas + self.overhead
+    }
+
+    /// The L1 data fee charged for a transaction with the given `data`.
+    pub fn data_fee(&self, data: &[u8]) -> L1DataFee {
+        let l1_gas_used = self.l1_gas_used(data);
+        L1DataFee {
+            l1_gas_used,
+            l1_gas_price: self.l1_gas_price,
+            fee: (l1_gas_used as f64 * self.l1_gas_price as f64 * self.scalar) as u128,
+        }
+    }
+}
+
+/// The L1 data fee charged for a single transaction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct L1DataFee {
+    /// The L1 gas used to post the transaction's data.
+    pub l1_gas_used: u64,
+
+    /// The price of L1 gas in wei the fee was charged at.
+    pub l1_gas_price: u128,
+
+    /// The fee in wei.
+    pub fee: u128,
+}
+
+impl L1DataFee {
+    /// The fields of a [`TransactionReceipt`] that report this fee.
+    pub(crate) fn receipt_fields(&self) -> OtherFields {
+        let mut fields = OtherFields::default();
+        for (key, value) in [
+            ("l1Fee", ethers::types::U256::from(self.fee)),
+            ("l1GasUsed", ethers::types::U256::from(self.l1_gas_used)),
+            ("l1GasPrice", ethers::types::U256::from(self.l1_gas_price)),
+        ] {
+            fields.insert(key.to_string(), serde_json::json!(value));
+        }
+        fields
+    }
+
+    /// Reads the fee off a `receipt` from an [`Environment`] that charges
+    /// one. Returns `None` if the receipt does not report an L1 data fee.
+    pub fn from_receipt(receipt: &TransactionReceipt) -> Option<Self> {
+        let field = |key| {
+            receipt
+                .other
+                .get_deserialized::<ethers::types::U256>(key)?
+                .ok()
+        };
+        Some(Self {
+            l1_gas_used: field("l1GasUsed")?.as_u64(),
+            l1_gas_price: field("l1GasPrice")?.as_u128(),
+            fee: field("l1Fee")?.as_u128(),
+        })
+    }
+}
+
+/// The size of `data` after FastLZ (level 1) compression, without compressing
+/// it. This is a port of the estimate OP Stack chains use since the Fjord
+/// upgrade.
+pub fn flz_compress_len(data: &[u8]) -> usize {
+    let u24 = |index: usize| {
+        u32::from(data[index]) | u32::from(data[index + 1]) << 8 | u32::from(data[index + 2]) << 16
+    };
+    let hash = |value: u32| (2654435769u32.wrapping_mul(value) >> 19) as usize & 0x1fff;
+    let literals = |length: usize| {
+        0x21 * (length / 0x20)
+            + match length % 0x20 {
+                0 => 0,
+                rest => rest + 1,
+            }
+    };
+    let matched = |length: usize| {
+        let length = length - 1;
+        3 * (length / 262) + if length % 262 >= 6 { 3 } else { 2 }
+    };
+
+    let mut size = 0;
+    let mut table = vec![0usize; 8192];
+    let mut anchor = 0;
+    let limit = data.len().saturating_sub(13);
+    let mut index = anchor + 2;
+    while index < limit {
+        let mut reference;
+        loop {
+            let sequence = u24(index);
+            let slot = hash(sequence);
+            reference = table[slot];
+            table[slot] = index;
+            let distance = index - reference;
+            if index >= limit {
+                break;
+            }
+            index += 1;
+            if distance <= 0x1fff && sequence == u24(reference) {
+                break;
+            }
+        }
+        if index >= limit {
+            break;
+        }
+        index -= 1;
+        if index > anchor {
+            size += literals(index - anchor);
+        }
+        let length = common_length(data, reference + 3, index + 3, limit + 9);
+        size += matched(length);
+        index += length;
+        for _ in 0..2 {
+            table[hash(u24(index))] = index;
+            index += 1;
+        }
+        anchor = index;
+    }
+    size + literals(data.len() - anchor)
+}
+
+/// The length of the match between the data at `reference` and at `index`,
+/// scanning no further than `end`.
+fn common_length(data: &[u8], reference: usize, index: usize, end: usize) -> usize {
+    let mut length = 0;
+    let mut end = end - index;
+    while length < end {
+        if data[reference + length] != data[index + length] {
+            end = 0;
+        }
+        length += 1;
+    }
+    length
+}
@@ -22,6 +22,8 @@
 //! - `EnvironmentError`: Enum indicating the type of error that can be thrown
 //!  by the EVM.
 //! - `State`: Enum indicating the current state of the environment.
+//! - `Executor`: Carries out the instructions sent to an `Environment` against
+//!   its EVM, either on a thread of its own or driven by the caller.
 //! - `Socket`: Provides channels for communication between the EVM and the
 //!   outside world.
 //! - `EventBroadcaster`: Responsible for broadcasting Ethereum logs to
@@ -30,20 +32,21 @@
 #![warn(missing_docs, unsafe_code)]
 
 use std::{
-    convert::Infallible,
+    collections::BTreeMap,
     fmt::Debug,
     sync::{Arc, Mutex},
     thread::{self, JoinHandle},
 };
 
 use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
-use ethers::core::types::U64;
+use ethers::core::types::{spoof, U64};
 use revm::{
-    db::{CacheDB, EmptyDB},
+    db::{AccountState, CacheDB, EmptyDB},
     primitives::{
-        AccountInfo, EVMError, ExecutionResult, HashMap, InvalidTransaction, Log, TxEnv, U256,
+        AccountInfo, BlockEnv, Bytecode, EVMError, ExecutionResult, HashMap, InvalidTransaction,
+        Log, ResultAndState, SpecId, TxEnv, U256,
     },
-    EVM,
+    Database, DatabaseCommit, EVM,
 };
 // use hashbrown::{hash_map, HashMap as HashMapBrown};
 use serde::{Deserialize, Serialize};
@@ -60,16 +63,47 @@ pub mod cheatcodes;
 use cheatcodes::*;
 
 pub(crate) mod instruction;
+pub use instruction::InstructionKind;
 use instruction::*;
 
 pub mod errors;
 use errors::*;
 
+pub(crate) mod inspector;
+use inspector::*;
+pub use inspector::{BoxedInspector, PrecompileUsage};
+
+pub mod gas_report;
+use gas_report::GasReport;
+
+pub mod l1_fee;
+use l1_fee::*;
+
+pub mod precompile;
+use precompile::CustomPrecompiles;
+
+pub(crate) mod history;
+use history::{BlockRecord, BlockStore, ReceiptStore};
+
+pub mod abi_registry;
+use abi_registry::AbiRegistry;
+
+pub mod state_diff;
+use state_diff::{PendingStateDiff, StateDiffStore};
+
+pub mod trace;
+
+pub mod anvil;
+
 pub mod fork;
+use fork::ExternalDb;
 
 pub mod builder;
 use builder::*;
 
+pub mod executor;
+use executor::Executor;
+
 #[cfg(test)]
 pub(crate) mod tests;
 
@@ -80,16 +114,17 @@ pub(crate) type InstructionSender = Sender<Instruction>;
 pub(crate) type InstructionReceiver = Receiver<Instruction>;
 
 /// Alias for the sender of the channel for transmitting [`RevmResult`] emitted
-/// from transactions.
+/// from transactions. A new channel is made for every [`Instruction`] so that
+/// its [`Outcome`] can only be received by whoever sent it.
 pub(crate) type OutcomeSender = Sender<Result<Outcome, EnvironmentError>>;
 
-/// Alias for the receiver of the channel for transmitting [`RevmResult`]
-/// emitted from transactions.
-pub(crate) type OutcomeReceiver = Receiver<Result<Outcome, EnvironmentError>>;
+/// Alias for the sender of the copy of the database and current block of an
+/// [`Environment`] that it is branched from.
+pub(crate) type SnapshotSender = Sender<(CacheDB<ExternalDb>, BlockEnv)>;
 
 /// Alias for the sender used in the [`EventBroadcaster`] that transmits
-/// contract events via [`Log`].
-pub(crate) type EventSender = Sender<Vec<Log>>;
+/// contract events via [`Log`] and new blocks via [`Broadcast`].
+pub(crate) type EventSender = tokio::sync::mpsc::UnboundedSender<Broadcast>;
 
 /// Represents a sandboxed EVM environment.
 ///
@@ -126,7 +161,10 @@ pub struct Environment {
 
     /// The [`EVM`] that is used as an execution environment and database for
     /// calls and transactions.
-    db: Option<CacheDB<EmptyDB>>,
+    db: Option<CacheDB<ExternalDb>>,
+
+    /// The block the [`Environment`] starts at, if it is not the default one.
+    pub(crate) block: Option<BlockEnv>,
 
     /// This gives a means of letting the "outside world" connect to the
     /// [`Environment`] so that users (or agents) may send and receive data from
@@ -137,6 +175,14 @@ pub struct Environment {
     /// Used for assuring that the environment is stopped properly or for
     /// performing any blocking action the end user needs.
     pub(crate) handle: Option<JoinHandle<Result<(), EnvironmentError>>>,
+
+    /// The kinds of the instructions received so far, in order, when the
+    /// [`Environment`] was built to record them.
+    pub(crate) instruction_record: Option<Arc<Mutex<Vec<InstructionKind>>>>,
+
+    /// What the [`Environment`] was built to track about the execution of its
+    /// transactions.
+    pub(crate) profiler: Profiler,
 }
 
 /// Allow the end user to be able to access a debug printout for the
@@ -158,440 +204,215 @@ impl Environment {
     /// [`Manager`].
     pub(crate) fn new(
         environment_parameters: EnvironmentParameters,
-        db: Option<CacheDB<EmptyDB>>,
+        db: Option<CacheDB<ExternalDb>>,
     ) -> Self {
         let (instruction_sender, instruction_receiver) = unbounded();
         let socket = Socket {
             instruction_sender: Arc::new(instruction_sender),
             instruction_receiver,
             event_broadcaster: Arc::new(Mutex::new(EventBroadcaster::new())),
+            receipts: None,
+            blocks: BlockStore::default(),
+            abi_registry: AbiRegistry::new(),
+            state_diffs: None,
         };
 
         Self {
             parameters: environment_parameters,
             db,
+            block: None,
             socket,
             handle: None,
+            instruction_record: None,
+            profiler: Profiler::default(),
         }
     }
 
-    /// The [`EVM`] will be
-    /// offloaded onto a separate thread for processing.
+    /// The [`Executor`] of the [`Environment`] is offloaded onto a separate
+    /// thread for processing.
     /// Calls, transactions, and events will enter/exit through the `Socket`.
     pub(crate) fn run(&mut self) {
-        // Initialize the EVM used
-        let mut evm = EVM::new();
+        let executor = Executor::new(self);
+        self.handle = Some(thread::spawn(move || executor.run()));
+    }
 
-        if self.db.is_some() {
-            evm.database(self.db.take().unwrap());
-        } else {
-            evm.database(CacheDB::new(EmptyDB::new()));
-        };
+    /// The hardfork the [`Environment`] executes transactions under. This is
+    /// the one given to [`EnvironmentBuilder::spec_id`] and otherwise the
+    /// latest one `revm` supports.
+    pub fn spec_id(&self) -> SpecId {
+        self.parameters.spec_id.unwrap_or(SpecId::LATEST)
+    }
 
-        // Choose extra large code size and gas limit
-        evm.env.cfg.limit_contract_code_size = Some(0x100000);
-        evm.env.block.gas_limit = U256::MAX;
-
-        // Pull clones of the relevant data prepare to send into a new thread
-        let instruction_receiver = self.socket.instruction_receiver.clone();
-        let event_broadcaster = self.socket.event_broadcaster.clone();
-        let block_type = self.parameters.block_settings.clone();
-        let seeded_poisson = match block_type {
-            BlockSettings::RandomlySampled {
-                block_rate,
-                block_time,
-                seed,
-            } => Some(Arc::new(Mutex::new(SeededPoisson::new(
-                block_rate, block_time, seed,
-            )))),
-            BlockSettings::UserControlled => None,
-        };
-        let gas_settings = self.parameters.gas_settings.clone();
-        // let transaction_counts = self.transaction_counts.clone();
-
-        // Move the EVM and its socket to a new thread and retrieve this handle
-        let handle = thread::spawn(move || {
-            if let GasSettings::RandomlySampled { multiplier: _ } = gas_settings {
-                if seeded_poisson.is_none() {
-                    return Err(EnvironmentError::NotRandomlySampledBlockSettings);
-                }
-            }
-            // Get the first amount of transactions per block from the distribution and set
-            // the initial counter.
-            let mut transactions_per_block = seeded_poisson
-                .clone()
-                .map(|distribution| distribution.lock().unwrap().sample());
-            match gas_settings {
-                GasSettings::UserControlled => {
-                    evm.env.tx.gas_price = U256::from(0);
-                }
-                GasSettings::RandomlySampled { multiplier } => {
-                    let gas_price = (transactions_per_block
-                        .ok_or(EnvironmentError::NotRandomlySampledBlockSettings)?
-                        as f64)
-                        * multiplier;
-                    evm.env.tx.gas_price = U256::from(gas_price as u128);
-                }
-                GasSettings::Constant(gas_price) => {
-                    evm.env.tx.gas_price = U256::from(gas_price);
-                }
-            }
-            let mut transaction_index: usize = 0;
-            let mut cumulative_gas_per_block: U256 = U256::ZERO;
-
-            // Loop over the reception of calls/transactions sent through the socket
-            // The outermost check is to find what the `Environment`'s state is in
-            while let Ok(instruction) = instruction_receiver.recv() {
-                match instruction {
-                    Instruction::AddAccount {
-                        address,
-                        outcome_sender,
-                    } => {
-                        let db = evm.db.as_mut().unwrap();
-                        let recast_address =
-                            revm::primitives::Address::from(address.as_fixed_bytes());
-                        let account = revm::db::DbAccount {
-                            info: AccountInfo::default(),
-                            account_state: revm::db::AccountState::None,
-                            storage: HashMap::new(),
-                        };
-                        match db.accounts.insert(recast_address, account) {
-                            None => {
-                                outcome_sender
-                                    .send(Ok(Outcome::AddAccountCompleted))
-                                    .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
-                            }
-                            Some(_) => {
-                                outcome_sender
-                                    .send(Err(EnvironmentError::Account(
-                                        "Account already exists!".to_string(),
-                                    )))
-                                    .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
-                            }
-                        }
-                    }
-                    Instruction::BlockUpdate {
-                        block_number,
-                        block_timestamp,
-                        outcome_sender,
-                    } => {
-                        if block_type != BlockSettings::UserControlled {
-                            outcome_sender
-                                .send(Err(EnvironmentError::NotUserControlledBlockSettings))
-                                .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
-                        }
-                        // Update the block number and timestamp
-                        evm.env.block.number = block_number;
-                        evm.env.block.timestamp = block_timestamp;
-                        transaction_index = 0;
-                        cumulative_gas_per_block = U256::ZERO;
-
-                        let receipt_data = ReceiptData {
-                            block_number: convert_uint_to_u64(evm.env.block.number).unwrap(),
-                            transaction_index: U64::from(0), /* replace with actual
-                                                              * value */
-                            cumulative_gas_per_block: U256::from(0),
-                        };
-                        outcome_sender
-                            .send(Ok(Outcome::BlockUpdateCompleted(receipt_data)))
-                            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
-                    }
-                    Instruction::Cheatcode {
-                        cheatcode,
-                        outcome_sender,
-                    } => match cheatcode {
-                        Cheatcodes::Load {
-                            account,
-                            key,
-                            block: _,
-                        } => {
-                            // Get the underlying database.
-                            let db = evm.db.as_mut().unwrap();
-
-                            // Cast the ethers-rs cheatcode arguments into revm types.
-                            let recast_address =
-                                revm::primitives::Address::from(account.as_fixed_bytes());
-                            let recast_key = revm::primitives::B256::from(key.as_fixed_bytes());
-
-                            // Get the account storage value at the key in the db.
-                            match db.accounts.get_mut(&recast_address) {
-                                Some(account) => {
-                                    // Returns zero if the account is missing.
-                                    let value: revm::primitives::U256 = match account
-                                        .storage
-                                        .get::<revm::primitives::U256>(
-                                        &recast_key.into(),
-                                    ) {
-                                        Some(value) => *value,
-                                        None => revm::primitives::U256::ZERO,
-                                    };
-
-                                    // Sends the revm::primitives::U256 storage value back to the
-                                    // sender via CheatcodeReturn(revm::primitives::U256).
-                                    outcome_sender
-                                        .send(Ok(Outcome::CheatcodeReturn(
-                                            CheatcodesReturn::Load { value },
-                                        )))
-                                        .map_err(|e| {
-                                            EnvironmentError::Communication(e.to_string())
-                                        })?;
-                                }
-                                None => {
-                                    outcome_sender
-                                        .send(Err(EnvironmentError::Account(
-                                            "Account is missing!".to_string(),
-                                        )))
-                                        .map_err(|e| {
-                                            EnvironmentError::Communication(e.to_string())
-                                        })?;
-                                }
-                            };
-                        }
-                        Cheatcodes::Store {
-                            account,
-                            key,
-                            value,
-                        } => {
-                            // Get the underlying database
-                            let db = evm.db.as_mut().unwrap();
-
-                            // Cast the ethers-rs types passed in the cheatcode arguments into revm
-                            // primitive types
-                            let recast_address =
-                                revm::primitives::Address::from(account.as_fixed_bytes());
-                            let recast_key = revm::primitives::B256::from(key.as_fixed_bytes());
-                            let recast_value = revm::primitives::B256::from(value.as_fixed_bytes());
-
-                            // Mutate the db by inserting the new key-value pair into the account's
-                            // storage and send the successful
-                            // CheatcodeCompleted outcome.
-                            match db.accounts.get_mut(&recast_address) {
-                                Some(account) => {
-                                    account
-                                        .storage
-                                        .insert(recast_key.into(), recast_value.into());
-
-                                    outcome_sender
-                                        .send(Ok(Outcome::CheatcodeReturn(CheatcodesReturn::Store)))
-                                        .map_err(|e| {
-                                            EnvironmentError::Communication(e.to_string())
-                                        })?;
-                                }
-                                None => {
-                                    outcome_sender
-                                        .send(Err(EnvironmentError::Account(
-                                            "Account is missing!".to_string(),
-                                        )))
-                                        .map_err(|e| {
-                                            EnvironmentError::Communication(e.to_string())
-                                        })?;
-                                }
-                            };
-                        }
-                        Cheatcodes::Deal { address, amount } => {
-                            let db = evm.db.as_mut().unwrap();
-                            let recast_address =
-                                revm::primitives::Address::from(address.as_fixed_bytes());
-                            match db.accounts.get_mut(&recast_address) {
-                                Some(account) => {
-                                    account.info.balance += U256::from_limbs(amount.0);
-                                    outcome_sender
-                                        .send(Ok(Outcome::CheatcodeReturn(CheatcodesReturn::Deal)))
-                                        .map_err(|e| {
-                                            EnvironmentError::Communication(e.to_string())
-                                        })?;
-                                }
-                                None => {
-                                    outcome_sender
-                                        .send(Err(EnvironmentError::Account(
-                                            "Account is missing!".to_string(),
-                                        )))
-                                        .map_err(|e| {
-                                            EnvironmentError::Communication(e.to_string())
-                                        })?;
-                                }
-                            };
-                        }
-                    },
-                    // A `Call` is not state changing and will not create events.
-                    Instruction::Call {
-                        tx_env,
-                        outcome_sender,
-                    } => {
-                        // Set the tx_env and prepare to process it
-                        evm.env.tx = tx_env;
-
-                        let result = evm.transact()?.result;
-                        outcome_sender
-                            .send(Ok(Outcome::CallCompleted(result)))
-                            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
-                    }
-                    Instruction::SetGasPrice {
-                        gas_price,
-                        outcome_sender,
-                    } => {
-                        if GasSettings::UserControlled != gas_settings {
-                            outcome_sender
-                                .send(Err(EnvironmentError::NotUserControlledGasSettings))
-                                .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
-                        }
-                        evm.env.tx.gas_price = U256::from_limbs(gas_price.0);
-                        outcome_sender
-                            .send(Ok(Outcome::SetGasPriceCompleted))
-                            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
-                    }
-
-                    // A `Transaction` is state changing and will create events.
-                    Instruction::Transaction {
-                        tx_env,
-                        outcome_sender,
-                    } => {
-                        // Set the tx_env and prepare to process it
-                        evm.env.tx = tx_env;
-
-                        let execution_result =
-                            match evm.inspect_commit(revm::inspectors::GasInspector::default()) {
-                                Ok(result) => result,
-                                Err(e) => {
-                                    if let EVMError::Transaction(invalid_transaction) = e {
-                                        outcome_sender
-                                            .send(Err(EnvironmentError::Transaction(
-                                                invalid_transaction,
-                                            )))
-                                            .map_err(|e| {
-                                                EnvironmentError::Communication(e.to_string())
-                                            })?;
-                                        continue;
-                                    } else {
-                                        outcome_sender
-                                            .send(Err(EnvironmentError::Execution(e)))
-                                            .map_err(|e| {
-                                                EnvironmentError::Communication(e.to_string())
-                                            })?;
-                                        continue;
-                                    }
-                                }
-                            };
-                        let block_number = convert_uint_to_u64(evm.env.block.number)?;
-
-                        // increment cumulative gas per block
-                        cumulative_gas_per_block += U256::from(execution_result.clone().gas_used());
-
-                        // update transaction count for sender
-
-                        let event_broadcaster = event_broadcaster
-                            .lock()
-                            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
-                        let receipt_data = ReceiptData {
-                            block_number,
-                            transaction_index: transaction_index.into(),
-                            cumulative_gas_per_block,
-                        };
-                        event_broadcaster.broadcast(execution_result.logs())?;
-                        outcome_sender
-                            .send(Ok(Outcome::TransactionCompleted(
-                                execution_result,
-                                receipt_data,
-                            )))
-                            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
-                        transaction_index += 1;
-
-                        // Check whether we need to increment the block number given the
-                        // amount of transactions
-                        // that have occurred on the current block and increment
-                        // if need be and draw a new sample from the `SeededPoisson`
-                        // distribution. Only do so if there is a distribution in the
-                        // first place.
-                        if transactions_per_block.is_some_and(|x| x == transaction_index) {
-                            transaction_index = 0;
-                            evm.env.block.number += U256::from(1);
-
-                            // This unwrap cannot fail.
-                            let seeded_poisson_clone = seeded_poisson.clone().unwrap();
-                            let mut seeded_poisson_lock = seeded_poisson_clone.lock().unwrap();
-
-                            evm.env.block.timestamp += U256::from(seeded_poisson_lock.time_step);
-                            transactions_per_block = loop {
-                                let sample = Some(seeded_poisson_lock.sample());
-
-                                if sample == Some(0) {
-                                    evm.env.block.number += U256::from(1);
-                                    continue;
-                                } else {
-                                    break sample;
-                                }
-                            };
-                            if let GasSettings::RandomlySampled { multiplier } = gas_settings {
-                                let gas_price = (transactions_per_block
-                                    .ok_or(EnvironmentError::NotRandomlySampledBlockSettings)?
-                                    as f64)
-                                    * multiplier;
-                                evm.env.tx.gas_price = U256::from(gas_price as u128);
-                            };
-                        }
-                    }
-                    Instruction::Query {
-                        environment_data,
-                        outcome_sender,
-                    } => {
-                        let outcome = match environment_data {
-                            EnvironmentData::BlockNumber => {
-                                Ok(Outcome::QueryReturn(evm.env.block.number.to_string()))
-                            }
-                            EnvironmentData::BlockTimestamp => {
-                                Ok(Outcome::QueryReturn(evm.env.block.timestamp.to_string()))
-                            }
-                            EnvironmentData::GasPrice => {
-                                Ok(Outcome::QueryReturn(evm.env.tx.gas_price.to_string()))
-                            }
-                            EnvironmentData::Balance(address) => {
-                                // This unwrap should never fail.
-                                let db = evm.db().unwrap();
-                                match db.accounts.get::<revm::primitives::Address>(
-                                    &address.as_fixed_bytes().into(),
-                                ) {
-                                    Some(account) => {
-                                        Ok(Outcome::QueryReturn(account.info.balance.to_string()))
-                                    }
-                                    None => Err(EnvironmentError::Account(
-                                        "Account is missing!".to_string(),
-                                    )),
-                                }
-                            }
-
-                            EnvironmentData::TransactionCount(address) => {
-                                let db = evm.db().unwrap();
-                                match db.accounts.get::<revm::primitives::Address>(
-                                    &address.as_fixed_bytes().into(),
-                                ) {
-                                    Some(account) => {
-                                        Ok(Outcome::QueryReturn(account.info.nonce.to_string()))
-                                    }
-                                    None => Err(EnvironmentError::Account(
-                                        "Account is missing!".to_string(),
-                                    )),
-                                }
-                            }
-                        };
-                        outcome_sender
-                            .send(outcome)
-                            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
-                    }
-                    Instruction::Stop(outcome_sender) => {
-                        outcome_sender
-                            .send(Ok(Outcome::StopCompleted))
-                            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
-                        break;
-                    }
-                }
+    /// The chain ID of the [`Environment`]. This is the one given to
+    /// [`EnvironmentBuilder::chain_id`] and otherwise mainnet's.
+    pub fn chain_id(&self) -> u64 {
+        self.parameters.chain_id.unwrap_or(1)
+    }
+
+    /// The seed used for the randomness in the [`Environment`], if there is
+    /// any. This is the seed given to the [`EnvironmentBuilder`] if one was
+    /// set and otherwise the seed of [`BlockSettings::RandomlySampled`].
+    pub fn seed(&self) -> Option<u64> {
+        match self.parameters.block_settings {
+            BlockSettings::RandomlySampled { seed, .. } => {
+                Some(self.parameters.seed.unwrap_or(seed))
             }
-            Ok(())
-        });
-        self.handle = Some(handle);
+            BlockSettings::UserControlled => self.parameters.seed,
+        }
+    }
+
+    /// The kinds of the instructions the [`Environment`] has received so far,
+    /// in order. This is empty unless the [`Environment`] was built with
+    /// [`EnvironmentBuilder::record_instructions`].
+    pub fn recorded_instructions(&self) -> Vec<InstructionKind> {
+        self.instruction_record
+            .as_ref()
+            .map(|instruction_record| instruction_record.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+
+    /// Clears the instructions recorded so far, e.g., to only assert on what
+    /// happens after some setup.
+    pub fn clear_recorded_instructions(&self) {
+        if let Some(instruction_record) = &self.instruction_record {
+            instruction_record.lock().unwrap().clear();
+        }
+    }
+
+    /// Returns a summary of every account in the [`Environment`], ordered by
+    /// address. See [`AccountSummary`] for what is included.
+    pub fn dump_accounts(&self) -> Result<Vec<AccountSummary>, EnvironmentError> {
+        self.dump_accounts_filtered(AccountFilter::All)
+    }
+
+    /// Returns a summary of the accounts in the [`Environment`] that pass the
+    /// `filter`, e.g., only contracts or only externally owned accounts,
+    /// ordered by address.
+    pub fn dump_accounts_filtered(
+        &self,
+        filter: AccountFilter,
+    ) -> Result<Vec<AccountSummary>, EnvironmentError> {
+        let (outcome_sender, outcome_receiver) = bounded(1);
+        self.socket
+            .instruction_sender
+            .send(Instruction::Cheatcode {
+                cheatcode: Cheatcodes::Accounts { filter },
+                outcome_sender,
+            })
+            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+        match outcome_receiver
+            .recv()
+            .map_err(|e| EnvironmentError::Communication(e.to_string()))??
+        {
+            Outcome::CheatcodeReturn(CheatcodesReturn::Accounts { accounts }) => Ok(accounts),
+            _ => Err(EnvironmentError::Communication(
+                "Received an unexpected outcome for an accounts dump!".to_string(),
+            )),
+        }
+    }
+
+    /// The number of calls to each precompile made by the transactions in the
+    /// [`Environment`] and the gas they used, keyed by the name of the
+    /// precompile (e.g., `ecrecover` or `modexp`). This is empty unless the
+    /// [`Environment`] was built with
+    /// [`EnvironmentBuilder::track_precompiles`].
+    pub fn precompile_usage(&self) -> BTreeMap<String, PrecompileUsage> {
+        self.profiler
+            .precompiles
+            .as_ref()
+            .map(|precompiles| precompiles.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+
+    /// A [`GasReport`] of the gas used by every contract function called by
+    /// the transactions in the [`Environment`] so far, along with the
+    /// [`Environment::precompile_usage`]. The functions are empty unless the
+    /// [`Environment`] was built with [`EnvironmentBuilder::profile_gas`].
+    ///
+    /// Functions are identified by their selector; use
+    /// [`GasReport::with_abi`] to name them.
+    pub fn gas_report(&self) -> GasReport {
+        let functions = self
+            .profiler
+            .functions
+            .as_ref()
+            .map(|functions| functions.lock().unwrap().clone())
+            .unwrap_or_default();
+        GasReport::new(&functions, self.precompile_usage())
+    }
+
+    /// Registers the `abi` of the contract at `address` so that custom errors
+    /// in its reverts are decoded by the clients of the [`Environment`]. See
+    /// [`AbiRegistry`].
+    pub fn register_abi(&self, address: ethers::types::Address, abi: ethers::abi::Abi) {
+        self.socket.abi_registry.register(address, abi);
+    }
+
+    /// The [`AbiRegistry`] shared by the clients of the [`Environment`].
+    pub fn abi_registry(&self) -> &AbiRegistry {
+        &self.socket.abi_registry
+    }
+
+    /// Writes the accounts of the [`Environment`] at its current block to the
+    /// file at `path` in the format of `anvil --dump-state`, so Anvil can be
+    /// started from it with `anvil --load-state`. For a forked database, only
+    /// the accounts that have been fetched or loaded so far are written.
+    pub fn dump_state(&self, path: impl AsRef<std::path::Path>) -> Result<(), EnvironmentError> {
+        let (snapshot_sender, snapshot_receiver) = bounded(1);
+        self.socket
+            .instruction_sender
+            .send(Instruction::Snapshot(snapshot_sender))
+            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+        let (db, block) = snapshot_receiver
+            .recv()
+            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+        anvil::AnvilState::from_db(&db, &block).to_file(path)
+    }
+
+    /// Starts a new [`Environment`] from a copy of the worldstate of this one
+    /// at its current block, configured by `builder`. The branch carries on
+    /// from the same block number and timestamp but is otherwise independent:
+    /// it runs on a thread of its own, and nothing sent to either
+    /// [`Environment`] afterwards is seen by the other. This way, alternate
+    /// futures of a simulation (e.g., an oracle crash or not) can be run side
+    /// by side and compared with [`Environment::dump_accounts`].
+    ///
+    /// Any `db` set on the `builder` is replaced by the copy. The history of
+    /// blocks and receipts is not carried over, and neither are clients, so
+    /// the accounts of this [`Environment`] exist in the branch but have to be
+    /// connected to it with new labels.
+    pub fn branch(&self, builder: EnvironmentBuilder) -> Result<Environment, EnvironmentError> {
+        Ok(self.branches([builder])?.remove(0))
+    }
+
+    /// Starts one [`Environment`] per builder in `builders` from a single
+    /// copy of the worldstate of this one. See [`Environment::branch`].
+    pub fn branches(
+        &self,
+        builders: impl IntoIterator<Item = EnvironmentBuilder>,
+    ) -> Result<Vec<Environment>, EnvironmentError> {
+        let (snapshot_sender, snapshot_receiver) = bounded(1);
+        self.socket
+            .instruction_sender
+            .send(Instruction::Snapshot(snapshot_sender))
+            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+        let (db, block) = snapshot_receiver
+            .recv()
+            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+        Ok(builders
+            .into_iter()
+            .map(|mut builder| {
+                builder.db = Some(db.clone());
+                builder.block = Some(block.clone());
+                builder.build()
+            })
+            .collect())
     }
 
     /// Stops the execution of the environment.
-    /// This cannot be recovered from!
+    /// This cannot be recovered from! An [`Executor`] that was detached with
+    /// [`EnvironmentBuilder::build_detached`] carries out the stop the next
+    /// time it is polled.
     ///
     /// # Returns
     ///
@@ -610,6 +431,10 @@ impl Environment {
                     e
                 ))
             })?;
+        // A detached `Executor` carries out the stop the next time it is polled.
+        let Some(handle) = self.handle.take() else {
+            return Ok(());
+        };
         let outcome = outcome_receiver
             .recv()
             .map_err(|e| EnvironmentError::Communication(e.to_string()))??;
@@ -623,15 +448,9 @@ impl Environment {
             warn!("Stopped environment with no label.");
         }
         drop(self.socket.instruction_sender);
-        self.handle
-            .take()
-            .ok_or(EnvironmentError::Stop(
-                "failed to join the environment handle!".to_owned(),
-            ))?
-            .join()
-            .map_err(|_| {
-                EnvironmentError::Stop("Failed to join environment handle.".to_owned())
-            })??;
+        handle.join().map_err(|_| {
+            EnvironmentError::Stop("Failed to join environment handle.".to_owned())
+        })??;
         Ok(())
     }
 }
@@ -639,18 +458,50 @@ impl Environment {
 /// Provides channels for communication between the EVM and external entities.
 ///
 /// The socket contains senders and receivers for transactions, as well as an
-/// event broadcaster to broadcast logs from the EVM to subscribers.
+/// event broadcaster to broadcast logs from the EVM to subscribers, the
+/// [`AbiRegistry`] of its contracts, a [`BlockRecord`] of every block, and,
+/// if enabled, a store of the receipts of every block and of the state diff of
+/// every transaction.
 #[derive(Debug, Clone)]
 pub(crate) struct Socket {
     pub(crate) instruction_sender: Arc<InstructionSender>,
     pub(crate) instruction_receiver: InstructionReceiver,
     pub(crate) event_broadcaster: Arc<Mutex<EventBroadcaster>>,
+    pub(crate) receipts: Option<ReceiptStore>,
+    pub(crate) blocks: BlockStore,
+    pub(crate) abi_registry: AbiRegistry,
+    pub(crate) state_diffs: Option<StateDiffStore>,
+}
+
+/// The messages the [`EventBroadcaster`] sends out to its subscribers.
+#[derive(Clone, Debug)]
+pub(crate) enum Broadcast {
+    /// The [`Log`]s emitted by a transaction.
+    Logs(Vec<Log>),
+
+    /// The [`Environment`] moved on to a new block with the given number and
+    /// timestamp.
+    NewBlock {
+        /// The number of the new block.
+        number: U256,
+
+        /// The timestamp of the new block.
+        timestamp: U256,
+    },
+
+    /// A transaction with the given hash was executed and its changes are
+    /// about to be committed. Transactions that are invalid, and so never
+    /// execute, are not broadcast.
+    PendingTransaction(ethers::types::H256),
 }
 
-/// Responsible for broadcasting Ethereum logs to subscribers.
+/// Responsible for broadcasting Ethereum logs, new blocks, and pending
+/// transactions to subscribers.
 ///
 /// Maintains a list of senders to which logs are sent whenever they are
-/// produced by the EVM.
+/// produced by the EVM, to which new blocks are sent whenever the block
+/// changes, and to which transaction hashes are sent whenever a transaction
+/// arrives.
 #[derive(Clone, Debug)]
 pub(crate) struct EventBroadcaster(Vec<EventSender>);
 
@@ -661,21 +512,383 @@ impl EventBroadcaster {
     }
 
     /// Called from [`RevmMiddleware`] implementation when setting up a new
-    /// `FilterWatcher` as each watcher will need their own sender
+    /// `FilterWatcher` or subscription as each will need their own sender
     pub(crate) fn add_sender(&mut self, sender: EventSender) {
         self.0.push(sender);
     }
 
-    /// Loop through each sender and send  `Vec<Log>` emitted from a transaction
-    /// downstream to any and all receivers
-    fn broadcast(&self, logs: Vec<Log>) -> Result<(), EnvironmentError> {
-        for sender in &self.0 {
-            sender.send(logs.clone())?;
-        }
+    /// Loop through each sender and send the [`Broadcast`] downstream to any
+    /// and all receivers. Senders whose receiver has been dropped (e.g., an
+    /// ended subscription) are removed.
+    fn broadcast(&mut self, broadcast: Broadcast) {
+        self.0
+            .retain(|sender| sender.send(broadcast.clone()).is_ok());
+    }
+}
+
+/// Keeps track of how far along the current block the [`Environment`] is.
+struct BlockProgress {
+    /// The index the next transaction will have in the current block.
+    transaction_index: usize,
+
+    /// The total gas used by the transactions in the current block.
+    cumulative_gas_per_block: U256,
+
+    /// The limit on the gas the transactions in a single block can use, if
+    /// there is one.
+    block_gas_limit: Option<u64>,
+
+    /// The number of transactions that fit in the current block when using
+    /// [`BlockSettings::RandomlySampled`].
+    transactions_per_block: Option<usize>,
+
+    /// The [`BlockRecord`]s of the blocks moved through so far.
+    blocks: BlockStore,
+
+    /// Where the state diff of every transaction is kept, if they are
+    /// recorded.
+    state_diffs: Option<StateDiffStore>,
+}
+
+impl BlockProgress {
+    /// Starts a fresh [`BlockRecord`] for the `block` the [`EVM`] just moved
+    /// on to.
+    fn start_block(&self, block: &BlockEnv) -> Result<(), EnvironmentError> {
+        let number = convert_uint_to_u64(block.number)?.as_u64();
+        let record = BlockRecord {
+            timestamp: block.timestamp,
+            base_fee: block.basefee,
+            gas_limit: self
+                .block_gas_limit
+                .map(U256::from)
+                .unwrap_or(block.gas_limit),
+            ..Default::default()
+        };
+        self.blocks
+            .insert(number, record)
+            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Adds a transaction that used `gas_used` to the [`BlockRecord`] of the
+    /// `block` it was included in.
+    fn record_transaction(
+        &self,
+        block: &BlockEnv,
+        hash: ethers::types::H256,
+        gas_used: u64,
+    ) -> Result<(), EnvironmentError> {
+        let number = convert_uint_to_u64(block.number)?.as_u64();
+        let record = BlockRecord {
+            timestamp: block.timestamp,
+            base_fee: block.basefee,
+            gas_limit: block.gas_limit,
+            ..Default::default()
+        };
+        self.blocks
+            .add_transaction(number, record, hash, gas_used)
+            .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
         Ok(())
     }
 }
 
+/// Executes and commits a transaction, broadcasts its logs, and moves on to
+/// the next block if the current block is full.
+///
+/// When a `block_gas_limit` is set, a transaction that would push the gas used
+/// by the current block over the limit spills into the next block and is
+/// executed there instead. A transaction that does not fit in an empty block
+/// is rejected.
+///
+/// When an `l1_fee` is set, the sender must be able to cover the L1 data fee
+/// on top of the transaction's maximum cost, and the fee is taken from the
+/// sender's balance once the transaction is committed.
+///
+/// The outer error is fatal to the [`Environment`] whereas the inner error
+/// means the transaction itself could not be executed and should be sent back
+/// to the client.
+#[allow(clippy::too_many_arguments)]
+fn execute_transaction(
+    evm: &mut EVM<CacheDB<ExternalDb>>,
+    tx_env: TxEnv,
+    block_progress: &mut BlockProgress,
+    seeded_poisson: &Option<Arc<Mutex<SeededPoisson>>>,
+    gas_settings: &GasSettings,
+    l1_fee: &Option<L1FeeSettings>,
+    event_broadcaster: &Mutex<EventBroadcaster>,
+    profiler: &Profiler,
+) -> Result<Result<(ExecutionResult, ReceiptData), EnvironmentError>, EnvironmentError> {
+    let mut event_broadcaster = event_broadcaster
+        .lock()
+        .map_err(|e| EnvironmentError::Communication(e.to_string()))?;
+
+    // Make sure the sender can pay the L1 data fee on top of the transaction.
+    let caller = tx_env.caller;
+    let l1_data_fee = l1_fee
+        .as_ref()
+        .map(|settings| settings.data_fee(&tx_env.data));
+    if let Some(l1_data_fee) = &l1_data_fee {
+        let balance = match evm.db.as_mut().unwrap().basic(caller) {
+            Ok(info) => info.map(|info| info.balance).unwrap_or_default(),
+            Err(e) => return Ok(Err(EnvironmentError::Execution(EVMError::Database(e)))),
+        };
+        let max_cost = U256::from(tx_env.gas_limit)
+            .saturating_mul(tx_env.gas_price)
+            .saturating_add(tx_env.value)
+            .saturating_add(U256::from(l1_data_fee.fee));
+        if balance < max_cost {
+            return Ok(Err(EnvironmentError::Account(format!(
+                "The balance of {} does not cover the L1 data fee of {} wei!",
+                caller, l1_data_fee.fee
+            ))));
+        }
+    }
+
+    // Set the tx_env and prepare to process it
+    evm.env.tx = tx_env;
+    let mut inspectors = profiler.inspectors.lock()?;
+
+    let (
+        ResultAndState {
+            result: execution_result,
+            state,
+        },
+        inspector,
+    ) = loop {
+        let mut inspector = profiler.inspector(&mut inspectors);
+        let result_and_state = match evm.inspect(&mut inspector) {
+            Ok(result_and_state) => result_and_state,
+            Err(EVMError::Transaction(invalid_transaction)) => {
+                return Ok(Err(EnvironmentError::Transaction(invalid_transaction)))
+            }
+            Err(e) => return Ok(Err(EnvironmentError::Execution(e))),
+        };
+        let cumulative_gas_per_block = block_progress.cumulative_gas_per_block
+            + U256::from(result_and_state.result.gas_used());
+        match block_progress.block_gas_limit {
+            Some(block_gas_limit) if cumulative_gas_per_block > U256::from(block_gas_limit) => {
+                if block_progress.transaction_index == 0 {
+                    return Ok(Err(EnvironmentError::Transaction(
+                        InvalidTransaction::CallerGasLimitMoreThanBlock,
+                    )));
+                }
+                // Nothing was committed, so the transaction can be run again in the next
+                // block with the same `tx_env`.
+                let tx_env = evm.env.tx.clone();
+                advance_block(
+                    evm,
+                    block_progress,
+                    seeded_poisson,
+                    gas_settings,
+                    &mut event_broadcaster,
+                )?;
+                evm.env.tx = tx_env;
+            }
+            _ => break (result_and_state, inspector),
+        }
+    };
+
+    // The transaction is hashed once the block it lands in is known, and anyone
+    // watching pending transactions is told about it before it is committed.
+    let nonce = match evm.db.as_mut().unwrap().basic(caller) {
+        Ok(info) => info.map(|info| info.nonce).unwrap_or_default(),
+        Err(e) => return Ok(Err(EnvironmentError::Execution(EVMError::Database(e)))),
+    };
+    let block_number = convert_uint_to_u64(evm.env.block.number)?;
+    let transaction_index = U64::from(block_progress.transaction_index);
+    let hash = transaction_hash(
+        ethers::types::Address::from(caller.into_array()),
+        nonce,
+        block_number,
+        transaction_index,
+    );
+    event_broadcaster.broadcast(Broadcast::PendingTransaction(hash));
+
+    // The state is only kept past the commit when its diff is recorded.
+    let pending_state_diff = match block_progress.state_diffs {
+        Some(_) => Some((
+            PendingStateDiff::new(evm.db.as_mut().unwrap(), &state)?,
+            state.clone(),
+        )),
+        None => None,
+    };
+    evm.db.as_mut().unwrap().commit(state);
+    profiler.record(inspector)?;
+    if let Some(l1_data_fee) = &l1_data_fee {
+        if let Some(account) = evm.db.as_mut().unwrap().accounts.get_mut(&caller) {
+            account.info.balance = account
+                .info
+                .balance
+                .saturating_sub(U256::from(l1_data_fee.fee));
+        }
+    }
+    if let (Some(state_diffs), Some((pending_state_diff, state))) =
+        (&block_progress.state_diffs, pending_state_diff)
+    {
+        state_diffs.insert(
+            hash,
+            pending_state_diff.finish(evm.db.as_ref().unwrap(), &state),
+        )?;
+    }
+    // increment cumulative gas per block
+    block_progress.cumulative_gas_per_block += U256::from(execution_result.gas_used());
+    block_progress.record_transaction(&evm.env.block, hash, execution_result.gas_used())?;
+
+    let receipt_data = ReceiptData {
+        block_number,
+        transaction_index,
+        nonce,
+        cumulative_gas_per_block: block_progress.cumulative_gas_per_block,
+        l1_fee: l1_data_fee,
+    };
+    event_broadcaster.broadcast(Broadcast::Logs(execution_result.logs()));
+    block_progress.transaction_index += 1;
+
+    // Check whether we need to increment the block number given the amount of
+    // transactions that have occurred on the current block. Only do so if there
+    // is a distribution in the first place.
+    if block_progress
+        .transactions_per_block
+        .is_some_and(|x| x == block_progress.transaction_index)
+    {
+        advance_block(
+            evm,
+            block_progress,
+            seeded_poisson,
+            gas_settings,
+            &mut event_broadcaster,
+        )?;
+    }
+    Ok(Ok((execution_result, receipt_data)))
+}
+
+/// Moves the [`EVM`] on to the next block and broadcasts it.
+///
+/// When there is a `SeededPoisson` distribution, the timestamp moves forward
+/// by its time step and a new sample is drawn for the amount of transactions
+/// in the block, skipping over blocks that would be empty. The gas price is
+/// resampled alongside it when using [`GasSettings::RandomlySampled`].
+fn advance_block(
+    evm: &mut EVM<CacheDB<ExternalDb>>,
+    block_progress: &mut BlockProgress,
+    seeded_poisson: &Option<Arc<Mutex<SeededPoisson>>>,
+    gas_settings: &GasSettings,
+    event_broadcaster: &mut EventBroadcaster,
+) -> Result<(), EnvironmentError> {
+    block_progress.transaction_index = 0;
+    block_progress.cumulative_gas_per_block = U256::ZERO;
+    evm.env.block.number += U256::from(1);
+
+    if let Some(seeded_poisson) = seeded_poisson {
+        let mut seeded_poisson_lock = seeded_poisson.lock().unwrap();
+
+        evm.env.block.timestamp += U256::from(seeded_poisson_lock.time_step);
+        block_progress.transactions_per_block = loop {
+            let sample = Some(seeded_poisson_lock.sample());
+
+            if sample == Some(0) {
+                evm.env.block.number += U256::from(1);
+                continue;
+            } else {
+                break sample;
+            }
+        };
+        if let GasSettings::RandomlySampled { multiplier } = gas_settings {
+            let gas_price = (block_progress
+                .transactions_per_block
+                .ok_or(EnvironmentError::NotRandomlySampledBlockSettings)?
+                as f64)
+                * multiplier;
+            evm.env.tx.gas_price = U256::from(gas_price as u128);
+        };
+    }
+    block_progress.start_block(&evm.env.block)?;
+    event_broadcaster.broadcast(Broadcast::NewBlock {
+        number: evm.env.block.number,
+        timestamp: evm.env.block.timestamp,
+    });
+    Ok(())
+}
+
+/// The hash of a transaction, derived from its sender and nonce and where it
+/// was included, so no two transactions executed by an [`Environment`] share
+/// one. The [`Environment`] and the [`RevmMiddleware`] both hash transactions
+/// with this so that pending transactions can be matched up with receipts.
+///
+/// Like the hashes of real transactions, it is a keccak256 hash, but of these
+/// fields rather than of the signed transaction.
+pub(crate) fn transaction_hash(
+    sender: ethers::types::Address,
+    nonce: u64,
+    block_number: U64,
+    transaction_index: U64,
+) -> ethers::types::H256 {
+    let preimage = [
+        sender.as_bytes(),
+        &nonce.to_be_bytes(),
+        &block_number.as_u64().to_be_bytes(),
+        &transaction_index.as_u64().to_be_bytes(),
+    ]
+    .concat();
+    ethers::types::H256::from(ethers::utils::keccak256(preimage))
+}
+
+/// Applies the `state_overrides` of a call to the accounts in `db`.
+/// Balances, nonces, and code replace what is in the `db` while storage is
+/// either patched slot by slot ([`spoof::Storage::Diff`]) or replaced outright
+/// ([`spoof::Storage::Replace`]).
+fn apply_state_overrides(
+    db: &mut CacheDB<ExternalDb>,
+    state_overrides: std::collections::HashMap<ethers::types::Address, spoof::Account>,
+) -> Result<(), EnvironmentError> {
+    for (address, overrides) in state_overrides {
+        let account = db
+            .load_account(address.as_fixed_bytes().into())
+            .map_err(|e| EnvironmentError::Execution(EVMError::Database(e)))?;
+        // Overriding an account that does not exist brings it into existence.
+        if matches!(account.account_state, AccountState::NotExisting) {
+            account.account_state = AccountState::None;
+        }
+        if let Some(balance) = overrides.balance {
+            account.info.balance = U256::from_limbs(balance.0);
+        }
+        if let Some(nonce) = overrides.nonce {
+            account.info.nonce = nonce.as_u64();
+        }
+        if let Some(code) = overrides.code {
+            let bytecode = Bytecode::new_raw(revm::primitives::Bytes(code.0));
+            account.info.code_hash = bytecode.hash_slow();
+            account.info.code = Some(bytecode);
+        }
+        match overrides.storage {
+            Some(spoof::Storage::Diff(slots)) => {
+                for (key, value) in slots {
+                    account.storage.insert(
+                        U256::from_be_bytes(key.to_fixed_bytes()),
+                        U256::from_be_bytes(value.to_fixed_bytes()),
+                    );
+                }
+            }
+            Some(spoof::Storage::Replace(slots)) => {
+                // Clearing the storage makes any slot not given here read as zero
+                // instead of being fetched from the underlying db.
+                account.storage = slots
+                    .into_iter()
+                    .map(|(key, value)| {
+                        (
+                            U256::from_be_bytes(key.to_fixed_bytes()),
+                            U256::from_be_bytes(value.to_fixed_bytes()),
+                        )
+                    })
+                    .collect();
+                account.account_state = AccountState::StorageCleared;
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
 /// Convert a U256 to a U64, discarding the higher bits if the number is larger
 /// than 2^64 # Arguments
 /// * `input` - The U256 to convert.
@@ -0,0 +1,128 @@
+//! The `precompile` module lets precompiles implemented in Rust be added to an
+//! [`Environment`] with [`EnvironmentBuilder::with_precompile`].
+//!
+//! A [`Precompile`] is called whenever a transaction or call made in the
+//! [`Environment`] calls the address it was added at, be it directly or from a
+//! contract. This makes it possible to stand in for an expensive contract,
+//! such as a math library or a proof verifier, with native code. The calls are
+//! intercepted before any code at the address would run, so the address does
+//! not need to hold code and any value sent along with a call is not
+//! transferred.
+
+#![warn(missing_docs)]
+
+use revm::{
+    interpreter::{CallInputs, Gas, InstructionResult},
+    primitives::{Address, Bytes},
+};
+
+use super::*;
+
+/// A precompile implemented in Rust. It is given the calldata of a call and
+/// the gas the call may use.
+///
+/// Closures of the form `FnMut(&[u8], u64) -> PrecompileResult` implement
+/// this trait.
+pub trait Precompile: Send {
+    /// Runs the precompile on the `input` of a call that may use up to
+    /// `gas_limit` gas.
+    fn call(&mut self, input: &[u8], gas_limit: u64) -> PrecompileResult;
+}
+
+impl<F> Precompile for F
+where
+    F: FnMut(&[u8], u64) -> PrecompileResult + Send,
+{
+    fn call(&mut self, input: &[u8], gas_limit: u64) -> PrecompileResult {
+        self(input, gas_limit)
+    }
+}
+
+/// What a [`Precompile`] returns from a successful call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrecompileOutput {
+    /// The gas the call used.
+    pub gas_used: u64,
+
+    /// The data returned to the caller.
+    pub output: Vec<u8>,
+}
+
+/// The ways a call to a [`Precompile`] can fail.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum PrecompileError {
+    /// The call needs more gas than it was given. All of its gas is
+    /// consumed.
+    #[error("the precompile ran out of gas")]
+    OutOfGas,
+
+    /// The call reverts with the given data after using the given gas.
+    #[error("the precompile reverted")]
+    Revert {
+        /// The gas the call used.
+        gas_used: u64,
+
+        /// The revert data returned to the caller.
+        output: Vec<u8>,
+    },
+}
+
+/// The result of a call to a [`Precompile`].
+pub type PrecompileResult = Result<PrecompileOutput, PrecompileError>;
+
+/// The precompiles added to an [`Environment`], keyed by their address.
+#[derive(Clone, Default)]
+pub(crate) struct CustomPrecompiles(Arc<Mutex<HashMap<Address, Box<dyn Precompile>>>>);
+
+impl CustomPrecompiles {
+    /// Adds the `precompile` at `address`, replacing the one added there
+    /// before, if any.
+    pub(crate) fn insert(&mut self, address: Address, precompile: Box<dyn Precompile>) {
+        self.0.lock().unwrap().insert(address, precompile);
+    }
+
+    /// Whether no precompiles were added.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+
+    /// Whether a precompile was added at `address`.
+    pub(crate) fn contains(&self, address: &Address) -> bool {
+        self.0.lock().unwrap().contains_key(address)
+    }
+
+    /// Runs the precompile the call described by `inputs` is made to, if
+    /// there is one, and returns the outcome of the call as an
+    /// [`Inspector`](revm::Inspector) does.
+    pub(crate) fn call(&self, inputs: &CallInputs) -> Option<(InstructionResult, Gas, Bytes)> {
+        let mut precompiles = self.0.lock().ok()?;
+        let precompile = precompiles.get_mut(&inputs.contract)?;
+        let mut gas = Gas::new(inputs.gas_limit);
+        let (result, gas_used, output) = match precompile.call(&inputs.input, inputs.gas_limit) {
+            Ok(PrecompileOutput { gas_used, output }) => {
+                (InstructionResult::Return, gas_used, output)
+            }
+            Err(PrecompileError::Revert { gas_used, output }) => {
+                (InstructionResult::Revert, gas_used, output)
+            }
+            Err(PrecompileError::OutOfGas) => {
+                return Some((InstructionResult::PrecompileOOG, gas, Bytes::new()))
+            }
+        };
+        if !gas.record_cost(gas_used) {
+            return Some((InstructionResult::PrecompileOOG, gas, Bytes::new()));
+        }
+        Some((result, gas, output.into()))
+    }
+}
+
+impl Debug for CustomPrecompiles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let addresses = self.0.lock().map_or(vec![], |precompiles| {
+            precompiles.keys().copied().collect::<Vec<_>>()
+        });
+        f.debug_tuple("CustomPrecompiles")
+            .field(&addresses)
+            .finish()
+    }
+}
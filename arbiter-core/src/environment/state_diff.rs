@@ -0,0 +1,168 @@
+//! The `state_diff` module contains the [`StateDiff`] of a transaction, i.e.,
+//! the accounts it changed along with their balances and nonces before and
+//! after it and the storage slots it wrote.
+//!
+//! An [`Environment`] built with [`EnvironmentBuilder::record_state_diffs`]
+//! keeps the diff of every transaction it executes. Clients look them up by
+//! the hash of the transaction with [`RevmMiddleware::get_state_diff`], which
+//! allows checking invariants and accounting for every change precisely
+//! without reconstructing them from events. The diffs are kept in memory for
+//! the lifetime of the [`Environment`].
+
+#![warn(missing_docs)]
+
+use std::collections::HashMap;
+
+use ethers::types::{Address, H256, I256};
+use revm::primitives::State;
+
+use super::*;
+
+/// A value before and after a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change<T> {
+    /// The value before the transaction.
+    pub before: T,
+
+    /// The value after the transaction.
+    pub after: T,
+}
+
+impl<T: PartialEq> Change<T> {
+    /// The change from `before` to `after`, or `None` if there is none.
+    fn between(before: T, after: T) -> Option<Self> {
+        (before != after).then_some(Self { before, after })
+    }
+}
+
+/// What a transaction changed about a single account.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountDiff {
+    /// The ether balance of the account, if it changed.
+    pub balance: Option<Change<ethers::types::U256>>,
+
+    /// The nonce of the account, if it changed.
+    pub nonce: Option<Change<u64>>,
+
+    /// The storage slots that were written with a new value, ordered by slot.
+    pub storage: BTreeMap<H256, Change<H256>>,
+}
+
+impl AccountDiff {
+    /// Whether nothing about the account changed.
+    fn is_empty(&self) -> bool {
+        self.balance.is_none() && self.nonce.is_none() && self.storage.is_empty()
+    }
+}
+
+/// What a transaction changed about the accounts it touched. Accounts that
+/// were touched but left as they were are not included.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateDiff {
+    /// The changed accounts, ordered by address.
+    pub accounts: BTreeMap<Address, AccountDiff>,
+}
+
+impl StateDiff {
+    /// What the transaction changed about the account at `address`, if
+    /// anything.
+    pub fn account(&self, address: Address) -> Option<&AccountDiff> {
+        self.accounts.get(&address)
+    }
+
+    /// How much the ether balance of the account at `address` went up, which
+    /// is negative if it went down.
+    pub fn balance_change(&self, address: Address) -> I256 {
+        match self.account(address).and_then(|account| account.balance) {
+            Some(Change { before, after }) => I256::from_raw(after) - I256::from_raw(before),
+            None => I256::zero(),
+        }
+    }
+}
+
+/// The balance and nonce of each account in a [`State`] that is about to be
+/// committed, read from the database before it is.
+pub(crate) struct PendingStateDiff(HashMap<revm::primitives::Address, (U256, u64)>);
+
+impl PendingStateDiff {
+    /// Reads the balance and nonce the accounts in `state` have in `db`. This
+    /// has to be called before `state` is committed to `db`.
+    pub(crate) fn new(
+        db: &mut CacheDB<ExternalDb>,
+        state: &State,
+    ) -> Result<Self, EnvironmentError> {
+        let mut accounts = HashMap::with_capacity(state.len());
+        for address in state.keys() {
+            let info = db
+                .basic(*address)
+                .map_err(|e| EnvironmentError::Execution(EVMError::Database(e)))?
+                .unwrap_or_default();
+            accounts.insert(*address, (info.balance, info.nonce));
+        }
+        Ok(Self(accounts))
+    }
+
+    /// Finishes the diff once `state` has been committed to `db`.
+    pub(crate) fn finish(self, db: &CacheDB<ExternalDb>, state: &State) -> StateDiff {
+        let mut accounts = BTreeMap::new();
+        for (address, account) in state {
+            let (balance, nonce) = db
+                .accounts
+                .get(address)
+                .map(|account| (account.info.balance, account.info.nonce))
+                .unwrap_or_default();
+            let (balance_before, nonce_before) = self.0.get(address).copied().unwrap_or_default();
+            let diff = AccountDiff {
+                balance: Change::between(
+                    ethers::types::U256::from(balance_before.to_be_bytes()),
+                    ethers::types::U256::from(balance.to_be_bytes()),
+                ),
+                nonce: Change::between(nonce_before, nonce),
+                storage: account
+                    .storage
+                    .iter()
+                    .filter(|(_, slot)| slot.is_changed())
+                    .map(|(key, slot)| {
+                        (
+                            H256::from(key.to_be_bytes()),
+                            Change {
+                                before: H256::from(slot.previous_or_original_value.to_be_bytes()),
+                                after: H256::from(slot.present_value.to_be_bytes()),
+                            },
+                        )
+                    })
+                    .collect(),
+            };
+            if !diff.is_empty() {
+                accounts.insert(Address::from(address.into_array()), diff);
+            }
+        }
+        StateDiff { accounts }
+    }
+}
+
+/// The [`StateDiff`]s of the transactions executed by an [`Environment`],
+/// keyed by the hash of the transaction.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StateDiffStore(Arc<Mutex<HashMap<H256, StateDiff>>>);
+
+impl StateDiffStore {
+    /// Keeps the `diff` of the transaction with the given `hash`.
+    pub(crate) fn insert(&self, hash: H256, diff: StateDiff) -> Result<(), EnvironmentError> {
+        self.0
+            .lock()
+            .map_err(|e| EnvironmentError::Communication(e.to_string()))?
+            .insert(hash, diff);
+        Ok(())
+    }
+
+    /// The diff of the transaction with the given `hash`, if it was kept.
+    pub(crate) fn get(&self, hash: H256) -> Result<Option<StateDiff>, EnvironmentError> {
+        Ok(self
+            .0
+            .lock()
+            .map_err(|e| EnvironmentError::Communication(e.to_string()))?
+            .get(&hash)
+            .cloned())
+    }
+}
@@ -27,12 +27,44 @@ fn new_with_builder_custom_settings() {
         .build();
     assert_eq!(environment.parameters.label, Some(TEST_ENV_LABEL.into()));
 }
+#[test]
+fn seed_overrides_block_settings() {
+    let environment = EnvironmentBuilder::new()
+        .block_settings(BlockSettings::RandomlySampled {
+            block_rate: 1.0,
+            block_time: 12,
+            seed: 1,
+        })
+        .seed(2)
+        .build();
+    assert_eq!(environment.parameters.seed, Some(2));
+    assert_eq!(environment.seed(), Some(2));
+
+    let environment = EnvironmentBuilder::new()
+        .block_settings(BlockSettings::RandomlySampled {
+            block_rate: 1.0,
+            block_time: 12,
+            seed: 1,
+        })
+        .build();
+    assert_eq!(environment.seed(), Some(1));
+
+    let environment = EnvironmentBuilder::new().build();
+    assert_eq!(environment.seed(), None);
+}
+
 #[test]
 fn new_user_controlled() {
     let params = EnvironmentParameters {
         label: Some(TEST_ENV_LABEL.to_string()),
         block_settings: BlockSettings::UserControlled,
         gas_settings: GasSettings::UserControlled,
+        block_gas_limit: None,
+        seed: None,
+        l1_fee: None,
+        spec_id: None,
+        chain_id: None,
+        genesis: std::collections::HashMap::new(),
     };
     let environment = Environment::new(params, None);
     assert_eq!(environment.parameters.label, Some(TEST_ENV_LABEL.into()));
@@ -49,6 +81,12 @@ fn new_randomly_sampled() {
         label: Some(TEST_ENV_LABEL.to_string()),
         block_settings: block_type,
         gas_settings: GasSettings::RandomlySampled { multiplier: 1.0 },
+        block_gas_limit: None,
+        seed: None,
+        l1_fee: None,
+        spec_id: None,
+        chain_id: None,
+        genesis: std::collections::HashMap::new(),
     };
     let environment = Environment::new(params, None);
     assert_eq!(environment.parameters.label, Some(TEST_ENV_LABEL.into()));
@@ -60,6 +98,12 @@ fn run() {
         label: Some(TEST_ENV_LABEL.to_string()),
         block_settings: BlockSettings::UserControlled,
         gas_settings: GasSettings::UserControlled,
+        block_gas_limit: None,
+        seed: None,
+        l1_fee: None,
+        spec_id: None,
+        chain_id: None,
+        genesis: std::collections::HashMap::new(),
     };
     Environment::new(params, None);
 }
@@ -78,3 +122,45 @@ fn conversion() {
     let input = U256::from(u64::MAX) + U256::from(1);
     assert!(convert_uint_to_u64(input).is_err());
 }
+
+#[test]
+fn l1_data_fee() {
+    let settings = L1FeeSettings {
+        l1_gas_price: 10,
+        overhead: 100,
+        scalar: 1.5,
+        compression: CalldataCompression::None,
+    };
+    // Two zero bytes at 4 gas and two non-zero bytes at 16 gas.
+    assert_eq!(settings.l1_gas_used(&[0, 0, 1, 2]), 140);
+    assert_eq!(settings.data_fee(&[0, 0, 1, 2]).fee, 2100);
+
+    // Repetitive data compresses well below its length.
+    assert_eq!(flz_compress_len(&[]), 0);
+    assert!(flz_compress_len(&[0; 1000]) < 100);
+    let compressed = L1FeeSettings {
+        compression: CalldataCompression::FastLz,
+        ..settings
+    };
+    assert!(compressed.l1_gas_used(&[0; 1000]) < settings.l1_gas_used(&[0; 1000]));
+}
+
+#[test]
+fn format_token_amount() {
+    let weth = fork::TokenMetadata {
+        name: "Wrapped Ether".to_string(),
+        symbol: "WETH".to_string(),
+        decimals: 18,
+    };
+    let amount = ethers::types::U256::exp10(18) * 3 / 2;
+    assert_eq!(weth.format_amount(amount), "1.5 WETH");
+    assert_eq!(weth.format_amount(ethers::types::U256::exp10(18)), "1 WETH");
+    assert_eq!(weth.format_amount(ethers::types::U256::zero()), "0 WETH");
+
+    let usdc = fork::TokenMetadata {
+        name: "USD Coin".to_string(),
+        symbol: "USDC".to_string(),
+        decimals: 6,
+    };
+    assert_eq!(usdc.format_amount(1_234_567.into()), "1.234567 USDC");
+}
@@ -0,0 +1,228 @@
+//! The `trace` module contains the [`CallTracer`], an [`Inspector`] that
+//! records the calls and creations made by every transaction in an
+//! [`Environment`] it is given to with [`EnvironmentBuilder::with_inspector`].
+//!
+//! The recorded [`CallFrame`]s can be rendered with the names of the
+//! functions that were called through [`AbiRegistry::render_trace`].
+
+#![warn(missing_docs)]
+
+use revm::{
+    interpreter::{CallInputs, CallScheme, CreateInputs, Gas, InstructionResult},
+    primitives::{Address, Bytes},
+    EVMData, Inspector,
+};
+
+use super::*;
+
+/// How a [`CallFrame`] was entered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CallKind {
+    /// A `CALL`, or the call made by a transaction.
+    Call,
+
+    /// A `STATICCALL`.
+    StaticCall,
+
+    /// A `DELEGATECALL`.
+    DelegateCall,
+
+    /// A `CALLCODE`.
+    CallCode,
+
+    /// A `CREATE` or `CREATE2`, or a transaction deploying a contract.
+    Create,
+}
+
+/// A single call or creation made while executing a transaction.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallFrame {
+    /// How deep in the call stack the frame is, starting at `0` for the call
+    /// made by the transaction itself.
+    pub depth: usize,
+
+    /// How the frame was entered.
+    pub kind: CallKind,
+
+    /// The address that made the call or creation.
+    pub from: ethers::types::Address,
+
+    /// The address that was called or created. This is `None` for creations
+    /// that failed.
+    pub to: Option<ethers::types::Address>,
+
+    /// The value sent along.
+    pub value: ethers::types::U256,
+
+    /// The calldata, or the init code of a creation.
+    pub input: ethers::types::Bytes,
+
+    /// The data returned, or the revert data if the frame failed.
+    pub output: ethers::types::Bytes,
+
+    /// The gas used by the frame, including the frames it entered.
+    pub gas_used: u64,
+
+    /// Whether the frame succeeded.
+    pub success: bool,
+}
+
+/// The [`CallFrame`]s of each transaction, in the order they were entered.
+#[derive(Debug, Default)]
+struct Traces {
+    /// The frames of each transaction.
+    traces: Vec<Vec<CallFrame>>,
+
+    /// The indices of the frames that have not ended yet.
+    open: Vec<usize>,
+}
+
+/// Records a trace of the calls made by each transaction it inspects. Clones
+/// share the recorded traces, so a clone can be handed to
+/// [`EnvironmentBuilder::with_inspector`] and the original kept to read the
+/// traces with.
+///
+/// Every transaction is traced, including the ones that revert. Calls made
+/// with the `call` of a [`RevmMiddleware`] are not run with the inspectors of
+/// the [`Environment`] and so are not traced.
+#[derive(Clone, Debug, Default)]
+pub struct CallTracer(Arc<Mutex<Traces>>);
+
+impl CallTracer {
+    /// Creates a [`CallTracer`] that has not recorded anything yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The traces recorded so far, one per transaction.
+    pub fn traces(&self) -> Vec<Vec<CallFrame>> {
+        self.0.lock().unwrap().traces.clone()
+    }
+
+    /// The trace of the last transaction.
+    pub fn last(&self) -> Option<Vec<CallFrame>> {
+        self.0.lock().unwrap().traces.last().cloned()
+    }
+
+    /// Forgets the traces recorded so far.
+    pub fn clear(&self) {
+        let mut traces = self.0.lock().unwrap();
+        traces.traces.clear();
+        traces.open.clear();
+    }
+
+    /// Starts a frame, beginning a new trace if it is the first frame of a
+    /// transaction.
+    fn enter(&self, frame: CallFrame) {
+        let mut traces = self.0.lock().unwrap();
+        if traces.open.is_empty() {
+            traces.traces.push(vec![]);
+        }
+        let depth = traces.open.len();
+        let trace = traces.traces.last_mut().unwrap();
+        trace.push(CallFrame { depth, ..frame });
+        let index = trace.len() - 1;
+        traces.open.push(index);
+    }
+
+    /// Ends the frame that was entered last.
+    fn exit(&self, to: Option<Address>, gas_used: u64, ret: InstructionResult, output: &Bytes) {
+        let mut traces = self.0.lock().unwrap();
+        let Some(index) = traces.open.pop() else {
+            return;
+        };
+        let frame = &mut traces.traces.last_mut().unwrap()[index];
+        if let Some(to) = to {
+            frame.to = Some(recast_address(to));
+        }
+        frame.output = ethers::types::Bytes::from(output.to_vec());
+        frame.gas_used = gas_used;
+        frame.success = matches!(
+            ret,
+            InstructionResult::Continue
+                | InstructionResult::Stop
+                | InstructionResult::Return
+                | InstructionResult::SelfDestruct
+        );
+    }
+}
+
+impl<DB: Database> Inspector<DB> for CallTracer {
+    fn call(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &mut CallInputs,
+    ) -> (InstructionResult, Gas, Bytes) {
+        let kind = match inputs.context.scheme {
+            CallScheme::Call => CallKind::Call,
+            CallScheme::StaticCall => CallKind::StaticCall,
+            CallScheme::DelegateCall => CallKind::DelegateCall,
+            CallScheme::CallCode => CallKind::CallCode,
+        };
+        self.enter(CallFrame {
+            depth: 0,
+            kind,
+            from: recast_address(inputs.context.caller),
+            to: Some(recast_address(inputs.contract)),
+            value: ethers::types::U256::from_little_endian(
+                &inputs.context.apparent_value.to_le_bytes::<32>(),
+            ),
+            input: ethers::types::Bytes::from(inputs.input.to_vec()),
+            output: ethers::types::Bytes::default(),
+            gas_used: 0,
+            success: false,
+        });
+        (InstructionResult::Continue, Gas::new(0), Bytes::new())
+    }
+
+    fn call_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &CallInputs,
+        remaining_gas: Gas,
+        ret: InstructionResult,
+        out: Bytes,
+    ) -> (InstructionResult, Gas, Bytes) {
+        let gas_used = inputs.gas_limit.saturating_sub(remaining_gas.remaining());
+        self.exit(None, gas_used, ret, &out);
+        (ret, remaining_gas, out)
+    }
+
+    fn create(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &mut CreateInputs,
+    ) -> (InstructionResult, Option<Address>, Gas, Bytes) {
+        self.enter(CallFrame {
+            depth: 0,
+            kind: CallKind::Create,
+            from: recast_address(inputs.caller),
+            to: None,
+            value: ethers::types::U256::from_little_endian(&inputs.value.to_le_bytes::<32>()),
+            input: ethers::types::Bytes::from(inputs.init_code.to_vec()),
+            output: ethers::types::Bytes::default(),
+            gas_used: 0,
+            success: false,
+        });
+        (InstructionResult::Continue, None, Gas::new(0), Bytes::new())
+    }
+
+    fn create_end(
+        &mut self,
+        _data: &mut EVMData<'_, DB>,
+        inputs: &CreateInputs,
+        ret: InstructionResult,
+        address: Option<Address>,
+        remaining_gas: Gas,
+        out: Bytes,
+    ) -> (InstructionResult, Option<Address>, Gas, Bytes) {
+        let gas_used = inputs.gas_limit.saturating_sub(remaining_gas.remaining());
+        self.exit(address, gas_used, ret, &out);
+        (ret, address, remaining_gas, out)
+    }
+}
+
+/// Converts a `revm` address into an `ethers` one.
+fn recast_address(address: Address) -> ethers::types::Address {
+    ethers::types::Address::from(address.into_array())
+}
@@ -22,6 +22,8 @@
 //!   running the Ethereum-like blockchain environment.
 //! - **Middleware Implementation**: Customized middleware to reduce overhead
 //!   and provide optimal performance.
+//! - **Agent Logic**: Protocol independent helpers for agents, such as the
+//!   sizing of arbitrage trades against common CFMMs.
 //!
 //! For a detailed guide on getting started, check out the
 //! [Arbiter Github page](https://github.com/primitivefinance/arbiter/).
@@ -31,11 +33,18 @@
 
 #![warn(missing_docs)]
 
+#[cfg(feature = "contracts")]
+pub mod artifacts;
 #[cfg(feature = "contracts")]
 pub mod bindings; // TODO: Add better documentation here and some kind of overwrite protection.
+pub mod bytecode;
+#[cfg(feature = "data-collection")]
 pub mod data_collection;
 pub mod environment;
 pub mod math;
+pub mod metrics;
 pub mod middleware;
+pub mod runner;
+pub mod simulate;
 #[cfg(test)]
 mod tests;
@@ -8,6 +8,16 @@
 //! the [`RustQuant::stochastics`] module so that the end user may retrieve
 //! stochastic processes of their choosing in a simulation they build.
 //!
+//! The [`correlated`] module generates price paths for several assets whose
+//! returns are correlated, optionally switching between regimes.
+//!
+//! The [`estimation`] module fits the parameters of geometric Brownian motion,
+//! Ornstein-Uhlenbeck, and jump diffusion processes to historical prices.
+//!
+//! The [`wad`] module implements the WAD fixed-point math of the
+//! `ArbiterMath` contract natively so it can be used without calling into the
+//! EVM.
+//!
 //! # Examples
 //!
 //! ```
@@ -29,6 +39,10 @@ use statrs::distribution::Poisson;
 /// Re-export [`RustQuant`](https://crates.io/crates/RustQuant) stochastics package module.
 pub use RustQuant::stochastics::*;
 
+pub mod correlated;
+pub mod estimation;
+pub mod wad;
+
 /// Represents a Poisson distribution with a seeded random number generator.
 ///
 /// This is useful for generating deterministic random values from a Poisson
@@ -0,0 +1,367 @@
+//! The `correlated` module generates jointly distributed price paths for
+//! several assets at once.
+//!
+//! The stochastic processes re-exported from [`RustQuant`] each drive a
+//! single asset, so a simulation that trades across several pools or a basket
+//! of tokens gets independent prices out of them. A [`CorrelatedGbm`] instead
+//! moves every asset with a geometric Brownian motion whose log returns have a
+//! given covariance matrix. It can also switch between [`Regime`]s, each with
+//! its own drifts and covariance, following a Markov chain.
+//!
+//! # Examples
+//!
+//! ```
+//! # use arbiter_core::math::correlated::{CorrelatedGbm, Regime};
+//! // Two assets with 20% and 30% volatility whose returns are 50% correlated.
+//! let regime = Regime::new(vec![0.05, 0.05], vec![vec![0.04, 0.03], vec![0.03, 0.09]]).unwrap();
+//! let gbm = CorrelatedGbm::new(vec![1.0, 2000.0], regime).unwrap();
+//! let paths = gbm.generate(1.0, 365, 12345);
+//! assert_eq!(paths.prices.len(), 2);
+//! assert_eq!(paths.prices[0].len(), 366);
+//! ```
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use thiserror::Error;
+
+/// The tolerance within which a covariance matrix has to be symmetric and
+/// a row of transition probabilities has to sum to one.
+const TOLERANCE: f64 = 1e-9;
+
+/// Errors that can occur when setting up a [`CorrelatedGbm`].
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum CorrelatedError {
+    /// The vectors and matrices given do not all describe the same number of
+    /// assets or regimes.
+    #[error("mismatched dimensions! due to: {0}")]
+    Dimension(String),
+
+    /// The covariance matrix is not symmetric positive definite, so no
+    /// Brownian motion has it as its covariance.
+    #[error("the covariance matrix is not symmetric positive definite!")]
+    NotPositiveDefinite,
+
+    /// An initial price is not a finite positive number.
+    #[error("the initial price {0} is not a positive number!")]
+    InvalidPrice(f64),
+
+    /// A row of the transition matrix is not a probability distribution.
+    #[error("invalid transition probabilities! due to: {0}")]
+    InvalidTransitions(String),
+}
+
+/// The drifts and covariance that the log returns of the assets follow while
+/// a [`CorrelatedGbm`] is in this regime. Both are per unit of time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regime {
+    /// The drift of each asset.
+    drifts: Vec<f64>,
+
+    /// The variance of each asset, i.e., the diagonal of the covariance.
+    variances: Vec<f64>,
+
+    /// The lower triangular Cholesky factor of the covariance.
+    cholesky: Vec<Vec<f64>>,
+}
+
+impl Regime {
+    /// Constructs a new [`Regime`] from the `drifts` of the assets and the
+    /// `covariance` matrix of their log returns.
+    pub fn new(drifts: Vec<f64>, covariance: Vec<Vec<f64>>) -> Result<Self, CorrelatedError> {
+        if covariance.len() != drifts.len()
+            || covariance.iter().any(|row| row.len() != drifts.len())
+        {
+            return Err(CorrelatedError::Dimension(format!(
+                "the covariance matrix has to be {n} by {n} for {n} drifts",
+                n = drifts.len()
+            )));
+        }
+        let cholesky = cholesky(&covariance)?;
+        let variances = (0..drifts.len()).map(|i| covariance[i][i]).collect();
+        Ok(Self {
+            drifts,
+            variances,
+            cholesky,
+        })
+    }
+
+    /// The number of assets the regime describes.
+    pub fn assets(&self) -> usize {
+        self.drifts.len()
+    }
+}
+
+/// Computes the lower triangular `L` such that `L L^T = matrix`.
+fn cholesky(matrix: &[Vec<f64>]) -> Result<Vec<Vec<f64>>, CorrelatedError> {
+    let n = matrix.len();
+    let mut lower = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in 0..=i {
+            if (matrix[i][j] - matrix[j][i]).abs() > TOLERANCE {
+                return Err(CorrelatedError::NotPositiveDefinite);
+            }
+            let sum: f64 = (0..j).map(|k| lower[i][k] * lower[j][k]).sum();
+            if i == j {
+                let diagonal = matrix[i][i] - sum;
+                if diagonal.is_nan() || diagonal <= 0.0 {
+                    return Err(CorrelatedError::NotPositiveDefinite);
+                }
+                lower[i][j] = diagonal.sqrt();
+            } else {
+                lower[i][j] = (matrix[i][j] - sum) / lower[j][j];
+            }
+        }
+    }
+    Ok(lower)
+}
+
+/// Moves a set of assets with a geometric Brownian motion whose log returns
+/// are correlated, optionally switching between [`Regime`]s.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorrelatedGbm {
+    /// The price of each asset at time zero.
+    initial_prices: Vec<f64>,
+
+    /// The regimes the process can be in. It starts in the first one.
+    regimes: Vec<Regime>,
+
+    /// The probability of moving from the regime of a row to the regime of a
+    /// column in a single step.
+    transitions: Vec<Vec<f64>>,
+}
+
+impl CorrelatedGbm {
+    /// Constructs a new [`CorrelatedGbm`] that stays in a single `regime`.
+    pub fn new(initial_prices: Vec<f64>, regime: Regime) -> Result<Self, CorrelatedError> {
+        Self::regime_switching(initial_prices, vec![regime], vec![vec![1.0]])
+    }
+
+    /// Constructs a new [`CorrelatedGbm`] that starts in the first of the
+    /// `regimes` and moves between them with the per step probabilities in
+    /// `transitions`. Row `i` of `transitions` holds the probabilities of
+    /// going from regime `i` to each regime, so it has to sum to one.
+    pub fn regime_switching(
+        initial_prices: Vec<f64>,
+        regimes: Vec<Regime>,
+        transitions: Vec<Vec<f64>>,
+    ) -> Result<Self, CorrelatedError> {
+        if regimes.is_empty() {
+            return Err(CorrelatedError::Dimension(
+                "at least one regime is needed".to_string(),
+            ));
+        }
+        if let Some(price) = initial_prices
+            .iter()
+            .find(|price| !price.is_finite() || **price <= 0.0)
+        {
+            return Err(CorrelatedError::InvalidPrice(*price));
+        }
+        if regimes
+            .iter()
+            .any(|regime| regime.assets() != initial_prices.len())
+        {
+            return Err(CorrelatedError::Dimension(format!(
+                "every regime has to describe the {} assets with initial prices",
+                initial_prices.len()
+            )));
+        }
+        if transitions.len() != regimes.len()
+            || transitions.iter().any(|row| row.len() != regimes.len())
+        {
+            return Err(CorrelatedError::Dimension(format!(
+                "the transition matrix has to be {n} by {n} for {n} regimes",
+                n = regimes.len()
+            )));
+        }
+        for (i, row) in transitions.iter().enumerate() {
+            if row.iter().any(|p| !(0.0..=1.0).contains(p)) {
+                return Err(CorrelatedError::InvalidTransitions(format!(
+                    "row {} has a probability outside of [0, 1]",
+                    i
+                )));
+            }
+            if (row.iter().sum::<f64>() - 1.0).abs() > TOLERANCE {
+                return Err(CorrelatedError::InvalidTransitions(format!(
+                    "row {} does not sum to one",
+                    i
+                )));
+            }
+        }
+        Ok(Self {
+            initial_prices,
+            regimes,
+            transitions,
+        })
+    }
+
+    /// Generates the price paths of every asset from time zero to
+    /// `time_horizon` in `steps` equal steps. The same `seed` always gives the
+    /// same paths.
+    pub fn generate(&self, time_horizon: f64, steps: usize, seed: u64) -> CorrelatedPaths {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let dt = time_horizon / steps as f64;
+        let assets = self.initial_prices.len();
+        let mut prices: Vec<Vec<f64>> = self
+            .initial_prices
+            .iter()
+            .map(|price| {
+                let mut path = Vec::with_capacity(steps + 1);
+                path.push(*price);
+                path
+            })
+            .collect();
+        let mut regimes = Vec::with_capacity(steps);
+        let mut regime = 0;
+        for _ in 0..steps {
+            let current = &self.regimes[regime];
+            let shocks: Vec<f64> = (0..assets).map(|_| rng.sample(StandardNormal)).collect();
+            for (i, path) in prices.iter_mut().enumerate() {
+                let correlated: f64 = (0..=i).map(|k| current.cholesky[i][k] * shocks[k]).sum();
+                let exponent =
+                    (current.drifts[i] - 0.5 * current.variances[i]) * dt + dt.sqrt() * correlated;
+                let last = path[path.len() - 1];
+                path.push(last * exponent.exp());
+            }
+            regimes.push(regime);
+            regime = self.next_regime(regime, &mut rng);
+        }
+        CorrelatedPaths {
+            times: (0..=steps).map(|step| step as f64 * dt).collect(),
+            prices,
+            regimes,
+        }
+    }
+
+    /// Samples the regime that follows `regime`.
+    fn next_regime(&self, regime: usize, rng: &mut StdRng) -> usize {
+        if self.regimes.len() == 1 {
+            return 0;
+        }
+        let draw: f64 = rng.gen();
+        let mut cumulative = 0.0;
+        for (next, probability) in self.transitions[regime].iter().enumerate() {
+            cumulative += probability;
+            if draw < cumulative {
+                return next;
+            }
+        }
+        // Only reached when rounding leaves the row summing to just under one.
+        self.regimes.len() - 1
+    }
+}
+
+/// The output of [`CorrelatedGbm::generate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorrelatedPaths {
+    /// The time of each point on the paths, starting at zero.
+    pub times: Vec<f64>,
+
+    /// The path of each asset, in the order the initial prices were given.
+    pub prices: Vec<Vec<f64>>,
+
+    /// The regime the process was in during each step.
+    pub regimes: Vec<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_returns(path: &[f64]) -> Vec<f64> {
+        path.windows(2).map(|w| (w[1] / w[0]).ln()).collect()
+    }
+
+    fn correlation(a: &[f64], b: &[f64]) -> f64 {
+        let n = a.len() as f64;
+        let mean_a = a.iter().sum::<f64>() / n;
+        let mean_b = b.iter().sum::<f64>() / n;
+        let covariance: f64 = a
+            .iter()
+            .zip(b)
+            .map(|(x, y)| (x - mean_a) * (y - mean_b))
+            .sum();
+        let variance_a: f64 = a.iter().map(|x| (x - mean_a).powi(2)).sum();
+        let variance_b: f64 = b.iter().map(|y| (y - mean_b).powi(2)).sum();
+        covariance / (variance_a * variance_b).sqrt()
+    }
+
+    #[test]
+    fn cholesky_factor() {
+        let lower = cholesky(&[vec![4.0, 2.0], vec![2.0, 5.0]]).unwrap();
+        assert_eq!(lower, vec![vec![2.0, 0.0], vec![1.0, 2.0]]);
+        assert_eq!(
+            cholesky(&[vec![1.0, 2.0], vec![2.0, 1.0]]),
+            Err(CorrelatedError::NotPositiveDefinite)
+        );
+        assert_eq!(
+            cholesky(&[vec![1.0, 0.5], vec![0.0, 1.0]]),
+            Err(CorrelatedError::NotPositiveDefinite)
+        );
+    }
+
+    #[test]
+    fn correlated_returns() {
+        let regime = Regime::new(
+            vec![0.0, 0.0, 0.0],
+            vec![
+                vec![0.04, 0.048, -0.02],
+                vec![0.048, 0.09, 0.0],
+                vec![-0.02, 0.0, 0.0625],
+            ],
+        )
+        .unwrap();
+        let gbm = CorrelatedGbm::new(vec![1.0, 10.0, 100.0], regime).unwrap();
+        let paths = gbm.generate(100.0, 100_000, 1);
+        let returns: Vec<Vec<f64>> = paths.prices.iter().map(|p| log_returns(p)).collect();
+        assert!((correlation(&returns[0], &returns[1]) - 0.8).abs() < 0.01);
+        assert!((correlation(&returns[0], &returns[2]) + 0.4).abs() < 0.01);
+        assert!(correlation(&returns[1], &returns[2]).abs() < 0.01);
+        assert!(paths.regimes.iter().all(|regime| *regime == 0));
+        assert_eq!(paths, gbm.generate(100.0, 100_000, 1));
+    }
+
+    #[test]
+    fn regime_switches() {
+        let calm = Regime::new(vec![0.0], vec![vec![0.01]]).unwrap();
+        let volatile = Regime::new(vec![0.0], vec![vec![1.0]]).unwrap();
+        let gbm = CorrelatedGbm::regime_switching(
+            vec![1.0],
+            vec![calm, volatile],
+            vec![vec![0.9, 0.1], vec![0.0, 1.0]],
+        )
+        .unwrap();
+        let paths = gbm.generate(1.0, 1_000, 2);
+        let switch = paths
+            .regimes
+            .iter()
+            .position(|regime| *regime == 1)
+            .unwrap();
+        assert!(paths.regimes[..switch].iter().all(|regime| *regime == 0));
+        assert!(paths.regimes[switch..].iter().all(|regime| *regime == 1));
+    }
+
+    #[test]
+    fn invalid_setups() {
+        let regime = Regime::new(vec![0.0, 0.0], vec![vec![1.0, 0.0], vec![0.0, 1.0]]).unwrap();
+        assert!(matches!(
+            Regime::new(vec![0.0], vec![vec![1.0, 0.0], vec![0.0, 1.0]]),
+            Err(CorrelatedError::Dimension(_))
+        ));
+        assert!(matches!(
+            CorrelatedGbm::new(vec![1.0], regime.clone()),
+            Err(CorrelatedError::Dimension(_))
+        ));
+        assert_eq!(
+            CorrelatedGbm::new(vec![1.0, -1.0], regime.clone()),
+            Err(CorrelatedError::InvalidPrice(-1.0))
+        );
+        assert!(matches!(
+            CorrelatedGbm::regime_switching(
+                vec![1.0, 1.0],
+                vec![regime.clone(), regime],
+                vec![vec![0.5, 0.6], vec![0.0, 1.0]],
+            ),
+            Err(CorrelatedError::InvalidTransitions(_))
+        ));
+    }
+}
@@ -0,0 +1,403 @@
+//! The `estimation` module fits the parameters of common price processes to a
+//! historical price series so that a simulated price process can be
+//! calibrated to a real asset before a run.
+//!
+//! Each fit takes prices observed at a fixed interval `dt`, measured in the
+//! same unit of time that the fitted parameters are expressed in. For
+//! instance, daily closes with `dt = 1.0 / 365.0` give annualized parameters.
+//! The prices can be read from a column of a CSV file with [`read_prices`].
+//!
+//! # Examples
+//!
+//! ```
+//! # use arbiter_core::math::estimation::GbmParameters;
+//! let prices = [100.0, 101.0, 99.5, 102.0, 103.5, 102.5];
+//! let parameters = GbmParameters::fit(&prices, 1.0 / 365.0).unwrap();
+//! assert!(parameters.volatility > 0.0);
+//! ```
+
+use std::path::Path;
+
+use thiserror::Error;
+
+/// Errors that can occur when estimating the parameters of a price process.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum EstimationError {
+    /// The price file could not be read or parsed.
+    #[error("failed to read the prices! due to: {0}")]
+    Read(String),
+
+    /// There are not enough prices to fit the parameters.
+    #[error("at least {needed} prices are needed but {given} were given!")]
+    TooFewPrices {
+        /// The least number of prices the fit needs.
+        needed: usize,
+
+        /// The number of prices given.
+        given: usize,
+    },
+
+    /// A price is not a finite positive number.
+    #[error("the price {0} is not a positive number!")]
+    InvalidPrice(f64),
+
+    /// The time step between prices is not a finite positive number.
+    #[error("the time step {0} is not a positive number!")]
+    InvalidTimeStep(f64),
+
+    /// The prices do not identify the parameters, e.g., because they never
+    /// move or do not revert to a mean.
+    #[error("the parameters can not be identified from the prices! due to: {0}")]
+    Degenerate(String),
+}
+
+/// Reads the prices in the column named `column` of the CSV file at `path`.
+/// The first line of the file has to hold the column names and every other
+/// non-empty line one price per column, separated by commas.
+pub fn read_prices(path: impl AsRef<Path>, column: &str) -> Result<Vec<f64>, EstimationError> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| EstimationError::Read(e.to_string()))?;
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header = lines
+        .next()
+        .ok_or_else(|| EstimationError::Read("the file is empty".to_string()))?;
+    let index = header
+        .split(',')
+        .position(|name| name.trim() == column)
+        .ok_or_else(|| EstimationError::Read(format!("there is no column named {}", column)))?;
+    lines
+        .enumerate()
+        .map(|(row, line)| {
+            let field = line.split(',').nth(index).ok_or_else(|| {
+                EstimationError::Read(format!("row {} has no {} column", row + 1, column))
+            })?;
+            field.trim().parse::<f64>().map_err(|e| {
+                EstimationError::Read(format!("row {} has no price in it: {}", row + 1, e))
+            })
+        })
+        .collect()
+}
+
+/// The parameters of a geometric Brownian motion
+/// `dS = drift * S dt + volatility * S dW`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GbmParameters {
+    /// The drift per unit of time.
+    pub drift: f64,
+
+    /// The volatility per square root unit of time.
+    pub volatility: f64,
+}
+
+impl GbmParameters {
+    /// Fits the parameters to `prices` observed every `dt` by the maximum
+    /// likelihood of their log returns.
+    pub fn fit(prices: &[f64], dt: f64) -> Result<Self, EstimationError> {
+        let returns = log_returns(prices, dt, 3)?;
+        let (mean, variance) = mean_and_variance(&returns);
+        if variance == 0.0 {
+            return Err(EstimationError::Degenerate(
+                "the prices never change relative to each other".to_string(),
+            ));
+        }
+        let volatility = (variance / dt).sqrt();
+        Ok(Self {
+            drift: mean / dt + 0.5 * volatility * volatility,
+            volatility,
+        })
+    }
+}
+
+/// The parameters of an Ornstein-Uhlenbeck process
+/// `dX = mean_reversion * (mean - X) dt + volatility * dW`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OuParameters {
+    /// How fast the process reverts to its mean, per unit of time.
+    pub mean_reversion: f64,
+
+    /// The level the process reverts to.
+    pub mean: f64,
+
+    /// The volatility per square root unit of time.
+    pub volatility: f64,
+}
+
+impl OuParameters {
+    /// Fits the parameters to `prices` observed every `dt` by regressing each
+    /// price on the one before it, which is exact for the discretely observed
+    /// process.
+    pub fn fit(prices: &[f64], dt: f64) -> Result<Self, EstimationError> {
+        validate(prices, dt, 3)?;
+        let (previous, next) = (&prices[..prices.len() - 1], &prices[1..]);
+        let (mean_previous, variance_previous) = mean_and_variance(previous);
+        let (mean_next, _) = mean_and_variance(next);
+        if variance_previous == 0.0 {
+            return Err(EstimationError::Degenerate(
+                "the prices never change".to_string(),
+            ));
+        }
+        let covariance = previous
+            .iter()
+            .zip(next)
+            .map(|(x, y)| (x - mean_previous) * (y - mean_next))
+            .sum::<f64>()
+            / previous.len() as f64;
+        let slope = covariance / variance_previous;
+        if slope <= 0.0 || slope >= 1.0 {
+            return Err(EstimationError::Degenerate(format!(
+                "the autocorrelation {} of the prices is not that of a mean reverting process",
+                slope
+            )));
+        }
+        let intercept = mean_next - slope * mean_previous;
+        let residual_variance = previous
+            .iter()
+            .zip(next)
+            .map(|(x, y)| (y - intercept - slope * x).powi(2))
+            .sum::<f64>()
+            / previous.len() as f64;
+        let mean_reversion = -slope.ln() / dt;
+        Ok(Self {
+            mean_reversion,
+            mean: intercept / (1.0 - slope),
+            volatility: (residual_variance * 2.0 * mean_reversion / (1.0 - slope * slope)).sqrt(),
+        })
+    }
+}
+
+/// The parameters of a Merton jump diffusion, i.e., a geometric Brownian
+/// motion whose log price also jumps by normally distributed amounts at the
+/// times of a Poisson process.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JumpDiffusionParameters {
+    /// The drift of the diffusion per unit of time.
+    pub drift: f64,
+
+    /// The volatility of the diffusion per square root unit of time.
+    pub volatility: f64,
+
+    /// The expected number of jumps per unit of time.
+    pub jump_intensity: f64,
+
+    /// The mean of the jumps in the log price.
+    pub jump_mean: f64,
+
+    /// The standard deviation of the jumps in the log price.
+    pub jump_volatility: f64,
+}
+
+impl JumpDiffusionParameters {
+    /// The number of standard deviations past which a log return is counted as
+    /// a jump by default.
+    pub const DEFAULT_THRESHOLD: f64 = 4.0;
+
+    /// Fits the parameters to `prices` observed every `dt`. Log returns that
+    /// are more than `threshold` standard deviations of the diffusion away
+    /// from its mean are counted as jumps. The diffusion is then fit to the
+    /// other log returns and the jumps to what is left of theirs once the
+    /// diffusion is taken out, i.e., its mean and its variance.
+    pub fn fit(prices: &[f64], dt: f64, threshold: f64) -> Result<Self, EstimationError> {
+        let returns = log_returns(prices, dt, 3)?;
+        let mut jumps = vec![false; returns.len()];
+        // Counting the largest returns as jumps shrinks the deviation of the
+        // rest, so repeat until no more returns are counted.
+        let (mean, variance) = loop {
+            let diffusion: Vec<f64> = returns
+                .iter()
+                .zip(&jumps)
+                .filter(|(_, jump)| !**jump)
+                .map(|(r, _)| *r)
+                .collect();
+            if diffusion.len() < 2 {
+                return Err(EstimationError::Degenerate(
+                    "almost every price change is a jump".to_string(),
+                ));
+            }
+            let (mean, variance) = mean_and_variance(&diffusion);
+            if variance == 0.0 {
+                return Err(EstimationError::Degenerate(
+                    "the prices never change relative to each other".to_string(),
+                ));
+            }
+            let mut changed = false;
+            for (r, jump) in returns.iter().zip(jumps.iter_mut()) {
+                if !*jump && (r - mean).abs() > threshold * variance.sqrt() {
+                    *jump = true;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break (mean, variance);
+            }
+        };
+        let sizes: Vec<f64> = returns
+            .iter()
+            .zip(&jumps)
+            .filter(|(_, jump)| **jump)
+            .map(|(r, _)| r - mean)
+            .collect();
+        let (jump_mean, jump_variance) = if sizes.is_empty() {
+            (0.0, 0.0)
+        } else {
+            mean_and_variance(&sizes)
+        };
+        let volatility = (variance / dt).sqrt();
+        Ok(Self {
+            drift: mean / dt + 0.5 * volatility * volatility,
+            volatility,
+            jump_intensity: sizes.len() as f64 / (returns.len() as f64 * dt),
+            jump_mean,
+            jump_volatility: (jump_variance - variance).max(0.0).sqrt(),
+        })
+    }
+}
+
+/// Checks that there are at least `needed` valid prices and that `dt` is a
+/// valid time step.
+fn validate(prices: &[f64], dt: f64, needed: usize) -> Result<(), EstimationError> {
+    if !dt.is_finite() || dt <= 0.0 {
+        return Err(EstimationError::InvalidTimeStep(dt));
+    }
+    if prices.len() < needed {
+        return Err(EstimationError::TooFewPrices {
+            needed,
+            given: prices.len(),
+        });
+    }
+    match prices
+        .iter()
+        .find(|price| !price.is_finite() || **price <= 0.0)
+    {
+        Some(price) => Err(EstimationError::InvalidPrice(*price)),
+        None => Ok(()),
+    }
+}
+
+/// The log returns between consecutive `prices`, after validating them.
+fn log_returns(prices: &[f64], dt: f64, needed: usize) -> Result<Vec<f64>, EstimationError> {
+    validate(prices, dt, needed)?;
+    Ok(prices.windows(2).map(|w| (w[1] / w[0]).ln()).collect())
+}
+
+/// The mean and the (biased) variance of `values`.
+fn mean_and_variance(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+    (mean, variance)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use rand_distr::StandardNormal;
+
+    use super::*;
+
+    const DT: f64 = 1.0 / 365.0;
+
+    fn gbm(drift: f64, volatility: f64, steps: usize, rng: &mut StdRng) -> Vec<f64> {
+        let mut prices = vec![100.0];
+        for _ in 0..steps {
+            let shock: f64 = rng.sample(StandardNormal);
+            let exponent =
+                (drift - 0.5 * volatility * volatility) * DT + volatility * DT.sqrt() * shock;
+            prices.push(prices[prices.len() - 1] * exponent.exp());
+        }
+        prices
+    }
+
+    #[test]
+    fn fit_gbm() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let prices = gbm(0.1, 0.6, 365 * 200, &mut rng);
+        let parameters = GbmParameters::fit(&prices, DT).unwrap();
+        assert!((parameters.volatility - 0.6).abs() < 0.01);
+        assert!((parameters.drift - 0.1).abs() < 0.1);
+    }
+
+    #[test]
+    fn fit_ou() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let (mean_reversion, mean, volatility) = (5.0, 50.0, 4.0);
+        let decay = (-mean_reversion * DT).exp();
+        let deviation = volatility * ((1.0 - decay * decay) / (2.0 * mean_reversion)).sqrt();
+        let mut prices = vec![40.0];
+        for _ in 0..365 * 50 {
+            let shock: f64 = rng.sample(StandardNormal);
+            let last = prices[prices.len() - 1];
+            prices.push(mean + (last - mean) * decay + deviation * shock);
+        }
+        let parameters = OuParameters::fit(&prices, DT).unwrap();
+        assert!((parameters.mean_reversion - mean_reversion).abs() < 1.0);
+        assert!((parameters.mean - mean).abs() < 0.5);
+        assert!((parameters.volatility - volatility).abs() < 0.1);
+    }
+
+    #[test]
+    fn fit_jump_diffusion() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut prices = gbm(0.0, 0.3, 365 * 20, &mut rng);
+        // Drop the price by a fifth every 100 days.
+        let mut jump = 1.0;
+        for (day, price) in prices.iter_mut().enumerate() {
+            if day > 0 && day % 100 == 0 {
+                jump *= 0.8;
+            }
+            *price *= jump;
+        }
+        let parameters =
+            JumpDiffusionParameters::fit(&prices, DT, JumpDiffusionParameters::DEFAULT_THRESHOLD)
+                .unwrap();
+        assert!((parameters.volatility - 0.3).abs() < 0.02);
+        assert!((parameters.jump_intensity - 3.65).abs() < 0.1);
+        assert!((parameters.jump_mean - 0.8f64.ln()).abs() < 0.01);
+        assert!(parameters.jump_volatility < 0.02);
+
+        let without_jumps = JumpDiffusionParameters::fit(
+            &gbm(0.0, 0.3, 365, &mut rng),
+            DT,
+            JumpDiffusionParameters::DEFAULT_THRESHOLD,
+        )
+        .unwrap();
+        assert_eq!(without_jumps.jump_intensity, 0.0);
+    }
+
+    #[test]
+    fn invalid_prices() {
+        assert_eq!(
+            GbmParameters::fit(&[1.0, 2.0], DT),
+            Err(EstimationError::TooFewPrices {
+                needed: 3,
+                given: 2
+            })
+        );
+        assert_eq!(
+            GbmParameters::fit(&[1.0, 0.0, 2.0], DT),
+            Err(EstimationError::InvalidPrice(0.0))
+        );
+        assert_eq!(
+            GbmParameters::fit(&[1.0, 2.0, 3.0], 0.0),
+            Err(EstimationError::InvalidTimeStep(0.0))
+        );
+        assert!(matches!(
+            OuParameters::fit(&[1.0, 2.0, 4.0, 8.0], DT),
+            Err(EstimationError::Degenerate(_))
+        ));
+    }
+
+    #[test]
+    fn read_csv() {
+        let path = std::env::temp_dir().join("arbiter_estimation_read_csv.csv");
+        std::fs::write(&path, "timestamp,close\n1,100.5\n2,101\n\n3,99.25\n").unwrap();
+        assert_eq!(
+            read_prices(&path, "close").unwrap(),
+            vec![100.5, 101.0, 99.25]
+        );
+        assert!(matches!(
+            read_prices(&path, "open"),
+            Err(EstimationError::Read(_))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,390 @@
+//! `wad` module implements the WAD fixed-point math of the `ArbiterMath`
+//! contract natively in Rust. This includes multiplication and division,
+//! square roots, the natural logarithm and exponential, and the probability
+//! density, cumulative distribution, and inverse cumulative distribution
+//! functions of the standard normal distribution.
+//!
+//! Every function returns exactly what the corresponding function of
+//! `ArbiterMath` (and so `solmate`'s `FixedPointMathLib` and `solstat`'s
+//! `Gaussian`) returns for the same input, down to the last wei, and returns
+//! `None` where the contract reverts. This lets agents size trades with the
+//! same math the contracts use without making a call into the EVM for every
+//! intermediate value.
+//!
+//! # Examples
+//!
+//! ```
+//! # use arbiter_core::math::wad::{cdf, ppf, float_to_iwad, iwad_to_float};
+//! let x = float_to_iwad(1.5);
+//! let p = cdf(x).unwrap();
+//! assert!((iwad_to_float(p) - 0.9331927987311419).abs() < 1e-6);
+//! assert!((iwad_to_float(ppf(p).unwrap()) - 1.5).abs() < 1e-6);
+//! ```
+
+#![warn(missing_docs, unsafe_code)]
+
+use ethers::types::{I256, U256};
+
+/// One as a WAD, i.e., `1e18`.
+pub const WAD: U256 = U256([1_000_000_000_000_000_000, 0, 0, 0]);
+
+/// One as a signed WAD, i.e., `1e18`.
+pub const IWAD: I256 = I256::from_raw(WAD);
+
+/// `√2` as a WAD.
+const SQRT_2: i128 = 1_414213562373095048;
+
+/// `√(2π)` as a WAD.
+const SQRT_2PI: i128 = 2_506628274631000502;
+
+/// Coefficients of the approximation of the complementary error function
+/// from Numerical Recipes in C (2nd ed., p. 221) as WADs.
+const ERFC_A: i128 = 1_265512230000000000;
+const ERFC_B: i128 = 1_000023680000000000;
+const ERFC_C: i128 = 374091960000000000;
+const ERFC_D: i128 = 96784180000000000;
+const ERFC_E: i128 = -186288060000000000;
+const ERFC_F: i128 = 278868070000000000;
+const ERFC_G: i128 = -1_135203980000000000;
+const ERFC_H: i128 = 1_488515870000000000;
+const ERFC_I: i128 = -822152230000000000;
+const ERFC_J: i128 = 170872770000000000;
+
+/// Coefficients of the approximation of the inverse of the complementary
+/// error function from Numerical Recipes in C (3rd ed., p. 265) as WADs.
+const IERFC_A: i128 = -707110000000000000;
+const IERFC_B: i128 = 2_307530000000000000;
+const IERFC_C: i128 = 270610000000000000;
+const IERFC_D: i128 = 992290000000000000;
+const IERFC_E: i128 = 44810000000000000;
+const IERFC_F: i128 = 1_128379167095512570;
+
+/// Multiplies two WADs and rounds down, as `mulWadDown` does. Returns `None`
+/// if the product overflows.
+pub fn mul_wad_down(x: U256, y: U256) -> Option<U256> {
+    Some(x.checked_mul(y)? / WAD)
+}
+
+/// Multiplies two WADs and rounds up, as `mulWadUp` does. Returns `None` if
+/// the product overflows.
+pub fn mul_wad_up(x: U256, y: U256) -> Option<U256> {
+    div_up(x.checked_mul(y)?, WAD)
+}
+
+/// Divides two WADs and rounds down, as `divWadDown` does. Returns `None` if
+/// `y` is zero or the scaled dividend overflows.
+pub fn div_wad_down(x: U256, y: U256) -> Option<U256> {
+    x.checked_mul(WAD)?.checked_div(y)
+}
+
+/// Divides two WADs and rounds up, as `divWadUp` does. Returns `None` if `y`
+/// is zero or the scaled dividend overflows.
+pub fn div_wad_up(x: U256, y: U256) -> Option<U256> {
+    div_up(x.checked_mul(WAD)?, y)
+}
+
+/// Divides `x` by `y` and rounds up. Returns `None` if `y` is zero.
+fn div_up(x: U256, y: U256) -> Option<U256> {
+    if y.is_zero() {
+        return None;
+    }
+    let (quotient, remainder) = x.div_mod(y);
+    Some(quotient + U256::from(!remainder.is_zero() as u8))
+}
+
+/// The square root of `x` rounded down, as `FixedPointMathLib.sqrt` computes
+/// it. Note that this is the square root of an integer and not of a WAD.
+pub fn sqrt(x: U256) -> U256 {
+    x.integer_sqrt()
+}
+
+/// The square root of the WAD `x` as a WAD, rounded down. Returns `None` if
+/// `x` is too large to be scaled up by a WAD.
+pub fn sqrt_wad(x: U256) -> Option<U256> {
+    Some(sqrt(x.checked_mul(WAD)?))
+}
+
+/// The natural exponential of the WAD `x` as a WAD, as `expWad` computes it.
+/// Returns `None` if the result does not fit in an `I256`, which happens for
+/// `x` of about 135 and above.
+pub fn exp_wad(x: I256) -> Option<I256> {
+    // When the result is < 0.5 we return zero. This happens when
+    // x <= floor(log(0.5e18) * 1e18) ~ -42e18.
+    if x <= I256::from(-42139678854452767551_i128) {
+        return Some(I256::zero());
+    }
+    // When the result is > (2**255 - 1) / 1e18 we can not represent it as an int.
+    // This happens when x >= floor(log((2**255 - 1) / 1e18) * 1e18) ~ 135.
+    if x >= I256::from(135305999368893231589_i128) {
+        return None;
+    }
+
+    // Convert x to (-42, 136) * 2**96 for more intermediate precision and a binary
+    // basis. This base conversion is a multiplication by
+    // 1e18 / 2**96 = 5**18 / 2**78.
+    let x = x.wrapping_shl(78) / I256::from(5_i128.pow(18));
+
+    // Reduce the range of x to (-½ ln 2, ½ ln 2) * 2**96 by factoring out powers of
+    // two such that exp(x) = exp(x') * 2**k, where k is an integer. Solving
+    // this gives k = round(x / log(2)) and x' = x - k * log(2).
+    let ln_2 = I256::from(54916777467707473351141471128_i128);
+    let k = (x.wrapping_shl(96) / ln_2 + I256::one().wrapping_shl(95)).asr(96);
+    let x = x - k * ln_2;
+
+    // Evaluate using a (6, 7)-term rational approximation. p is made monic, we
+    // multiply by a scale factor later.
+    let y = x + I256::from(1346386616545796478920950773328_i128);
+    let y = (y * x).asr(96) + I256::from(57155421227552351082224309758442_i128);
+    let p = y + x - I256::from(94201549194550492254356042504812_i128);
+    let p = (p * y).asr(96) + I256::from(28719021644029726153956944680412240_i128);
+    // p is left in 2**192 basis so it does not need to be scaled up for the
+    // division.
+    let p = p * x + I256::from(4385272521454847904659076985693276_i128).wrapping_shl(96);
+
+    let mut q = x - I256::from(2855989394907223263936484059900_i128);
+    for coefficient in [
+        50020603652535783019961831881945_i128,
+        -533845033583426703283633433725380,
+        3604857256930695427073651918091429,
+        -14423608567350463180887372962807573,
+        26449188498355588339934803723976023,
+    ] {
+        q = (q * x).asr(96) + I256::from(coefficient);
+    }
+
+    // The q polynomial has no zeros in the domain, as all its roots are complex,
+    // and no scaling is needed as p is already 2**96 too large. r is in
+    // (0.09, 0.25) * 2**96.
+    let r = p / q;
+
+    // Multiply r by the scale factor s = ~6.031367120, the 2**k factor from the
+    // range reduction, and the 1e18 / 2**96 factor for the base conversion, all
+    // at once with an intermediate result in 2**213 basis so that the final
+    // right shift is always by a positive amount.
+    let scale = U256([17181495799676635891, 7188640403681034642, 11234296709, 0]);
+    let shift = (I256::from(195) - k).low_u64() as usize;
+    Some(I256::from_raw(
+        r.into_raw().overflowing_mul(scale).0 >> shift,
+    ))
+}
+
+/// The natural logarithm of the WAD `x` as a WAD, as `lnWad` computes it.
+/// Returns `None` if `x` is not positive.
+pub fn ln_wad(x: I256) -> Option<I256> {
+    if !x.is_positive() {
+        return None;
+    }
+
+    // Reduce the range of x to (1, 2) * 2**96 using
+    // ln(2^k * x) = k * ln(2) + ln(x). The conversion from 1e18 to 2**96 fixed
+    // point is made up for by adding ln(2**96 / 1e18) at the end.
+    let k = x.into_raw().bits() as i64 - 97;
+    let x = I256::from_raw((x.into_raw() << (159 - k) as usize) >> 159);
+
+    // Evaluate using an (8, 8)-term rational approximation. p is made monic, we
+    // multiply by a scale factor later.
+    let mut p = x + I256::from(3273285459638523848632254066296_i128);
+    for coefficient in [
+        24828157081833163892658089445524_i128,
+        43456485725739037958740375743393,
+        -11111509109440967052023855526967,
+        -45023709667254063763336534515857,
+        -14706773417378608786704636184526,
+    ] {
+        p = (p * x).asr(96) + I256::from(coefficient);
+    }
+    // p is left in 2**192 basis so it does not need to be scaled up for the
+    // division.
+    let p = p * x - I256::from(795164235651350426258249787498_i128).wrapping_shl(96);
+
+    // q is monic by convention.
+    let mut q = x + I256::from(5573035233440673466300451813936_i128);
+    for coefficient in [
+        71694874799317883764090561454958_i128,
+        283447036172924575727196451306956,
+        401686690394027663651624208769553,
+        204048457590392012362485061816622,
+        31853899698501571402653359427138,
+        909429971244387300277376558375,
+    ] {
+        q = (q * x).asr(96) + I256::from(coefficient);
+    }
+
+    // The q polynomial has no zeros in the domain and no scaling is needed as p is
+    // already 2**96 too large. r is in (0, 0.125) * 2**96.
+    let r = p / q;
+
+    // Multiply by the scale factor s = 5.549… * 5e18 * 2**96, add k * ln(2) and
+    // ln(2**96 / 1e18) in the same 5**18 * 2**192 basis, and convert back to a WAD.
+    let scale = I256::from_raw(U256([6696670060420420870, 15753826755151634833, 4928, 0]));
+    let ln_2 = I256::from_raw(U256([
+        15565476582146962099,
+        8508605878662066247,
+        12940911783117004858,
+        2644146654357,
+    ]));
+    let ln_base = I256::from_raw(U256([
+        16718079191102669444,
+        3848919035412515418,
+        8176266154714820771,
+        95732107772300,
+    ]));
+    Some((r * scale + ln_2 * I256::from(k) + ln_base).asr(174))
+}
+
+/// Multiplies two signed WADs and rounds towards zero. Returns `None` if the
+/// product overflows.
+fn mul_iwad(x: I256, y: I256) -> Option<I256> {
+    Some(x.checked_mul(y)? / IWAD)
+}
+
+/// Divides two signed WADs and rounds towards zero. Returns `None` if `y` is
+/// zero or the scaled dividend overflows.
+fn div_iwad(x: I256, y: I256) -> Option<I256> {
+    x.checked_mul(IWAD)?.checked_div(y)
+}
+
+/// The complementary error function of the WAD `x`, `erfc(x) = 1 - erf(x)`,
+/// approximated with a fractional error of less than 1.2e-7.
+fn erfc(x: I256) -> Option<I256> {
+    // Beyond ±6.24 the complementary error function is within a few wei of its
+    // limits.
+    let bound = I256::from(6_240000000000000000_i128);
+    if x.is_zero() {
+        return Some(IWAD);
+    } else if x >= bound {
+        return Some(I256::zero());
+    } else if x <= -bound {
+        return Some(IWAD * I256::from(2));
+    }
+    let z = x.checked_abs()?;
+    let t = div_iwad(IWAD, IWAD.checked_add(div_iwad(z, IWAD * I256::from(2))?)?)?;
+    let mut step = I256::from(ERFC_J);
+    for coefficient in [ERFC_I, ERFC_H, ERFC_G, ERFC_F] {
+        step = I256::from(coefficient).checked_add(mul_iwad(t, step)?)?;
+    }
+    for coefficient in [ERFC_E, ERFC_D, ERFC_C, ERFC_B] {
+        step = I256::from(coefficient).checked_add(mul_iwad(t, step)?)?;
+    }
+    let step = mul_iwad(t, step)?;
+    let k = (-mul_iwad(z, z)?)
+        .checked_sub(I256::from(ERFC_A))?
+        .checked_add(step)?;
+    let r = mul_iwad(t, exp_wad(k)?)?;
+    match x.is_negative() {
+        true => (IWAD * I256::from(2)).checked_sub(r),
+        false => Some(r),
+    }
+}
+
+/// The inverse of the complementary error function for a WAD `x` in
+/// `(0, 2)`, refined with two steps of Newton's method.
+fn ierfc(x: I256) -> Option<I256> {
+    let two = IWAD * I256::from(2);
+    if !x.is_positive() || x >= two {
+        return None;
+    }
+    let xx = match x < IWAD {
+        true => x,
+        false => two - x,
+    };
+    let ln = ln_wad(mul_iwad(IWAD / I256::from(2), xx)?)?;
+    let t = mul_iwad(-two, ln)?;
+    if t.is_negative() {
+        return None;
+    }
+    let t = I256::from_raw(sqrt(t.into_raw()) * U256::exp10(9));
+
+    let numerator = I256::from(IERFC_B).checked_add(mul_iwad(t, I256::from(IERFC_C))?)?;
+    let denominator = mul_iwad(
+        I256::from(IERFC_D).checked_add(mul_iwad(t, I256::from(IERFC_E))?)?,
+        t,
+    )?
+    .checked_add(IWAD)?;
+    let mut z = mul_iwad(
+        I256::from(IERFC_A),
+        div_iwad(numerator, denominator)?.checked_sub(t)?,
+    )?;
+    for _ in 0..2 {
+        let error = erfc(z)?.checked_sub(xx)?;
+        let derivative = mul_iwad(I256::from(IERFC_F), exp_wad(-mul_iwad(z, z)?)?)?
+            .checked_sub(mul_iwad(error, z)?)?;
+        z = z.checked_add(div_iwad(error, derivative)?)?;
+    }
+    match x < IWAD {
+        true => Some(z),
+        false => z.checked_neg(),
+    }
+}
+
+/// The probability density function of the standard normal distribution at
+/// the WAD `x`, as `Gaussian.pdf` computes it.
+pub fn pdf(x: I256) -> Option<I256> {
+    let exponent = x.checked_neg()?.checked_mul(x)? / (IWAD * I256::from(2));
+    div_iwad(exp_wad(exponent)?, I256::from(SQRT_2PI))
+}
+
+/// The cumulative distribution function of the standard normal distribution
+/// at the WAD `x`, as `Gaussian.cdf` computes it.
+pub fn cdf(x: I256) -> Option<I256> {
+    let negated = div_iwad(x, I256::from(SQRT_2))?.checked_neg()?;
+    Some(erfc(negated)?.checked_mul(IWAD)? / (IWAD * I256::from(2)))
+}
+
+/// The inverse of the cumulative distribution function of the standard
+/// normal distribution, also known as the percent point function, at the
+/// WAD `x`, as `Gaussian.ppf` computes it. Returns `None` unless `x` is a
+/// probability strictly between zero and one.
+pub fn ppf(x: I256) -> Option<I256> {
+    if x == IWAD / I256::from(2) {
+        return Some(I256::zero());
+    }
+    if x >= IWAD {
+        return None;
+    }
+    mul_iwad(-I256::from(SQRT_2), ierfc(x.checked_mul(I256::from(2))?)?)
+}
+
+/// Converts a floating-point number to a signed WAD, rounding towards zero.
+pub fn float_to_iwad(x: f64) -> I256 {
+    I256::from((x * 1e18) as i128)
+}
+
+/// Converts a signed WAD to a floating-point number.
+pub fn iwad_to_float(x: I256) -> f64 {
+    x.as_i128() as f64 / 1e18
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gaussian() {
+        let one = I256::one();
+        assert_eq!(cdf(one), Some(I256::from(500000000000000000_i128)));
+        assert_eq!(pdf(one), Some(I256::from(398942280401432678_i128)));
+        assert_eq!(ppf(one), Some(I256::from(-8710427241990476442_i128)));
+        assert_eq!(ppf(IWAD), None);
+        assert_eq!(ppf(I256::zero()), None);
+    }
+
+    #[test]
+    fn logarithm_and_exponential() {
+        assert_eq!(ln_wad(IWAD), Some(I256::zero()));
+        assert_eq!(ln_wad(I256::zero()), None);
+        assert_eq!(exp_wad(I256::zero()), Some(IWAD));
+        assert_eq!(exp_wad(I256::from(136) * IWAD), None);
+    }
+
+    #[test]
+    fn fixed_point() {
+        assert_eq!(mul_wad_down(WAD, U256::from(2)), Some(U256::from(2)));
+        assert_eq!(
+            div_wad_up(U256::from(1), U256::from(3)),
+            Some(U256::from(333333333333333334_u64))
+        );
+        assert_eq!(sqrt_wad(U256::from(4) * WAD), Some(U256::from(2) * WAD));
+        assert_eq!(div_wad_down(WAD, U256::zero()), None);
+    }
+}
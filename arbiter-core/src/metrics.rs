@@ -0,0 +1,322 @@
+//! The `metrics` module provides the [`Accountant`] which keeps track of the
+//! portfolios of accounts over the course of a simulation.
+//!
+//! At each point in time the [`Accountant`] is asked to [`Accountant::record`],
+//! it reads the ether and token balances of every tracked account from the
+//! [`Environment`], values them against the reference prices it is given, and
+//! stores a [`Snapshot`]. The profit and loss (PnL) of an account is the change
+//! in the value of its portfolio since the first snapshot. It is marked to
+//! market: every balance is valued at the latest prices, so the PnL includes
+//! gains and losses on balances that are still held rather than only those
+//! realized by trading.
+//!
+//! The history of snapshots can be serialized directly, written to a CSV file
+//! with [`Accountant::write_csv`], or exported along with the events of a
+//! simulation with
+//! [`EventLogger::add_metrics`](crate::data_collection::EventLogger::add_metrics).
+//! For dataframes, [`Accountant::records`]
+//! flattens the history into one [`PortfolioRecord`] per account per snapshot,
+//! which serializes to JSON as
+//!
+//! ```json
+//! {
+//!   "block_number": 12,
+//!   "block_timestamp": 144,
+//!   "account": "trader",
+//!   "ether": 1.5,
+//!   "ARBX": 10.0,
+//!   "ARBY": 4200.0,
+//!   "value": 4203.0,
+//!   "pnl": -12.5
+//! }
+//! ```
+//!
+//! with a column for the balance of each tracked token, keyed by its symbol.
+
+#![warn(missing_docs)]
+
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+use ethers::{
+    providers::Middleware,
+    types::{Address, U256},
+    utils::format_units,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(doc, doc(hidden))]
+#[cfg_attr(doc, allow(unused_imports))]
+#[cfg(doc)]
+use crate::environment::Environment;
+use crate::middleware::{errors::RevmMiddlewareError, RevmMiddleware};
+
+/// The key of the price of ether in the prices given to
+/// [`Accountant::record`].
+pub const ETHER: &str = "ETH";
+
+/// The portfolio of a single account at a point in time.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Portfolio {
+    /// The ether balance of the account in ether.
+    pub ether: f64,
+
+    /// The token balances of the account in whole tokens, keyed by the token's
+    /// symbol.
+    pub tokens: BTreeMap<String, f64>,
+
+    /// The value of the ether and tokens at the reference prices.
+    pub value: f64,
+
+    /// The change in value since the first snapshot of the account, marked to
+    /// market at the reference prices.
+    pub pnl: f64,
+}
+
+/// The portfolios of all tracked accounts at a point in time.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The block number the snapshot was taken at.
+    pub block_number: u64,
+
+    /// The block timestamp the snapshot was taken at.
+    pub block_timestamp: u64,
+
+    /// The reference prices the portfolios were valued at.
+    pub prices: BTreeMap<String, f64>,
+
+    /// The portfolios keyed by the label of the account.
+    pub portfolios: BTreeMap<String, Portfolio>,
+}
+
+/// The portfolio of a single account in a single [`Snapshot`], flattened into
+/// one row of a table.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PortfolioRecord {
+    /// The block number the snapshot was taken at.
+    pub block_number: u64,
+
+    /// The block timestamp the snapshot was taken at.
+    pub block_timestamp: u64,
+
+    /// The label of the account.
+    pub account: String,
+
+    /// The ether balance of the account in ether.
+    pub ether: f64,
+
+    /// The token balances of the account in whole tokens, keyed by the token's
+    /// symbol. Each token is a column of its own when serialized.
+    #[serde(flatten)]
+    pub tokens: BTreeMap<String, f64>,
+
+    /// The value of the ether and tokens at the reference prices.
+    pub value: f64,
+
+    /// The change in value since the first snapshot of the account, marked to
+    /// market at the reference prices.
+    pub pnl: f64,
+}
+
+/// An ERC-20 token tracked by the [`Accountant`].
+#[derive(Clone, Debug)]
+struct TrackedToken {
+    address: Address,
+    decimals: u8,
+}
+
+/// Tracks the ether and token balances, the value, and the mark-to-market PnL
+/// of a set of accounts over time.
+#[derive(Debug)]
+pub struct Accountant {
+    client: Arc<RevmMiddleware>,
+    accounts: BTreeMap<String, Address>,
+    tokens: BTreeMap<String, TrackedToken>,
+    history: Vec<Snapshot>,
+}
+
+impl Accountant {
+    /// Creates a new [`Accountant`] that reads balances through the `client`.
+    pub fn new(client: Arc<RevmMiddleware>) -> Self {
+        Self {
+            client,
+            accounts: BTreeMap::new(),
+            tokens: BTreeMap::new(),
+            history: vec![],
+        }
+    }
+
+    /// Tracks the portfolio of the account at `address` under `label`.
+    pub fn track_account(mut self, label: impl Into<String>, address: Address) -> Self {
+        self.accounts.insert(label.into(), address);
+        self
+    }
+
+    /// Tracks the balances of the ERC-20 token at `address` under `symbol`.
+    /// The `symbol` is also the key of the token's price in the prices given
+    /// to [`Accountant::record`].
+    pub fn track_token(
+        mut self,
+        symbol: impl Into<String>,
+        address: Address,
+        decimals: u8,
+    ) -> Self {
+        self.tokens
+            .insert(symbol.into(), TrackedToken { address, decimals });
+        self
+    }
+
+    /// Reads the balances of every tracked account, values them at `prices`,
+    /// and stores the resulting [`Snapshot`].
+    ///
+    /// # Arguments
+    ///
+    /// * `prices` - The reference price of every tracked token keyed by its
+    ///   symbol and the price of ether keyed by [`ETHER`]. Ether is valued at
+    ///   zero if its price is left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the price of a tracked token is missing or if a
+    /// balance could not be read.
+    pub async fn record(
+        &mut self,
+        prices: BTreeMap<String, f64>,
+    ) -> Result<&Snapshot, RevmMiddlewareError> {
+        if let Some(symbol) = self
+            .tokens
+            .keys()
+            .find(|symbol| !prices.contains_key(*symbol))
+        {
+            return Err(RevmMiddlewareError::MissingData(format!(
+                "No price was given for the token {}!",
+                symbol
+            )));
+        }
+        // The balances of all the accounts in a token are read in one batch.
+        let addresses: Vec<Address> = self.accounts.values().copied().collect();
+        let mut token_balances = BTreeMap::new();
+        for (symbol, token) in &self.tokens {
+            let balances = self.client.balances_of(token.address, &addresses).await?;
+            token_balances.insert(symbol, balances);
+        }
+        let mut portfolios = BTreeMap::new();
+        for (index, (label, address)) in self.accounts.iter().enumerate() {
+            let ether = to_float(self.client.get_balance(*address, None).await?, 18)?;
+            let mut value = ether * prices.get(ETHER).copied().unwrap_or_default();
+            let mut tokens = BTreeMap::new();
+            for (symbol, token) in &self.tokens {
+                let balance = to_float(token_balances[symbol][index], token.decimals)?;
+                value += balance * prices[symbol];
+                tokens.insert(symbol.clone(), balance);
+            }
+            let initial_value = self
+                .history
+                .iter()
+                .find_map(|snapshot| snapshot.portfolios.get(label))
+                .map_or(value, |portfolio| portfolio.value);
+            portfolios.insert(
+                label.clone(),
+                Portfolio {
+                    ether,
+                    tokens,
+                    value,
+                    pnl: value - initial_value,
+                },
+            );
+        }
+        self.history.push(Snapshot {
+            block_number: self.client.get_block_number().await?.as_u64(),
+            block_timestamp: self.client.get_block_timestamp().await?.as_u64(),
+            prices,
+            portfolios,
+        });
+        Ok(self.history.last().unwrap())
+    }
+
+    /// All the snapshots recorded so far, in order.
+    pub fn history(&self) -> &[Snapshot] {
+        &self.history
+    }
+
+    /// The client the balances are read through.
+    pub(crate) fn client(&self) -> &Arc<RevmMiddleware> {
+        &self.client
+    }
+
+    /// The most recent snapshot, if any was recorded.
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.history.last()
+    }
+
+    /// The history flattened into one [`PortfolioRecord`] per account per
+    /// snapshot, in order.
+    pub fn records(&self) -> Vec<PortfolioRecord> {
+        self.records_since(0)
+    }
+
+    /// The snapshots from the one at index `snapshot` on, flattened like
+    /// [`Accountant::records`].
+    pub(crate) fn records_since(&self, snapshot: usize) -> Vec<PortfolioRecord> {
+        self.history
+            .iter()
+            .skip(snapshot)
+            .flat_map(|snapshot| {
+                snapshot
+                    .portfolios
+                    .iter()
+                    .map(|(label, portfolio)| PortfolioRecord {
+                        block_number: snapshot.block_number,
+                        block_timestamp: snapshot.block_timestamp,
+                        account: label.clone(),
+                        ether: portfolio.ether,
+                        tokens: portfolio.tokens.clone(),
+                        value: portfolio.value,
+                        pnl: portfolio.pnl,
+                    })
+            })
+            .collect()
+    }
+
+    /// Writes the history to a CSV file at `path` with one row per account
+    /// per snapshot. A token tracked only after a snapshot was recorded has an
+    /// empty cell in that snapshot's rows.
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let mut columns = vec![
+            "block_number".to_string(),
+            "block_timestamp".to_string(),
+            "account".to_string(),
+            "ether".to_string(),
+        ];
+        columns.extend(self.tokens.keys().cloned());
+        columns.extend(["value".to_string(), "pnl".to_string()]);
+        let mut lines = vec![columns.join(",")];
+        for record in self.records() {
+            let mut row = vec![
+                record.block_number.to_string(),
+                record.block_timestamp.to_string(),
+                record.account,
+                record.ether.to_string(),
+            ];
+            row.extend(
+                self.tokens
+                    .keys()
+                    .map(|symbol| match record.tokens.get(symbol) {
+                        Some(balance) => balance.to_string(),
+                        None => String::new(),
+                    }),
+            );
+            row.extend([record.value.to_string(), record.pnl.to_string()]);
+            lines.push(row.join(","));
+        }
+        lines.push(String::new());
+        std::fs::write(path, lines.join("\n"))
+    }
+}
+
+/// Converts an amount with the given number of `decimals` into a float.
+fn to_float(amount: U256, decimals: u8) -> Result<f64, RevmMiddlewareError> {
+    format_units(amount, decimals as u32)
+        .map_err(|e| RevmMiddlewareError::Conversion(e.to_string()))?
+        .parse()
+        .map_err(|e: std::num::ParseFloatError| RevmMiddlewareError::Conversion(e.to_string()))
+}
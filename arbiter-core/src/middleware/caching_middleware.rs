@@ -0,0 +1,182 @@
+//! The `caching_middleware` module provides a middleware implementation that
+//! memoizes the results of view calls, so that many agents polling the same
+//! contract state every step do not each have the [`Environment`] execute the
+//! same call again.
+//!
+//! A cached result is only reused while the state it was read from cannot
+//! have changed. The cache is cleared whenever the [`Environment`] moves on to
+//! a new block or executes a transaction from any client, which the
+//! middleware learns about from filters installed on the inner middleware.
+//! Cheatcodes that write state (e.g., [`Cheatcodes::Store`] or
+//! [`Cheatcodes::Deal`]) are not seen by the filters, so the cache has to be
+//! cleared with [`CachingMiddleware::clear`] after using them.
+//!
+//! Main components:
+//! - [`CachingMiddleware`]: The core middleware implementation.
+//! - [`CachingMiddlewareError`]: Error type for the middleware.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{FilterKind, Middleware, MiddlewareError},
+    types::{transaction::eip2718::TypedTransaction, *},
+};
+use thiserror::Error;
+
+#[cfg_attr(doc, doc(hidden))]
+#[cfg_attr(doc, allow(unused_imports))]
+#[cfg(doc)]
+use crate::environment::{cheatcodes::Cheatcodes, Environment};
+
+/// What a cached call result is looked up by: the sender, the contract, and
+/// the calldata of the call.
+type CallKey = (Option<Address>, Address, Bytes);
+
+#[derive(Debug)]
+/// Middleware that memoizes the results of calls made against the latest
+/// block until the state they were read from changes.
+pub struct CachingMiddleware<M> {
+    inner: M,
+    filters: futures_locks::Mutex<Option<(U256, U256)>>,
+    cache: Mutex<HashMap<CallKey, Bytes>>,
+    hits: AtomicU64,
+}
+
+impl<M> CachingMiddleware<M>
+where
+    M: Middleware,
+{
+    /// Wraps the `inner` middleware with an empty cache.
+    pub fn new(inner: M) -> Self {
+        Self {
+            inner,
+            filters: Default::default(),
+            cache: Default::default(),
+            hits: Default::default(),
+        }
+    }
+
+    /// Removes every cached call result.
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// The number of calls that were answered from the cache so far.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::SeqCst)
+    }
+
+    /// Clears the cache if a new block or a transaction has been seen since
+    /// the last time this was checked. The filters that report them are
+    /// installed the first time this is called.
+    async fn invalidate(&self) -> Result<(), CachingMiddlewareError<M>> {
+        let mut filters = self.filters.lock().await;
+        let (blocks, transactions) = match *filters {
+            Some(filters) => filters,
+            None => {
+                let blocks = self
+                    .inner
+                    .new_filter(FilterKind::NewBlocks)
+                    .await
+                    .map_err(MiddlewareError::from_err)?;
+                let transactions = self
+                    .inner
+                    .new_filter(FilterKind::PendingTransactions)
+                    .await
+                    .map_err(MiddlewareError::from_err)?;
+                *filters = Some((blocks, transactions));
+                // Nothing can have been cached before the filters existed.
+                return Ok(());
+            }
+        };
+        let new_blocks: Vec<H256> = self
+            .inner
+            .get_filter_changes(blocks)
+            .await
+            .map_err(MiddlewareError::from_err)?;
+        let new_transactions: Vec<H256> = self
+            .inner
+            .get_filter_changes(transactions)
+            .await
+            .map_err(MiddlewareError::from_err)?;
+        if !new_blocks.is_empty() || !new_transactions.is_empty() {
+            self.clear();
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+/// Thrown when an error happens at the Caching Middleware
+pub enum CachingMiddlewareError<M: Middleware> {
+    /// Thrown when the internal middleware errors
+    #[error("{0}")]
+    MiddlewareError(M::Error),
+}
+
+impl<M: Middleware> MiddlewareError for CachingMiddlewareError<M> {
+    type Inner = M::Error;
+
+    fn from_err(src: M::Error) -> Self {
+        CachingMiddlewareError::MiddlewareError(src)
+    }
+
+    fn as_inner(&self) -> Option<&Self::Inner> {
+        match self {
+            CachingMiddlewareError::MiddlewareError(e) => Some(e),
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<M> Middleware for CachingMiddleware<M>
+where
+    M: Middleware,
+{
+    type Error = CachingMiddlewareError<M>;
+    type Provider = M::Provider;
+    type Inner = M;
+
+    fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    /// Returns the cached result of the call if the same sender already made
+    /// it with the same calldata since the last block or transaction, and
+    /// otherwise makes the call and caches its result. Calls at a specific
+    /// `block` and calls without a recipient or calldata are passed through.
+    async fn call(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<Bytes, Self::Error> {
+        let (Some(to), Some(data), None) = (tx.to_addr(), tx.data(), block) else {
+            return self
+                .inner
+                .call(tx, block)
+                .await
+                .map_err(MiddlewareError::from_err);
+        };
+        let key = (tx.from().copied(), *to, data.clone());
+        self.invalidate().await?;
+        if let Some(output) = self.cache.lock().unwrap().get(&key) {
+            self.hits.fetch_add(1, Ordering::SeqCst);
+            return Ok(output.clone());
+        }
+        let output = self
+            .inner
+            .call(tx, block)
+            .await
+            .map_err(MiddlewareError::from_err)?;
+        self.cache.lock().unwrap().insert(key, output.clone());
+        Ok(output)
+    }
+}
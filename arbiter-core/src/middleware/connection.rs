@@ -1,38 +1,48 @@
 //! Messengers/connections to the underlying EVM in the environment.
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fmt::Debug,
+    pin::Pin,
     sync::{Arc, Mutex, Weak},
+    task::{ready, Context, Poll},
 };
 
 use ethers::{
-    prelude::ProviderError,
-    providers::JsonRpcClient,
-    types::{Filter, FilteredParams},
+    prelude::{
+        k256::sha2::{Digest, Sha256},
+        ProviderError,
+    },
+    providers::{JsonRpcClient, PubsubClient},
+    types::{
+        transaction::eip2718::TypedTransaction, Block, BlockId, BlockNumber, Filter,
+        FilterBlockOption, FilteredParams, Log, Transaction, TransactionReceipt, TxHash, H256, U64,
+    },
 };
+use futures_util::Stream;
 use serde::{de::DeserializeOwned, Serialize};
+use serde_json::value::RawValue;
+use tokio::sync::mpsc::UnboundedReceiver;
 
 use super::cast::revm_logs_to_ethers_logs;
-use crate::environment::{EventBroadcaster, InstructionSender, OutcomeReceiver, OutcomeSender};
+use crate::environment::{
+    abi_registry::AbiRegistry,
+    history::{BlockStore, ReceiptStore},
+    state_diff::StateDiffStore,
+    Broadcast, EventBroadcaster, InstructionSender,
+};
 
 /// Represents a connection to the EVM contained in the corresponding
 /// [`Environment`].
-#[derive(Debug)]
+///
+/// Every instruction a [`Connection`] sends carries a channel of its own for
+/// the [`Environment`] to reply on, so a client can be shared between many
+/// tasks that make calls and send transactions at the same time.
+#[derive(Debug, Clone)]
 pub struct Connection {
     /// Used to send calls and transactions to the [`Environment`] to be
     /// executed by `revm`.
     pub(crate) instruction_sender: Weak<InstructionSender>,
 
-    /// Used to send results back to a client that made a call/transaction with
-    /// the [`Environment`]. This [`ResultSender`] is passed along with a
-    /// call/transaction so the [`Environment`] can reply back with the
-    /// [`ExecutionResult`].
-    pub(crate) outcome_sender: OutcomeSender,
-
-    /// Used to receive the [`ExecutionResult`] from the [`Environment`] upon
-    /// call/transact.
-    pub(crate) outcome_receiver: OutcomeReceiver,
-
     /// A reference to the [`EventBroadcaster`] so that more receivers of the
     /// broadcast can be taken from it.
     pub(crate) event_broadcaster: Arc<Mutex<EventBroadcaster>>,
@@ -41,6 +51,29 @@ pub struct Connection {
     /// generated by `revm` and output by the [`Environment`].
     pub(crate) filter_receivers:
         Arc<tokio::sync::Mutex<HashMap<ethers::types::U256, FilterReceiver>>>,
+
+    /// Subscriptions that have been requested via `eth_subscribe` but whose
+    /// [`NotificationStream`] has not yet been handed out via
+    /// [`PubsubClient::subscribe`].
+    pub(crate) subscriptions: Arc<Mutex<HashMap<ethers::types::U256, NotificationStream>>>,
+
+    /// The receipts of every block, shared by all the clients of the
+    /// [`Environment`], if it was built to store them.
+    pub(crate) receipts: Option<ReceiptStore>,
+
+    /// What the [`Environment`] has kept about each of its blocks.
+    pub(crate) blocks: BlockStore,
+
+    /// The ABIs of the contracts in the [`Environment`] that reverts are
+    /// decoded with.
+    pub(crate) abi_registry: AbiRegistry,
+
+    /// The state diff of every transaction, shared by all the clients of the
+    /// [`Environment`], if it was built to record them.
+    pub(crate) state_diffs: Option<StateDiffStore>,
+
+    /// The chain ID of the [`Environment`].
+    pub(crate) chain_id: u64,
 }
 
 #[async_trait::async_trait]
@@ -48,8 +81,12 @@ impl JsonRpcClient for Connection {
     type Error = ProviderError;
 
     /// Processes a JSON-RPC request and returns the response.
-    /// Currently only handles the `eth_getFilterChanges` call since this is
-    /// used for polling events emitted from the [`Environment`].
+    /// Currently handles the `eth_newBlockFilter`,
+    /// `eth_newPendingTransactionFilter`, and `eth_getFilterChanges` calls
+    /// used for polling events emitted from the [`Environment`] along with
+    /// `eth_subscribe` and `eth_unsubscribe` for pushing them instead.
+    /// `eth_getBlockReceipts` and `eth_getLogs` are handled when the
+    /// [`Environment`] stores receipts.
     async fn request<T: Serialize + Send + Sync, R: DeserializeOwned>(
         &self,
         method: &str,
@@ -57,9 +94,6 @@ impl JsonRpcClient for Connection {
     ) -> Result<R, ProviderError> {
         match method {
             "eth_getFilterChanges" => {
-                // TODO: The extra json serialization/deserialization can probably be avoided
-                // somehow
-
                 // Get the `Filter` ID from the params `T`
                 // First convert it into a JSON `Value`
                 let value = serde_json::to_value(&params)?;
@@ -88,38 +122,462 @@ impl JsonRpcClient for Connection {
                             "The filter ID does not seem to match any that this client owns!"
                                 .to_string(),
                         ))?;
-                let mut logs = vec![];
-                let filtered_params = FilteredParams::new(Some(filter_receiver.filter.clone()));
-                if let Ok(received_logs) = filter_receiver.receiver.try_recv() {
-                    let ethers_logs = revm_logs_to_ethers_logs(received_logs);
-                    for log in ethers_logs {
-                        if filtered_params.filter_address(&log)
-                            && filtered_params.filter_topics(&log)
-                        {
-                            logs.push(log);
+                let mut changes = vec![];
+                while let Ok(broadcast) = filter_receiver.receiver.try_recv() {
+                    match (&filter_receiver.kind, broadcast) {
+                        (ListenerKind::Logs(filtered_params), Broadcast::Logs(received_logs)) => {
+                            for log in revm_logs_to_ethers_logs(received_logs) {
+                                if filtered_params.filter_address(&log)
+                                    && filtered_params.filter_topics(&log)
+                                {
+                                    changes.push(serde_json::to_value(log)?);
+                                }
+                            }
+                        }
+                        (ListenerKind::NewBlocks, Broadcast::NewBlock { number, .. }) => {
+                            changes.push(serde_json::to_value(block_hash(number))?);
+                        }
+                        (
+                            ListenerKind::PendingTransactions,
+                            Broadcast::PendingTransaction(hash),
+                        ) => {
+                            changes.push(serde_json::to_value(hash)?);
                         }
+                        _ => {}
                     }
                 }
-                // Take the logs and Stringify then JSONify to cast into `R`.
-                let logs_str = serde_json::to_string(&logs)?;
-                let logs_deserializeowned: R = serde_json::from_str(&logs_str)?;
-                Ok(logs_deserializeowned)
+                // `R` is only known to be deserializable, so the changes still make a trip
+                // through JSON `Value`s on their way to it, but not through a string.
+                Ok(serde_json::from_value(serde_json::Value::Array(changes))?)
+            }
+            "eth_chainId" => Ok(serde_json::from_value(serde_json::to_value(
+                ethers::types::U256::from(self.chain_id),
+            )?)?),
+            "eth_newBlockFilter" => {
+                let id = self.install_filter(ListenerKind::NewBlocks).await?;
+                Ok(serde_json::from_value(serde_json::to_value(id)?)?)
+            }
+            "eth_newPendingTransactionFilter" => {
+                let id = self
+                    .install_filter(ListenerKind::PendingTransactions)
+                    .await?;
+                Ok(serde_json::from_value(serde_json::to_value(id)?)?)
+            }
+            "eth_getBlockReceipts" => {
+                let receipts = self.receipts.as_ref().ok_or(ProviderError::CustomError(
+                    "The `Environment` was not built to store receipts!".to_string(),
+                ))?;
+                let value = serde_json::to_value(&params)?;
+                let block_number = match value
+                    .as_array()
+                    .and_then(|params| params.first())
+                    .cloned()
+                    .map(serde_json::from_value::<BlockNumber>)
+                    .transpose()?
+                {
+                    Some(BlockNumber::Number(block_number)) => block_number.as_u64(),
+                    Some(BlockNumber::Latest) | None => receipts
+                        .latest_block()
+                        .map_err(|e| ProviderError::CustomError(e.to_string()))?
+                        .unwrap_or_default(),
+                    Some(block_number) => {
+                        return Err(ProviderError::CustomError(format!(
+                            "Receipts can only be retrieved by block number or for the latest block, not for {:?}!",
+                            block_number
+                        )))
+                    }
+                };
+                let block_receipts = receipts
+                    .block_receipts(block_number)
+                    .map_err(|e| ProviderError::CustomError(e.to_string()))?;
+                Ok(serde_json::from_value(serde_json::to_value(
+                    block_receipts,
+                )?)?)
+            }
+            "eth_getLogs" => {
+                let value = serde_json::to_value(&params)?;
+                let filter = value
+                    .as_array()
+                    .and_then(|params| params.first())
+                    .cloned()
+                    .map(serde_json::from_value::<Filter>)
+                    .transpose()?
+                    .unwrap_or_default();
+                Ok(serde_json::from_value(serde_json::to_value(
+                    self.logs(&filter)?,
+                )?)?)
+            }
+            "eth_subscribe" => {
+                let value = serde_json::to_value(&params)?;
+                let params = value.as_array().ok_or(ProviderError::CustomError(
+                    "The params passed to `eth_subscribe` were not an array!".to_string(),
+                ))?;
+                let kind = match params.first().and_then(|kind| kind.as_str()) {
+                    Some("logs") => {
+                        let filter = match params.get(1) {
+                            Some(filter) => serde_json::from_value(filter.clone())?,
+                            None => Filter::default(),
+                        };
+                        ListenerKind::Logs(Box::new(FilteredParams::new(Some(filter))))
+                    }
+                    Some("newHeads") => ListenerKind::NewBlocks,
+                    Some("newPendingTransactions") => ListenerKind::PendingTransactions,
+                    _ => return Err(ProviderError::UnsupportedRPC),
+                };
+
+                let (event_sender, event_receiver) = tokio::sync::mpsc::unbounded_channel();
+                self.event_broadcaster
+                    .lock()
+                    .map_err(|e| ProviderError::CustomError(e.to_string()))?
+                    .add_sender(event_sender);
+                let id = ethers::types::U256::from(rand::random::<u128>());
+                self.subscriptions
+                    .lock()
+                    .map_err(|e| ProviderError::CustomError(e.to_string()))?
+                    .insert(
+                        id,
+                        NotificationStream {
+                            kind,
+                            receiver: event_receiver,
+                            pending: VecDeque::new(),
+                        },
+                    );
+                Ok(serde_json::from_value(serde_json::to_value(id)?)?)
+            }
+            "eth_unsubscribe" => {
+                let value = serde_json::to_value(&params)?;
+                let id: ethers::types::U256 = serde_json::from_value(
+                    value
+                        .as_array()
+                        .and_then(|ids| ids.first())
+                        .cloned()
+                        .ok_or(ProviderError::CustomError(
+                            "No subscription ID was passed to `eth_unsubscribe`!".to_string(),
+                        ))?,
+                )?;
+                self.unsubscribe(id)?;
+                Ok(serde_json::from_value(serde_json::Value::Bool(true))?)
             }
             _ => Err(ProviderError::UnsupportedRPC),
         }
     }
 }
 
-/// Packages together a [`crossbeam_channel::Receiver<Vec<Log>>`] along with a
-/// [`Filter`] for events. Allows the client to have a stream of filtered
-/// events.
+impl Connection {
+    /// Keeps a transaction and its receipt if the [`Environment`] was built to
+    /// store receipts.
+    pub(crate) fn store_receipt(
+        &self,
+        tx: &TypedTransaction,
+        receipt: &TransactionReceipt,
+    ) -> Result<(), ProviderError> {
+        let (Some(receipts), Some(block_number)) = (&self.receipts, receipt.block_number) else {
+            return Ok(());
+        };
+        let transaction = Transaction {
+            hash: receipt.transaction_hash,
+            nonce: tx.nonce().copied().unwrap_or_default(),
+            block_hash: receipt.block_hash,
+            block_number: receipt.block_number,
+            transaction_index: Some(receipt.transaction_index),
+            from: receipt.from,
+            to: receipt.to,
+            value: tx.value().copied().unwrap_or_default(),
+            gas_price: receipt.effective_gas_price,
+            gas: tx.gas().copied().unwrap_or_default(),
+            input: tx.data().cloned().unwrap_or_default(),
+            transaction_type: receipt.transaction_type,
+            access_list: tx.access_list().cloned(),
+            chain_id: tx.chain_id().map(|chain_id| chain_id.as_u64().into()),
+            ..Default::default()
+        };
+        receipts
+            .insert(block_number.as_u64(), transaction, receipt.clone())
+            .map_err(|e| ProviderError::CustomError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// The logs in the stored receipts that match the `filter`, in the order
+    /// they were emitted. The bloom filter of each block in the range is
+    /// checked first so that only the receipts of blocks that may hold a
+    /// matching log are looked at.
+    pub(crate) fn logs(&self, filter: &Filter) -> Result<Vec<Log>, ProviderError> {
+        let receipts = self.receipts.as_ref().ok_or(ProviderError::CustomError(
+            "The `Environment` was not built to store receipts!".to_string(),
+        ))?;
+        let latest = self
+            .blocks
+            .last()
+            .map_err(|e| ProviderError::CustomError(e.to_string()))?
+            .unwrap_or_default();
+        let resolve = |block_number: Option<BlockNumber>| match block_number {
+            Some(BlockNumber::Number(block_number)) => block_number.as_u64(),
+            Some(BlockNumber::Earliest) => 0,
+            _ => latest,
+        };
+        let (from_block, to_block) = match filter.block_option {
+            FilterBlockOption::Range {
+                from_block,
+                to_block,
+            } => (resolve(from_block), resolve(to_block)),
+            FilterBlockOption::AtBlockHash(hash) => {
+                match self
+                    .blocks
+                    .find(|number| block_hash(revm::primitives::U256::from(number)) == hash)
+                    .map_err(|e| ProviderError::CustomError(e.to_string()))?
+                {
+                    Some(number) => (number, number),
+                    None => return Ok(vec![]),
+                }
+            }
+        };
+        if from_block > to_block {
+            return Ok(vec![]);
+        }
+
+        let filtered_params = FilteredParams::new(Some(filter.clone()));
+        let address_filter = FilteredParams::address_filter(&filter.address);
+        let topics_filter =
+            FilteredParams::topics_filter(&Some(filtered_params.flat_topics.clone()));
+        let mut logs = vec![];
+        let blooms = receipts
+            .blooms(from_block, to_block)
+            .map_err(|e| ProviderError::CustomError(e.to_string()))?;
+        for (number, bloom) in blooms {
+            if !FilteredParams::matches_address(bloom, &address_filter)
+                || !FilteredParams::matches_topics(bloom, &topics_filter)
+            {
+                continue;
+            }
+            let block_receipts = receipts
+                .block_receipts(number)
+                .map_err(|e| ProviderError::CustomError(e.to_string()))?;
+            let hash = block_hash(revm::primitives::U256::from(number));
+            let block_logs = block_receipts.iter().flat_map(|receipt| {
+                receipt
+                    .logs
+                    .iter()
+                    .enumerate()
+                    .map(move |(transaction_log_index, log)| (receipt, transaction_log_index, log))
+            });
+            for (log_index, (receipt, transaction_log_index, log)) in block_logs.enumerate() {
+                if !filtered_params.filter_address(log) || !filtered_params.filter_topics(log) {
+                    continue;
+                }
+                logs.push(Log {
+                    block_hash: Some(hash),
+                    block_number: Some(U64::from(number)),
+                    transaction_hash: Some(receipt.transaction_hash),
+                    transaction_index: Some(receipt.transaction_index),
+                    log_index: Some(log_index.into()),
+                    transaction_log_index: Some(transaction_log_index.into()),
+                    removed: Some(false),
+                    ..log.clone()
+                });
+            }
+        }
+        Ok(logs)
+    }
+
+    /// The transaction with the given `hash` and its receipt, if the
+    /// [`Environment`] was built to store receipts and has executed it.
+    pub(crate) fn stored_transaction(
+        &self,
+        hash: H256,
+    ) -> Result<Option<(Transaction, TransactionReceipt)>, ProviderError> {
+        let receipts = self.receipts.as_ref().ok_or(ProviderError::CustomError(
+            "The `Environment` was not built to store receipts!".to_string(),
+        ))?;
+        receipts
+            .transaction(hash)
+            .map_err(|e| ProviderError::CustomError(e.to_string()))
+    }
+
+    /// Builds the block object for the block with the given ID from what the
+    /// [`Environment`] has kept about it. Returns `None` if the
+    /// [`Environment`] has not reached the block.
+    pub(crate) fn block(&self, block_id: BlockId) -> Result<Option<Block<TxHash>>, ProviderError> {
+        let number = match block_id {
+            BlockId::Hash(hash) => self
+                .blocks
+                .find(|number| block_hash(revm::primitives::U256::from(number)) == hash),
+            BlockId::Number(BlockNumber::Number(number)) => Ok(Some(number.as_u64())),
+            BlockId::Number(BlockNumber::Earliest) => self.blocks.first(),
+            BlockId::Number(_) => self.blocks.last(),
+        }
+        .map_err(|e| ProviderError::CustomError(e.to_string()))?;
+        let record = match number {
+            Some(number) => self
+                .blocks
+                .get(number)
+                .map_err(|e| ProviderError::CustomError(e.to_string()))?,
+            None => None,
+        };
+        let (Some(number), Some(record)) = (number, record) else {
+            return Ok(None);
+        };
+        Ok(Some(Block {
+            hash: Some(block_hash(revm::primitives::U256::from(number))),
+            parent_hash: match number {
+                0 => H256::zero(),
+                _ => block_hash(revm::primitives::U256::from(number - 1)),
+            },
+            number: Some(U64::from(number)),
+            timestamp: ethers::types::U256::from(record.timestamp.to_be_bytes()),
+            gas_limit: ethers::types::U256::from(record.gas_limit.to_be_bytes()),
+            gas_used: ethers::types::U256::from(record.gas_used.to_be_bytes()),
+            base_fee_per_gas: Some(ethers::types::U256::from(record.base_fee.to_be_bytes())),
+            transactions: record.transactions,
+            ..Default::default()
+        }))
+    }
+
+    /// Installs a filter of the given kind by registering a new sender with
+    /// the [`EventBroadcaster`] and returns the ID that the filter's changes
+    /// can be polled with via `eth_getFilterChanges`.
+    pub(crate) async fn install_filter(
+        &self,
+        kind: ListenerKind,
+    ) -> Result<ethers::types::U256, ProviderError> {
+        let (event_sender, event_receiver) = tokio::sync::mpsc::unbounded_channel();
+        self.event_broadcaster
+            .lock()
+            .map_err(|e| ProviderError::CustomError(e.to_string()))?
+            .add_sender(event_sender);
+        let id = ethers::types::U256::from(rand::random::<u128>());
+        self.filter_receivers.lock().await.insert(
+            id,
+            FilterReceiver {
+                kind,
+                receiver: event_receiver,
+            },
+        );
+        Ok(id)
+    }
+}
+
+impl PubsubClient for Connection {
+    type NotificationStream = NotificationStream;
+
+    /// Hands out the [`NotificationStream`] for a subscription that was set up
+    /// with an `eth_subscribe` request. Notifications are pushed into the
+    /// stream by the [`EventBroadcaster`] as soon as the [`Environment`]
+    /// produces them.
+    fn subscribe<T: Into<ethers::types::U256>>(
+        &self,
+        id: T,
+    ) -> Result<Self::NotificationStream, Self::Error> {
+        self.subscriptions
+            .lock()
+            .map_err(|e| ProviderError::CustomError(e.to_string()))?
+            .remove(&id.into())
+            .ok_or(ProviderError::CustomError(
+                "The subscription ID does not seem to match any that this client owns!".to_string(),
+            ))
+    }
+
+    /// Removes a subscription that has not yet been handed out. Once the
+    /// [`NotificationStream`] is dropped, the [`EventBroadcaster`] stops
+    /// sending to it on its own.
+    fn unsubscribe<T: Into<ethers::types::U256>>(&self, id: T) -> Result<(), Self::Error> {
+        self.subscriptions
+            .lock()
+            .map_err(|e| ProviderError::CustomError(e.to_string()))?
+            .remove(&id.into());
+        Ok(())
+    }
+}
+
+/// What a filter or a subscription made with `eth_subscribe` is listening
+/// for.
+#[derive(Debug)]
+pub(crate) enum ListenerKind {
+    /// Logs that pass the inner filter.
+    Logs(Box<FilteredParams>),
+
+    /// New blocks.
+    NewBlocks,
+
+    /// Transactions that were executed and are about to be committed.
+    PendingTransactions,
+}
+
+/// A stream of JSON-RPC notifications for a subscription that is fed directly
+/// by the [`EventBroadcaster`] of the [`Environment`].
+#[derive(Debug)]
+pub struct NotificationStream {
+    /// What the subscription is listening for.
+    kind: ListenerKind,
+
+    /// The receiver for the channel that receives broadcasts from the
+    /// [`Environment`].
+    receiver: UnboundedReceiver<Broadcast>,
+
+    /// Notifications that have been received but not yet yielded, since a
+    /// single broadcast can hold many logs.
+    pending: VecDeque<Box<RawValue>>,
+}
+
+impl Stream for NotificationStream {
+    type Item = Box<RawValue>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(notification) = self.pending.pop_front() {
+                return Poll::Ready(Some(notification));
+            }
+            let broadcast = match ready!(self.receiver.poll_recv(cx)) {
+                Some(broadcast) => broadcast,
+                None => return Poll::Ready(None),
+            };
+            let notifications = match (&self.kind, broadcast) {
+                (ListenerKind::Logs(filtered_params), Broadcast::Logs(logs)) => {
+                    revm_logs_to_ethers_logs(logs)
+                        .into_iter()
+                        .filter(|log| {
+                            filtered_params.filter_address(log)
+                                && filtered_params.filter_topics(log)
+                        })
+                        .filter_map(|log| serde_json::value::to_raw_value(&log).ok())
+                        .collect()
+                }
+                (ListenerKind::NewBlocks, Broadcast::NewBlock { number, timestamp }) => {
+                    let block: Block<TxHash> = Block {
+                        hash: Some(block_hash(number)),
+                        number: Some(U64::from(number.as_limbs()[0])),
+                        timestamp: ethers::types::U256::from(timestamp.to_be_bytes()),
+                        ..Default::default()
+                    };
+                    serde_json::value::to_raw_value(&block)
+                        .into_iter()
+                        .collect()
+                }
+                (ListenerKind::PendingTransactions, Broadcast::PendingTransaction(hash)) => {
+                    serde_json::value::to_raw_value(&hash).into_iter().collect()
+                }
+                _ => vec![],
+            };
+            self.pending.extend(notifications);
+        }
+    }
+}
+
+/// Packages together an [`UnboundedReceiver<Broadcast>`] along with what the
+/// filter is listening for. Allows the client to poll for filtered events.
 #[derive(Debug)]
 pub(crate) struct FilterReceiver {
-    /// The filter definition used for this receiver.
-    /// Comes from the `ethers-rs` crate.
-    pub(crate) filter: Filter,
+    /// What the filter is listening for, e.g., logs matching a [`Filter`].
+    pub(crate) kind: ListenerKind,
+
+    /// The receiver for the channel that receives broadcasts from the
+    /// broadcaster. These are filtered upon reception.
+    pub(crate) receiver: UnboundedReceiver<Broadcast>,
+}
 
-    /// The receiver for the channel that receives logs from the broadcaster.
-    /// These are filtered upon reception.
-    pub(crate) receiver: crossbeam_channel::Receiver<Vec<revm::primitives::Log>>,
+/// Hashes a block number the same way the [`RevmMiddleware`] does for the
+/// block hashes in its receipts.
+fn block_hash(number: revm::primitives::U256) -> H256 {
+    let mut block_hasher = Sha256::new();
+    block_hasher.update(U64::from(number.as_limbs()[0]).to_string().as_bytes());
+    H256::from_slice(&block_hasher.finalize())
 }
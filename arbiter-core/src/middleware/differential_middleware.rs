@@ -0,0 +1,403 @@
+//! The `differential_middleware` module provides a middleware implementation
+//! that replays every transaction a client sends to the [`Environment`] on a
+//! reference node, e.g., Anvil or Geth, and compares the results. This checks
+//! that the `revm` configuration of the [`Environment`] matches the semantics
+//! of a real chain.
+//!
+//! For each transaction, the status, the gas used, the address of a deployed
+//! contract, and the logs are compared. When the [`Environment`] is built with
+//! [`EnvironmentBuilder::record_state_diffs`], the balances, nonces, and
+//! storage slots of every account the transaction changed are compared as
+//! well. Each mismatch is kept as a [`Divergence`].
+//!
+//! The reference node has to start from the same state as the [`Environment`]
+//! and accept transactions from the addresses of its clients, e.g., an Anvil
+//! node started with `--auto-impersonate` and a base fee of zero. Everything
+//! that depends on the block, such as its number or timestamp, only matches if
+//! the [`Environment`] is kept in step with the reference node.
+//!
+//! Main components:
+//! - [`DifferentialMiddleware`]: The core middleware implementation.
+//! - [`Divergence`]: A mismatch between the [`Environment`] and the reference
+//!   node.
+//! - [`DifferentialMiddlewareError`]: Error type for the middleware.
+
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{Middleware, MiddlewareError, PendingTransaction},
+    types::{transaction::eip2718::TypedTransaction, *},
+};
+use thiserror::Error;
+
+use super::{
+    connection::Connection, errors::RevmMiddlewareError, resolved_pending_transaction,
+    RevmMiddleware,
+};
+#[cfg_attr(doc, doc(hidden))]
+#[cfg_attr(doc, allow(unused_imports))]
+#[cfg(doc)]
+use crate::environment::{builder::EnvironmentBuilder, Environment};
+
+/// A way in which the [`Environment`] and the reference node disagree about
+/// a transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DivergenceKind {
+    /// Whether the transaction succeeded.
+    Status {
+        /// Whether it succeeded in the [`Environment`].
+        environment: bool,
+        /// Whether it succeeded on the reference node.
+        reference: bool,
+    },
+
+    /// The gas used by the transaction.
+    GasUsed {
+        /// The gas used in the [`Environment`].
+        environment: Option<U256>,
+        /// The gas used on the reference node.
+        reference: Option<U256>,
+    },
+
+    /// The address of the contract the transaction deployed.
+    ContractAddress {
+        /// The address in the [`Environment`].
+        environment: Option<Address>,
+        /// The address on the reference node.
+        reference: Option<Address>,
+    },
+
+    /// The logs the transaction emitted, compared by their address, topics,
+    /// and data.
+    Logs {
+        /// The logs emitted in the [`Environment`].
+        environment: Vec<Log>,
+        /// The logs emitted on the reference node.
+        reference: Vec<Log>,
+    },
+
+    /// The ether balance of an account after the transaction.
+    Balance {
+        /// The account.
+        account: Address,
+        /// The balance in the [`Environment`].
+        environment: U256,
+        /// The balance on the reference node.
+        reference: U256,
+    },
+
+    /// The nonce of an account after the transaction.
+    Nonce {
+        /// The account.
+        account: Address,
+        /// The nonce in the [`Environment`].
+        environment: u64,
+        /// The nonce on the reference node.
+        reference: U256,
+    },
+
+    /// A storage slot of an account after the transaction.
+    Storage {
+        /// The account.
+        account: Address,
+        /// The slot.
+        slot: H256,
+        /// The value in the [`Environment`].
+        environment: H256,
+        /// The value on the reference node.
+        reference: H256,
+    },
+
+    /// The reference node could not be queried to compare the transaction.
+    Unchecked {
+        /// Why the reference node could not be queried.
+        reason: String,
+    },
+}
+
+/// A transaction the [`Environment`] and the reference node disagree about.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// The transaction as it was sent to both.
+    pub transaction: TypedTransaction,
+
+    /// How they disagree.
+    pub kind: DivergenceKind,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "transaction from {:?} to {:?} diverged: {:?}",
+            self.transaction.from(),
+            self.transaction.to_addr(),
+            self.kind
+        )
+    }
+}
+
+/// What every [`DifferentialMiddleware`] made with
+/// [`DifferentialMiddleware::with_client`] shares.
+#[derive(Debug)]
+struct Shared<R> {
+    reference: R,
+    order: futures_locks::Mutex<()>,
+    divergences: Mutex<Vec<Divergence>>,
+}
+
+#[derive(Debug)]
+/// Middleware that sends every transaction to a reference node as well as to
+/// the [`Environment`] and keeps the [`Divergence`]s between the two.
+pub struct DifferentialMiddleware<R> {
+    inner: Arc<RevmMiddleware>,
+    shared: Arc<Shared<R>>,
+}
+
+impl<R> DifferentialMiddleware<R>
+where
+    R: Middleware,
+{
+    /// Wraps the `inner` client to replay its transactions on the
+    /// `reference` node.
+    pub fn new(inner: Arc<RevmMiddleware>, reference: R) -> Self {
+        Self {
+            inner,
+            shared: Arc::new(Shared {
+                reference,
+                order: Default::default(),
+                divergences: Default::default(),
+            }),
+        }
+    }
+
+    /// Wraps another client of the same [`Environment`] to replay its
+    /// transactions on the same reference node. The transactions of both
+    /// reach the reference node in the order they were executed and their
+    /// divergences are kept together.
+    pub fn with_client(&self, inner: Arc<RevmMiddleware>) -> Self {
+        Self {
+            inner,
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// The reference node transactions are replayed on.
+    pub fn reference(&self) -> &R {
+        &self.shared.reference
+    }
+
+    /// Every divergence found so far, in the order of the transactions they
+    /// were found for.
+    pub fn divergences(&self) -> Vec<Divergence> {
+        self.shared.divergences.lock().unwrap().clone()
+    }
+
+    /// Compares the receipts a transaction got from the [`Environment`] and
+    /// from the reference node, along with the state it left behind.
+    async fn compare(
+        &self,
+        environment: &Result<TransactionReceipt, RevmMiddlewareError>,
+        reference: &Result<TransactionReceipt, String>,
+    ) -> Vec<DivergenceKind> {
+        let succeeded = |receipt: Option<&TransactionReceipt>| {
+            receipt.is_some_and(|receipt| receipt.status != Some(0.into()))
+        };
+        let (environment_succeeded, reference_succeeded) = (
+            succeeded(environment.as_ref().ok()),
+            succeeded(reference.as_ref().ok()),
+        );
+        let (Ok(environment), Ok(reference), true, true) = (
+            environment,
+            reference,
+            environment_succeeded,
+            reference_succeeded,
+        ) else {
+            return match environment_succeeded == reference_succeeded {
+                true => vec![],
+                false => vec![DivergenceKind::Status {
+                    environment: environment_succeeded,
+                    reference: reference_succeeded,
+                }],
+            };
+        };
+
+        let mut divergences = vec![];
+        if environment.gas_used != reference.gas_used {
+            divergences.push(DivergenceKind::GasUsed {
+                environment: environment.gas_used,
+                reference: reference.gas_used,
+            });
+        }
+        if environment.contract_address != reference.contract_address {
+            divergences.push(DivergenceKind::ContractAddress {
+                environment: environment.contract_address,
+                reference: reference.contract_address,
+            });
+        }
+        let contents = |logs: &[Log]| {
+            logs.iter()
+                .map(|log| (log.address, log.topics.clone(), log.data.clone()))
+                .collect::<Vec<_>>()
+        };
+        if contents(&environment.logs) != contents(&reference.logs) {
+            divergences.push(DivergenceKind::Logs {
+                environment: environment.logs.clone(),
+                reference: reference.logs.clone(),
+            });
+        }
+
+        // The state is only compared if the `Environment` recorded what the
+        // transaction changed.
+        let Ok(Some(state_diff)) = self
+            .inner
+            .get_state_diff(environment.transaction_hash)
+            .await
+        else {
+            return divergences;
+        };
+        let reference = &self.shared.reference;
+        for (account, diff) in state_diff.accounts {
+            let checked = async {
+                if let Some(balance) = diff.balance {
+                    let reference = reference.get_balance(account, None).await?;
+                    if balance.after != reference {
+                        divergences.push(DivergenceKind::Balance {
+                            account,
+                            environment: balance.after,
+                            reference,
+                        });
+                    }
+                }
+                if let Some(nonce) = diff.nonce {
+                    let reference = reference.get_transaction_count(account, None).await?;
+                    if U256::from(nonce.after) != reference {
+                        divergences.push(DivergenceKind::Nonce {
+                            account,
+                            environment: nonce.after,
+                            reference,
+                        });
+                    }
+                }
+                for (slot, value) in diff.storage {
+                    let reference = reference.get_storage_at(account, slot, None).await?;
+                    if value.after != reference {
+                        divergences.push(DivergenceKind::Storage {
+                            account,
+                            slot,
+                            environment: value.after,
+                            reference,
+                        });
+                    }
+                }
+                Ok::<_, R::Error>(())
+            }
+            .await;
+            if let Err(e) = checked {
+                divergences.push(DivergenceKind::Unchecked {
+                    reason: e.to_string(),
+                });
+            }
+        }
+        divergences
+    }
+}
+
+#[derive(Error, Debug)]
+/// Thrown when an error happens at the Differential Middleware
+pub enum DifferentialMiddlewareError {
+    /// Thrown when the internal middleware errors
+    #[error("{0}")]
+    MiddlewareError(RevmMiddlewareError),
+}
+
+impl MiddlewareError for DifferentialMiddlewareError {
+    type Inner = RevmMiddlewareError;
+
+    fn from_err(src: RevmMiddlewareError) -> Self {
+        DifferentialMiddlewareError::MiddlewareError(src)
+    }
+
+    fn as_inner(&self) -> Option<&Self::Inner> {
+        match self {
+            DifferentialMiddlewareError::MiddlewareError(e) => Some(e),
+        }
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<R> Middleware for DifferentialMiddleware<R>
+where
+    R: Middleware,
+{
+    type Error = DifferentialMiddlewareError;
+    type Provider = Connection;
+    type Inner = RevmMiddleware;
+
+    fn inner(&self) -> &RevmMiddleware {
+        &self.inner
+    }
+
+    /// Sends the transaction to the [`Environment`] and then to the reference
+    /// node, and keeps the [`Divergence`]s between the two. The outcome in
+    /// the [`Environment`] is returned either way, and a transaction that
+    /// fails in the [`Environment`] is still sent to the reference node to
+    /// check that it fails there too.
+    async fn send_transaction<T: Into<TypedTransaction> + Send + Sync>(
+        &self,
+        tx: T,
+        block: Option<BlockId>,
+    ) -> Result<PendingTransaction<'_, Self::Provider>, Self::Error> {
+        let mut tx: TypedTransaction = tx.into();
+        self.inner
+            .fill_transaction(&mut tx, block)
+            .await
+            .map_err(MiddlewareError::from_err)?;
+
+        // Holding the order keeps transactions from reaching the reference node
+        // in a different order than the `Environment` executed them in.
+        let _order = self.shared.order.lock().await;
+        let environment = match self.inner.send_transaction(tx.clone(), block).await {
+            Ok(pending_tx) => pending_tx
+                .await
+                .map_err(RevmMiddlewareError::from)
+                .and_then(|receipt| {
+                    receipt.ok_or(RevmMiddlewareError::MissingData(
+                        "The transaction did not return a receipt!".to_string(),
+                    ))
+                }),
+            Err(e) => Err(e),
+        };
+        let reference = match self
+            .shared
+            .reference
+            .send_transaction(tx.clone(), None)
+            .await
+        {
+            Ok(pending_tx) => pending_tx
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|receipt| {
+                    receipt.ok_or("The reference node dropped the transaction!".to_string())
+                }),
+            Err(e) => Err(e.to_string()),
+        };
+        let divergences = self.compare(&environment, &reference).await;
+        self.shared
+            .divergences
+            .lock()
+            .unwrap()
+            .extend(divergences.into_iter().map(|kind| Divergence {
+                transaction: tx.clone(),
+                kind,
+            }));
+        let receipt = environment.map_err(MiddlewareError::from_err)?;
+
+        Ok(resolved_pending_transaction(self.provider(), receipt))
+    }
+}
@@ -54,15 +54,25 @@ pub enum RevmMiddlewareError {
 
     /// The execution of a transaction was reverted, indicating that the
     /// transaction was not successful.
-    #[error("execution failed to succeed due to revert!\n gas used is: {gas_used}\n output is {output:?}")]
+    #[error("execution failed to succeed due to revert!\n gas used is: {gas_used}\n output is {output:?}\n {decoded}")]
     ExecutionRevert {
         /// Provides the amount of gas used by the transaction.
         gas_used: u64,
 
         /// Provides the output or reason why the transaction was reverted.
         output: revm::primitives::Bytes,
+
+        /// The decoded output. Custom errors are decoded with the ABIs in the
+        /// [`Environment`]'s
+        /// [`AbiRegistry`](crate::environment::abi_registry::AbiRegistry).
+        decoded: super::revert::RevertError,
     },
 
+    /// An action did not have the outcome it was expected to have, e.g., it
+    /// succeeded where it was expected to revert.
+    #[error("unexpected outcome! {0}")]
+    UnexpectedOutcome(String),
+
     /// The execution of a transaction halted unexpectedly.
     #[error("execution failed to succeed due to halt!\n reason is: {reason:?}\n gas used is: {gas_used}")]
     ExecutionHalt {
@@ -72,6 +82,10 @@ pub enum RevmMiddlewareError {
         /// Provides the amount of gas used by the transaction.
         gas_used: u64,
     },
+
+    /// Writing data collected from the [`Environment`] to disk failed.
+    #[error("failed to write collected data! due to: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 impl MiddlewareError for RevmMiddlewareError {
@@ -0,0 +1,198 @@
+//! The `mock` module provides a middleware with programmable responses that
+//! exposes the same [`Middleware`] surface as the [`RevmMiddleware`]. It is
+//! meant for unit-testing strategy code without spinning up an
+//! [`Environment`].
+//!
+//! Main components:
+//! - [`MockRevmMiddleware`]: The mock middleware implementation.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{Middleware, MockProvider, PendingTransaction, Provider},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, BlockId, Bytes, NameOrAddress,
+        TransactionReceipt, U256,
+    },
+    utils::get_contract_address,
+};
+
+#[cfg_attr(doc, doc(hidden))]
+#[cfg_attr(doc, allow(unused_imports))]
+#[cfg(doc)]
+use super::RevmMiddleware;
+use super::{errors::RevmMiddlewareError, resolved_pending_transaction};
+#[cfg_attr(doc, doc(hidden))]
+#[cfg_attr(doc, allow(unused_imports))]
+#[cfg(doc)]
+use crate::environment::Environment;
+
+/// A stand-in for the [`RevmMiddleware`] whose responses are programmed up
+/// front instead of coming from an [`Environment`].
+///
+/// Balances are returned from what was set with
+/// [`MockRevmMiddleware::set_balance`] (defaulting to zero), calls return
+/// what was set with [`MockRevmMiddleware::set_call_return`], and
+/// transactions resolve to the receipts pushed with
+/// [`MockRevmMiddleware::push_receipt`] in the order they were pushed. When no
+/// receipt was pushed, a successful receipt is made up for the transaction.
+/// Every transaction sent is recorded and can be inspected with
+/// [`MockRevmMiddleware::sent_transactions`].
+#[derive(Debug)]
+pub struct MockRevmMiddleware {
+    address: Address,
+    provider: Provider<MockProvider>,
+    nonce: Mutex<U256>,
+    balances: Mutex<HashMap<Address, U256>>,
+    call_returns: Mutex<HashMap<(Address, Bytes), Bytes>>,
+    receipts: Mutex<VecDeque<TransactionReceipt>>,
+    sent_transactions: Mutex<Vec<TypedTransaction>>,
+}
+
+impl MockRevmMiddleware {
+    /// Creates a new [`MockRevmMiddleware`] that sends transactions from the
+    /// given `address`.
+    pub fn new(address: Address) -> Arc<Self> {
+        Arc::new(Self {
+            address,
+            provider: Provider::new(MockProvider::new()),
+            nonce: Mutex::new(U256::zero()),
+            balances: Mutex::new(HashMap::new()),
+            call_returns: Mutex::new(HashMap::new()),
+            receipts: Mutex::new(VecDeque::new()),
+            sent_transactions: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Sets the balance that is returned for `address`.
+    pub fn set_balance(&self, address: Address, balance: U256) {
+        self.balances.lock().unwrap().insert(address, balance);
+    }
+
+    /// Sets the `output` that is returned for a call to `to` with the given
+    /// calldata `data`.
+    pub fn set_call_return(&self, to: Address, data: impl Into<Bytes>, output: impl Into<Bytes>) {
+        self.call_returns
+            .lock()
+            .unwrap()
+            .insert((to, data.into()), output.into());
+    }
+
+    /// Queues a receipt for the next transaction that is sent.
+    pub fn push_receipt(&self, receipt: TransactionReceipt) {
+        self.receipts.lock().unwrap().push_back(receipt);
+    }
+
+    /// Returns all the transactions that have been sent so far.
+    pub fn sent_transactions(&self) -> Vec<TypedTransaction> {
+        self.sent_transactions.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Middleware for MockRevmMiddleware {
+    type Provider = MockProvider;
+    type Error = RevmMiddlewareError;
+    type Inner = Provider<MockProvider>;
+
+    /// Returns a reference to the inner [`Provider`] wrapping a
+    /// [`MockProvider`] which is never called by the overridden methods.
+    fn inner(&self) -> &Self::Inner {
+        &self.provider
+    }
+
+    /// Returns the address transactions are sent from.
+    fn default_sender(&self) -> Option<Address> {
+        Some(self.address)
+    }
+
+    /// Returns the balance set for the address or zero if none was set.
+    async fn get_balance<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        _block: Option<BlockId>,
+    ) -> Result<U256, Self::Error> {
+        let address = match from.into() {
+            NameOrAddress::Address(address) => address,
+            NameOrAddress::Name(name) => {
+                return Err(RevmMiddlewareError::MissingData(format!(
+                    "ENS names are not supported, tried to resolve {}!",
+                    name
+                )))
+            }
+        };
+        Ok(self
+            .balances
+            .lock()
+            .unwrap()
+            .get(&address)
+            .copied()
+            .unwrap_or_default())
+    }
+
+    /// Returns the output set for the call's destination and calldata.
+    async fn call(
+        &self,
+        tx: &TypedTransaction,
+        _block: Option<BlockId>,
+    ) -> Result<Bytes, Self::Error> {
+        let to = match tx.to() {
+            Some(NameOrAddress::Address(address)) => *address,
+            _ => {
+                return Err(RevmMiddlewareError::MissingData(
+                    "Calls to the `MockRevmMiddleware` need a destination address!".to_string(),
+                ))
+            }
+        };
+        let data = tx.data().cloned().unwrap_or_default();
+        self.call_returns
+            .lock()
+            .unwrap()
+            .get(&(to, data.clone()))
+            .cloned()
+            .ok_or(RevmMiddlewareError::MissingData(format!(
+                "No return was set for a call to {:?} with data {}!",
+                to, data
+            )))
+    }
+
+    /// Records the transaction and resolves it to the next pushed receipt, or
+    /// to a made up successful receipt when there is none.
+    async fn send_transaction<T: Into<TypedTransaction> + Send + Sync>(
+        &self,
+        tx: T,
+        _block: Option<BlockId>,
+    ) -> Result<PendingTransaction<'_, Self::Provider>, Self::Error> {
+        let tx: TypedTransaction = tx.into();
+        let nonce = {
+            let mut nonce = self.nonce.lock().unwrap();
+            let current = *nonce;
+            *nonce += U256::one();
+            current
+        };
+        let receipt = match self.receipts.lock().unwrap().pop_front() {
+            Some(receipt) => receipt,
+            None => {
+                let (to, contract_address) = match tx.to() {
+                    Some(NameOrAddress::Address(address)) => (Some(*address), None),
+                    _ => (None, Some(get_contract_address(self.address, nonce))),
+                };
+                TransactionReceipt {
+                    transaction_hash: tx.sighash(),
+                    from: self.address,
+                    to,
+                    contract_address,
+                    status: Some(1.into()),
+                    ..Default::default()
+                }
+            }
+        };
+        self.sent_transactions.lock().unwrap().push(tx);
+
+        Ok(resolved_pending_transaction(self.provider(), receipt))
+    }
+}
@@ -6,8 +6,15 @@
 //! Main components:
 //! - [`RevmMiddleware`]: The core middleware implementation.
 //! - [`RevmMiddlewareError`]: Error type for the middleware.
+//! - [`revert::RevertError`]: The decoded data of a revert.
 //! - [`Connection`]: Handles communication with the Ethereum VM.
 //! - `FilterReceiver`: Facilitates event watching based on certain filters.
+//! - [`mock::MockRevmMiddleware`]: A stand-in with programmable responses for
+//!   unit tests.
+//! - [`caching_middleware::CachingMiddleware`]: Memoizes the results of view
+//!   calls until the state they were read from changes.
+//! - [`differential_middleware::DifferentialMiddleware`]: Replays transactions
+//!   on a reference node and compares the results.
 
 #![warn(missing_docs)]
 
@@ -22,18 +29,24 @@ use ethers::{
         },
         ProviderError,
     },
-    providers::{FilterKind, FilterWatcher, Middleware, PendingTransaction, Provider},
+    providers::{
+        FilterKind, FilterWatcher, JsonRpcClient, Middleware, PendingTransaction, Provider,
+    },
     signers::{Signer, Wallet},
     types::{
-        transaction::eip2718::TypedTransaction, Address, BlockId, Bloom, Bytes, Filter, Log,
-        NameOrAddress, Transaction, TransactionReceipt, U256 as eU256, U64,
+        spoof, transaction::eip2718::TypedTransaction, Address, Block, BlockId, Bloom, Bytes,
+        Filter, FilteredParams, Log, NameOrAddress, Transaction, TransactionReceipt, TxHash,
+        U256 as eU256, U64,
     },
 };
 use futures_timer::Delay;
 use rand::{rngs::StdRng, SeedableRng};
-use revm::primitives::{CreateScheme, Output, TransactTo, TxEnv, U256};
+use revm::primitives::{CreateScheme, ExecutionResult, Output, TransactTo, TxEnv, U256};
 
-use crate::environment::{cheatcodes::*, instruction::*, Environment};
+use crate::environment::{
+    builder::CREATE2_DEPLOYER, cheatcodes::*, instruction::*, state_diff::StateDiff,
+    transaction_hash, Environment, OutcomeSender,
+};
 
 /// Possible errors thrown by interacting with the revm middleware client.
 pub mod errors;
@@ -50,6 +63,15 @@ pub mod cast;
 use cast::*;
 
 pub mod nonce_middleware;
+
+pub mod caching_middleware;
+
+pub mod differential_middleware;
+
+pub mod revert;
+
+pub mod mock;
+
 /// A middleware structure that integrates with `revm`.
 ///
 /// [`RevmMiddleware`] serves as a bridge between the application and `revm`'s
@@ -80,10 +102,17 @@ pub mod nonce_middleware;
 /// Use a seed like `Some("test_label")` for maintaining a
 /// consistent address across simulations and client labeling. Seeding is be
 /// useful for debugging and post-processing.
+///
+/// A client can hold a keyring of several signers, added with
+/// [`RevmMiddleware::add_signer`], so that many accounts can be driven
+/// through a single connection to the [`Environment`]. Transactions are sent
+/// from the signer chosen with [`RevmMiddleware::with_sender`], or from the
+/// `from` field of the transaction if it is set to a signer in the keyring.
 #[derive(Debug)]
 pub struct RevmMiddleware {
     provider: Provider<Connection>,
     wallet: Wallet<SigningKey>,
+    keyring: Arc<std::sync::RwLock<HashMap<Address, Wallet<SigningKey>>>>,
 }
 
 impl RevmMiddleware {
@@ -114,35 +143,104 @@ impl RevmMiddleware {
         environment: &Environment,
         seed_and_label: Option<&str>,
     ) -> Result<Arc<Self>, RevmMiddlewareError> {
-        let instruction_sender = &Arc::clone(&environment.socket.instruction_sender);
-        let (outcome_sender, outcome_receiver) = crossbeam_channel::unbounded();
-        let wallet = if let Some(seed) = seed_and_label {
-            let mut hasher = Sha256::new();
-            hasher.update(seed);
-            let hashed = hasher.finalize();
-            let mut rng: StdRng = SeedableRng::from_seed(hashed.into());
-            Wallet::new(&mut rng)
-        } else {
-            let mut rng = rand::thread_rng();
-            Wallet::new(&mut rng)
-        };
-        instruction_sender
-            .send(Instruction::AddAccount {
-                address: wallet.address(),
-                outcome_sender: outcome_sender.clone(),
-            })
-            .map_err(|e| RevmMiddlewareError::Send(e.to_string()))?;
-        outcome_receiver.recv()??;
-
+        let wallet = generate_wallet(seed_and_label, environment.chain_id());
         let connection = Connection {
-            instruction_sender: Arc::downgrade(instruction_sender),
-            outcome_sender,
-            outcome_receiver: outcome_receiver.clone(),
+            instruction_sender: Arc::downgrade(&environment.socket.instruction_sender),
             event_broadcaster: Arc::clone(&environment.socket.event_broadcaster),
             filter_receivers: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            subscriptions: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            receipts: environment.socket.receipts.clone(),
+            blocks: environment.socket.blocks.clone(),
+            abi_registry: environment.socket.abi_registry.clone(),
+            state_diffs: environment.socket.state_diffs.clone(),
+            chain_id: environment.chain_id(),
         };
         let provider = Provider::new(connection);
-        Ok(Arc::new(Self { wallet, provider }))
+        let keyring = HashMap::from([(wallet.address(), wallet.clone())]);
+        let client = Self {
+            wallet,
+            provider,
+            keyring: Arc::new(std::sync::RwLock::new(keyring)),
+        };
+        client.add_account(client.address())?;
+        Ok(Arc::new(client))
+    }
+
+    /// Adds a signer to the keyring of this client and returns its address.
+    /// The signer is generated from the `seed_and_label` the same way as in
+    /// [`RevmMiddleware::new`] and its account is added to the
+    /// [`Environment`]. The keyring is shared with every client made from this
+    /// one with [`RevmMiddleware::with_sender`].
+    pub fn add_signer(&self, seed_and_label: Option<&str>) -> Result<Address, RevmMiddlewareError> {
+        let wallet = generate_wallet(seed_and_label, self.provider().as_ref().chain_id);
+        let address = wallet.address();
+        self.add_account(address)?;
+        self.keyring.write().unwrap().insert(address, wallet);
+        Ok(address)
+    }
+
+    /// Returns the addresses of the signers in the keyring of this client.
+    pub fn signers(&self) -> Vec<Address> {
+        let mut signers: Vec<Address> = self.keyring.read().unwrap().keys().copied().collect();
+        signers.sort();
+        signers
+    }
+
+    /// Returns a client that sends transactions from the signer at `address`
+    /// in the keyring of this client. It shares the connection to the
+    /// [`Environment`] and the keyring with this client, so no new channels
+    /// are opened for it.
+    pub fn with_sender(&self, address: Address) -> Result<Arc<Self>, RevmMiddlewareError> {
+        let wallet = self.signer(&address)?;
+        Ok(Arc::new(Self {
+            provider: self.provider.clone(),
+            wallet,
+            keyring: Arc::clone(&self.keyring),
+        }))
+    }
+
+    /// Returns the signer at `address` in the keyring of this client.
+    fn signer(&self, address: &Address) -> Result<Wallet<SigningKey>, RevmMiddlewareError> {
+        self.keyring
+            .read()
+            .unwrap()
+            .get(address)
+            .cloned()
+            .ok_or_else(|| {
+                RevmMiddlewareError::MissingData(format!(
+                    "No signer for {:?} in the keyring of the client!",
+                    address
+                ))
+            })
+    }
+
+    /// Sends the [`Instruction`] made by `instruction` to the [`Environment`]
+    /// along with a channel of its own for the reply, and waits for the
+    /// [`Outcome`].
+    fn request(
+        &self,
+        instruction: impl FnOnce(OutcomeSender) -> Instruction,
+    ) -> Result<Outcome, RevmMiddlewareError> {
+        let instruction_sender = self
+            .provider()
+            .as_ref()
+            .instruction_sender
+            .upgrade()
+            .ok_or_else(|| RevmMiddlewareError::Send("Environment is offline!".to_string()))?;
+        let (outcome_sender, outcome_receiver) = crossbeam_channel::bounded(1);
+        instruction_sender
+            .send(instruction(outcome_sender))
+            .map_err(|e| RevmMiddlewareError::Send(e.to_string()))?;
+        Ok(outcome_receiver.recv()??)
+    }
+
+    /// Adds an unfunded account at `address` to the [`Environment`].
+    fn add_account(&self, address: Address) -> Result<(), RevmMiddlewareError> {
+        self.request(|outcome_sender| Instruction::AddAccount {
+            address,
+            outcome_sender,
+        })?;
+        Ok(())
     }
 
     /// Allows the user to update the block number and timestamp of the
@@ -157,51 +255,31 @@ impl RevmMiddleware {
     ) -> Result<ReceiptData, RevmMiddlewareError> {
         let block_number: ethers::types::U256 = block_number.into();
         let block_timestamp: ethers::types::U256 = block_timestamp.into();
-        let provider = self.provider().as_ref();
-        if let Some(instruction_sender) = provider.instruction_sender.upgrade() {
-            instruction_sender
-                .send(Instruction::BlockUpdate {
-                    block_number: revm_primitives::FixedBytes::<32>(block_number.into()).into(),
-                    block_timestamp: revm_primitives::FixedBytes::<32>(block_timestamp.into())
-                        .into(),
-                    outcome_sender: provider.outcome_sender.clone(),
-                })
-                .map_err(|e| RevmMiddlewareError::Send(e.to_string()))?;
-            match provider.outcome_receiver.recv() {
-                Ok(Ok(Outcome::BlockUpdateCompleted(receipt_data))) => Ok(receipt_data),
-                _ => Err(RevmMiddlewareError::MissingData(
-                    "Block did not update Successfully".to_string(),
-                )),
-            }
-        } else {
-            Err(RevmMiddlewareError::Send(
-                "Environment is offline!".to_string(),
-            ))
+        match self.request(|outcome_sender| Instruction::BlockUpdate {
+            block_number: revm_primitives::FixedBytes::<32>(block_number.into()).into(),
+            block_timestamp: revm_primitives::FixedBytes::<32>(block_timestamp.into()).into(),
+            outcome_sender,
+        })? {
+            Outcome::BlockUpdateCompleted(receipt_data) => Ok(receipt_data),
+            _ => Err(RevmMiddlewareError::MissingData(
+                "Block did not update Successfully".to_string(),
+            )),
         }
     }
 
     /// Returns the timestamp of the current block.
     pub async fn get_block_timestamp(&self) -> Result<ethers::types::U256, RevmMiddlewareError> {
-        if let Some(instruction_sender) = self.provider().as_ref().instruction_sender.upgrade() {
-            instruction_sender
-                .send(Instruction::Query {
-                    environment_data: EnvironmentData::BlockTimestamp,
-                    outcome_sender: self.provider().as_ref().outcome_sender.clone(),
-                })
-                .map_err(|e| RevmMiddlewareError::Send(e.to_string()))?;
-            match self.provider().as_ref().outcome_receiver.recv()?? {
-                Outcome::QueryReturn(outcome) => {
-                    ethers::types::U256::from_str_radix(outcome.as_ref(), 10)
-                        .map_err(|e| RevmMiddlewareError::Conversion(e.to_string()))
-                }
-                _ => Err(RevmMiddlewareError::MissingData(
-                    "Wrong variant returned via query!".to_string(),
-                )),
+        match self.request(|outcome_sender| Instruction::Query {
+            environment_data: EnvironmentData::BlockTimestamp,
+            outcome_sender,
+        })? {
+            Outcome::QueryReturn(outcome) => {
+                ethers::types::U256::from_str_radix(outcome.as_ref(), 10)
+                    .map_err(|e| RevmMiddlewareError::Conversion(e.to_string()))
             }
-        } else {
-            Err(RevmMiddlewareError::Send(
-                "Environment is offline!".to_string(),
-            ))
+            _ => Err(RevmMiddlewareError::MissingData(
+                "Wrong variant returned via query!".to_string(),
+            )),
         }
     }
 
@@ -210,24 +288,14 @@ impl RevmMiddleware {
         &self,
         cheatcode: Cheatcodes,
     ) -> Result<CheatcodesReturn, RevmMiddlewareError> {
-        if let Some(instruction_sender) = self.provider.as_ref().instruction_sender.upgrade() {
-            instruction_sender
-                .send(Instruction::Cheatcode {
-                    cheatcode,
-                    outcome_sender: self.provider().as_ref().outcome_sender.clone(),
-                })
-                .map_err(|e| RevmMiddlewareError::Send(e.to_string()))?;
-
-            match self.provider().as_ref().outcome_receiver.recv()?? {
-                Outcome::CheatcodeReturn(outcome) => Ok(outcome),
-                _ => Err(RevmMiddlewareError::MissingData(
-                    "Wrong variant returned via instruction outcome!".to_string(),
-                )),
-            }
-        } else {
-            Err(RevmMiddlewareError::Send(
-                "Environment is offline!".to_string(),
-            ))
+        match self.request(|outcome_sender| Instruction::Cheatcode {
+            cheatcode,
+            outcome_sender,
+        })? {
+            Outcome::CheatcodeReturn(outcome) => Ok(outcome),
+            _ => Err(RevmMiddlewareError::MissingData(
+                "Wrong variant returned via instruction outcome!".to_string(),
+            )),
         }
     }
 
@@ -244,27 +312,301 @@ impl RevmMiddleware {
         &self,
         gas_price: ethers::types::U256,
     ) -> Result<(), RevmMiddlewareError> {
-        if let Some(instruction_sender) = self.provider().as_ref().instruction_sender.upgrade() {
-            instruction_sender
-                .send(Instruction::SetGasPrice {
-                    gas_price,
-                    outcome_sender: self.provider().as_ref().outcome_sender.clone(),
+        match self.request(|outcome_sender| Instruction::SetGasPrice {
+            gas_price,
+            outcome_sender,
+        })? {
+            Outcome::SetGasPriceCompleted => Ok(()),
+            _ => Err(RevmMiddlewareError::MissingData(
+                "Wrong variant returned via instruction outcome!".to_string(),
+            )),
+        }
+    }
+
+    /// Calls a contract method the same way as [`Middleware::call`] but with
+    /// the given `state_overrides` applied on top of the worldstate first.
+    /// This allows for simulating a call with modified balances, nonces,
+    /// code, or storage without mutating the [`Environment`].
+    ///
+    /// The overrides are applied to a temporary copy of the database that is
+    /// thrown away once the call completes.
+    pub async fn call_with_overrides(
+        &self,
+        tx: &TypedTransaction,
+        state_overrides: &spoof::State,
+    ) -> Result<Bytes, RevmMiddlewareError> {
+        // `spoof::State` does not expose its accounts, so we pull them out through its
+        // serialized form.
+        let state_overrides: HashMap<Address, spoof::Account> = serde_json::from_value(
+            serde_json::to_value(state_overrides).map_err(RevmMiddlewareError::Json)?,
+        )
+        .map_err(RevmMiddlewareError::Json)?;
+        self.call_with_state(tx, Some(state_overrides))
+    }
+
+    /// Sends a batch of transactions to the [`Environment`] in a single
+    /// instruction. The transactions are executed in order and the
+    /// [`ExecutionResult`] of each is returned in that same order.
+    ///
+    /// This avoids a round trip through the [`Environment`]'s channels per
+    /// transaction which can dominate the runtime of large simulations.
+    /// If a transaction is invalid (e.g., the sender cannot pay for gas), the
+    /// batch stops there and the error is returned. Any transactions before it
+    /// remain committed.
+    pub async fn send_batch(
+        &self,
+        txs: Vec<TypedTransaction>,
+    ) -> Result<Vec<ExecutionResult>, RevmMiddlewareError> {
+        let gas_price = U256::from_limbs(self.get_gas_price().await?.0);
+        let tx_envs = txs
+            .iter()
+            .map(|tx| self.build_tx_env(tx, gas_price))
+            .collect::<Result<Vec<_>, _>>()?;
+        match self.request(|outcome_sender| Instruction::BatchTransaction {
+            tx_envs,
+            outcome_sender,
+        })? {
+            Outcome::BatchTransactionCompleted(execution_results) => Ok(execution_results),
+            _ => Err(RevmMiddlewareError::MissingData(
+                "Wrong variant returned via instruction outcome!".to_string(),
+            )),
+        }
+    }
+
+    /// Returns the [`StateDiff`] of a transaction executed by the
+    /// [`Environment`], looked up by its hash, or `None` if it has not
+    /// executed it. This requires the [`Environment`] to be built with
+    /// [`EnvironmentBuilder::record_state_diffs`](crate::environment::builder::EnvironmentBuilder::record_state_diffs).
+    pub async fn get_state_diff(
+        &self,
+        transaction_hash: TxHash,
+    ) -> Result<Option<StateDiff>, RevmMiddlewareError> {
+        let state_diffs = self.provider().as_ref().state_diffs.as_ref().ok_or(
+            RevmMiddlewareError::MissingData(
+                "The `Environment` was not built to record state diffs!".to_string(),
+            ),
+        )?;
+        Ok(state_diffs.get(transaction_hash)?)
+    }
+
+    /// Sends a batch of calls to the [`Environment`] in a single instruction.
+    /// The calls are all executed against the same state, the same way as
+    /// [`Middleware::call`], and the [`ExecutionResult`] of each is returned
+    /// in the order they were given. A call that reverts does not stop the
+    /// rest of the batch.
+    ///
+    /// This avoids a round trip through the [`Environment`]'s channels per
+    /// call, e.g., when polling some piece of state for many agents at once.
+    pub async fn call_batch(
+        &self,
+        txs: &[TypedTransaction],
+    ) -> Result<Vec<ExecutionResult>, RevmMiddlewareError> {
+        let tx_envs = txs
+            .iter()
+            .map(|tx| self.build_call_env(tx))
+            .collect::<Result<Vec<_>, _>>()?;
+        match self.request(|outcome_sender| Instruction::BatchCall {
+            tx_envs,
+            outcome_sender,
+        })? {
+            Outcome::BatchCallCompleted(execution_results) => Ok(execution_results),
+            _ => Err(RevmMiddlewareError::MissingData(
+                "Wrong variant returned via instruction outcome!".to_string(),
+            )),
+        }
+    }
+
+    /// Reads the ERC-20 `balanceOf` of each of the `accounts` in the `token`
+    /// with a single [`RevmMiddleware::call_batch`]. The balances are returned
+    /// in the order of the `accounts`. If any of the reads reverts or does not
+    /// return a full word, an error is returned instead.
+    pub async fn balances_of(
+        &self,
+        token: Address,
+        accounts: &[Address],
+    ) -> Result<Vec<eU256>, RevmMiddlewareError> {
+        let selector = ethers::utils::id("balanceOf(address)");
+        let txs = accounts
+            .iter()
+            .map(|account| {
+                let data = [
+                    selector.as_slice(),
+                    &ethers::abi::encode(&[ethers::abi::Token::Address(*account)]),
+                ]
+                .concat();
+                ethers::types::TransactionRequest::new()
+                    .to(token)
+                    .data(data)
+                    .into()
+            })
+            .collect::<Vec<TypedTransaction>>();
+        self.call_batch(&txs)
+            .await?
+            .into_iter()
+            .map(|execution_result| {
+                let output = unpack_execution_result(execution_result)
+                    .map_err(|e| self.provider().as_ref().decode_revert(Some(token), e))?
+                    .output;
+                let output = output.data();
+                if output.len() < 32 {
+                    return Err(RevmMiddlewareError::MissingData(format!(
+                        "The call to `balanceOf` on {:?} did not return a balance!",
+                        token
+                    )));
+                }
+                Ok(eU256::from_big_endian(&output[..32]))
+            })
+            .collect()
+    }
+
+    /// Executes a bundle of transactions in order against a temporary copy of
+    /// the worldstate, the same way as [`RevmMiddleware::send_batch`] but
+    /// without committing any of them or emitting their events. Each
+    /// transaction sees the state left by the ones before it.
+    ///
+    /// The [`BundleSimulation`] holds the [`ExecutionResult`] of each
+    /// transaction along with the gas they used together and how much the
+    /// ether balance of this client changed. A transaction that reverts does
+    /// not stop the bundle, but one that is invalid (e.g., the sender cannot
+    /// pay for gas) fails the whole simulation.
+    pub async fn simulate_bundle(
+        &self,
+        txs: Vec<TypedTransaction>,
+    ) -> Result<BundleSimulation, RevmMiddlewareError> {
+        let gas_price = U256::from_limbs(self.get_gas_price().await?.0);
+        let tx_envs = txs
+            .iter()
+            .map(|tx| self.build_tx_env(tx, gas_price))
+            .collect::<Result<Vec<_>, _>>()?;
+        match self.request(|outcome_sender| Instruction::SimulateBundle {
+            tx_envs,
+            outcome_sender,
+        })? {
+            Outcome::SimulateBundleCompleted {
+                execution_results,
+                balance_before,
+                balance_after,
+            } => {
+                let balance_before =
+                    ethers::types::I256::from_raw(eU256::from(balance_before.to_be_bytes()));
+                let balance_after =
+                    ethers::types::I256::from_raw(eU256::from(balance_after.to_be_bytes()));
+                Ok(BundleSimulation {
+                    gas_used: execution_results
+                        .iter()
+                        .map(|result| result.gas_used())
+                        .sum(),
+                    results: execution_results,
+                    profit: balance_after - balance_before,
                 })
-                .map_err(|e| RevmMiddlewareError::Send(e.to_string()))?;
-            match self.provider().as_ref().outcome_receiver.recv()?? {
-                Outcome::SetGasPriceCompleted => Ok(()),
-                _ => Err(RevmMiddlewareError::MissingData(
-                    "Wrong variant returned via instruction outcome!".to_string(),
-                )),
+            }
+            _ => Err(RevmMiddlewareError::MissingData(
+                "Wrong variant returned via instruction outcome!".to_string(),
+            )),
+        }
+    }
+
+    /// Deploys a contract through the canonical CREATE2 deployer at
+    /// [`CREATE2_DEPLOYER`], so it lands at the same address as it would on a
+    /// live chain for the same `salt`, `bytecode`, and `constructor_args`.
+    /// Returns the address of the deployed contract.
+    ///
+    /// The [`Environment`] must be built with
+    /// [`EnvironmentBuilder::create2_deployer`](crate::environment::builder::EnvironmentBuilder::create2_deployer).
+    /// Deploying the same contract with the same salt twice reverts.
+    pub async fn deploy_create2<T: ethers::abi::Tokenize>(
+        &self,
+        salt: [u8; 32],
+        bytecode: Bytes,
+        constructor_args: T,
+    ) -> Result<Address, RevmMiddlewareError> {
+        if self.get_code(CREATE2_DEPLOYER, None).await?.is_empty() {
+            return Err(RevmMiddlewareError::MissingData(
+                "The CREATE2 deployer is not deployed! Build the `Environment` with `EnvironmentBuilder::create2_deployer`.".to_string(),
+            ));
+        }
+        let mut init_code = bytecode.to_vec();
+        init_code.extend(ethers::abi::encode(&constructor_args.into_tokens()));
+        let address = ethers::utils::get_create2_address(CREATE2_DEPLOYER, salt, init_code.clone());
+        let data = [salt.as_slice(), &init_code].concat();
+        let tx = ethers::types::TransactionRequest::new()
+            .to(CREATE2_DEPLOYER)
+            .data(data);
+        self.send_transaction(tx, None).await?.await?;
+        Ok(address)
+    }
+
+    /// Builds the [`TxEnv`] that `revm` executes for a transaction sent by
+    /// this client.
+    fn build_tx_env(
+        &self,
+        tx: &TypedTransaction,
+        gas_price: U256,
+    ) -> Result<TxEnv, RevmMiddlewareError> {
+        // A `from` field other than this client's sender must be a signer in its
+        // keyring.
+        let caller = match tx.from() {
+            Some(&from) if from != self.wallet.address() => self.signer(&from)?.address(),
+            _ => self.wallet.address(),
+        };
+        build_tx_env_from(tx, gas_price, caller)
+    }
+
+    /// Builds the [`TxEnv`] that `revm` executes for a call made by this
+    /// client. Calls change no state, so as with `eth_call` they can be made
+    /// from any address in the `from` field, signer or not.
+    fn build_call_env(&self, tx: &TypedTransaction) -> Result<TxEnv, RevmMiddlewareError> {
+        let caller = tx.from().copied().unwrap_or(self.wallet.address());
+        build_tx_env_from(tx, U256::ZERO, caller)
+    }
+
+    /// Sends an [`Instruction::Call`] with optional state overrides to the
+    /// [`Environment`] and unpacks the output.
+    fn call_with_state(
+        &self,
+        tx: &TypedTransaction,
+        state_overrides: Option<HashMap<Address, spoof::Account>>,
+    ) -> Result<Bytes, RevmMiddlewareError> {
+        let tx_env = self.build_call_env(tx)?;
+        let outcome = self.request(|outcome_sender| Instruction::Call {
+            tx_env,
+            state_overrides,
+            outcome_sender,
+        })?;
+
+        if let Outcome::CallCompleted(execution_result) = outcome {
+            let output = unpack_execution_result(execution_result)
+                .map_err(|e| {
+                    self.provider()
+                        .as_ref()
+                        .decode_revert(tx.to_addr().copied(), e)
+                })?
+                .output;
+            match output {
+                Output::Create(bytes, ..) => Ok(Bytes::from(bytes.to_vec())),
+                Output::Call(bytes) => Ok(Bytes::from(bytes.to_vec())),
             }
         } else {
-            Err(RevmMiddlewareError::Send(
-                "Environment is offline!".to_string(),
-            ))
+            panic!("This should never happen!")
         }
     }
 }
 
+/// Generates a signer from a seed and label, or a random one if there is none.
+fn generate_wallet(seed_and_label: Option<&str>, chain_id: u64) -> Wallet<SigningKey> {
+    if let Some(seed) = seed_and_label {
+        let mut hasher = Sha256::new();
+        hasher.update(seed);
+        let hashed = hasher.finalize();
+        let mut rng: StdRng = SeedableRng::from_seed(hashed.into());
+        Wallet::new(&mut rng)
+    } else {
+        let mut rng = rand::thread_rng();
+        Wallet::new(&mut rng)
+    }
+    .with_chain_id(chain_id)
+}
+
 #[async_trait::async_trait]
 impl Middleware for RevmMiddleware {
     type Provider = Connection;
@@ -303,50 +645,12 @@ impl Middleware for RevmMiddleware {
         _block: Option<BlockId>,
     ) -> Result<PendingTransaction<'_, Self::Provider>, Self::Error> {
         let tx: TypedTransaction = tx.into();
-
-        // Check the `to` field of the transaction to determine if it is a call or a
-        // deploy. If there is no `to` field, then it is a `Deploy` else it is a
-        // `Call`.
-        let transact_to = match tx.to_addr() {
-            Some(&to) => TransactTo::Call(to.to_fixed_bytes().into()),
-            None => TransactTo::Create(CreateScheme::Create),
-        };
-        let tx_env = TxEnv {
-            caller: self.wallet.address().to_fixed_bytes().into(),
-            gas_limit: u64::MAX,
-            gas_price: revm::primitives::U256::from_limbs(self.get_gas_price().await?.0),
-            gas_priority_fee: None,
-            transact_to,
-            value: U256::ZERO,
-            data: revm_primitives::Bytes(bytes::Bytes::from(
-                tx.data()
-                    .ok_or(RevmMiddlewareError::MissingData(
-                        "Data missing in transaction!".to_string(),
-                    ))?
-                    .to_vec(),
-            )),
-            chain_id: None,
-            nonce: None,
-            access_list: Vec::new(),
-            blob_hashes: Vec::new(),
-            max_fee_per_blob_gas: None,
-        };
-        let instruction = Instruction::Transaction {
+        let gas_price = U256::from_limbs(self.get_gas_price().await?.0);
+        let tx_env = self.build_tx_env(&tx, gas_price)?;
+        let outcome = self.request(|outcome_sender| Instruction::Transaction {
             tx_env: tx_env.clone(),
-            outcome_sender: self.provider.as_ref().outcome_sender.clone(),
-        };
-
-        if let Some(instruction_sender) = self.provider().as_ref().instruction_sender.upgrade() {
-            instruction_sender
-                .send(instruction)
-                .map_err(|e| RevmMiddlewareError::Send(e.to_string()))?;
-        } else {
-            return Err(RevmMiddlewareError::Send(
-                "Environment is offline!".to_string(),
-            ));
-        }
-
-        let outcome = self.provider().as_ref().outcome_receiver.recv()??;
+            outcome_sender,
+        })?;
 
         if let Outcome::TransactionCompleted(execution_result, receipt_data) = outcome {
             let Success {
@@ -355,21 +659,24 @@ impl Middleware for RevmMiddleware {
                 _gas_refunded: _,
                 logs,
                 output,
-            } = unpack_execution_result(execution_result)?;
+            } = unpack_execution_result(execution_result).map_err(|e| {
+                self.provider()
+                    .as_ref()
+                    .decode_revert(tx.to_addr().copied(), e)
+            })?;
 
             let to: Option<ethers::types::H160> = match tx_env.transact_to {
                 TransactTo::Call(address) => Some(address.into_array().into()),
                 TransactTo::Create(_) => None,
             };
 
-            // Note that this is technically not the correct construction on the tx hash
-            // but until we increment the nonce correctly this will do
-            let sender = self.wallet.address();
-            let data = tx_env.clone().data;
-            let mut hasher = Sha256::new();
-            hasher.update(sender.as_bytes());
-            hasher.update(data.as_ref());
-            let hash = hasher.finalize();
+            let sender = recast_address(tx_env.caller);
+            let hash = transaction_hash(
+                sender,
+                receipt_data.nonce,
+                receipt_data.block_number,
+                receipt_data.transaction_index,
+            );
 
             let mut block_hasher = Sha256::new();
             block_hasher.update(receipt_data.block_number.to_string().as_bytes());
@@ -378,6 +685,12 @@ impl Middleware for RevmMiddleware {
 
             match output {
                 Output::Create(_, address) => {
+                    if let Some(address) = address {
+                        self.provider()
+                            .as_ref()
+                            .abi_registry
+                            .register_deployment(recast_address(address), &tx_env.data);
+                    }
                     let tx_receipt = TransactionReceipt {
                         block_hash,
                         block_number: Some(receipt_data.block_number),
@@ -386,7 +699,7 @@ impl Middleware for RevmMiddleware {
                         from: sender,
                         gas_used: Some(gas_used.into()),
                         effective_gas_price: Some(tx_env.clone().gas_price.to_be_bytes().into()), /* TODO */
-                        transaction_hash: ethers::types::TxHash::from_slice(&hash),
+                        transaction_hash: hash,
                         to,
                         cumulative_gas_used: receipt_data
                             .cumulative_gas_per_block
@@ -409,24 +722,16 @@ impl Middleware for RevmMiddleware {
                             _ => None,
                         },
                         transaction_index: receipt_data.transaction_index,
+                        other: receipt_data
+                            .l1_fee
+                            .map(|l1_fee| l1_fee.receipt_fields())
+                            .unwrap_or_default(),
                         ..Default::default()
                     };
 
-                    // TODO: I'm not sure we need to set the confirmations.
-                    let mut pending_tx =
-                        PendingTransaction::new(ethers::types::H256::zero(), self.provider())
-                            .interval(Duration::ZERO)
-                            .confirmations(0);
-
-                    let state_ptr: *mut PendingTxState =
-                        &mut pending_tx as *mut _ as *mut PendingTxState;
-
-                    // Modify the value (this assumes you have access to the enum variants)
-                    unsafe {
-                        *state_ptr = PendingTxState::CheckingReceipt(Some(tx_receipt));
-                    }
+                    self.provider().as_ref().store_receipt(&tx, &tx_receipt)?;
 
-                    Ok(pending_tx)
+                    Ok(resolved_pending_transaction(self.provider(), tx_receipt))
                 }
                 Output::Call(_) => {
                     let tx_receipt = TransactionReceipt {
@@ -437,7 +742,7 @@ impl Middleware for RevmMiddleware {
                         from: sender,
                         gas_used: Some(gas_used.into()),
                         effective_gas_price: Some(tx_env.clone().gas_price.to_be_bytes().into()),
-                        transaction_hash: ethers::types::TxHash::from_slice(&hash),
+                        transaction_hash: hash,
                         to,
                         cumulative_gas_used: receipt_data
                             .cumulative_gas_per_block
@@ -460,25 +765,16 @@ impl Middleware for RevmMiddleware {
                             _ => None,
                         },
                         transaction_index: receipt_data.transaction_index,
+                        other: receipt_data
+                            .l1_fee
+                            .map(|l1_fee| l1_fee.receipt_fields())
+                            .unwrap_or_default(),
                         ..Default::default()
                     };
 
-                    // TODO: Create the actual tx_hash
-                    // TODO: I'm not sure we need to set the confirmations.
-                    let mut pending_tx =
-                        PendingTransaction::new(ethers::types::H256::zero(), self.provider())
-                            .interval(Duration::ZERO)
-                            .confirmations(0);
+                    self.provider().as_ref().store_receipt(&tx, &tx_receipt)?;
 
-                    let state_ptr: *mut PendingTxState =
-                        &mut pending_tx as *mut _ as *mut PendingTxState;
-
-                    // Modify the value (this assumes you have access to the enum variants)
-                    unsafe {
-                        *state_ptr = PendingTxState::CheckingReceipt(Some(tx_receipt));
-                    }
-
-                    Ok(pending_tx)
+                    Ok(resolved_pending_transaction(self.provider(), tx_receipt))
                 }
             }
         } else {
@@ -499,111 +795,24 @@ impl Middleware for RevmMiddleware {
         tx: &TypedTransaction,
         _block: Option<BlockId>,
     ) -> Result<Bytes, Self::Error> {
-        let tx = tx.clone();
-
-        // Check the `to` field of the transaction to determine if it is a call or a
-        // deploy. If there is no `to` field, then it is a `Deploy` else it is a
-        // `Call`.
-        let transact_to = match tx.to_addr() {
-            Some(&to) => TransactTo::Call(to.to_fixed_bytes().into()),
-            None => TransactTo::Create(CreateScheme::Create),
-        };
-        let tx_env = TxEnv {
-            caller: self.wallet.address().to_fixed_bytes().into(),
-            gas_limit: u64::MAX,
-            gas_price: U256::ZERO,
-            gas_priority_fee: None,
-            transact_to,
-            value: U256::ZERO,
-            data: revm_primitives::Bytes(bytes::Bytes::from(
-                tx.data()
-                    .ok_or(RevmMiddlewareError::MissingData(
-                        "Data missing in transaction!".to_string(),
-                    ))?
-                    .to_vec(),
-            )),
-            chain_id: None,
-            nonce: None,
-            access_list: Vec::new(),
-            blob_hashes: Vec::new(),
-            max_fee_per_blob_gas: None,
-        };
-        let instruction = Instruction::Call {
-            tx_env,
-            outcome_sender: self.provider().as_ref().outcome_sender.clone(),
-        };
-        if let Some(instruction_sender) = self.provider().as_ref().instruction_sender.upgrade() {
-            instruction_sender
-                .send(instruction)
-                .map_err(|e| RevmMiddlewareError::Send(e.to_string()))?;
-        } else {
-            return Err(RevmMiddlewareError::Send(
-                "Environment is offline!".to_string(),
-            ));
-        }
-        let outcome = self.provider().as_ref().outcome_receiver.recv()??;
-
-        if let Outcome::CallCompleted(execution_result) = outcome {
-            let output = unpack_execution_result(execution_result)?.output;
-            match output {
-                Output::Create(bytes, ..) => {
-                    return Ok(Bytes::from(bytes.to_vec()));
-                }
-                Output::Call(bytes) => {
-                    return Ok(Bytes::from(bytes.to_vec()));
-                }
-            }
-        } else {
-            panic!("This should never happen!")
-        }
+        self.call_with_state(tx, None)
     }
 
     /// Creates a new filter for incoming Ethereum logs based on certain
-    /// criteria.
+    /// criteria, for new blocks, or for pending transactions.
     ///
-    /// Currently, this method supports log filters. Other filters like
-    /// `NewBlocks` and `PendingTransactions` are not yet implemented.
+    /// Note that transactions do not actually sit in a pending state in the
+    /// [`Environment`] -- they are broadcast as pending once they have been
+    /// executed, right before their changes are committed.
     async fn new_filter(&self, filter: FilterKind<'_>) -> Result<ethers::types::U256, Self::Error> {
-        let (_method, args) = match filter {
-            FilterKind::NewBlocks => unimplemented!(
-                "Filtering via new `FilterKind::NewBlocks` has not been implemented yet!"
-            ),
-            FilterKind::PendingTransactions => {
-                unimplemented!("Filtering via `FilterKind::PendingTransactions` has not been implemented yet! 
-                At the current development stage of Arbiter, transactions do not actually sit in a pending state
-                 -- they are executed immediately.")
+        let kind = match filter {
+            FilterKind::NewBlocks => ListenerKind::NewBlocks,
+            FilterKind::PendingTransactions => ListenerKind::PendingTransactions,
+            FilterKind::Logs(filter) => {
+                ListenerKind::Logs(Box::new(FilteredParams::new(Some(filter.clone()))))
             }
-            FilterKind::Logs(filter) => ("eth_newFilter", filter),
-        };
-        let filter = args.clone();
-        let mut hasher = Sha256::new();
-        hasher.update(serde_json::to_string(&args).map_err(RevmMiddlewareError::Json)?);
-        let hash = hasher.finalize();
-        let id = ethers::types::U256::from(ethers::types::H256::from_slice(&hash).as_bytes());
-        let (event_sender, event_receiver) =
-            crossbeam_channel::unbounded::<Vec<revm::primitives::Log>>();
-        let filter_receiver = FilterReceiver {
-            filter,
-            receiver: event_receiver,
         };
-        self.provider()
-            .as_ref()
-            .event_broadcaster
-            .lock()
-            .map_err(|e| {
-                RevmMiddlewareError::EventBroadcaster(format!(
-                    "Failed to gain lock on the `Connection`'s `event_broadcaster` due to {:?} ",
-                    e
-                ))
-            })?
-            .add_sender(event_sender);
-        self.provider()
-            .as_ref()
-            .filter_receivers
-            .lock()
-            .await
-            .insert(id, filter_receiver);
-        Ok(id)
+        Ok(self.provider().as_ref().install_filter(kind).await?)
     }
 
     /// Starts watching for logs that match a specific filter.
@@ -618,51 +827,107 @@ impl Middleware for RevmMiddleware {
         Ok(FilterWatcher::new(id, self.provider()).interval(Duration::ZERO))
     }
 
+    /// Starts watching for the hashes of new blocks.
+    async fn watch_blocks(
+        &self,
+    ) -> Result<FilterWatcher<'_, Self::Provider, ethers::types::H256>, Self::Error> {
+        let id = self.new_filter(FilterKind::NewBlocks).await?;
+        Ok(FilterWatcher::new(id, self.provider()).interval(Duration::ZERO))
+    }
+
+    /// Starts watching for the hashes of transactions as they are executed
+    /// by the [`Environment`], right before their changes are committed.
+    async fn watch_pending_transactions(
+        &self,
+    ) -> Result<FilterWatcher<'_, Self::Provider, ethers::types::H256>, Self::Error> {
+        let id = self.new_filter(FilterKind::PendingTransactions).await?;
+        Ok(FilterWatcher::new(id, self.provider()).interval(Duration::ZERO))
+    }
+
+    /// Returns the chain ID of the [`Environment`], which is also the one
+    /// this client signs transactions for.
+    async fn get_chainid(&self) -> Result<ethers::types::U256, Self::Error> {
+        Ok(self.provider().as_ref().chain_id.into())
+    }
+
     async fn get_gas_price(&self) -> Result<ethers::types::U256, Self::Error> {
-        if let Some(instruction_sender) = self.provider().as_ref().instruction_sender.upgrade() {
-            instruction_sender
-                .send(Instruction::Query {
-                    environment_data: EnvironmentData::GasPrice,
-                    outcome_sender: self.provider().as_ref().outcome_sender.clone(),
-                })
-                .map_err(|e| RevmMiddlewareError::Send(e.to_string()))?;
-            match self.provider().as_ref().outcome_receiver.recv()?? {
-                Outcome::QueryReturn(outcome) => {
-                    ethers::types::U256::from_str_radix(outcome.as_ref(), 10)
-                        .map_err(|e| RevmMiddlewareError::Conversion(e.to_string()))
-                }
-                _ => Err(RevmMiddlewareError::MissingData(
-                    "Wrong variant returned via query!".to_string(),
-                )),
+        match self.request(|outcome_sender| Instruction::Query {
+            environment_data: EnvironmentData::GasPrice,
+            outcome_sender,
+        })? {
+            Outcome::QueryReturn(outcome) => {
+                ethers::types::U256::from_str_radix(outcome.as_ref(), 10)
+                    .map_err(|e| RevmMiddlewareError::Conversion(e.to_string()))
             }
-        } else {
-            Err(RevmMiddlewareError::Send(
-                "Environment is offline!".to_string(),
-            ))
+            _ => Err(RevmMiddlewareError::MissingData(
+                "Wrong variant returned via query!".to_string(),
+            )),
         }
     }
 
+    /// Returns the logs emitted in the [`Environment`] that match the
+    /// `filter`. Each block keeps a bloom filter of its logs so that blocks
+    /// that cannot hold a matching log are skipped, which keeps queries over
+    /// long histories fast. This requires the [`Environment`] to be built
+    /// with [`EnvironmentBuilder::store_receipts`](crate::environment::builder::EnvironmentBuilder::store_receipts).
+    async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, Self::Error> {
+        Ok(self.provider().as_ref().logs(filter)?)
+    }
+
+    /// Returns the block with the given number or hash, built from what the
+    /// [`Environment`] kept about it: its timestamp, base fee, gas used, and
+    /// the hashes of its transactions. Returns `None` for blocks the
+    /// [`Environment`] has not reached.
+    async fn get_block<T: Into<BlockId> + Send + Sync>(
+        &self,
+        block_hash_or_number: T,
+    ) -> Result<Option<Block<TxHash>>, Self::Error> {
+        Ok(self
+            .provider()
+            .as_ref()
+            .block(block_hash_or_number.into())?)
+    }
+
+    /// Returns a transaction executed by the [`Environment`], looked up by
+    /// its hash. This requires the [`Environment`] to be built with
+    /// [`EnvironmentBuilder::store_receipts`](crate::environment::builder::EnvironmentBuilder::store_receipts).
+    async fn get_transaction<T: Send + Sync + Into<TxHash>>(
+        &self,
+        transaction_hash: T,
+    ) -> Result<Option<Transaction>, Self::Error> {
+        Ok(self
+            .provider()
+            .as_ref()
+            .stored_transaction(transaction_hash.into())?
+            .map(|(transaction, _)| transaction))
+    }
+
+    /// Returns the receipt of a transaction executed by the [`Environment`],
+    /// looked up by its hash. This requires the [`Environment`] to be built
+    /// with [`EnvironmentBuilder::store_receipts`](crate::environment::builder::EnvironmentBuilder::store_receipts).
+    async fn get_transaction_receipt<T: Send + Sync + Into<TxHash>>(
+        &self,
+        transaction_hash: T,
+    ) -> Result<Option<TransactionReceipt>, Self::Error> {
+        Ok(self
+            .provider()
+            .as_ref()
+            .stored_transaction(transaction_hash.into())?
+            .map(|(_, receipt)| receipt))
+    }
+
     async fn get_block_number(&self) -> Result<U64, Self::Error> {
-        if let Some(instruction_sender) = self.provider().as_ref().instruction_sender.upgrade() {
-            instruction_sender
-                .send(Instruction::Query {
-                    environment_data: EnvironmentData::BlockNumber,
-                    outcome_sender: self.provider().as_ref().outcome_sender.clone(),
-                })
-                .map_err(|e| RevmMiddlewareError::Send(e.to_string()))?;
-            match self.provider().as_ref().outcome_receiver.recv()?? {
-                Outcome::QueryReturn(outcome) => {
-                    ethers::types::U64::from_str_radix(outcome.as_ref(), 10)
-                        .map_err(|e| RevmMiddlewareError::Conversion(e.to_string()))
-                }
-                _ => Err(RevmMiddlewareError::MissingData(
-                    "Wrong variant returned via query!".to_string(),
-                )),
+        match self.request(|outcome_sender| Instruction::Query {
+            environment_data: EnvironmentData::BlockNumber,
+            outcome_sender,
+        })? {
+            Outcome::QueryReturn(outcome) => {
+                ethers::types::U64::from_str_radix(outcome.as_ref(), 10)
+                    .map_err(|e| RevmMiddlewareError::Conversion(e.to_string()))
             }
-        } else {
-            Err(RevmMiddlewareError::Send(
-                "Environment is offline!".to_string(),
-            ))
+            _ => Err(RevmMiddlewareError::MissingData(
+                "Wrong variant returned via query!".to_string(),
+            )),
         }
     }
 
@@ -686,28 +951,17 @@ impl Middleware for RevmMiddleware {
             NameOrAddress::Address(address) => address,
         };
 
-        if let Some(instruction_sender) = self.provider().as_ref().instruction_sender.upgrade() {
-            instruction_sender
-                .send(Instruction::Query {
-                    environment_data: EnvironmentData::Balance(ethers::types::Address::from(
-                        address,
-                    )),
-                    outcome_sender: self.provider().as_ref().outcome_sender.clone(),
-                })
-                .map_err(|e| RevmMiddlewareError::Send(e.to_string()))?;
-            match self.provider().as_ref().outcome_receiver.recv()?? {
-                Outcome::QueryReturn(outcome) => {
-                    ethers::types::U256::from_str_radix(outcome.as_ref(), 10)
-                        .map_err(|e| RevmMiddlewareError::Conversion(e.to_string()))
-                }
-                _ => Err(RevmMiddlewareError::MissingData(
-                    "Wrong variant returned via query!".to_string(),
-                )),
+        match self.request(|outcome_sender| Instruction::Query {
+            environment_data: EnvironmentData::Balance(address),
+            outcome_sender,
+        })? {
+            Outcome::QueryReturn(outcome) => {
+                ethers::types::U256::from_str_radix(outcome.as_ref(), 10)
+                    .map_err(|e| RevmMiddlewareError::Conversion(e.to_string()))
             }
-        } else {
-            Err(RevmMiddlewareError::Send(
-                "Environment is offline!".to_string(),
-            ))
+            _ => Err(RevmMiddlewareError::MissingData(
+                "Wrong variant returned via query!".to_string(),
+            )),
         }
     }
 
@@ -726,27 +980,17 @@ impl Middleware for RevmMiddleware {
             }
             NameOrAddress::Address(address) => address,
         };
-        if let Some(instruction_sender) = self.provider().as_ref().instruction_sender.upgrade() {
-            instruction_sender
-                .send(Instruction::Query {
-                    environment_data: EnvironmentData::TransactionCount(address),
-                    outcome_sender: self.provider().as_ref().outcome_sender.clone(),
-                })
-                .map_err(|e| RevmMiddlewareError::Send(e.to_string()))?;
-
-            match self.provider().as_ref().outcome_receiver.recv()?? {
-                Outcome::QueryReturn(outcome) => {
-                    ethers::types::U256::from_str_radix(outcome.as_ref(), 10)
-                        .map_err(|e| RevmMiddlewareError::Conversion(e.to_string()))
-                }
-                _ => Err(RevmMiddlewareError::MissingData(
-                    "Wrong variant returned via query!".to_string(),
-                )),
+        match self.request(|outcome_sender| Instruction::Query {
+            environment_data: EnvironmentData::TransactionCount(address),
+            outcome_sender,
+        })? {
+            Outcome::QueryReturn(outcome) => {
+                ethers::types::U256::from_str_radix(outcome.as_ref(), 10)
+                    .map_err(|e| RevmMiddlewareError::Conversion(e.to_string()))
             }
-        } else {
-            Err(RevmMiddlewareError::Send(
-                "Environment is offline!".to_string(),
-            ))
+            _ => Err(RevmMiddlewareError::MissingData(
+                "Wrong variant returned via query!".to_string(),
+            )),
         }
     }
 
@@ -776,6 +1020,34 @@ impl Middleware for RevmMiddleware {
 
         Ok(())
     }
+    /// Fetches the deployed code of the account at `address`. The code is
+    /// always that of the current state of the [`Environment`] and the `block`
+    /// is ignored.
+    async fn get_code<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        at: T,
+        _block: Option<BlockId>,
+    ) -> Result<ethers::types::Bytes, RevmMiddlewareError> {
+        let address = match at.into() {
+            NameOrAddress::Name(_) => {
+                return Err(RevmMiddlewareError::MissingData(
+                    "Querying code via name is not supported!".to_string(),
+                ))
+            }
+            NameOrAddress::Address(address) => address,
+        };
+
+        match self
+            .apply_cheatcode(Cheatcodes::Code { account: address })
+            .await?
+        {
+            CheatcodesReturn::Code { code } => Ok(code),
+            _ => Err(RevmMiddlewareError::MissingData(
+                "Wrong variant returned via cheatcode!".to_string(),
+            )),
+        }
+    }
+
     /// Fetches the value stored at the storage slot `key` for an account at
     /// `address`. todo: implement the storage at a specific block feature.
     async fn get_storage_at<T: Into<NameOrAddress> + Send + Sync>(
@@ -817,12 +1089,71 @@ impl Middleware for RevmMiddleware {
     }
 }
 
+/// Builds the [`TxEnv`] that `revm` executes for `tx` sent from `caller`.
+fn build_tx_env_from(
+    tx: &TypedTransaction,
+    gas_price: U256,
+    caller: Address,
+) -> Result<TxEnv, RevmMiddlewareError> {
+    // Check the `to` field of the transaction to determine if it is a call or a
+    // deploy. If there is no `to` field, then it is a `Deploy` else it is a
+    // `Call`.
+    let transact_to = match tx.to_addr() {
+        Some(&to) => TransactTo::Call(to.to_fixed_bytes().into()),
+        None => TransactTo::Create(CreateScheme::Create),
+    };
+    Ok(TxEnv {
+        caller: caller.to_fixed_bytes().into(),
+        gas_limit: u64::MAX,
+        gas_price,
+        gas_priority_fee: None,
+        transact_to,
+        value: U256::ZERO,
+        data: revm_primitives::Bytes(bytes::Bytes::from(
+            tx.data()
+                .ok_or(RevmMiddlewareError::MissingData(
+                    "Data missing in transaction!".to_string(),
+                ))?
+                .to_vec(),
+        )),
+        chain_id: None,
+        nonce: None,
+        access_list: Vec::new(),
+        blob_hashes: Vec::new(),
+        max_fee_per_blob_gas: None,
+    })
+}
+
 #[cfg(target_arch = "wasm32")]
 pub(crate) type PinBoxFut<'a, T> = Pin<Box<dyn Future<Output = Result<T, ProviderError>> + 'a>>;
 #[cfg(not(target_arch = "wasm32"))]
 pub(crate) type PinBoxFut<'a, T> =
     Pin<Box<dyn Future<Output = Result<T, ProviderError>> + Send + 'a>>;
 
+/// A [`PendingTransaction`] for a transaction that has already been executed,
+/// which resolves to its `receipt` the first time it is polled. The
+/// [`RevmMiddleware`] and the stand-ins for it hand these back from
+/// `send_transaction`.
+pub(crate) fn resolved_pending_transaction<P: JsonRpcClient>(
+    provider: &Provider<P>,
+    receipt: TransactionReceipt,
+) -> PendingTransaction<'_, P> {
+    let mut pending_tx = PendingTransaction::new(receipt.transaction_hash, provider)
+        .interval(Duration::ZERO)
+        .confirmations(0);
+    let state_ptr: *mut PendingTxState = &mut pending_tx as *mut _ as *mut PendingTxState;
+    // SAFETY: ethers does not export the state of a `PendingTransaction`, so it
+    // is overwritten through `PendingTxState`, which mirrors it variant for
+    // variant. This relies on the layout of the `PendingTransaction` of the
+    // ethers version pinned in `Cargo.toml`, and `PendingTxState` has to be
+    // checked against it whenever that version changes. The state that is
+    // written holds no references, so it is valid for any lifetime.
+    unsafe {
+        *state_ptr = PendingTxState::CheckingReceipt(Some(receipt));
+    }
+    pending_tx
+}
+
 // Because this is the exact same struct it will have the exact same memory
 // aliment allowing us to bypass the fact that ethers-rs doesn't export this
 // enum normally We box the TransactionReceipts to keep the enum small.
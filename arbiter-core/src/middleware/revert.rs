@@ -0,0 +1,367 @@
+//! The `revert` module decodes the data returned by a reverted transaction or
+//! call into a [`RevertError`] and lets tests assert that an action reverts
+//! with [`RevmMiddleware::expect_revert_with`].
+
+#![warn(missing_docs)]
+
+use std::fmt::{self, Display};
+
+use ethers::{
+    abi::{decode, Abi, ParamType, Token},
+    contract::ContractError,
+};
+
+use super::*;
+use crate::environment::abi_registry::AbiRegistry;
+
+/// The selector of Solidity's `Error(string)`, which is what `require` and
+/// `revert` with a reason string return.
+pub const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// The selector of Solidity's `Panic(uint256)`, which is what failing
+/// assertions, arithmetic overflows, and the like return.
+pub const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// The decoded data of a revert.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RevertError {
+    /// A revert with a reason string, i.e., `Error(string)`.
+    Reason(String),
+
+    /// A `Panic(uint256)` with its panic code.
+    Panic(eU256),
+
+    /// A custom error found in the ABI the data was decoded with.
+    Custom {
+        /// The name of the error.
+        name: String,
+
+        /// The selector of the error.
+        selector: [u8; 4],
+
+        /// The decoded arguments of the error.
+        args: Vec<Token>,
+    },
+
+    /// Data that could not be decoded, e.g., a custom error without an ABI or
+    /// a revert without any data.
+    Unknown(Bytes),
+}
+
+impl RevertError {
+    /// Decodes the `data` of a revert. Custom errors are only decoded if they
+    /// are part of the `abi`.
+    pub fn decode(data: &[u8], abi: Option<&Abi>) -> Self {
+        let unknown = || RevertError::Unknown(Bytes::from(data.to_vec()));
+        if data.len() < 4 {
+            return unknown();
+        }
+        let (selector, args) = data.split_at(4);
+        match selector {
+            selector if selector == ERROR_SELECTOR => {
+                match decode(&[ParamType::String], args).map(|mut tokens| tokens.pop()) {
+                    Ok(Some(Token::String(reason))) => RevertError::Reason(reason),
+                    _ => unknown(),
+                }
+            }
+            selector if selector == PANIC_SELECTOR => {
+                match decode(&[ParamType::Uint(256)], args).map(|mut tokens| tokens.pop()) {
+                    Ok(Some(Token::Uint(code))) => RevertError::Panic(code),
+                    _ => unknown(),
+                }
+            }
+            selector => abi
+                .and_then(|abi| {
+                    abi.errors().find_map(|error| {
+                        if error.signature()[..4] != *selector {
+                            return None;
+                        }
+                        error.decode(args).ok().map(|args| RevertError::Custom {
+                            name: error.name.clone(),
+                            selector: [selector[0], selector[1], selector[2], selector[3]],
+                            args,
+                        })
+                    })
+                })
+                .unwrap_or_else(unknown),
+        }
+    }
+
+    /// Decodes the `data` of a revert from a call to the contract at
+    /// `address`, or of a deployment if there is no `address`. Custom errors
+    /// are decoded with the ABI the `registry` has for the contract and
+    /// otherwise with the first other ABI in it that declares the error.
+    pub fn decode_with_registry(
+        data: &[u8],
+        registry: &AbiRegistry,
+        address: Option<Address>,
+    ) -> Self {
+        match RevertError::decode(data, None) {
+            RevertError::Unknown(_) if data.len() >= 4 => registry
+                .find_map(address, |abi| match RevertError::decode(data, Some(abi)) {
+                    custom @ RevertError::Custom { .. } => Some(custom),
+                    _ => None,
+                })
+                .unwrap_or_else(|| RevertError::Unknown(Bytes::from(data.to_vec()))),
+            decoded => decoded,
+        }
+    }
+
+    /// The selector the revert data started with, if it had one.
+    pub fn selector(&self) -> Option<[u8; 4]> {
+        match self {
+            RevertError::Reason(_) => Some(ERROR_SELECTOR),
+            RevertError::Panic(_) => Some(PANIC_SELECTOR),
+            RevertError::Custom { selector, .. } => Some(*selector),
+            RevertError::Unknown(data) if data.len() >= 4 => {
+                Some([data[0], data[1], data[2], data[3]])
+            }
+            RevertError::Unknown(_) => None,
+        }
+    }
+
+    /// Whether this revert is the `expected` one.
+    pub fn matches(&self, expected: &ExpectedRevert) -> bool {
+        match (expected, self) {
+            (ExpectedRevert::Any, _) => true,
+            (ExpectedRevert::Reason(expected), RevertError::Reason(reason)) => expected == reason,
+            (ExpectedRevert::Panic(expected), RevertError::Panic(code)) => {
+                eU256::from(*expected) == *code
+            }
+            (ExpectedRevert::Selector(expected), _) => self.selector() == Some(*expected),
+            _ => false,
+        }
+    }
+}
+
+impl Display for RevertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevertError::Reason(reason) => write!(f, "reverted with reason \"{}\"", reason),
+            RevertError::Panic(code) => match panic_description(*code) {
+                Some(description) => write!(f, "panicked with code {:#x} ({})", code, description),
+                None => write!(f, "panicked with code {:#x}", code),
+            },
+            RevertError::Custom { name, args, .. } => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "reverted with {}({})", name, args)
+            }
+            RevertError::Unknown(data) => write!(f, "reverted with data {}", data),
+        }
+    }
+}
+
+/// What an action is expected to revert with in
+/// [`RevmMiddleware::expect_revert_with`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExpectedRevert {
+    /// Any revert.
+    Any,
+
+    /// A revert with exactly this reason string.
+    Reason(String),
+
+    /// A `Panic(uint256)` with this panic code, e.g., `0x11` for an
+    /// arithmetic overflow.
+    Panic(u64),
+
+    /// A revert whose data starts with this selector. This is how custom
+    /// errors are expected, e.g., with the `selector()` of an error generated
+    /// by `abigen`.
+    Selector([u8; 4]),
+}
+
+impl From<&str> for ExpectedRevert {
+    fn from(reason: &str) -> Self {
+        ExpectedRevert::Reason(reason.to_string())
+    }
+}
+
+impl From<String> for ExpectedRevert {
+    fn from(reason: String) -> Self {
+        ExpectedRevert::Reason(reason)
+    }
+}
+
+impl From<[u8; 4]> for ExpectedRevert {
+    fn from(selector: [u8; 4]) -> Self {
+        ExpectedRevert::Selector(selector)
+    }
+}
+
+impl Display for ExpectedRevert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpectedRevert::Any => write!(f, "any revert"),
+            ExpectedRevert::Reason(reason) => write!(f, "the reason \"{}\"", reason),
+            ExpectedRevert::Panic(code) => write!(f, "a panic with code {:#x}", code),
+            ExpectedRevert::Selector(selector) => {
+                write!(f, "the selector {}", Bytes::from(selector.to_vec()))
+            }
+        }
+    }
+}
+
+/// Errors that can carry the data of a revert.
+pub trait RevertData {
+    /// The data returned by the revert, or `None` if this error is not a
+    /// revert.
+    fn revert_data(&self) -> Option<Bytes>;
+
+    /// The decoded data of the revert, or `None` if this error is not a
+    /// revert. Unless the error was already decoded, custom errors are left
+    /// as [`RevertError::Unknown`].
+    fn revert_error(&self) -> Option<RevertError> {
+        self.revert_data()
+            .map(|data| RevertError::decode(&data, None))
+    }
+}
+
+impl RevertData for RevmMiddlewareError {
+    fn revert_data(&self) -> Option<Bytes> {
+        match self {
+            RevmMiddlewareError::ExecutionRevert { output, .. } => {
+                Some(Bytes::from(output.0.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    fn revert_error(&self) -> Option<RevertError> {
+        match self {
+            RevmMiddlewareError::ExecutionRevert { decoded, .. } => Some(decoded.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl<M: Middleware> RevertData for ContractError<M>
+where
+    M::Error: RevertData,
+{
+    fn revert_data(&self) -> Option<Bytes> {
+        self.as_revert()
+            .cloned()
+            .or_else(|| self.as_middleware_error().and_then(RevertData::revert_data))
+    }
+
+    fn revert_error(&self) -> Option<RevertError> {
+        match self.as_revert() {
+            Some(data) => Some(RevertError::decode(data, None)),
+            None => self
+                .as_middleware_error()
+                .and_then(RevertData::revert_error),
+        }
+    }
+}
+
+impl Connection {
+    /// Decodes the custom error in a revert of a call to the contract at
+    /// `address` with the [`AbiRegistry`] of the [`Environment`]. Other
+    /// errors are returned as they are.
+    pub(crate) fn decode_revert(
+        &self,
+        address: Option<Address>,
+        error: RevmMiddlewareError,
+    ) -> RevmMiddlewareError {
+        match error {
+            RevmMiddlewareError::ExecutionRevert {
+                gas_used,
+                output,
+                decoded: RevertError::Unknown(_),
+            } => {
+                let decoded =
+                    RevertError::decode_with_registry(&output, &self.abi_registry, address);
+                RevmMiddlewareError::ExecutionRevert {
+                    gas_used,
+                    output,
+                    decoded,
+                }
+            }
+            error => error,
+        }
+    }
+}
+
+impl RevmMiddleware {
+    /// Registers the `abi` of the contract at `address` with the
+    /// [`Environment`] so that the custom errors of the contract are decoded
+    /// when calls or transactions of any client revert.
+    pub fn register_abi(&self, address: Address, abi: Abi) {
+        self.provider().as_ref().abi_registry.register(address, abi);
+    }
+}
+
+impl RevmMiddleware {
+    /// Awaits the `action`, e.g., a pending contract call or transaction, and
+    /// checks that it reverts as `expected`. A reason string, an
+    /// [`ExpectedRevert`], or the selector of a custom error can be given as
+    /// the expectation.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(RevertError)` with the decoded revert if the action reverted as
+    ///   expected.
+    /// * `Err(RevmMiddlewareError::UnexpectedOutcome)` if the action succeeded,
+    ///   reverted differently, or failed without reverting.
+    pub async fn expect_revert_with<T, E>(
+        &self,
+        expected: impl Into<ExpectedRevert>,
+        action: impl Future<Output = Result<T, E>>,
+    ) -> Result<RevertError, RevmMiddlewareError>
+    where
+        E: RevertData + Display,
+    {
+        let expected = expected.into();
+        let error = match action.await {
+            Ok(_) => {
+                return Err(RevmMiddlewareError::UnexpectedOutcome(format!(
+                    "expected {} but the action succeeded",
+                    expected
+                )))
+            }
+            Err(error) => error,
+        };
+        let revert = match error.revert_error() {
+            Some(revert) => revert,
+            None => {
+                return Err(RevmMiddlewareError::UnexpectedOutcome(format!(
+                    "expected {} but the action failed without reverting: {}",
+                    expected, error
+                )))
+            }
+        };
+        if revert.matches(&expected) {
+            Ok(revert)
+        } else {
+            Err(RevmMiddlewareError::UnexpectedOutcome(format!(
+                "expected {} but the action {}",
+                expected, revert
+            )))
+        }
+    }
+}
+
+/// What a Solidity panic code means.
+fn panic_description(code: eU256) -> Option<&'static str> {
+    if code > eU256::from(u8::MAX) {
+        return None;
+    }
+    Some(match code.as_u64() {
+        0x00 => "generic compiler panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array",
+        0x31 => "pop from an empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to an uninitialized function",
+        _ => return None,
+    })
+}
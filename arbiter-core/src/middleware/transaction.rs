@@ -3,7 +3,7 @@ use revm::primitives::{ExecutionResult, Output};
 /// Unwraps the result of the EVM execution into a more structured `Success`
 /// type.
 use super::cast::revm_logs_to_ethers_logs;
-use super::errors::RevmMiddlewareError;
+use super::{errors::RevmMiddlewareError, revert::RevertError};
 
 /// Contains the result of a successful transaction execution.
 #[derive(Debug)]
@@ -20,11 +20,35 @@ pub struct Success {
     pub output: Output,
 }
 
+/// The outcome of simulating a bundle of transactions with
+/// [`RevmMiddleware::simulate_bundle`](super::RevmMiddleware::simulate_bundle).
+#[derive(Debug, Clone, PartialEq)]
+pub struct BundleSimulation {
+    /// The result of each transaction, in the order they were given.
+    pub results: Vec<ExecutionResult>,
+
+    /// The gas used by all the transactions together.
+    pub gas_used: u64,
+
+    /// How much the ether balance of the sender changed over the bundle, gas
+    /// costs included.
+    pub profit: ethers::types::I256,
+}
+
+impl BundleSimulation {
+    /// Whether every transaction in the bundle succeeded.
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(|result| result.is_success())
+    }
+}
+
 /// Unpacks the result of the EVM execution.
 ///
 /// This function converts the raw execution result from the EVM into a more
 /// structured [`Success`] type or an error indicating the failure of the
 /// execution.
+/// Reason strings and panics in the data of a revert are decoded here, while
+/// custom errors are left for the caller to decode with the ABIs it knows.
 pub fn unpack_execution_result(
     execution_result: ExecutionResult,
 ) -> Result<Success, RevmMiddlewareError> {
@@ -46,7 +70,12 @@ pub fn unpack_execution_result(
             })
         }
         ExecutionResult::Revert { gas_used, output } => {
-            Err(RevmMiddlewareError::ExecutionRevert { gas_used, output })
+            let decoded = RevertError::decode(&output, None);
+            Err(RevmMiddlewareError::ExecutionRevert {
+                gas_used,
+                output,
+                decoded,
+            })
         }
         ExecutionResult::Halt { reason, gas_used } => {
             Err(RevmMiddlewareError::ExecutionHalt { reason, gas_used })
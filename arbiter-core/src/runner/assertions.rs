@@ -0,0 +1,338 @@
+//! The `assertions` module lets a config declare simple checks that every run
+//! of a simulation has to pass, so that correctness checks can be added
+//! without writing Rust.
+//!
+//! [`Assertions`] are read from the `assertions` array of a TOML config, which
+//! can sit next to its `sweep` section:
+//!
+//! ```toml
+//! # The balance of `account` never drops below `min`. Without a `token`, the
+//! # ether balance is checked. `min` is in whole tokens of `decimals`
+//! # decimals, which defaults to 18.
+//! [[assertions]]
+//! kind = "balance"
+//! account = "0x2e8c9b7f3c5b0a1d4e6f8a9b0c1d2e3f4a5b6c7d"
+//! token = "0x1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b"
+//! min = 1000.0
+//!
+//! # At least `at_least` logs of `event` are emitted up to and including block
+//! # `by_block`, by the contract at `address` if one is given.
+//! [[assertions]]
+//! kind = "event"
+//! event = "Transfer(address,address,uint256)"
+//! at_least = 10
+//! by_block = 100
+//! ```
+//!
+//! A run starts checking the assertions against its [`Environment`] with
+//! [`Assertions::watch`] and collects the [`AssertionFailure`]s once it is
+//! done with [`AssertionWatcher::finish`].
+
+#![warn(missing_docs)]
+
+use std::{fmt::Display, sync::Arc, time::Duration};
+
+use ethers::{
+    providers::{FilterKind, FilterWatcher, Middleware},
+    types::{Address, Filter, U256},
+    utils::{format_units, parse_units},
+};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{sync::oneshot, task::JoinHandle};
+
+use crate::middleware::{errors::RevmMiddlewareError, RevmMiddleware};
+#[cfg_attr(doc, doc(hidden))]
+#[cfg_attr(doc, allow(unused_imports))]
+#[cfg(doc)]
+use crate::{environment::builder::EnvironmentBuilder, environment::Environment};
+
+/// Errors that can occur when reading [`Assertions`].
+#[derive(Error, Debug, Clone)]
+pub enum AssertionError {
+    /// The config could not be parsed.
+    #[error("failed to parse the assertions config! due to: {0}")]
+    Config(String),
+
+    /// An assertion can not be checked as it is written.
+    #[error("invalid assertion {0}! due to: {1}")]
+    InvalidAssertion(usize, String),
+}
+
+/// A check that a run of a simulation has to pass.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Assertion {
+    /// The balance of `account` never drops below `min`.
+    Balance {
+        /// The account whose balance is checked.
+        account: Address,
+
+        /// The ERC-20 token the balance is held in. The ether balance is
+        /// checked if none is given.
+        #[serde(default)]
+        token: Option<Address>,
+
+        /// The lowest balance allowed, in whole tokens.
+        min: f64,
+
+        /// The decimals of the token. Defaults to 18.
+        #[serde(default = "default_decimals")]
+        decimals: u32,
+    },
+
+    /// At least `at_least` logs of `event` are emitted up to and including
+    /// block `by_block`.
+    Event {
+        /// The signature of the event, e.g.,
+        /// `Transfer(address,address,uint256)`.
+        event: String,
+
+        /// The contract that has to emit the logs. Logs from any contract
+        /// count if none is given.
+        #[serde(default)]
+        address: Option<Address>,
+
+        /// The least number of logs that have to be emitted.
+        at_least: usize,
+
+        /// The last block in which logs are counted.
+        by_block: u64,
+    },
+}
+
+fn default_decimals() -> u32 {
+    18
+}
+
+impl Display for Assertion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Assertion::Balance {
+                account,
+                token: Some(token),
+                min,
+                ..
+            } => write!(
+                f,
+                "balance of {:?} in token {:?} never below {}",
+                account, token, min
+            ),
+            Assertion::Balance {
+                account,
+                token: None,
+                min,
+                ..
+            } => write!(f, "ether balance of {:?} never below {}", account, min),
+            Assertion::Event {
+                event,
+                at_least,
+                by_block,
+                ..
+            } => write!(
+                f,
+                "event {} occurs at least {} times by block {}",
+                event, at_least, by_block
+            ),
+        }
+    }
+}
+
+/// An [`Assertion`] that a run did not pass, along with why.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AssertionFailure {
+    /// The assertion that failed.
+    pub assertion: Assertion,
+
+    /// What the run did instead.
+    pub reason: String,
+}
+
+impl Display for AssertionFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} failed: {}", self.assertion, self.reason)
+    }
+}
+
+/// The assertions declared in a config.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Assertions(pub Vec<Assertion>);
+
+/// The layout of a config file holding an `assertions` array.
+#[derive(Deserialize)]
+struct AssertionsFile {
+    #[serde(default)]
+    assertions: Vec<Assertion>,
+}
+
+impl Assertions {
+    /// Reads the [`Assertions`] from the `assertions` array of a TOML config.
+    /// A config without one has no assertions. Any other sections of the
+    /// config are ignored.
+    pub fn from_toml(config: &str) -> Result<Self, AssertionError> {
+        let file: AssertionsFile =
+            toml::from_str(config).map_err(|e| AssertionError::Config(e.to_string()))?;
+        for (index, assertion) in file.assertions.iter().enumerate() {
+            if let Assertion::Balance { min, decimals, .. } = assertion {
+                minimum_balance(*min, *decimals)
+                    .map_err(|reason| AssertionError::InvalidAssertion(index, reason))?;
+            }
+        }
+        Ok(Self(file.assertions))
+    }
+
+    /// Starts checking the assertions against the [`Environment`] the
+    /// `client` is connected to. Balances are checked right away, after every
+    /// transaction that emits logs, and whenever the [`Environment`] moves on
+    /// to a new block. Events are counted when the watcher is finished, which
+    /// requires the [`Environment`] to be built with
+    /// [`EnvironmentBuilder::store_receipts`].
+    pub async fn watch(
+        &self,
+        client: Arc<RevmMiddleware>,
+    ) -> Result<AssertionWatcher, RevmMiddlewareError> {
+        let logs = client.new_filter(FilterKind::Logs(&Filter::new())).await?;
+        let blocks = client.new_filter(FilterKind::NewBlocks).await?;
+        let (stop, stopped) = oneshot::channel();
+        let assertions = self.0.clone();
+        let task_client = client.clone();
+        let task = tokio::spawn(async move {
+            let client = task_client;
+            let logs = FilterWatcher::<_, ethers::types::Log>::new(logs, client.provider())
+                .interval(Duration::ZERO)
+                .map(|_| ());
+            let blocks = FilterWatcher::<_, ethers::types::H256>::new(blocks, client.provider())
+                .interval(Duration::ZERO)
+                .map(|_| ());
+            let mut updates = futures_util::stream::select(logs, blocks).take_until(stopped);
+            let mut failures = vec![];
+            check_balances(&client, &assertions, &mut failures).await;
+            while updates.next().await.is_some() {
+                check_balances(&client, &assertions, &mut failures).await;
+            }
+            failures
+        });
+        Ok(AssertionWatcher {
+            assertions: self.0.clone(),
+            client,
+            stop,
+            task,
+        })
+    }
+}
+
+/// Checks the [`Assertions`] of a run while it goes. Created with
+/// [`Assertions::watch`].
+#[derive(Debug)]
+pub struct AssertionWatcher {
+    assertions: Vec<Assertion>,
+    client: Arc<RevmMiddleware>,
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<Vec<AssertionFailure>>,
+}
+
+impl AssertionWatcher {
+    /// Stops watching, checks the balances one last time, counts the events,
+    /// and returns every assertion that failed. Each assertion fails at most
+    /// once, with the first violation found.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task checking the balances panicked.
+    pub async fn finish(self) -> Vec<AssertionFailure> {
+        // Sending only fails if the task already stopped on its own.
+        let _ = self.stop.send(());
+        let mut failures = self.task.await.unwrap();
+        check_balances(&self.client, &self.assertions, &mut failures).await;
+        for assertion in &self.assertions {
+            let Assertion::Event {
+                event,
+                address,
+                at_least,
+                by_block,
+            } = assertion
+            else {
+                continue;
+            };
+            let mut filter = Filter::new().event(event).from_block(0).to_block(*by_block);
+            if let Some(address) = address {
+                filter = filter.address(*address);
+            }
+            let reason = match self.client.get_logs(&filter).await {
+                Ok(logs) if logs.len() >= *at_least => continue,
+                Ok(logs) => format!("only {} occurred by block {}", logs.len(), by_block),
+                Err(e) => format!("the logs could not be fetched: {}", e),
+            };
+            failures.push(AssertionFailure {
+                assertion: assertion.clone(),
+                reason,
+            });
+        }
+        failures
+    }
+}
+
+/// Checks every balance assertion that has not failed yet and adds the ones
+/// that fail now to `failures`.
+async fn check_balances(
+    client: &RevmMiddleware,
+    assertions: &[Assertion],
+    failures: &mut Vec<AssertionFailure>,
+) {
+    for assertion in assertions {
+        let Assertion::Balance {
+            account,
+            token,
+            min,
+            decimals,
+        } = assertion
+        else {
+            continue;
+        };
+        if failures
+            .iter()
+            .any(|failure| &failure.assertion == assertion)
+        {
+            continue;
+        }
+        // The minimum was validated when the assertions were read.
+        let minimum = minimum_balance(*min, *decimals).unwrap_or_default();
+        let reason = match balance(client, *account, *token).await {
+            Ok(balance) if balance >= minimum => continue,
+            Ok(balance) => format!(
+                "the balance was {} at block {}",
+                format_units(balance, *decimals).unwrap_or_else(|_| balance.to_string()),
+                client.get_block_number().await.unwrap_or_default()
+            ),
+            Err(e) => format!("the balance could not be fetched: {}", e),
+        };
+        failures.push(AssertionFailure {
+            assertion: assertion.clone(),
+            reason,
+        });
+    }
+}
+
+/// The smallest unit amount of `min` whole tokens with `decimals` decimals.
+fn minimum_balance(min: f64, decimals: u32) -> Result<U256, String> {
+    if !min.is_finite() || min < 0.0 {
+        return Err(format!("the minimum balance {} is not a valid amount", min));
+    }
+    parse_units(min.to_string(), decimals)
+        .map(U256::from)
+        .map_err(|e| e.to_string())
+}
+
+/// The balance of `account` in `token`, or in ether if there is no `token`.
+async fn balance(
+    client: &RevmMiddleware,
+    account: Address,
+    token: Option<Address>,
+) -> Result<U256, RevmMiddlewareError> {
+    let Some(token) = token else {
+        return client.get_balance(account, None).await;
+    };
+    // There is exactly one balance for the one account.
+    Ok(client.balances_of(token, &[account]).await?.remove(0))
+}
@@ -0,0 +1,145 @@
+//! The `runner` module provides the [`SimulationRunner`] for running many
+//! independent simulations in parallel, e.g., for Monte Carlo parameter
+//! sweeps.
+//!
+//! Each simulation is given its own set of parameters (a seed, an
+//! [`EnvironmentParameters`], or anything else) and is expected to build its
+//! own [`Environment`]. Simulations run on a pool of worker threads, each
+//! with its own single-threaded `tokio` runtime, and their outputs are
+//! collected in the order their parameters were given.
+//!
+//! # Examples
+//!
+//! ```
+//! use arbiter_core::{
+//!     environment::builder::{BlockSettings, EnvironmentBuilder},
+//!     middleware::RevmMiddleware,
+//!     runner::SimulationRunner,
+//! };
+//! use ethers::providers::Middleware;
+//!
+//! let block_numbers = SimulationRunner::new(1..=4)
+//!     .threads(2)
+//!     .run(|seed| async move {
+//!         let environment = EnvironmentBuilder::new()
+//!             .block_settings(BlockSettings::RandomlySampled {
+//!                 block_rate: 1.0,
+//!                 block_time: 12,
+//!                 seed,
+//!             })
+//!             .build();
+//!         let client = RevmMiddleware::new(&environment, None).unwrap();
+//!         client.get_block_number().await.unwrap()
+//!     });
+//! assert_eq!(block_numbers.len(), 4);
+//! ```
+//!
+//! To expand ranges of parameters into the runs, see the [`sweep`] module. To
+//! check every run against assertions declared in a config, see the
+//! [`assertions`] module.
+
+#![warn(missing_docs)]
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    num::NonZeroUsize,
+    sync::Mutex,
+    thread::{self, available_parallelism},
+};
+
+pub mod assertions;
+pub mod sweep;
+
+#[cfg_attr(doc, doc(hidden))]
+#[cfg_attr(doc, allow(unused_imports))]
+#[cfg(doc)]
+use crate::environment::{builder::EnvironmentParameters, Environment};
+
+/// Runs the same simulation for many sets of parameters in parallel.
+///
+/// The simulation is a closure that takes one set of parameters and returns a
+/// future yielding the output of that run. The closure is responsible for
+/// building the [`Environment`] the run uses so that runs never share state.
+#[derive(Clone, Debug)]
+pub struct SimulationRunner<P> {
+    /// The parameters for each of the runs.
+    parameters: Vec<P>,
+
+    /// The number of worker threads to run simulations on. Defaults to the
+    /// available parallelism of the machine.
+    threads: Option<NonZeroUsize>,
+}
+
+impl<P: Send> SimulationRunner<P> {
+    /// Creates a new [`SimulationRunner`] that does one run per item in
+    /// `parameters`.
+    pub fn new(parameters: impl IntoIterator<Item = P>) -> Self {
+        Self {
+            parameters: parameters.into_iter().collect(),
+            threads: None,
+        }
+    }
+
+    /// Sets the number of worker threads that runs are spread across.
+    /// A value of zero is treated as one.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(NonZeroUsize::new(threads).unwrap_or(NonZeroUsize::MIN));
+        self
+    }
+
+    /// Runs the `simulation` once for each set of parameters and returns the
+    /// outputs in the same order as the parameters were given.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a simulation panics or if a worker thread fails to build its
+    /// `tokio` runtime.
+    pub fn run<F, Fut, O>(self, simulation: F) -> Vec<O>
+    where
+        F: Fn(P) -> Fut + Sync,
+        Fut: Future<Output = O>,
+        O: Send,
+    {
+        let runs = self.parameters.len();
+        let threads = self
+            .threads
+            .or_else(|| available_parallelism().ok())
+            .map_or(1, NonZeroUsize::get)
+            .min(runs);
+        let queue = Mutex::new(
+            self.parameters
+                .into_iter()
+                .enumerate()
+                .collect::<VecDeque<_>>(),
+        );
+
+        let mut outputs = thread::scope(|scope| {
+            let workers = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let runtime = tokio::runtime::Builder::new_current_thread()
+                            .enable_all()
+                            .build()
+                            .expect("failed to build a tokio runtime for a simulation");
+                        let mut outputs = vec![];
+                        loop {
+                            let next = queue.lock().unwrap().pop_front();
+                            let Some((index, parameters)) = next else {
+                                break;
+                            };
+                            outputs.push((index, runtime.block_on(simulation(parameters))));
+                        }
+                        outputs
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        outputs.sort_unstable_by_key(|(index, _)| *index);
+        outputs.into_iter().map(|(_, output)| output).collect()
+    }
+}
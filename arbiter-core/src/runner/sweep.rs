@@ -0,0 +1,249 @@
+//! The `sweep` module expands ranges of simulation parameters into the sets of
+//! parameters for individual runs and feeds them into the
+//! [`SimulationRunner`].
+//!
+//! A [`Sweep`] is usually read from the `sweep` section of a TOML config:
+//!
+//! ```toml
+//! [sweep]
+//! # Either "grid" for the cartesian product of all parameters or "random" for
+//! # `samples` random draws seeded by `seed`.
+//! mode = "grid"
+//! threads = 4
+//!
+//! [sweep.parameters]
+//! # A list of values is swept over as given.
+//! fee_tier = [0.0005, 0.003, 0.01]
+//! # A range is split into `steps` evenly spaced values for a grid and is
+//! # sampled uniformly for random draws.
+//! volatility = { start = 0.1, end = 1.0, steps = 10 }
+//! agents = { start = 1, end = 10, steps = 10 }
+//! ```
+//!
+//! Each run is handed a [`ParameterSet`] and its output comes back tagged with
+//! that set in a [`SweepOutput`].
+
+#![warn(missing_docs)]
+
+use std::collections::BTreeMap;
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::*;
+
+/// Errors that can occur when reading or expanding a [`Sweep`].
+#[derive(Error, Debug, Clone)]
+pub enum SweepError {
+    /// The config could not be parsed or has no `sweep` section.
+    #[error("failed to parse the sweep config! due to: {0}")]
+    Config(String),
+
+    /// A parameter's values or range can not be swept over.
+    #[error("invalid range for parameter {0}! due to: {1}")]
+    InvalidRange(String, String),
+}
+
+/// How the parameters of a [`Sweep`] are combined into runs.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum SweepMode {
+    /// Every combination of the parameters' values is run.
+    #[default]
+    Grid,
+
+    /// `samples` sets of parameters are drawn at random, with each parameter
+    /// picked independently.
+    Random {
+        /// The number of runs to draw.
+        samples: usize,
+
+        /// The seed for drawing the parameters so that a sweep is
+        /// reproducible.
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+/// The values a single parameter of a [`Sweep`] takes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ParameterRange {
+    /// An explicit list of values.
+    Values(Vec<f64>),
+
+    /// A closed range between `start` and `end`.
+    Range {
+        /// The first value of the range.
+        start: f64,
+
+        /// The last value of the range.
+        end: f64,
+
+        /// The number of evenly spaced values the range is split into for a
+        /// [`SweepMode::Grid`]. Random draws are taken uniformly over the
+        /// whole range.
+        steps: usize,
+    },
+}
+
+impl ParameterRange {
+    /// The values taken by this parameter in a [`SweepMode::Grid`].
+    fn grid_values(&self) -> Vec<f64> {
+        match self {
+            ParameterRange::Values(values) => values.clone(),
+            ParameterRange::Range { start, end, steps } => match steps {
+                1 => vec![*start],
+                _ => (0..*steps)
+                    .map(|step| start + (end - start) * step as f64 / (steps - 1) as f64)
+                    .collect(),
+            },
+        }
+    }
+
+    /// Draws a single value of this parameter for a [`SweepMode::Random`].
+    fn sample(&self, rng: &mut StdRng) -> f64 {
+        match self {
+            ParameterRange::Values(values) => *values.choose(rng).unwrap(),
+            ParameterRange::Range { start, end, .. } if start == end => *start,
+            ParameterRange::Range { start, end, .. } => {
+                rng.gen_range(start.min(*end)..=start.max(*end))
+            }
+        }
+    }
+
+    fn validate(&self, name: &str) -> Result<(), SweepError> {
+        let invalid = |reason: &str| SweepError::InvalidRange(name.to_owned(), reason.to_owned());
+        match self {
+            ParameterRange::Values(values) if values.is_empty() => {
+                Err(invalid("the list of values is empty"))
+            }
+            ParameterRange::Range { steps: 0, .. } => Err(invalid("the range has zero steps")),
+            ParameterRange::Range { start, end, .. } if !start.is_finite() || !end.is_finite() => {
+                Err(invalid("the range is not finite"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The parameters of a single run of a [`Sweep`], keyed by name.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParameterSet(pub BTreeMap<String, f64>);
+
+impl ParameterSet {
+    /// Returns the value of the parameter with the given `name`.
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.0.get(name).copied()
+    }
+}
+
+/// The output of a single run of a [`Sweep`] tagged with the parameters it was
+/// run with.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SweepOutput<O> {
+    /// The parameters the run was given.
+    pub parameters: ParameterSet,
+
+    /// What the run returned.
+    pub output: O,
+}
+
+/// A sweep over ranges of parameters that is expanded into a [`ParameterSet`]
+/// for each run.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Sweep {
+    /// How the parameters are combined into runs, given by the `mode` key of
+    /// the config.
+    #[serde(flatten)]
+    pub mode: SweepMode,
+
+    /// The number of worker threads to run on. Defaults to the available
+    /// parallelism of the machine.
+    #[serde(default)]
+    pub threads: Option<usize>,
+
+    /// The parameters to sweep over, keyed by name.
+    pub parameters: BTreeMap<String, ParameterRange>,
+}
+
+/// The layout of a config file holding a `sweep` section.
+#[derive(Deserialize)]
+struct SweepFile {
+    sweep: Sweep,
+}
+
+impl Sweep {
+    /// Reads a [`Sweep`] from the `sweep` section of a TOML config. Any other
+    /// sections of the config are ignored.
+    pub fn from_toml(config: &str) -> Result<Self, SweepError> {
+        let file: SweepFile =
+            toml::from_str(config).map_err(|e| SweepError::Config(e.to_string()))?;
+        Ok(file.sweep)
+    }
+
+    /// Expands the sweep into the [`ParameterSet`] of every run. A grid is
+    /// ordered with the last parameter (by name) changing fastest.
+    pub fn expand(&self) -> Result<Vec<ParameterSet>, SweepError> {
+        for (name, range) in &self.parameters {
+            range.validate(name)?;
+        }
+        match &self.mode {
+            SweepMode::Grid => Ok(self.parameters.iter().fold(
+                vec![ParameterSet::default()],
+                |sets, (name, range)| {
+                    let values = range.grid_values();
+                    sets.into_iter()
+                        .flat_map(|set| {
+                            values.iter().map(move |value| {
+                                let mut set = set.clone();
+                                set.0.insert(name.clone(), *value);
+                                set
+                            })
+                        })
+                        .collect()
+                },
+            )),
+            SweepMode::Random { samples, seed } => {
+                let mut rng = StdRng::seed_from_u64(*seed);
+                Ok((0..*samples)
+                    .map(|_| {
+                        ParameterSet(
+                            self.parameters
+                                .iter()
+                                .map(|(name, range)| (name.clone(), range.sample(&mut rng)))
+                                .collect(),
+                        )
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// Expands the sweep and runs the `simulation` once for each
+    /// [`ParameterSet`] on a [`SimulationRunner`]. The outputs are returned in
+    /// the order given by [`Sweep::expand`], each tagged with its parameters.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as [`SimulationRunner::run`].
+    pub fn run<F, Fut, O>(&self, simulation: F) -> Result<Vec<SweepOutput<O>>, SweepError>
+    where
+        F: Fn(ParameterSet) -> Fut + Sync,
+        Fut: Future<Output = O>,
+        O: Send,
+    {
+        let sets = self.expand()?;
+        let mut runner = SimulationRunner::new(sets.clone());
+        if let Some(threads) = self.threads {
+            runner = runner.threads(threads);
+        }
+        let outputs = runner.run(simulation);
+        Ok(sets
+            .into_iter()
+            .zip(outputs)
+            .map(|(parameters, output)| SweepOutput { parameters, output })
+            .collect())
+    }
+}
@@ -0,0 +1,556 @@
+//! The `arbitrage` module sizes the trade that brings the price of a constant
+//! function market maker (CFMM) in line with a reference price, such as the
+//! price on another venue or the next step of a price path.
+//!
+//! Each kind of pool is described by a [`PoolModel`], which knows its
+//! trading function and fee and is handed the state of a pool when asked for
+//! a trade. This lets an arbitrageur agent keep its sizing logic apart from
+//! the protocol it trades on:
+//! - [`ConstantProduct`]: Uniswap V2 style pools.
+//! - [`Rmm01`]: RMM-01 pools as used by Primitive's Portfolio.
+//! - [`StableSwap`]: Curve style stable-swap pools of two tokens.
+//!
+//! Amounts and prices are floating point numbers in whole tokens, and prices
+//! are always the price of token X in units of token Y. Use
+//! [`float_to_wad`](crate::math::float_to_wad) to turn a trade into the
+//! amounts a contract call expects.
+//!
+//! # Examples
+//!
+//! ```
+//! use arbiter_core::simulate::arbitrage::{ConstantProduct, PoolModel, Reserves, Trade};
+//!
+//! let pool = ConstantProduct { fee: 0.003 };
+//! let reserves = Reserves {
+//!     x: 100.0,
+//!     y: 200_000.0,
+//! };
+//! let current_price = pool.spot_price(&reserves).unwrap();
+//! // The pool prices X at 2000 Y while it trades at 1900 Y elsewhere, so X is
+//! // sold to the pool.
+//! let trade = pool
+//!     .optimal_trade(current_price, 1900.0, &reserves)
+//!     .unwrap();
+//! assert!(matches!(trade, Trade::XForY { .. }));
+//! assert!(trade.profit(1900.0) > 0.0);
+//! ```
+
+#![warn(missing_docs)]
+
+use statrs::distribution::{ContinuousCDF, Normal};
+
+/// A trade that an arbitrageur makes against a pool.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trade {
+    /// Swap `amount_in` of token X for `amount_out` of token Y, which lowers
+    /// the price of X in the pool.
+    XForY {
+        /// The amount of token X sent to the pool, fee included.
+        amount_in: f64,
+
+        /// The amount of token Y received from the pool.
+        amount_out: f64,
+    },
+
+    /// Swap `amount_in` of token Y for `amount_out` of token X, which raises
+    /// the price of X in the pool.
+    YForX {
+        /// The amount of token Y sent to the pool, fee included.
+        amount_in: f64,
+
+        /// The amount of token X received from the pool.
+        amount_out: f64,
+    },
+}
+
+impl Trade {
+    /// The profit of the trade in units of token Y when token X is worth
+    /// `price` units of token Y elsewhere.
+    pub fn profit(&self, price: f64) -> f64 {
+        match self {
+            Trade::XForY {
+                amount_in,
+                amount_out,
+            } => amount_out - amount_in * price,
+            Trade::YForX {
+                amount_in,
+                amount_out,
+            } => amount_out * price - amount_in,
+        }
+    }
+}
+
+/// The trading function and fee of a kind of pool, which is all that is
+/// needed to size the trades made against it.
+pub trait PoolModel {
+    /// The state of a pool that its prices are derived from, e.g., its
+    /// reserves.
+    type PoolState;
+
+    /// The marginal price of token X in units of token Y that a pool in
+    /// `pool_state` quotes before fees.
+    ///
+    /// Returns `None` if the pool cannot quote a price in `pool_state`, e.g.,
+    /// because its reserves are empty or outside the range its trading
+    /// function is defined on.
+    fn spot_price(&self, pool_state: &Self::PoolState) -> Option<f64>;
+
+    /// The trade that moves a pool in `pool_state`, whose price is
+    /// `current_price`, to where its marginal price after fees is
+    /// `target_price`. This is the trade that maximizes the profit of an
+    /// arbitrageur who can trade at `target_price` elsewhere.
+    ///
+    /// Returns `None` if the prices are equal, if the difference does not
+    /// cover the fee of the pool, if either price is not a positive number, or
+    /// if the pool cannot quote a price in `pool_state`.
+    fn optimal_trade(
+        &self,
+        current_price: f64,
+        target_price: f64,
+        pool_state: &Self::PoolState,
+    ) -> Option<Trade>;
+}
+
+/// The reserves of a pool of two tokens.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Reserves {
+    /// The reserve of token X.
+    pub x: f64,
+
+    /// The reserve of token Y.
+    pub y: f64,
+}
+
+/// A pool with the constant product trading function `x * y = k`, as used
+/// by Uniswap V2 and its forks.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConstantProduct {
+    /// The fee charged on the amount sent to the pool, e.g., `0.003` for 30
+    /// basis points.
+    pub fee: f64,
+}
+
+impl PoolModel for ConstantProduct {
+    type PoolState = Reserves;
+
+    fn spot_price(&self, pool_state: &Reserves) -> Option<f64> {
+        (pool_state.x > 0.0).then(|| pool_state.y / pool_state.x)
+    }
+
+    fn optimal_trade(
+        &self,
+        current_price: f64,
+        target_price: f64,
+        pool_state: &Reserves,
+    ) -> Option<Trade> {
+        let gamma = 1.0 - self.fee;
+        let Reserves { x, y } = *pool_state;
+        let k = x * y;
+        match direction(current_price, target_price)? {
+            Direction::XForY => {
+                let new_x = (gamma * k / target_price).sqrt();
+                (new_x > x).then(|| Trade::XForY {
+                    amount_in: (new_x - x) / gamma,
+                    amount_out: y - k / new_x,
+                })
+            }
+            Direction::YForX => {
+                let new_x = (k / (gamma * target_price)).sqrt();
+                (new_x < x).then(|| Trade::YForX {
+                    amount_in: (k / new_x - y) / gamma,
+                    amount_out: x - new_x,
+                })
+            }
+        }
+    }
+}
+
+/// A pool with the RMM-01 trading function
+/// `y = K * Φ(Φ⁻¹(1 - x) - σ√τ) + k`, whose liquidity replicates a covered
+/// call, as used by Primitive's Portfolio. Its reserves are given per unit of
+/// liquidity, so `x` lies in `(0, 1)` and `y` in `(0, K)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rmm01 {
+    /// The strike price `K` of the pool.
+    pub strike: f64,
+
+    /// The implied volatility `σ` of the pool.
+    pub sigma: f64,
+
+    /// The time to maturity `τ` of the pool in years.
+    pub tau: f64,
+
+    /// The fee charged on the amount sent to the pool, e.g., `0.003` for 30
+    /// basis points.
+    pub fee: f64,
+}
+
+/// The state of an [`Rmm01`] pool.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rmm01Reserves {
+    /// The total reserve of token X.
+    pub x: f64,
+
+    /// The total reserve of token Y.
+    pub y: f64,
+
+    /// The total liquidity of the pool, which the reserves per unit of
+    /// liquidity are derived from.
+    pub liquidity: f64,
+}
+
+impl Rmm01 {
+    /// The marginal price of token X at a reserve of `x` per unit of
+    /// liquidity, or `None` if `x` is not within `(0, 1)`.
+    fn price(&self, x: f64) -> Option<f64> {
+        if !in_unit_interval(x) {
+            return None;
+        }
+        let sigma_sqrt_tau = self.sigma * self.tau.sqrt();
+        Some(
+            self.strike
+                * (standard_normal().inverse_cdf(1.0 - x) * sigma_sqrt_tau
+                    - sigma_sqrt_tau.powi(2) / 2.0)
+                    .exp(),
+        )
+    }
+
+    /// The reserve of token X per unit of liquidity at which the marginal
+    /// price of token X is `price`.
+    fn x_at(&self, price: f64) -> f64 {
+        let sigma_sqrt_tau = self.sigma * self.tau.sqrt();
+        1.0 - standard_normal()
+            .cdf(((price / self.strike).ln() + sigma_sqrt_tau.powi(2) / 2.0) / sigma_sqrt_tau)
+    }
+
+    /// The reserve of token Y per unit of liquidity that goes with a reserve
+    /// of `x` per unit of liquidity, without the invariant `k`. The reserve
+    /// `x` must be within `[0, 1]`.
+    fn trading_function(&self, x: f64) -> f64 {
+        let normal = standard_normal();
+        self.strike * normal.cdf(normal.inverse_cdf(1.0 - x) - self.sigma * self.tau.sqrt())
+    }
+}
+
+impl PoolModel for Rmm01 {
+    type PoolState = Rmm01Reserves;
+
+    fn spot_price(&self, pool_state: &Rmm01Reserves) -> Option<f64> {
+        self.price(pool_state.x / pool_state.liquidity)
+    }
+
+    fn optimal_trade(
+        &self,
+        current_price: f64,
+        target_price: f64,
+        pool_state: &Rmm01Reserves,
+    ) -> Option<Trade> {
+        let gamma = 1.0 - self.fee;
+        let liquidity = pool_state.liquidity;
+        let x = pool_state.x / liquidity;
+        let y = pool_state.y / liquidity;
+        if !in_unit_interval(x) {
+            return None;
+        }
+        let invariant = y - self.trading_function(x);
+        match direction(current_price, target_price)? {
+            Direction::XForY => {
+                let new_x = self.x_at(target_price / gamma);
+                (new_x > x).then(|| Trade::XForY {
+                    amount_in: (new_x - x) * liquidity / gamma,
+                    amount_out: (y - self.trading_function(new_x) - invariant) * liquidity,
+                })
+            }
+            Direction::YForX => {
+                let new_x = self.x_at(target_price * gamma);
+                (new_x < x).then(|| Trade::YForX {
+                    amount_in: (self.trading_function(new_x) + invariant - y) * liquidity / gamma,
+                    amount_out: (x - new_x) * liquidity,
+                })
+            }
+        }
+    }
+}
+
+/// A stable-swap pool of two tokens with the trading function
+/// `4A(x + y) + D = 4AD + D³ / (4xy)`, as used by Curve. The amplification
+/// `A` flattens the curve around the balanced point so that the price stays
+/// close to one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StableSwap {
+    /// The amplification coefficient `A` of the pool.
+    pub amplification: f64,
+
+    /// The fee charged on the amount sent to the pool, e.g., `0.0004` for 4
+    /// basis points.
+    pub fee: f64,
+}
+
+impl StableSwap {
+    /// The invariant `D` of a pool with the given reserves, found with
+    /// Newton's method as the pool itself does.
+    fn invariant(&self, reserves: &Reserves) -> f64 {
+        let ann = 4.0 * self.amplification;
+        let sum = reserves.x + reserves.y;
+        let mut d = sum;
+        for _ in 0..255 {
+            let d_product = d.powi(3) / (4.0 * reserves.x * reserves.y);
+            let previous = d;
+            d = (ann * sum + 2.0 * d_product) * d / ((ann - 1.0) * d + 3.0 * d_product);
+            if (d - previous).abs() <= d * f64::EPSILON {
+                break;
+            }
+        }
+        d
+    }
+
+    /// The reserve of token Y that goes with a reserve of `x` of token X for
+    /// the invariant `d`.
+    fn y_at(&self, x: f64, d: f64) -> f64 {
+        let ann = 4.0 * self.amplification;
+        let b = ann * x + d - ann * d;
+        (-b + (b * b + ann * d.powi(3) / x).sqrt()) / (2.0 * ann)
+    }
+
+    /// The marginal price of token X at a reserve of `x` of token X for the
+    /// invariant `d`.
+    fn price(&self, x: f64, d: f64) -> f64 {
+        let ann = 4.0 * self.amplification;
+        let y = self.y_at(x, d);
+        let d_product = d.powi(3) / (4.0 * x * y);
+        (ann + d_product / x) / (ann + d_product / y)
+    }
+
+    /// The reserve of token X at which the marginal price of token X is
+    /// `price` for the invariant `d`. The price falls as the reserve grows, so
+    /// the reserve is found by bisection starting from `x`.
+    fn x_at(&self, price: f64, x: f64, d: f64) -> f64 {
+        let (mut low, mut high) = (x, x);
+        while self.price(high, d) > price && high.is_finite() {
+            high *= 2.0;
+        }
+        while self.price(low, d) < price && low > f64::MIN_POSITIVE {
+            low /= 2.0;
+        }
+        for _ in 0..128 {
+            let middle = (low + high) / 2.0;
+            if self.price(middle, d) > price {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        (low + high) / 2.0
+    }
+}
+
+impl PoolModel for StableSwap {
+    type PoolState = Reserves;
+
+    fn spot_price(&self, pool_state: &Reserves) -> Option<f64> {
+        (pool_state.x > 0.0 && pool_state.y > 0.0)
+            .then(|| self.price(pool_state.x, self.invariant(pool_state)))
+    }
+
+    fn optimal_trade(
+        &self,
+        current_price: f64,
+        target_price: f64,
+        pool_state: &Reserves,
+    ) -> Option<Trade> {
+        let gamma = 1.0 - self.fee;
+        let Reserves { x, y } = *pool_state;
+        let d = self.invariant(pool_state);
+        match direction(current_price, target_price)? {
+            Direction::XForY => {
+                let new_x = self.x_at(target_price / gamma, x, d);
+                (new_x > x).then(|| Trade::XForY {
+                    amount_in: (new_x - x) / gamma,
+                    amount_out: y - self.y_at(new_x, d),
+                })
+            }
+            Direction::YForX => {
+                let new_x = self.x_at(target_price * gamma, x, d);
+                (new_x < x).then(|| Trade::YForX {
+                    amount_in: (self.y_at(new_x, d) - y) / gamma,
+                    amount_out: x - new_x,
+                })
+            }
+        }
+    }
+}
+
+/// The way a pool has to be traded to move its price.
+enum Direction {
+    /// Token X is sold to the pool to lower its price.
+    XForY,
+
+    /// Token X is bought from the pool to raise its price.
+    YForX,
+}
+
+/// The way to trade a pool quoting `current_price` towards `target_price`,
+/// if the prices are positive and differ.
+fn direction(current_price: f64, target_price: f64) -> Option<Direction> {
+    if !(current_price > 0.0 && target_price > 0.0 && target_price.is_finite()) {
+        return None;
+    }
+    if target_price < current_price {
+        Some(Direction::XForY)
+    } else if target_price > current_price {
+        Some(Direction::YForX)
+    } else {
+        None
+    }
+}
+
+/// Whether `x` lies strictly between zero and one, the range of the reserves
+/// per unit of liquidity of an [`Rmm01`] pool.
+fn in_unit_interval(x: f64) -> bool {
+    x > 0.0 && x < 1.0
+}
+
+/// The standard normal distribution.
+fn standard_normal() -> Normal {
+    Normal::new(0.0, 1.0).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Asserts that `a` and `b` agree up to a relative error of `tolerance`.
+    fn assert_close(a: f64, b: f64, tolerance: f64) {
+        assert!(
+            ((a - b) / b).abs() < tolerance,
+            "{} and {} differ by more than {}",
+            a,
+            b,
+            tolerance
+        );
+    }
+
+    #[test]
+    fn constant_product() {
+        let pool = ConstantProduct { fee: 0.003 };
+        let reserves = Reserves {
+            x: 100.0,
+            y: 200_000.0,
+        };
+        assert_eq!(pool.spot_price(&reserves), Some(2000.0));
+
+        let Some(Trade::XForY {
+            amount_in,
+            amount_out,
+        }) = pool.optimal_trade(2000.0, 1900.0, &reserves)
+        else {
+            panic!("expected X to be sold to the pool");
+        };
+        // The pool quotes the target price after fees once the trade is made.
+        let new_x = reserves.x + amount_in * 0.997;
+        let new_y = reserves.y - amount_out;
+        assert_close(new_x * new_y, reserves.x * reserves.y, 1e-12);
+        assert_close(0.997 * new_y / new_x, 1900.0, 1e-12);
+
+        let Some(Trade::YForX {
+            amount_in,
+            amount_out,
+        }) = pool.optimal_trade(2000.0, 2100.0, &reserves)
+        else {
+            panic!("expected X to be bought from the pool");
+        };
+        let new_x = reserves.x - amount_out;
+        let new_y = reserves.y + amount_in * 0.997;
+        assert_close(new_y / new_x / 0.997, 2100.0, 1e-12);
+
+        // Price differences within the fee are not worth trading on.
+        assert!(pool.optimal_trade(2000.0, 1998.0, &reserves).is_none());
+        assert!(pool.optimal_trade(2000.0, 2000.0, &reserves).is_none());
+        assert!(pool.optimal_trade(2000.0, 0.0, &reserves).is_none());
+    }
+
+    #[test]
+    fn rmm01() {
+        let pool = Rmm01 {
+            strike: 1.0,
+            sigma: 1.0,
+            tau: 1.0,
+            fee: 0.001,
+        };
+        let reserves = Rmm01Reserves {
+            x: 0.5 * 10.0,
+            y: pool.trading_function(0.5) * 10.0,
+            liquidity: 10.0,
+        };
+        let current_price = pool.spot_price(&reserves).unwrap();
+        assert_close(current_price, (-0.5f64).exp(), 1e-9);
+
+        for target_price in [0.5, 0.8] {
+            let trade = pool
+                .optimal_trade(current_price, target_price, &reserves)
+                .unwrap();
+            let new_x = match trade {
+                Trade::XForY { amount_in, .. } => reserves.x + amount_in * 0.999,
+                Trade::YForX { amount_out, .. } => reserves.x - amount_out,
+            };
+            let new_price = pool.price(new_x / reserves.liquidity).unwrap();
+            match trade {
+                Trade::XForY { .. } => assert_close(new_price * 0.999, target_price, 1e-9),
+                Trade::YForX { .. } => assert_close(new_price / 0.999, target_price, 1e-9),
+            }
+            assert!(trade.profit(target_price) > 0.0);
+        }
+
+        // Reserves per unit of liquidity outside of `(0, 1)` cannot be priced.
+        for x in [0.0, 10.0, 20.0] {
+            let reserves = Rmm01Reserves { x, ..reserves };
+            assert!(pool.spot_price(&reserves).is_none());
+            assert!(pool.optimal_trade(current_price, 0.5, &reserves).is_none());
+        }
+    }
+
+    #[test]
+    fn stable_swap() {
+        let pool = StableSwap {
+            amplification: 100.0,
+            fee: 0.0004,
+        };
+        let balanced = Reserves {
+            x: 1_000_000.0,
+            y: 1_000_000.0,
+        };
+        assert_close(pool.spot_price(&balanced).unwrap(), 1.0, 1e-12);
+
+        let reserves = Reserves {
+            x: 800_000.0,
+            y: 1_200_000.0,
+        };
+        let current_price = pool.spot_price(&reserves).unwrap();
+        assert!(current_price > 1.0);
+        let Some(Trade::XForY {
+            amount_in,
+            amount_out,
+        }) = pool.optimal_trade(current_price, 1.0, &reserves)
+        else {
+            panic!("expected X to be sold to the pool");
+        };
+        let new_reserves = Reserves {
+            x: reserves.x + amount_in * 0.9996,
+            y: reserves.y - amount_out,
+        };
+        assert_close(
+            pool.invariant(&new_reserves),
+            pool.invariant(&reserves),
+            1e-9,
+        );
+        assert_close(pool.spot_price(&new_reserves).unwrap() * 0.9996, 1.0, 1e-9);
+        assert!(
+            Trade::XForY {
+                amount_in,
+                amount_out
+            }
+            .profit(1.0)
+                > 0.0
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! The `fuzz` module turns a simulation into a stateful fuzz test of the
+//! protocol it runs against, built on [`proptest`].
+//!
+//! A [`Fuzzer`] draws values from a [`Strategy`], e.g., the parameters of the
+//! agents of a simulation, the inputs of the calls they make, or both, and
+//! runs the simulation once for each of them. The simulation builds its own
+//! [`Environment`] and returns why it failed, if it did, e.g., from the
+//! violations of its [`Invariants`](super::invariants::Invariants). When a run
+//! fails, the value it was given is shrunk to the simplest one that still
+//! fails and returned in a [`FuzzFailure`], which can be run again with
+//! [`Fuzzer::replay`] to debug it. The values are drawn from a seeded
+//! generator, so a [`Fuzzer`] with the same seed finds the same failure.
+//!
+//! Besides the strategies of [`proptest`] itself, [`address`], [`uint`], and
+//! [`tokens`] generate the inputs of contract calls.
+//!
+//! This module requires the `fuzz` feature.
+//!
+//! # Examples
+//!
+//! ```
+//! use arbiter_core::simulate::fuzz::{uint, FuzzError, Fuzzer};
+//! use ethers::types::U256;
+//!
+//! // Finds that sending more than the balance of 1000 fails and shrinks the
+//! // amount to the smallest one that does.
+//! let error = Fuzzer::new(256)
+//!     .seed(7)
+//!     .run(uint(), |amount| async move {
+//!         if amount > U256::from(1000) {
+//!             return Err(format!("insufficient balance for {}", amount));
+//!         }
+//!         Ok(())
+//!     })
+//!     .unwrap_err();
+//! let FuzzError::Failure(failure) = error else {
+//!     panic!("fuzzing was aborted");
+//! };
+//! assert_eq!(failure.input, U256::from(1001));
+//! ```
+
+#![warn(missing_docs)]
+
+use std::{fmt::Debug, future::Future};
+
+use ethers::{
+    abi::{ParamType, Token},
+    types::{Address, I256, U256},
+};
+use proptest::{
+    collection::vec,
+    prelude::*,
+    strategy::BoxedStrategy,
+    test_runner::{Config, RngAlgorithm, TestCaseError, TestError, TestRng, TestRunner},
+};
+use thiserror::Error;
+
+#[cfg_attr(doc, doc(hidden))]
+#[cfg_attr(doc, allow(unused_imports))]
+#[cfg(doc)]
+use crate::environment::Environment;
+
+/// The most elements [`tokens`] puts in a dynamic array.
+const MAX_ARRAY_LENGTH: usize = 4;
+
+/// The most bytes [`tokens`] puts in dynamic bytes or a string.
+const MAX_BYTES_LENGTH: usize = 64;
+
+/// Why fuzzing a simulation stopped without all of its runs passing.
+#[derive(Error, Debug, Clone)]
+pub enum FuzzError<T: Debug> {
+    /// A run failed. The input is the simplest one found that still fails.
+    #[error("{0}")]
+    Failure(FuzzFailure<T>),
+
+    /// The strategy rejected too many of the values it drew.
+    #[error("fuzzing was aborted! due to: {0}")]
+    Aborted(String),
+}
+
+/// A run of a simulation that failed, shrunk to the simplest input that
+/// still fails.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("the simulation failed for input {input:?}! due to: {reason}")]
+pub struct FuzzFailure<T: Debug> {
+    /// The input the simulation failed for.
+    pub input: T,
+
+    /// Why the simulation failed for the input.
+    pub reason: String,
+}
+
+/// Runs a simulation for many values drawn from a [`Strategy`] and shrinks
+/// the ones it fails for.
+#[derive(Clone, Debug)]
+pub struct Fuzzer {
+    /// How many values are drawn.
+    cases: u32,
+
+    /// The seed the values are drawn with.
+    seed: u64,
+}
+
+impl Fuzzer {
+    /// Creates a [`Fuzzer`] that runs the simulation for `cases` values drawn
+    /// with a seed of zero.
+    pub fn new(cases: u32) -> Self {
+        Self { cases, seed: 0 }
+    }
+
+    /// Sets the seed the values are drawn with.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Runs the `simulation` once for each value drawn from the `strategy`
+    /// and stops at the first one it fails for. That value is then shrunk
+    /// by running the `simulation` again for simpler values until none of
+    /// them fail.
+    ///
+    /// The runs happen one after the other on a single-threaded `tokio`
+    /// runtime. A simulation that panics fails like one that returns an
+    /// error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `tokio` runtime cannot be built.
+    pub fn run<S, F, Fut>(&self, strategy: S, simulation: F) -> Result<(), FuzzError<S::Value>>
+    where
+        S: Strategy,
+        F: Fn(S::Value) -> Fut,
+        Fut: Future<Output = Result<(), String>>,
+    {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to build a tokio runtime for fuzzing");
+        let mut seed = [0; 32];
+        seed[..8].copy_from_slice(&self.seed.to_le_bytes());
+        let mut runner = TestRunner::new_with_rng(
+            Config {
+                cases: self.cases,
+                failure_persistence: None,
+                ..Config::default()
+            },
+            TestRng::from_seed(RngAlgorithm::ChaCha, &seed),
+        );
+        runner
+            .run(&strategy, |input| {
+                runtime
+                    .block_on(simulation(input))
+                    .map_err(TestCaseError::fail)
+            })
+            .map_err(|e| match e {
+                TestError::Fail(reason, input) => FuzzError::Failure(FuzzFailure {
+                    input,
+                    reason: reason.to_string(),
+                }),
+                TestError::Abort(reason) => FuzzError::Aborted(reason.to_string()),
+            })
+    }
+
+    /// Runs the `simulation` once for the `input` of a [`FuzzFailure`], e.g.,
+    /// to step through it with logging turned on.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `tokio` runtime cannot be built.
+    pub fn replay<T, F, Fut>(input: T, simulation: F) -> Result<(), String>
+    where
+        F: FnOnce(T) -> Fut,
+        Fut: Future<Output = Result<(), String>>,
+    {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to build a tokio runtime for fuzzing")
+            .block_on(simulation(input))
+    }
+}
+
+/// Generates any address. Shrinks towards the zero address.
+pub fn address() -> impl Strategy<Value = Address> {
+    any::<[u8; 20]>().prop_map(Address::from)
+}
+
+/// Generates any unsigned 256-bit integer. Shrinks towards zero, clearing the
+/// most significant bits first.
+pub fn uint() -> impl Strategy<Value = U256> {
+    uint_of(256)
+}
+
+/// Generates an unsigned integer of the given number of `bits`, as found in a
+/// `uint<bits>` argument. Shrinks towards zero.
+fn uint_of(bits: usize) -> BoxedStrategy<U256> {
+    let bits = bits.min(256);
+    // Arrays shrink their first element first, so the most significant limb
+    // goes first.
+    any::<[u64; 4]>()
+        .prop_map(move |[highest, high, low, lowest]| {
+            let value = U256([lowest, low, high, highest]);
+            match bits {
+                256 => value,
+                _ => value & ((U256::one() << bits) - 1),
+            }
+        })
+        .boxed()
+}
+
+/// Generates a signed integer of the given number of `bits`, as found in an
+/// `int<bits>` argument, in its two's complement form. Shrinks towards zero.
+fn int_of(bits: usize) -> BoxedStrategy<U256> {
+    let bits = bits.clamp(1, 256);
+    (uint_of(bits - 1), any::<bool>())
+        .prop_map(|(magnitude, negative)| {
+            let value = I256::from_raw(magnitude);
+            match negative {
+                true => (-value - I256::one()).into_raw(),
+                false => value.into_raw(),
+            }
+        })
+        .boxed()
+}
+
+/// Generates arguments for a contract call that takes parameters of the
+/// given types, e.g., the inputs of an [`ethers::abi::Function`]. The
+/// tokens can be encoded into calldata with
+/// [`Function::encode_input`](ethers::abi::Function::encode_input).
+///
+/// Dynamic arrays hold at most four elements, and dynamic bytes and strings
+/// at most 64 bytes. Each token shrinks towards its simplest value, e.g.,
+/// zero, `false`, or an empty array.
+pub fn tokens(params: &[ParamType]) -> BoxedStrategy<Vec<Token>> {
+    params.iter().map(token).collect::<Vec<_>>().boxed()
+}
+
+/// Generates a single argument of the type `param`.
+fn token(param: &ParamType) -> BoxedStrategy<Token> {
+    match param {
+        ParamType::Address => address().prop_map(Token::Address).boxed(),
+        ParamType::Bool => any::<bool>().prop_map(Token::Bool).boxed(),
+        ParamType::Uint(bits) => uint_of(*bits).prop_map(Token::Uint).boxed(),
+        ParamType::Int(bits) => int_of(*bits).prop_map(Token::Int).boxed(),
+        ParamType::Bytes => vec(any::<u8>(), 0..=MAX_BYTES_LENGTH)
+            .prop_map(Token::Bytes)
+            .boxed(),
+        ParamType::FixedBytes(size) => vec(any::<u8>(), *size).prop_map(Token::FixedBytes).boxed(),
+        // Characters take up to four bytes each, so the string is cut at the
+        // last one that still fits.
+        ParamType::String => vec(any::<char>(), 0..=MAX_BYTES_LENGTH)
+            .prop_map(|chars| {
+                let mut string = String::new();
+                for character in chars {
+                    if string.len() + character.len_utf8() > MAX_BYTES_LENGTH {
+                        break;
+                    }
+                    string.push(character);
+                }
+                Token::String(string)
+            })
+            .boxed(),
+        ParamType::Array(inner) => vec(token(inner), 0..=MAX_ARRAY_LENGTH)
+            .prop_map(Token::Array)
+            .boxed(),
+        ParamType::FixedArray(inner, size) => {
+            vec(token(inner), *size).prop_map(Token::FixedArray).boxed()
+        }
+        ParamType::Tuple(params) => tokens(params).prop_map(Token::Tuple).boxed(),
+    }
+}
@@ -0,0 +1,270 @@
+//! The `invariants` module checks properties that have to hold throughout a
+//! run of a simulation, such as the total supply of a token equaling the sum
+//! of its balances or the invariant of a pool never decreasing.
+//!
+//! An invariant is a closure registered with [`Invariants::add`]. It reads
+//! whatever state it needs through the client it is handed and returns why it
+//! does not hold if it is violated. [`Invariants::watch`] checks every
+//! invariant against the [`Environment`] of a client after each transaction
+//! or each block, as set by [`CheckFrequency`]. Each violation is flagged with
+//! the block and the transaction it was found after. The violations are
+//! collected once the run is done with [`InvariantWatcher::finish`]. A run
+//! that should halt on the first violation can wait on
+//! [`InvariantWatcher::violated`] alongside its agents instead.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use arbiter_core::{
+//!     middleware::RevmMiddleware,
+//!     simulate::invariants::{InvariantViolation, Invariants},
+//! };
+//! use ethers::{providers::Middleware, types::Address};
+//!
+//! async fn run(client: Arc<RevmMiddleware>, treasury: Address) -> Vec<InvariantViolation> {
+//!     let watcher = Invariants::new()
+//!         .add("treasury keeps its ether", move |client| async move {
+//!             let balance = client
+//!                 .get_balance(treasury, None)
+//!                 .await
+//!                 .map_err(|e| e.to_string())?;
+//!             if balance.is_zero() {
+//!                 return Err("the treasury is empty".to_string());
+//!             }
+//!             Ok(())
+//!         })
+//!         .watch(client)
+//!         .await
+//!         .unwrap();
+//!     // ... run the agents of the simulation ...
+//!     watcher.finish().await
+//! }
+//! ```
+
+#![warn(missing_docs)]
+
+use std::{fmt::Display, future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use ethers::{
+    providers::{FilterKind, FilterWatcher, Middleware},
+    types::H256,
+};
+use futures_util::StreamExt;
+use tokio::{
+    sync::{oneshot, watch},
+    task::JoinHandle,
+};
+
+#[cfg_attr(doc, doc(hidden))]
+#[cfg_attr(doc, allow(unused_imports))]
+#[cfg(doc)]
+use crate::environment::Environment;
+use crate::middleware::{errors::RevmMiddlewareError, RevmMiddleware};
+
+/// A check of an invariant as it is stored by [`Invariants`].
+type Check = Box<
+    dyn Fn(Arc<RevmMiddleware>) -> Pin<Box<dyn Future<Output = Result<(), String>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// How often [`Invariants`] are checked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CheckFrequency {
+    /// After every transaction.
+    #[default]
+    Transaction,
+
+    /// Whenever the [`Environment`] moves on to a new block.
+    Block,
+}
+
+/// An invariant that did not hold, along with where it was found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvariantViolation {
+    /// The name the invariant was added with.
+    pub invariant: String,
+
+    /// Why the invariant does not hold, as returned by its check.
+    pub reason: String,
+
+    /// The block the [`Environment`] was in when the violation was found.
+    pub block_number: u64,
+
+    /// The transaction the violation was found after, if it was checked
+    /// after a transaction.
+    pub transaction: Option<H256>,
+}
+
+impl Display for InvariantViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "invariant `{}` violated in block {}",
+            self.invariant, self.block_number
+        )?;
+        if let Some(transaction) = self.transaction {
+            write!(f, " after transaction {:?}", transaction)?;
+        }
+        write!(f, ": {}", self.reason)
+    }
+}
+
+/// The invariants of a simulation.
+#[derive(Default)]
+pub struct Invariants {
+    checks: Vec<(String, Check)>,
+    frequency: CheckFrequency,
+}
+
+impl std::fmt::Debug for Invariants {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Invariants")
+            .field(
+                "checks",
+                &self.checks.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            )
+            .field("frequency", &self.frequency)
+            .finish()
+    }
+}
+
+impl Invariants {
+    /// Creates an empty set of invariants that are checked after every
+    /// transaction.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an invariant under the given `name`. The `check` returns why the
+    /// invariant does not hold if it is violated.
+    pub fn add<F, Fut>(mut self, name: impl Into<String>, check: F) -> Self
+    where
+        F: Fn(Arc<RevmMiddleware>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.checks
+            .push((name.into(), Box::new(move |client| Box::pin(check(client)))));
+        self
+    }
+
+    /// Sets how often the invariants are checked.
+    pub fn frequency(mut self, frequency: CheckFrequency) -> Self {
+        self.frequency = frequency;
+        self
+    }
+
+    /// Starts checking the invariants against the [`Environment`] the
+    /// `client` is connected to. They are checked right away and then as
+    /// often as set by [`Invariants::frequency`]. Since transactions keep
+    /// being executed while the invariants are checked, a check that follows a
+    /// transaction may also see the ones right after it.
+    pub async fn watch(
+        self,
+        client: Arc<RevmMiddleware>,
+    ) -> Result<InvariantWatcher, RevmMiddlewareError> {
+        let filter = match self.frequency {
+            CheckFrequency::Transaction => FilterKind::PendingTransactions,
+            CheckFrequency::Block => FilterKind::NewBlocks,
+        };
+        let filter = client.new_filter(filter).await?;
+        let (stop, stopped) = oneshot::channel();
+        let (violated_sender, violated) = watch::channel(false);
+        let frequency = self.frequency;
+        let task = tokio::spawn(async move {
+            let updates = FilterWatcher::<_, H256>::new(filter, client.provider())
+                .interval(Duration::ZERO)
+                .map(Some);
+            let mut updates = futures_util::stream::once(async { None })
+                .chain(updates)
+                .take_until(stopped);
+            let mut violations: Vec<InvariantViolation> = vec![];
+            while let Some(update) = updates.next().await {
+                let transaction = match frequency {
+                    CheckFrequency::Transaction => update,
+                    CheckFrequency::Block => None,
+                };
+                self.check(&client, transaction, &mut violations).await;
+                if !violations.is_empty() {
+                    violated_sender.send_replace(true);
+                }
+            }
+            violations
+        });
+        Ok(InvariantWatcher {
+            violated,
+            stop,
+            task,
+        })
+    }
+
+    /// Checks every invariant that has not been violated yet and adds the
+    /// ones that are violated now to `violations`.
+    async fn check(
+        &self,
+        client: &Arc<RevmMiddleware>,
+        transaction: Option<H256>,
+        violations: &mut Vec<InvariantViolation>,
+    ) {
+        for (name, check) in &self.checks {
+            if violations
+                .iter()
+                .any(|violation| &violation.invariant == name)
+            {
+                continue;
+            }
+            if let Err(reason) = check(client.clone()).await {
+                violations.push(InvariantViolation {
+                    invariant: name.clone(),
+                    reason,
+                    block_number: client
+                        .get_block_number()
+                        .await
+                        .map(|number| number.as_u64())
+                        .unwrap_or_default(),
+                    transaction,
+                });
+            }
+        }
+    }
+}
+
+/// Checks the [`Invariants`] of a run while it goes. Created with
+/// [`Invariants::watch`].
+#[derive(Debug)]
+pub struct InvariantWatcher {
+    violated: watch::Receiver<bool>,
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<Vec<InvariantViolation>>,
+}
+
+impl InvariantWatcher {
+    /// Whether an invariant has been violated so far.
+    pub fn is_violated(&self) -> bool {
+        *self.violated.borrow()
+    }
+
+    /// Waits until an invariant is violated. This never completes if none is,
+    /// so it is meant to be raced against the rest of the run, e.g., with
+    /// `tokio::select!`, to halt it on the first violation.
+    pub async fn violated(&mut self) {
+        if self.violated.wait_for(|violated| *violated).await.is_err() {
+            // The task stopped without a violation, so there will not be one.
+            std::future::pending::<()>().await;
+        }
+    }
+
+    /// Stops checking and returns every invariant that was violated. Each
+    /// invariant is reported at most once, with the first violation found.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the task checking the invariants panicked.
+    pub async fn finish(self) -> Vec<InvariantViolation> {
+        // Sending only fails if the task already stopped on its own.
+        let _ = self.stop.send(());
+        self.task.await.unwrap()
+    }
+}
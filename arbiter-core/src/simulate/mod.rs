@@ -0,0 +1,20 @@
+//! The `simulate` module provides logic that the agents of a simulation
+//! commonly need and that does not depend on the protocol they act on, so it
+//! can be reused across simulations.
+//!
+//! The [`arbitrage`] module sizes the trades an arbitrageur makes to move the
+//! price of a constant function market maker (CFMM) to a reference price.
+//!
+//! The [`invariants`] module checks properties that have to hold throughout a
+//! run after every transaction or block and flags the ones that are violated.
+//!
+//! The [`fuzz`] module runs a simulation for many generated agent parameters
+//! and call inputs and shrinks the ones it fails for. It requires the `fuzz`
+//! feature.
+
+#![warn(missing_docs)]
+
+pub mod arbitrage;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+pub mod invariants;
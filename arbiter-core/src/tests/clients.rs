@@ -25,3 +25,146 @@ fn signer_collision() {
     RevmMiddleware::new(&environment, Some("0")).unwrap();
     assert!(RevmMiddleware::new(&environment, Some("0")).is_err());
 }
+
+#[tokio::test]
+async fn keyring_signers() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let signer = client.add_signer(Some("keyring_signer")).unwrap();
+    assert!(client.add_signer(Some("keyring_signer")).is_err());
+    let mut signers = vec![client.address(), signer];
+    signers.sort();
+    assert_eq!(client.signers(), signers);
+
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    arbiter_token
+        .mint(signer, U256::from(TEST_MINT_AMOUNT))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    // A client made with `with_sender` sends from the signer.
+    let signer_client = client.with_sender(signer).unwrap();
+    assert_eq!(signer_client.address(), signer);
+    let receipt = ArbiterToken::new(arbiter_token.address(), signer_client)
+        .transfer(client.address(), U256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(receipt.from, signer);
+
+    // So does a transaction with the signer in its `from` field.
+    arbiter_token
+        .transfer(client.address(), U256::from(1))
+        .from(signer)
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    assert_eq!(
+        arbiter_token
+            .balance_of(client.address())
+            .call()
+            .await
+            .unwrap(),
+        U256::from(2)
+    );
+    assert_eq!(
+        arbiter_token.balance_of(signer).call().await.unwrap(),
+        U256::from(TEST_MINT_AMOUNT - 2)
+    );
+
+    // Addresses without a signer in the keyring cannot be sent from.
+    let stranger = Address::random();
+    assert!(client.with_sender(stranger).is_err());
+    assert!(arbiter_token
+        .transfer(client.address(), U256::from(1))
+        .from(stranger)
+        .send()
+        .await
+        .is_err());
+
+    // Calls change no state, so they can still be made from them.
+    assert_eq!(
+        arbiter_token
+            .balance_of(signer)
+            .from(stranger)
+            .call()
+            .await
+            .unwrap(),
+        U256::from(TEST_MINT_AMOUNT - 2)
+    );
+}
+
+#[tokio::test]
+async fn mock_middleware() {
+    let sender = Address::from_str(TEST_MINT_TO).unwrap();
+    let client = mock::MockRevmMiddleware::new(sender);
+    client.set_balance(sender, U256::from(TEST_MINT_AMOUNT));
+    assert_eq!(
+        client.get_balance(sender, None).await.unwrap(),
+        U256::from(TEST_MINT_AMOUNT)
+    );
+
+    let arbiter_token = ArbiterToken::new(Address::random(), client.clone());
+    let balance_of = arbiter_token.balance_of(sender);
+    client.set_call_return(
+        arbiter_token.address(),
+        balance_of.calldata().unwrap(),
+        ethers::abi::encode(&[ethers::abi::Token::Uint(U256::from(TEST_MINT_AMOUNT))]),
+    );
+    assert_eq!(
+        balance_of.call().await.unwrap(),
+        U256::from(TEST_MINT_AMOUNT)
+    );
+    assert!(arbiter_token.total_supply().call().await.is_err());
+
+    let receipt = arbiter_token
+        .mint(sender, U256::from(TEST_MINT_AMOUNT))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(receipt.status, Some(1.into()));
+    assert_eq!(receipt.to, Some(arbiter_token.address()));
+    assert_eq!(client.sent_transactions().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_client() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let tasks = (1..=64u64)
+        .map(|amount| {
+            let arbiter_token = arbiter_token.clone();
+            tokio::spawn(async move {
+                let to = Address::from_low_u64_be(amount);
+                arbiter_token
+                    .mint(to, U256::from(amount))
+                    .send()
+                    .await
+                    .unwrap()
+                    .await
+                    .unwrap();
+                // Each task has to read back its own balance and not one that
+                // was meant for another task.
+                let balance = arbiter_token.balance_of(to).call().await.unwrap();
+                assert_eq!(balance, U256::from(amount));
+            })
+        })
+        .collect::<Vec<_>>();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    assert_eq!(
+        arbiter_token.total_supply().call().await.unwrap(),
+        U256::from((1..=64u64).sum::<u64>())
+    );
+}
@@ -110,6 +110,56 @@ async fn arbiter_math() {
     assert_eq!(sqrt_output, ethers::types::U256::from(1_000_000_000));
 }
 
+#[tokio::test]
+async fn native_math_matches_arbiter_math() {
+    use ethers::types::I256;
+
+    use crate::math::wad;
+
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_math = deploy_arbiter_math(client).await.unwrap();
+    let iwad = wad::float_to_iwad;
+
+    for x in [
+        -7.0, -3.3, -1.0, -0.25, 0.0, 1e-18, 0.5, 1.0, 2.5, 6.5, 1e19,
+    ] {
+        let x = iwad(x);
+        assert_eq!(arbiter_math.cdf(x).call().await.ok(), wad::cdf(x));
+        assert_eq!(arbiter_math.pdf(x).call().await.ok(), wad::pdf(x));
+    }
+    for p in [0.0, 1e-18, 0.001, 0.1, 0.26738786, 0.5, 0.9, 0.999999, 1.0] {
+        let p = iwad(p);
+        assert_eq!(arbiter_math.ppf(p).call().await.ok(), wad::ppf(p));
+    }
+    for x in [-1.0, 0.0, 1e-18, 0.3, 1.0, 2.5, 1e30] {
+        let x = iwad(x);
+        assert_eq!(arbiter_math.log(x).call().await.ok(), wad::ln_wad(x));
+    }
+    let max = I256::MAX.into_raw();
+    for (x, y) in [(1.0, 2.0), (0.3, 0.7), (1e-18, 1e-18), (123.456, 0.0)] {
+        let (x, y) = (iwad(x).into_raw(), iwad(y).into_raw());
+        for (x, y) in [(x, y), (max, y)] {
+            assert_eq!(
+                arbiter_math.mul_wad_down(x, y).call().await.ok(),
+                wad::mul_wad_down(x, y)
+            );
+            assert_eq!(
+                arbiter_math.mul_wad_up(x, y).call().await.ok(),
+                wad::mul_wad_up(x, y)
+            );
+            assert_eq!(
+                arbiter_math.div_wad_down(x, y).call().await.ok(),
+                wad::div_wad_down(x, y)
+            );
+            assert_eq!(
+                arbiter_math.div_wad_up(x, y).call().await.ok(),
+                wad::div_wad_up(x, y)
+            );
+        }
+        assert_eq!(arbiter_math.sqrt(x).call().await.unwrap(), wad::sqrt(x));
+    }
+}
+
 // TODO: It would be good to change this to `token_functions` and test all
 // relevant ERC20 functions (e.g., transfer, approve, etc.).
 #[tokio::test]
@@ -307,3 +357,18 @@ async fn price_simulation_oracle() {
         assert_eq!(new_price, wad_price);
     }
 }
+
+#[test]
+fn embedded_artifacts_are_intact() {
+    for artifact in crate::artifacts::ARTIFACTS.iter() {
+        assert!(
+            artifact.verify(),
+            "the bytecode of {} no longer matches its artifact, run `arbiter bind --artifacts`",
+            artifact.name
+        );
+    }
+    let token = crate::artifacts::artifact("ArbiterToken").unwrap();
+    assert_eq!(token.bytecode, &*ARBITERTOKEN_BYTECODE);
+    assert!(crate::artifacts::artifact("Unknown").is_none());
+    assert_eq!(crate::artifacts::embedded_size(), 28893);
+}
@@ -2,7 +2,7 @@ use tokio::io::AsyncReadExt;
 use tracing_test::traced_test;
 
 use super::*;
-use crate::data_collection::EventLogger;
+use crate::data_collection::{EventLogger, OutputFormat};
 
 #[traced_test]
 #[tokio::test(flavor = "multi_thread")]
@@ -92,3 +92,194 @@ async fn data_capture_output_validation() {
     assert_eq!(contents0, contents1);
     tokio::fs::remove_dir_all("./test_output2").await.unwrap();
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn metadata_output() {
+    let environment = builder::EnvironmentBuilder::new()
+        .block_settings(builder::BlockSettings::RandomlySampled {
+            block_rate: TEST_BLOCK_RATE,
+            block_time: TEST_BLOCK_TIME,
+            seed: TEST_ENV_SEED,
+        })
+        .seed(42)
+        .build();
+    let listener = EventLogger::builder()
+        .path("./test_output3")
+        .metadata(&environment.parameters);
+
+    listener.run().unwrap();
+
+    let metadata = tokio::fs::read_to_string("./test_output3/metadata.json")
+        .await
+        .unwrap();
+    let parameters: builder::EnvironmentParameters = serde_json::from_str(&metadata).unwrap();
+    assert_eq!(parameters.seed, Some(42));
+    tokio::fs::remove_dir_all("./test_output3").await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn json_lines_and_receipts_output() {
+    let environment = builder::EnvironmentBuilder::new().store_receipts().build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbx = deploy_arbx(client.clone()).await.unwrap();
+    let listener = EventLogger::builder()
+        .path("./test_output4")
+        .format(OutputFormat::JsonLines)
+        .add(arbx.events(), "arbx")
+        .add_receipts(client.clone(), "transactions");
+
+    listener.run().unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    for _ in 0..3 {
+        arbx.approve(client.address(), U256::from(1))
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+    client.update_block(1, 12).unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    let approvals = tokio::fs::read_to_string("./test_output4/arbx/ApprovalFilter.jsonl")
+        .await
+        .unwrap();
+    assert_eq!(approvals.lines().count(), 3);
+    for line in approvals.lines() {
+        let approval: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert!(approval.get("owner").is_some());
+    }
+
+    let receipts = tokio::fs::read_to_string("./test_output4/transactions/receipts.jsonl")
+        .await
+        .unwrap();
+    // The deployment and the three approvals.
+    assert_eq!(receipts.lines().count(), 4);
+    let receipt: serde_json::Value =
+        serde_json::from_str(receipts.lines().last().unwrap()).unwrap();
+    assert_eq!(receipt["status"], "0x1");
+    tokio::fs::remove_dir_all("./test_output4").await.unwrap();
+}
+
+#[cfg(feature = "parquet")]
+#[tokio::test(flavor = "multi_thread")]
+async fn parquet_output() {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbx = deploy_arbx(client.clone()).await.unwrap();
+    let listener = EventLogger::builder()
+        .path("./test_output5")
+        .format(OutputFormat::Parquet)
+        .add(arbx.events(), "arbx");
+    let (stop_sender, stop_receiver) = tokio::sync::oneshot::channel::<()>();
+    let logging = tokio::spawn(listener.run_until(async move {
+        stop_receiver.await.unwrap();
+    }));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    for _ in 0..5 {
+        arbx.approve(client.address(), U256::from(1))
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    stop_sender.send(()).unwrap();
+    logging.await.unwrap().unwrap();
+
+    let file = std::fs::File::open("./test_output5/arbx/ApprovalFilter.parquet").unwrap();
+    let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+        .unwrap()
+        .build()
+        .unwrap();
+    let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(
+        batches.iter().map(|batch| batch.num_rows()).sum::<usize>(),
+        5
+    );
+    let schema = batches[0].schema();
+    assert!(schema.field_with_name("owner").is_ok());
+    assert!(schema.field_with_name("amount").is_ok());
+    tokio::fs::remove_dir_all("./test_output5").await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn metrics_output() {
+    use crate::metrics::{Accountant, ETHER};
+
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbx = deploy_arbx(client.clone()).await.unwrap();
+    let accountant = Arc::new(tokio::sync::Mutex::new(
+        Accountant::new(client.clone())
+            .track_account("trader", client.address())
+            .track_token(TEST_ARG_SYMBOL, arbx.address(), TEST_ARG_DECIMALS),
+    ));
+    let listener = EventLogger::builder()
+        .path("./test_output6")
+        .add_metrics(accountant.clone(), "metrics");
+    let (stop_sender, stop_receiver) = tokio::sync::oneshot::channel::<()>();
+    let logging = tokio::spawn(listener.run_until(async move {
+        stop_receiver.await.unwrap();
+    }));
+
+    let prices = std::collections::BTreeMap::from([
+        (TEST_ARG_SYMBOL.to_string(), 2.0),
+        (ETHER.to_string(), 0.0),
+    ]);
+    for _ in 0..2 {
+        accountant
+            .lock()
+            .await
+            .record(prices.clone())
+            .await
+            .unwrap();
+    }
+    // Snapshots that were not written at a new block are written on stop.
+    stop_sender.send(()).unwrap();
+    logging.await.unwrap().unwrap();
+
+    let portfolios = tokio::fs::read_to_string("./test_output6/metrics/portfolios.csv")
+        .await
+        .unwrap();
+    let mut lines = portfolios.lines();
+    assert!(lines.next().unwrap().contains("pnl"));
+    assert_eq!(lines.count(), 2);
+    tokio::fs::remove_dir_all("./test_output6").await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn receipts_of_last_block_written_on_stop() {
+    let environment = builder::EnvironmentBuilder::new().store_receipts().build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbx = deploy_arbx(client.clone()).await.unwrap();
+    let listener = EventLogger::builder()
+        .path("./test_output7")
+        .format(OutputFormat::JsonLines)
+        .add_receipts(client.clone(), "transactions");
+    let (stop_sender, stop_receiver) = tokio::sync::oneshot::channel::<()>();
+    let logging = tokio::spawn(listener.run_until(async move {
+        stop_receiver.await.unwrap();
+    }));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    arbx.approve(client.address(), U256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    // The block is never moved on from, so its receipts are only written on stop.
+    stop_sender.send(()).unwrap();
+    logging.await.unwrap().unwrap();
+
+    let receipts = tokio::fs::read_to_string("./test_output7/transactions/receipts.jsonl")
+        .await
+        .unwrap();
+    // The deployment and the approval.
+    assert_eq!(receipts.lines().count(), 2);
+    tokio::fs::remove_dir_all("./test_output7").await.unwrap();
+}
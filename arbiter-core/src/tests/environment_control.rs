@@ -1,7 +1,13 @@
 use super::*;
+#[cfg(feature = "fork")]
+use crate::environment::fork::{CachePolicy, ForkCache, ForkedDb};
 use crate::{
     bindings::weth::weth,
-    environment::{builder::EnvironmentBuilder, fork::Fork},
+    environment::{
+        builder::{EnvironmentBuilder, SpecId},
+        fork::{DiskData, Fork},
+        l1_fee::{CalldataCompression, L1DataFee, L1FeeSettings},
+    },
 };
 
 #[tokio::test]
@@ -51,6 +57,485 @@ async fn receipt_data() {
     assert!(cumulative_gas <= receipt_1.cumulative_gas_used);
 }
 
+#[tokio::test]
+async fn block_gas_limit() {
+    let block_gas_limit = 5_000_000;
+    let environment = EnvironmentBuilder::new()
+        .block_gas_limit(block_gas_limit)
+        .build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+
+    // Mint until a transaction no longer fits in the first block.
+    let mut previous_receipt = None;
+    for _ in 0..1000 {
+        let receipt = arbiter_token
+            .mint(client.default_sender().unwrap(), 1000u64.into())
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap()
+            .unwrap();
+        if receipt.block_number != Some(0.into()) {
+            let previous_receipt: ethers::types::TransactionReceipt = previous_receipt.unwrap();
+            assert!(previous_receipt.cumulative_gas_used <= U256::from(block_gas_limit));
+            assert!(
+                previous_receipt.cumulative_gas_used + receipt.gas_used.unwrap()
+                    > U256::from(block_gas_limit)
+            );
+            assert_eq!(receipt.block_number, Some(1.into()));
+            assert_eq!(receipt.transaction_index, 0.into());
+            assert_eq!(Some(receipt.cumulative_gas_used), receipt.gas_used);
+            return;
+        }
+        previous_receipt = Some(receipt);
+    }
+    panic!("The block gas limit was never reached!");
+}
+
+#[tokio::test]
+async fn transaction_exceeding_block_gas_limit() {
+    let environment = EnvironmentBuilder::new().block_gas_limit(21_000).build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    assert!(deploy_arbx(client).await.is_err());
+}
+
+#[tokio::test]
+async fn record_instructions() {
+    let environment = EnvironmentBuilder::new().record_instructions().build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    assert_eq!(
+        environment.recorded_instructions(),
+        vec![InstructionKind::AddAccount]
+    );
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+
+    environment.clear_recorded_instructions();
+    arbiter_token
+        .mint(client.default_sender().unwrap(), 1000u64.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    arbiter_token
+        .balance_of(client.default_sender().unwrap())
+        .call()
+        .await
+        .unwrap();
+    arbiter_token.total_supply().call().await.unwrap();
+    assert_eq!(
+        environment.recorded_instructions(),
+        vec![
+            InstructionKind::Query,
+            InstructionKind::Transaction,
+            InstructionKind::Call,
+            InstructionKind::Call
+        ]
+    );
+}
+
+#[tokio::test]
+async fn block_receipts() {
+    let environment = EnvironmentBuilder::new().store_receipts().build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let mut receipts = vec![];
+    for _ in 0..3 {
+        let receipt = arbiter_token
+            .mint(client.default_sender().unwrap(), 1000u64.into())
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap()
+            .unwrap();
+        receipts.push(receipt);
+    }
+    client.update_block(1, 2).unwrap();
+    arbiter_token
+        .mint(client.default_sender().unwrap(), 1000u64.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    let block_receipts = client.get_block_receipts(0u64).await.unwrap();
+    assert_eq!(block_receipts.len(), 4);
+    assert!(block_receipts[0].contract_address.is_some());
+    assert_eq!(block_receipts[1..], receipts[..]);
+    assert_eq!(client.get_block_receipts(1u64).await.unwrap().len(), 1);
+    assert_eq!(
+        client
+            .get_block_receipts(ethers::types::BlockNumber::Latest)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn get_logs() {
+    let environment = EnvironmentBuilder::new().store_receipts().build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    arbiter_token
+        .mint(client.default_sender().unwrap(), 1000u64.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    for block_number in 1..100u64 {
+        client
+            .update_block(block_number, block_number * 12)
+            .unwrap();
+    }
+    let receipt = arbiter_token
+        .approve(client.address(), U256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let approvals = client
+        .get_logs(
+            &Filter::new()
+                .address(arbiter_token.address())
+                .event("Approval(address,address,uint256)")
+                .from_block(0),
+        )
+        .await
+        .unwrap();
+    assert_eq!(approvals.len(), 1);
+    assert_eq!(approvals[0].block_number, Some(99.into()));
+    assert_eq!(
+        approvals[0].transaction_hash,
+        Some(receipt.transaction_hash)
+    );
+    assert_eq!(approvals[0].log_index, Some(0.into()));
+
+    let transfers = client
+        .get_logs(
+            &Filter::new()
+                .event("Transfer(address,address,uint256)")
+                .from_block(0)
+                .to_block(98),
+        )
+        .await
+        .unwrap();
+    assert_eq!(transfers.len(), 1);
+    assert_eq!(transfers[0].block_number, Some(0.into()));
+
+    // The default range is the latest block only.
+    assert_eq!(
+        client
+            .get_logs(&Filter::new().address(arbiter_token.address()))
+            .await
+            .unwrap(),
+        approvals
+    );
+    assert!(client
+        .get_logs(&Filter::new().address(Address::random()).from_block(0))
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn get_transaction_by_hash() {
+    let environment = EnvironmentBuilder::new().store_receipts().build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let mint = arbiter_token.mint(client.default_sender().unwrap(), 1000u64.into());
+    let receipt = mint.send().await.unwrap().await.unwrap().unwrap();
+
+    let transaction = client
+        .get_transaction(receipt.transaction_hash)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(transaction.hash, receipt.transaction_hash);
+    assert_eq!(transaction.from, client.address());
+    assert_eq!(transaction.to, Some(arbiter_token.address()));
+    assert_eq!(transaction.input, mint.calldata().unwrap());
+    assert_eq!(transaction.block_number, receipt.block_number);
+    assert_eq!(
+        client
+            .get_transaction_receipt(receipt.transaction_hash)
+            .await
+            .unwrap(),
+        Some(receipt)
+    );
+    assert!(client
+        .get_transaction(ethers::types::H256::zero())
+        .await
+        .unwrap()
+        .is_none());
+
+    // Without stored receipts there is nothing to look transactions up in.
+    let (_environment, client) = startup_user_controlled().unwrap();
+    assert!(client
+        .get_transaction_receipt(ethers::types::H256::zero())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn repeated_transactions_have_distinct_hashes() {
+    let environment = EnvironmentBuilder::new().store_receipts().build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let mint = arbiter_token.mint(client.default_sender().unwrap(), 1000u64.into());
+    let pending = mint.send().await.unwrap();
+    let pending_hash = *pending;
+    let first = pending.await.unwrap().unwrap();
+    assert_eq!(pending_hash, first.transaction_hash);
+    let second = mint.send().await.unwrap().await.unwrap().unwrap();
+    let hashes = vec![first.transaction_hash, second.transaction_hash];
+    assert_ne!(hashes[0], hashes[1]);
+
+    for receipt in [first, second] {
+        let transaction = client
+            .get_transaction(receipt.transaction_hash)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(transaction.block_number, receipt.block_number);
+        assert_eq!(
+            transaction.transaction_index,
+            Some(receipt.transaction_index)
+        );
+        assert_eq!(
+            client
+                .get_transaction_receipt(receipt.transaction_hash)
+                .await
+                .unwrap(),
+            Some(receipt)
+        );
+    }
+    let block = client.get_block(0u64).await.unwrap().unwrap();
+    assert_eq!(block.transactions[1..], hashes);
+}
+
+#[tokio::test]
+async fn get_state_diff() {
+    use crate::environment::state_diff::Change;
+
+    let environment = EnvironmentBuilder::new().record_state_diffs().build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let receipt = arbiter_token
+        .mint(client.address(), TEST_MINT_AMOUNT.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let state_diff = client
+        .get_state_diff(receipt.transaction_hash)
+        .await
+        .unwrap()
+        .unwrap();
+    let sender = state_diff.account(client.address()).unwrap();
+    assert_eq!(
+        sender.nonce,
+        Some(Change {
+            before: 1,
+            after: 2
+        })
+    );
+    assert_eq!(
+        state_diff.balance_change(client.address()),
+        ethers::types::I256::zero()
+    );
+
+    // The mint writes the total supply in slot 2 and the balance of the
+    // recipient in the mapping in slot 3.
+    let token = state_diff.account(arbiter_token.address()).unwrap();
+    assert_eq!(token.storage.len(), 2);
+    assert_eq!(
+        token.storage[&ethers::types::H256::from_low_u64_be(2)],
+        Change {
+            before: ethers::types::H256::zero(),
+            after: ethers::types::H256::from_low_u64_be(TEST_MINT_AMOUNT as u64),
+        }
+    );
+
+    // Repeating the mint records a diff of its own without replacing the first.
+    let repeated = arbiter_token
+        .mint(client.address(), TEST_MINT_AMOUNT.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    let repeated_diff = client
+        .get_state_diff(repeated.transaction_hash)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        repeated_diff
+            .account(arbiter_token.address())
+            .unwrap()
+            .storage[&ethers::types::H256::from_low_u64_be(2)],
+        Change {
+            before: ethers::types::H256::from_low_u64_be(TEST_MINT_AMOUNT as u64),
+            after: ethers::types::H256::from_low_u64_be(2 * TEST_MINT_AMOUNT as u64),
+        }
+    );
+    assert_eq!(
+        client
+            .get_state_diff(receipt.transaction_hash)
+            .await
+            .unwrap(),
+        Some(state_diff.clone())
+    );
+    assert!(client
+        .get_state_diff(ethers::types::H256::zero())
+        .await
+        .unwrap()
+        .is_none());
+
+    // Without recorded state diffs there is nothing to look them up in.
+    let (_environment, client) = startup_user_controlled().unwrap();
+    assert!(client
+        .get_state_diff(ethers::types::H256::zero())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn get_block() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let receipt = arbiter_token
+        .mint(client.default_sender().unwrap(), 1000u64.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    client.update_block(1, 13).unwrap();
+
+    let block = client.get_block(0u64).await.unwrap().unwrap();
+    assert_eq!(block.number, Some(0.into()));
+    assert_eq!(block.timestamp, 1.into());
+    assert_eq!(block.transactions.len(), 2);
+    assert_eq!(block.transactions[1], receipt.transaction_hash);
+    assert_eq!(block.hash, receipt.block_hash);
+    assert!(block.gas_used > receipt.gas_used.unwrap());
+
+    // Blocks can be looked up by hash and the latest block is empty.
+    let by_hash = client
+        .get_block(block.hash.unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(by_hash.transactions, block.transactions);
+    let latest = client
+        .get_block(ethers::types::BlockNumber::Latest)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(latest.number, Some(1.into()));
+    assert_eq!(latest.timestamp, 13.into());
+    assert_eq!(latest.parent_hash, block.hash.unwrap());
+    assert!(latest.transactions.is_empty());
+    assert!(client.get_block(2u64).await.unwrap().is_none());
+}
+
+#[cfg(feature = "persistent-history")]
+#[tokio::test]
+async fn persistent_history() {
+    let path = std::env::temp_dir().join("arbiter_persistent_history");
+    let environment = EnvironmentBuilder::new()
+        .with_persistent_history(&path)
+        .unwrap()
+        .build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let receipt = arbiter_token
+        .mint(client.default_sender().unwrap(), 1000u64.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    client.update_block(1, 12).unwrap();
+
+    // The history on disk answers the same queries as the one in memory.
+    let block_receipts = client.get_block_receipts(0u64).await.unwrap();
+    assert_eq!(block_receipts.len(), 2);
+    assert_eq!(block_receipts[1], receipt);
+    let transaction = client
+        .get_transaction(receipt.transaction_hash)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(transaction.block_number, receipt.block_number);
+    let transfers = client
+        .get_logs(&Filter::new().address(arbiter_token.address()).from_block(0))
+        .await
+        .unwrap();
+    assert_eq!(transfers.len(), 1);
+    assert_eq!(
+        transfers[0].transaction_hash,
+        Some(receipt.transaction_hash)
+    );
+    let block = client
+        .get_block(receipt.block_hash.unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(block.transactions.len(), 2);
+    assert_eq!(block.transactions[1], receipt.transaction_hash);
+    let latest = client
+        .get_block(ethers::types::BlockNumber::Latest)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(latest.number, Some(1.into()));
+
+    drop(client);
+    drop(environment);
+    std::fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn simulation_runner() {
+    let balances = crate::runner::SimulationRunner::new(1..=4u64)
+        .threads(2)
+        .run(|amount| async move {
+            let (_environment, client) = startup_user_controlled().unwrap();
+            let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+            arbiter_token
+                .mint(client.address(), amount.into())
+                .send()
+                .await
+                .unwrap()
+                .await
+                .unwrap();
+            arbiter_token
+                .balance_of(client.address())
+                .call()
+                .await
+                .unwrap()
+        });
+    assert_eq!(balances, (1..=4u64).map(U256::from).collect::<Vec<_>>());
+}
+
 // If we are using the `seed == 1`, then we will have 3, 2, 3, 0, 2...
 // transactions per block. We should check these.
 #[tokio::test]
@@ -131,6 +616,64 @@ async fn user_update_block() {
     assert_eq!(block_timestamp, new_block_timestamp.into());
 }
 
+#[tokio::test]
+async fn update_block_not_user_controlled() {
+    let (_environment, client) = startup_randomly_sampled().unwrap();
+    assert!(matches!(
+        client.update_block(69, 420),
+        Err(crate::middleware::errors::RevmMiddlewareError::Environment(
+            crate::environment::errors::EnvironmentError::NotUserControlledBlockSettings
+        ))
+    ));
+    assert_eq!(
+        client.get_block_number().await.unwrap(),
+        ethers::types::U64::from(0)
+    );
+
+    // The environment keeps running after refusing the update.
+    client
+        .apply_cheatcode(Cheatcodes::Deal {
+            address: client.address(),
+            amount: U256::MAX,
+        })
+        .await
+        .unwrap();
+    let arbiter_token = deploy_arbx(client).await.unwrap();
+    assert_eq!(
+        arbiter_token.name().call().await.unwrap(),
+        ARBITER_TOKEN_X_NAME
+    );
+}
+
+#[tokio::test]
+async fn set_gas_price_not_user_controlled() {
+    let (_environment, client) = startup_constant_gas().unwrap();
+    assert!(matches!(
+        client.set_gas_price(U256::from(1)).await,
+        Err(crate::middleware::errors::RevmMiddlewareError::Environment(
+            crate::environment::errors::EnvironmentError::NotUserControlledGasSettings
+        ))
+    ));
+    assert_eq!(
+        client.get_gas_price().await.unwrap(),
+        U256::from(TEST_GAS_PRICE)
+    );
+
+    // The environment keeps running after refusing the gas price.
+    client
+        .apply_cheatcode(Cheatcodes::Deal {
+            address: client.address(),
+            amount: U256::MAX,
+        })
+        .await
+        .unwrap();
+    let arbiter_token = deploy_arbx(client).await.unwrap();
+    assert_eq!(
+        arbiter_token.name().call().await.unwrap(),
+        ARBITER_TOKEN_X_NAME
+    );
+}
+
 #[tokio::test]
 async fn randomly_sampled_gas_price() {
     let (environment, client) = startup_randomly_sampled().unwrap();
@@ -218,22 +761,596 @@ async fn constant_gas_price() {
     }
 }
 
-#[tokio::test]
-async fn stop_environment() {
-    let (environment, client) = startup_user_controlled().unwrap();
-    environment.stop().unwrap();
-    assert!(deploy_arbx(client).await.is_err());
-}
+#[test]
+fn parameter_sweep() {
+    use crate::runner::sweep::*;
 
-#[tokio::test]
-async fn fork_into_arbiter() {
-    let fork = Fork::from_disk("../example_fork/fork_into_test.json").unwrap();
+    let grid = Sweep::from_toml(
+        r#"
+        [sweep]
+        mode = "grid"
+        threads = 2
 
-    // Get the environment going
-    let environment = EnvironmentBuilder::new().db(fork.db).build();
+        [sweep.parameters]
+        fee_tier = [0.0005, 0.003, 0.01]
+        volatility = { start = 0.1, end = 0.5, steps = 3 }
+        "#,
+    )
+    .unwrap();
+    let sets = grid.expand().unwrap();
+    assert_eq!(sets.len(), 9);
+    assert_eq!(sets[0].get("fee_tier"), Some(0.0005));
+    assert_eq!(sets[0].get("volatility"), Some(0.1));
+    assert_eq!(sets[8].get("fee_tier"), Some(0.01));
+    assert_eq!(sets[8].get("volatility"), Some(0.5));
 
-    // Create a client
-    let client = RevmMiddleware::new(&environment, Some("name")).unwrap();
+    let outputs = grid
+        .run(|parameters| async move { parameters.get("fee_tier").unwrap() * 2.0 })
+        .unwrap();
+    assert_eq!(outputs.len(), 9);
+    assert!(outputs.iter().zip(sets).all(|(output, parameters)| {
+        output.parameters == parameters
+            && output.output == parameters.get("fee_tier").unwrap() * 2.0
+    }));
+
+    let random = Sweep::from_toml(
+        r#"
+        [sweep]
+        mode = "random"
+        samples = 16
+        seed = 7
+
+        [sweep.parameters]
+        agents = { start = 1, end = 10, steps = 10 }
+        "#,
+    )
+    .unwrap();
+    let samples = random.expand().unwrap();
+    assert_eq!(samples.len(), 16);
+    assert!(samples
+        .iter()
+        .all(|set| (1.0..=10.0).contains(&set.get("agents").unwrap())));
+    assert_eq!(samples, random.expand().unwrap());
+}
+
+#[tokio::test]
+async fn config_assertions() {
+    use crate::runner::assertions::*;
+
+    let environment = EnvironmentBuilder::new().store_receipts().build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let assertions = Assertions::from_toml(&format!(
+        r#"
+        [[assertions]]
+        kind = "balance"
+        account = "{account:?}"
+        token = "{token:?}"
+        min = 500.0
+        decimals = 0
+
+        [[assertions]]
+        kind = "event"
+        event = "Transfer(address,address,uint256)"
+        address = "{token:?}"
+        at_least = 2
+        by_block = 1
+
+        [[assertions]]
+        kind = "event"
+        event = "Transfer(address,address,uint256)"
+        at_least = 3
+        by_block = 1
+        "#,
+        account = client.address(),
+        token = arbiter_token.address(),
+    ))
+    .unwrap();
+    assert_eq!(assertions.0.len(), 3);
+
+    arbiter_token
+        .mint(client.address(), 1000u64.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    let watcher = assertions.watch(client.clone()).await.unwrap();
+    client.update_block(1, 12).unwrap();
+    arbiter_token
+        .transfer(Address::random(), 600u64.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    client.update_block(2, 24).unwrap();
+    arbiter_token
+        .mint(Address::random(), 1000u64.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    // The balance dropped to 400 and only two transfers happened by block 1.
+    let failures = watcher.finish().await;
+    assert_eq!(failures.len(), 2);
+    assert_eq!(failures[0].assertion, assertions.0[0]);
+    assert!(failures[0].reason.contains("400"));
+    assert_eq!(failures[1].assertion, assertions.0[2]);
+
+    assert!(Assertions::from_toml("").unwrap().0.is_empty());
+    assert!(Assertions::from_toml(
+        r#"
+        [[assertions]]
+        kind = "balance"
+        account = "0x0000000000000000000000000000000000000001"
+        min = -1.0
+        "#
+    )
+    .is_err());
+}
+
+#[tokio::test]
+async fn invariants() {
+    use crate::simulate::invariants::*;
+
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let token = arbiter_token.clone();
+    let mut watcher = Invariants::new()
+        .add("the client holds the whole supply", move |client| {
+            let token = token.clone();
+            async move {
+                let total_supply = token.total_supply().call().await.unwrap();
+                let balance = token.balance_of(client.address()).call().await.unwrap();
+                if balance != total_supply {
+                    return Err(format!("{} of {} held", balance, total_supply));
+                }
+                Ok(())
+            }
+        })
+        .watch(client.clone())
+        .await
+        .unwrap();
+
+    arbiter_token
+        .mint(client.address(), TEST_MINT_AMOUNT.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    assert!(!watcher.is_violated());
+    arbiter_token
+        .mint(Address::random(), TEST_MINT_AMOUNT.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    tokio::time::timeout(std::time::Duration::from_secs(5), watcher.violated())
+        .await
+        .unwrap();
+
+    let violations = watcher.finish().await;
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].invariant, "the client holds the whole supply");
+    assert_eq!(
+        violations[0].reason,
+        format!("{} of {} held", TEST_MINT_AMOUNT, 2 * TEST_MINT_AMOUNT)
+    );
+    // The check that caught it may have followed either mint, depending on
+    // when the watcher got to run.
+    assert!(violations[0].transaction.is_some());
+}
+
+#[tokio::test]
+async fn dump_accounts() {
+    let (environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    arbiter_token
+        .mint(client.address(), TEST_MINT_AMOUNT.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    let accounts = environment.dump_accounts().unwrap();
+    assert!(accounts
+        .windows(2)
+        .all(|pair| pair[0].address < pair[1].address));
+    let token = accounts
+        .iter()
+        .find(|account| account.address == arbiter_token.address())
+        .unwrap();
+    assert!(token.is_contract());
+    assert!(token.storage_slots > 0);
+    let sender = accounts
+        .iter()
+        .find(|account| account.address == client.address())
+        .unwrap();
+    assert!(!sender.is_contract());
+    assert_eq!(sender.nonce, 2);
+
+    let contracts = environment
+        .dump_accounts_filtered(AccountFilter::Contracts)
+        .unwrap();
+    assert!(contracts.iter().all(AccountSummary::is_contract));
+    assert!(contracts
+        .iter()
+        .any(|account| account.address == arbiter_token.address()));
+    let eoas = environment
+        .dump_accounts_filtered(AccountFilter::Eoas)
+        .unwrap();
+    assert!(eoas.iter().all(|account| !account.is_contract()));
+    assert!(eoas
+        .iter()
+        .any(|account| account.address == client.address()));
+    assert_eq!(contracts.len() + eoas.len(), accounts.len());
+}
+
+#[tokio::test]
+async fn branch_environment() {
+    let (environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let to = Address::from_str(TEST_MINT_TO).unwrap();
+    arbiter_token
+        .mint(to, TEST_MINT_AMOUNT.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    client.update_block(5, 60).unwrap();
+
+    let branches = environment
+        .branches([
+            EnvironmentBuilder::new(),
+            EnvironmentBuilder::new().gas_settings(builder::GasSettings::Constant(TEST_GAS_PRICE)),
+        ])
+        .unwrap();
+
+    // The parent moves on without the branches seeing it.
+    arbiter_token
+        .mint(to, TEST_MINT_AMOUNT.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    assert_eq!(
+        arbiter_token.balance_of(to).call().await.unwrap(),
+        U256::from(2 * TEST_MINT_AMOUNT)
+    );
+    for (index, branch) in branches.iter().enumerate() {
+        let branch_client = RevmMiddleware::new(branch, Some(&index.to_string())).unwrap();
+        assert_eq!(branch_client.get_block_number().await.unwrap(), 5.into());
+        assert_eq!(
+            branch_client.get_block_timestamp().await.unwrap(),
+            60.into()
+        );
+        let branch_token = ArbiterToken::new(arbiter_token.address(), branch_client);
+        assert_eq!(
+            branch_token.balance_of(to).call().await.unwrap(),
+            U256::from(TEST_MINT_AMOUNT)
+        );
+    }
+    assert_eq!(
+        branches[1]
+            .dump_accounts_filtered(AccountFilter::Contracts)
+            .unwrap(),
+        branches[0]
+            .dump_accounts_filtered(AccountFilter::Contracts)
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn dump_and_load_anvil_state() {
+    let (environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let to = Address::from_str(TEST_MINT_TO).unwrap();
+    arbiter_token
+        .mint(to, TEST_MINT_AMOUNT.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    client.update_block(5, 60).unwrap();
+    let path = std::env::temp_dir().join(format!("arbiter_anvil_state_{:?}.json", to));
+    environment.dump_state(&path).unwrap();
+
+    let state = anvil::AnvilState::from_file(&path).unwrap();
+    assert_eq!(state.best_block_number, Some(5));
+    let token_account = &state.accounts[&arbiter_token.address()];
+    assert!(!token_account.code.is_empty());
+    assert!(!token_account.storage.is_empty());
+
+    let loaded = EnvironmentBuilder::new().load_state(&path).unwrap().build();
+    let loaded_client = RevmMiddleware::new(&loaded, Some("loaded")).unwrap();
+    assert_eq!(loaded_client.get_block_number().await.unwrap(), 5.into());
+    let loaded_token = ArbiterToken::new(arbiter_token.address(), loaded_client);
+    assert_eq!(
+        loaded_token.balance_of(to).call().await.unwrap(),
+        U256::from(TEST_MINT_AMOUNT)
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn load_state_dumped_by_anvil() {
+    let path = std::env::temp_dir().join("arbiter_state_dumped_by_anvil.json");
+    std::fs::write(
+        &path,
+        r#"{
+            "block": { "number": "0x10", "coinbase": "0x0000000000000000000000000000000000000000", "timestamp": "0x64", "gas_limit": "0x1c9c380", "basefee": "0x0", "difficulty": "0x0", "prevrandao": null },
+            "accounts": {
+                "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266": { "nonce": 1, "balance": "0x3635c9adc5dea00000", "code": "0x", "storage": {} },
+                "0x5fbdb2315678afecb367f032d93f642f64180aa3": { "nonce": 1, "balance": "0x0", "code": "0x6080", "storage": { "0x0": "0x2a" } }
+            }
+        }"#,
+    )
+    .unwrap();
+    let state = anvil::AnvilState::from_file(&path).unwrap();
+    let block = state.block_env().unwrap();
+    assert_eq!(block.number, revm::primitives::U256::from(16));
+    assert_eq!(block.timestamp, revm::primitives::U256::from(100));
+
+    let db = state.to_db();
+    let contract: revm::primitives::Address =
+        Address::from_str("0x5fbdb2315678afecb367f032d93f642f64180aa3")
+            .unwrap()
+            .to_fixed_bytes()
+            .into();
+    let account = &db.accounts[&contract];
+    assert!(account.info.code.is_some());
+    assert_eq!(
+        account.storage[&revm::primitives::U256::ZERO],
+        revm::primitives::U256::from(42)
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn genesis_accounts() {
+    let funded = Address::from_low_u64_be(0xaa);
+    let contract = Address::from_low_u64_be(0xbb);
+    // Runtime code that returns the value in slot 0.
+    let code = ethers::types::Bytes::from(vec![
+        0x60, 0x00, 0x54, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3,
+    ]);
+    let genesis = std::collections::HashMap::from([
+        (
+            funded,
+            builder::GenesisAccount {
+                balance: U256::from(1337),
+                nonce: 3,
+                ..Default::default()
+            },
+        ),
+        (
+            contract,
+            builder::GenesisAccount {
+                code: Some(code.clone()),
+                storage: std::collections::HashMap::from([(U256::zero(), U256::from(42))]),
+                ..Default::default()
+            },
+        ),
+    ]);
+    let environment = EnvironmentBuilder::new().with_genesis(genesis).build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+
+    assert_eq!(
+        client.get_balance(funded, None).await.unwrap(),
+        U256::from(1337)
+    );
+    assert_eq!(
+        client.get_transaction_count(funded, None).await.unwrap(),
+        U256::from(3)
+    );
+    assert_eq!(client.get_code(contract, None).await.unwrap(), code);
+    let output = client
+        .call(
+            &ethers::types::TransactionRequest::new().to(contract).into(),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(U256::from_big_endian(&output), U256::from(42));
+}
+
+#[tokio::test]
+async fn precompile_usage() {
+    let environment = EnvironmentBuilder::new().track_precompiles().build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let sha256 = Address::from_low_u64_be(2);
+    let identity = Address::from_low_u64_be(4);
+    for to in [sha256, sha256, identity] {
+        let tx = ethers::types::TransactionRequest::new()
+            .to(to)
+            .data(b"hello".to_vec());
+        client
+            .send_transaction(tx, None)
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+
+    let usage = environment.precompile_usage();
+    assert_eq!(usage.len(), 2);
+    // 60 gas plus 12 gas per word of input.
+    assert_eq!(usage["sha256"], PrecompileUsage { calls: 2, gas: 144 });
+    // 15 gas plus 3 gas per word of input.
+    assert_eq!(usage["identity"], PrecompileUsage { calls: 1, gas: 18 });
+}
+
+#[tokio::test]
+async fn gas_report() {
+    let environment = EnvironmentBuilder::new().profile_gas().build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    for _ in 0..3 {
+        arbiter_token
+            .mint(client.default_sender().unwrap(), 1000u64.into())
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+
+    let report = environment
+        .gas_report()
+        .with_abi(arbiter_token.address(), &ARBITERTOKEN_ABI);
+    let mint = report
+        .contract(arbiter_token.address())
+        .find(|entry| entry.function.as_deref() == Some("mint"))
+        .unwrap();
+    assert_eq!(mint.calls, 3);
+    assert!(mint.min <= mint.median && mint.median <= mint.max);
+    // Minting to an empty balance costs more than adding to an existing one.
+    assert!(mint.min < mint.max);
+    assert!(report.to_string().contains("mint"));
+    assert!(report.precompiles.is_empty());
+
+    // Selectors serialize as hex so the report reads cleanly as JSON.
+    let json = serde_json::to_value(&report).unwrap();
+    let entry = json["functions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["function"] == "mint")
+        .unwrap();
+    assert_eq!(
+        entry["selector"],
+        ethers::types::Bytes::from(mint.selector.unwrap().to_vec()).to_string()
+    );
+    assert_eq!(
+        serde_json::from_value::<crate::environment::gas_report::GasReport>(json).unwrap(),
+        report
+    );
+}
+
+#[tokio::test]
+async fn custom_inspector() {
+    use revm::{
+        db::CacheDB,
+        interpreter::{CallInputs, Gas, InstructionResult},
+        primitives::Bytes,
+        EVMData, Inspector,
+    };
+
+    use crate::environment::fork::ExternalDb;
+
+    /// Records the address of every contract that is called.
+    struct CallRecorder(Arc<std::sync::Mutex<Vec<Address>>>);
+
+    impl Inspector<CacheDB<ExternalDb>> for CallRecorder {
+        fn call(
+            &mut self,
+            _data: &mut EVMData<'_, CacheDB<ExternalDb>>,
+            inputs: &mut CallInputs,
+        ) -> (InstructionResult, Gas, Bytes) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(Address::from(inputs.contract.into_array()));
+            (InstructionResult::Continue, Gas::new(0), Bytes::new())
+        }
+    }
+
+    let calls = Arc::new(std::sync::Mutex::new(vec![]));
+    let environment = EnvironmentBuilder::new()
+        .with_inspector(CallRecorder(calls.clone()))
+        .build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    for _ in 0..2 {
+        arbiter_token
+            .mint(client.address(), 1000u64.into())
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+    // Calls are not inspected.
+    arbiter_token
+        .balance_of(client.address())
+        .call()
+        .await
+        .unwrap();
+
+    assert_eq!(*calls.lock().unwrap(), vec![arbiter_token.address(); 2]);
+}
+
+#[tokio::test]
+async fn l1_data_fee() {
+    let settings = L1FeeSettings {
+        l1_gas_price: 1_000_000_000,
+        overhead: 188,
+        scalar: 1.0,
+        compression: CalldataCompression::None,
+    };
+    let environment = EnvironmentBuilder::new().l1_fee(settings.clone()).build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    client
+        .apply_cheatcode(Cheatcodes::Deal {
+            address: client.address(),
+            amount: ethers::utils::parse_ether(1).unwrap(),
+        })
+        .await
+        .unwrap();
+    let balance_before = client.get_balance(client.address(), None).await.unwrap();
+
+    let data = vec![0, 0, 1, 2];
+    let tx = ethers::types::TransactionRequest::new()
+        .to(Address::from_low_u64_be(0x1234))
+        .data(data.clone());
+    let receipt = client
+        .send_transaction(tx, None)
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The gas price is zero, so the sender only pays the L1 data fee.
+    let l1_data_fee = L1DataFee::from_receipt(&receipt).unwrap();
+    assert_eq!(l1_data_fee, settings.data_fee(&data));
+    assert_eq!(l1_data_fee.l1_gas_used, 2 * 4 + 2 * 16 + 188);
+    let balance_after = client.get_balance(client.address(), None).await.unwrap();
+    assert_eq!(balance_before - balance_after, U256::from(l1_data_fee.fee));
+
+    // An account that can not pay the fee has its transaction rejected.
+    let unfunded = RevmMiddleware::new(&environment, Some("unfunded")).unwrap();
+    let tx = ethers::types::TransactionRequest::new()
+        .to(Address::from_low_u64_be(0x1234))
+        .data(data);
+    assert!(unfunded.send_transaction(tx, None).await.is_err());
+}
+
+#[tokio::test]
+async fn stop_environment() {
+    let (environment, client) = startup_user_controlled().unwrap();
+    environment.stop().unwrap();
+    assert!(deploy_arbx(client).await.is_err());
+}
+
+#[tokio::test]
+async fn fork_into_arbiter() {
+    let fork = Fork::from_disk("../example_fork/fork_into_test.json").unwrap();
+
+    // Get the environment going
+    let environment = EnvironmentBuilder::new().db(fork.db).build();
+
+    // Create a client
+    let client = RevmMiddleware::new(&environment, Some("name")).unwrap();
 
     // Deal with the weth contract
     let weth_meta = fork.contracts_meta.get("weth").unwrap();
@@ -250,3 +1367,353 @@ async fn fork_into_arbiter() {
         .unwrap();
     assert_eq!(balance, U256::from(34890707020710109111_u128));
 }
+
+#[test]
+fn fork_from_disk_at() {
+    let data = std::fs::read_to_string("../example_fork/fork_into_test.json").unwrap();
+    let mut disk_data: DiskData = serde_json::from_str(&data).unwrap();
+    let raw = std::mem::take(&mut disk_data.raw);
+    disk_data.snapshots.insert("pre".to_string(), raw);
+    let path = std::env::temp_dir().join("arbiter_fork_snapshots.json");
+    std::fs::write(&path, serde_json::to_string(&disk_data).unwrap()).unwrap();
+    let path = path.to_str().unwrap();
+
+    let fork = Fork::from_disk(path).unwrap();
+    assert!(fork.db.accounts.is_empty());
+    let fork = Fork::from_disk_at(path, "pre").unwrap();
+    assert!(!fork.db.accounts.is_empty());
+    assert!(Fork::from_disk_at(path, "post").is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "fork")]
+#[tokio::test]
+async fn forked_db_serves_from_cache() {
+    let address = Address::random();
+    let mut cache = ForkCache::new(1);
+    cache.accounts.insert(
+        address,
+        revm::primitives::AccountInfo {
+            balance: revm::primitives::U256::from(1337),
+            ..Default::default()
+        },
+    );
+    let path = std::env::temp_dir().join("arbiter_forked_db_cache.json");
+    std::fs::write(&path, serde_json::to_string(&cache).unwrap()).unwrap();
+
+    // Nothing is listening at this URL, so the account can only come from the
+    // cache.
+    let forked_db = ForkedDb::new("http://localhost:1", 1)
+        .unwrap()
+        .cache(&path)
+        .unwrap();
+    let environment = EnvironmentBuilder::new().db(forked_db).build();
+    let client = RevmMiddleware::new(&environment, Some("name")).unwrap();
+
+    let balance = client.get_balance(address, None).await.unwrap();
+    assert_eq!(balance, U256::from(1337));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "fork")]
+#[tokio::test]
+async fn forked_db_serves_offline_from_cache_directory() {
+    let address = Address::random();
+    let directory = std::env::temp_dir().join(format!("arbiter_fork_cache_{:?}", address));
+    let accounts = directory.join("1").join("1");
+    std::fs::create_dir_all(&accounts).unwrap();
+    std::fs::write(
+        directory.join("chain_ids.json"),
+        r#"{"http://localhost:1": 1}"#,
+    )
+    .unwrap();
+    let info = revm::primitives::AccountInfo {
+        balance: revm::primitives::U256::from(1337),
+        ..Default::default()
+    };
+    std::fs::write(
+        accounts.join(format!("{:?}.json", address)),
+        serde_json::json!({ "info": info, "storage": {} }).to_string(),
+    )
+    .unwrap();
+
+    // Nothing is listening at this URL, and the fork is offline, so the
+    // account can only come from the cache and anything else fails.
+    let forked_db = ForkedDb::new("http://localhost:1", 1)
+        .unwrap()
+        .cache_dir(&directory, CachePolicy::Offline)
+        .unwrap();
+    assert_eq!(forked_db.chain_id(), Some(1));
+    let environment = EnvironmentBuilder::new().db(forked_db).build();
+    let client = RevmMiddleware::new(&environment, Some("name")).unwrap();
+
+    let balance = client.get_balance(address, None).await.unwrap();
+    assert_eq!(balance, U256::from(1337));
+    assert!(client.get_balance(Address::random(), None).await.is_err());
+
+    // An offline fork cannot find the chain of a node it has not cached.
+    assert!(ForkedDb::new("http://localhost:2", 1)
+        .unwrap()
+        .cache_dir(&directory, CachePolicy::Offline)
+        .is_err());
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[cfg(feature = "fork")]
+#[tokio::test]
+async fn forked_db_fetch_errors_are_returned_to_the_caller() {
+    let directory =
+        std::env::temp_dir().join(format!("arbiter_fork_fetch_errors_{:?}", Address::random()));
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(
+        directory.join("chain_ids.json"),
+        r#"{"http://localhost:1": 1}"#,
+    )
+    .unwrap();
+    let forked_db = ForkedDb::new("http://localhost:1", 1)
+        .unwrap()
+        .cache_dir(&directory, CachePolicy::Offline)
+        .unwrap();
+    let environment = EnvironmentBuilder::new().db(forked_db).build();
+    let client = RevmMiddleware::new(&environment, Some("name")).unwrap();
+
+    // Nothing is cached for the contract, so every call to it fails to load.
+    let call: ethers::types::transaction::eip2718::TypedTransaction =
+        ethers::types::TransactionRequest::new()
+            .to(Address::random())
+            .into();
+    assert!(client.call(&call, None).await.is_err());
+    assert!(client.call_batch(&[call.clone()]).await.is_err());
+    assert!(client
+        .call_with_overrides(&call, &ethers::types::spoof::state())
+        .await
+        .is_err());
+
+    // The `Environment` is still running afterwards.
+    assert_eq!(client.get_block_number().await.unwrap(), 0.into());
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+/// Serves a JSON-RPC node on a local port that answers every account with the
+/// same nonce, balance, and storage, and returns its URL.
+#[cfg(feature = "fork")]
+fn mock_node() -> String {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                // Requests keep coming over the same connection until it is closed.
+                loop {
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                            return;
+                        }
+                        let line = line.trim_end();
+                        if line.is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':') {
+                            if name.eq_ignore_ascii_case("content-length") {
+                                content_length = value.trim().parse().unwrap();
+                            }
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).unwrap();
+                    let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                    let result = match request["method"].as_str().unwrap() {
+                        "eth_getTransactionCount" => "0x7".to_string(),
+                        "eth_getBalance" => "0x539".to_string(),
+                        "eth_getCode" => "0x".to_string(),
+                        "eth_getStorageAt" => format!("{:#066x}", 42),
+                        method => panic!("unexpected method {}", method),
+                    };
+                    let response = serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": request["id"],
+                        "result": result,
+                    })
+                    .to_string();
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                        response.len(),
+                        response
+                    )
+                    .unwrap();
+                }
+            });
+        }
+    });
+    url
+}
+
+#[cfg(feature = "fork")]
+#[tokio::test]
+async fn forked_db_fetches_from_node() {
+    // The `ForkedDb` is made within this runtime but fetches from the thread
+    // the `Environment` executes on, which has no runtime of its own.
+    let forked_db = ForkedDb::new(&mock_node(), 1).unwrap();
+    let environment = EnvironmentBuilder::new().db(forked_db).build();
+    let client = RevmMiddleware::new(&environment, Some("name")).unwrap();
+
+    let address = Address::random();
+    assert_eq!(
+        client.get_balance(address, None).await.unwrap(),
+        U256::from(1337)
+    );
+    assert_eq!(
+        client.get_transaction_count(address, None).await.unwrap(),
+        U256::from(7)
+    );
+    assert_eq!(
+        client
+            .get_storage_at(address, ethers::types::H256::zero(), None)
+            .await
+            .unwrap(),
+        ethers::types::H256::from_low_u64_be(42)
+    );
+}
+
+#[tokio::test]
+async fn spec_id_gates_opcodes() {
+    // Init code that deploys an empty contract with `PUSH0`, which was added in
+    // Shanghai.
+    let deploy = ethers::types::TransactionRequest::new().data(vec![0x5f, 0x5f, 0xf3]);
+
+    let environment = EnvironmentBuilder::new().spec_id(SpecId::MERGE).build();
+    assert_eq!(environment.spec_id(), SpecId::MERGE);
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let error = client
+        .send_transaction(deploy.clone(), None)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        crate::middleware::errors::RevmMiddlewareError::ExecutionHalt { .. }
+    ));
+
+    let environment = EnvironmentBuilder::new().spec_id(SpecId::SHANGHAI).build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let receipt = client
+        .send_transaction(deploy, None)
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(receipt.contract_address.is_some());
+
+    let environment = EnvironmentBuilder::new().build();
+    assert_eq!(environment.spec_id(), SpecId::LATEST);
+}
+
+#[tokio::test]
+async fn detached_executor() {
+    let (environment, mut executor) = EnvironmentBuilder::new().build_detached();
+    // Clients block until their instructions are carried out, so the executor
+    // is polled from another thread.
+    let driver = std::thread::spawn(move || {
+        while executor.poll().unwrap().is_continue() {
+            std::thread::yield_now();
+        }
+    });
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbiter_token = deploy_arbx(client).await.unwrap();
+    assert_eq!(
+        arbiter_token.name().call().await.unwrap(),
+        ARBITER_TOKEN_X_NAME
+    );
+    environment.stop().unwrap();
+    driver.join().unwrap();
+}
+
+#[tokio::test]
+async fn custom_precompile() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use ethers::types::transaction::eip2718::TypedTransaction;
+
+    use crate::environment::precompile::{PrecompileError, PrecompileOutput};
+
+    let precompile_address = Address::from_low_u64_be(0x100);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let environment = EnvironmentBuilder::new()
+        .with_precompile(precompile_address, move |input: &[u8], _gas_limit| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            if input.is_empty() {
+                return Err(PrecompileError::Revert {
+                    gas_used: 10,
+                    output: vec![],
+                });
+            }
+            Ok(PrecompileOutput {
+                gas_used: 100,
+                output: input.iter().rev().copied().collect(),
+            })
+        })
+        .build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+
+    // Calling the precompile directly.
+    let tx: TypedTransaction = ethers::types::TransactionRequest::new()
+        .to(precompile_address)
+        .data(vec![1, 2, 3])
+        .into();
+    let output = client.call(&tx, None).await.unwrap();
+    assert_eq!(output.to_vec(), vec![3, 2, 1]);
+
+    // Calling it from a contract that forwards its calldata to the precompile
+    // and returns what the precompile returned.
+    let mut runtime = vec![
+        0x36, 0x60, 0x00, 0x60, 0x00, 0x37, 0x60, 0x00, 0x60, 0x00, 0x36, 0x60, 0x00, 0x60, 0x00,
+        0x73,
+    ];
+    runtime.extend(precompile_address.as_bytes());
+    runtime.extend([
+        0x5a, 0xf1, 0x50, 0x3d, 0x60, 0x00, 0x60, 0x00, 0x3e, 0x3d, 0x60, 0x00, 0xf3,
+    ]);
+    let mut init_code = vec![
+        0x60, 0x31, 0x60, 0x0c, 0x60, 0x00, 0x39, 0x60, 0x31, 0x60, 0x00, 0xf3,
+    ];
+    init_code.extend(runtime);
+    let deploy = ethers::types::TransactionRequest::new().data(init_code);
+    let forwarder = client
+        .send_transaction(deploy, None)
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap()
+        .contract_address
+        .unwrap();
+    let tx: TypedTransaction = ethers::types::TransactionRequest::new()
+        .to(forwarder)
+        .data(vec![4, 5])
+        .into();
+    let output = client.call(&tx, None).await.unwrap();
+    assert_eq!(output.to_vec(), vec![5, 4]);
+    let receipt = client
+        .send_transaction(tx, None)
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(receipt.status, Some(1.into()));
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    // A precompile that reverts reverts the call.
+    let tx: TypedTransaction = ethers::types::TransactionRequest::new()
+        .to(precompile_address)
+        .into();
+    assert!(client.call(&tx, None).await.is_err());
+}
@@ -0,0 +1,62 @@
+use std::collections::BTreeMap;
+
+use super::*;
+use crate::metrics::{Accountant, ETHER};
+
+#[tokio::test]
+async fn accountant_tracks_pnl() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    arbiter_token
+        .mint(client.address(), float_to_wad(10.0))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    let mut accountant = Accountant::new(client.clone())
+        .track_account("trader", client.address())
+        .track_token(TEST_ARG_SYMBOL, arbiter_token.address(), TEST_ARG_DECIMALS);
+    let prices = BTreeMap::from([(TEST_ARG_SYMBOL.to_string(), 2.0), (ETHER.to_string(), 0.0)]);
+
+    let snapshot = accountant.record(prices.clone()).await.unwrap();
+    let portfolio = &snapshot.portfolios["trader"];
+    assert_eq!(portfolio.tokens[TEST_ARG_SYMBOL], 10.0);
+    assert_eq!(portfolio.value, 20.0);
+    assert_eq!(portfolio.pnl, 0.0);
+
+    arbiter_token
+        .mint(client.address(), float_to_wad(5.0))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    let snapshot = accountant.record(prices).await.unwrap();
+    let portfolio = &snapshot.portfolios["trader"];
+    assert_eq!(portfolio.tokens[TEST_ARG_SYMBOL], 15.0);
+    assert_eq!(portfolio.pnl, 10.0);
+    assert_eq!(accountant.history().len(), 2);
+
+    // A missing price is an error.
+    assert!(accountant.record(BTreeMap::new()).await.is_err());
+
+    let records = serde_json::to_value(accountant.records()).unwrap();
+    assert_eq!(records.as_array().unwrap().len(), 2);
+    assert_eq!(records[1]["account"], "trader");
+    assert_eq!(records[1][TEST_ARG_SYMBOL], 15.0);
+    assert_eq!(records[1]["pnl"], 10.0);
+
+    accountant.write_csv("./test_accountant.csv").unwrap();
+    let csv = std::fs::read_to_string("./test_accountant.csv").unwrap();
+    assert_eq!(csv.lines().count(), 3);
+    assert!(csv.starts_with("block_number,block_timestamp,account,ether,ARBT,value,pnl"));
+
+    // A token tracked after the snapshots were recorded leaves its cells empty.
+    let accountant = accountant.track_token("LATE", Address::random(), 18);
+    accountant.write_csv("./test_accountant.csv").unwrap();
+    let csv = std::fs::read_to_string("./test_accountant.csv").unwrap();
+    assert!(csv.lines().nth(1).unwrap().contains(",10,,"));
+    std::fs::remove_file("./test_accountant.csv").unwrap();
+}
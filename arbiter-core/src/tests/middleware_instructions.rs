@@ -1,7 +1,11 @@
 use ethers::types::transaction::eip2718::TypedTransaction;
 
 use super::*;
-use crate::middleware::nonce_middleware::NonceManagerMiddleware;
+use crate::middleware::{
+    caching_middleware::CachingMiddleware,
+    differential_middleware::{DifferentialMiddleware, DivergenceKind},
+    nonce_middleware::NonceManagerMiddleware,
+};
 
 #[tokio::test]
 async fn deploy() {
@@ -25,6 +29,120 @@ async fn call() {
     );
 }
 
+#[tokio::test]
+async fn send_batch() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let txs: Vec<TypedTransaction> = (0..3)
+        .map(|_| {
+            arbiter_token
+                .mint(
+                    Address::from_str(TEST_MINT_TO).unwrap(),
+                    ethers::types::U256::from(TEST_MINT_AMOUNT),
+                )
+                .tx
+        })
+        .collect();
+    let execution_results = client.send_batch(txs).await.unwrap();
+    assert_eq!(execution_results.len(), 3);
+    assert!(execution_results.iter().all(|result| result.is_success()));
+
+    let balance = arbiter_token
+        .balance_of(Address::from_str(TEST_MINT_TO).unwrap())
+        .call()
+        .await
+        .unwrap();
+    assert_eq!(balance, ethers::types::U256::from(3 * TEST_MINT_AMOUNT));
+}
+
+#[tokio::test]
+async fn balances_of() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let accounts: Vec<Address> = (1..=3).map(Address::from_low_u64_be).collect();
+    let txs: Vec<TypedTransaction> = accounts
+        .iter()
+        .zip(1..)
+        .map(|(account, multiple)| {
+            arbiter_token
+                .mint(
+                    *account,
+                    ethers::types::U256::from(multiple * TEST_MINT_AMOUNT),
+                )
+                .tx
+        })
+        .collect();
+    client.send_batch(txs).await.unwrap();
+
+    let balances = client
+        .balances_of(arbiter_token.address(), &accounts)
+        .await
+        .unwrap();
+    assert_eq!(
+        balances,
+        vec![
+            ethers::types::U256::from(TEST_MINT_AMOUNT),
+            ethers::types::U256::from(2 * TEST_MINT_AMOUNT),
+            ethers::types::U256::from(3 * TEST_MINT_AMOUNT),
+        ]
+    );
+
+    // An account without code returns nothing rather than a balance.
+    assert!(client.balances_of(accounts[0], &accounts).await.is_err());
+}
+
+#[tokio::test]
+async fn simulate_bundle() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let to = Address::from_str(TEST_MINT_TO).unwrap();
+    let amount = ethers::types::U256::from(TEST_MINT_AMOUNT);
+    let mint = arbiter_token.mint(client.address(), amount).tx;
+    // The transfer only succeeds because it sees the mint before it.
+    let transfer = arbiter_token.transfer(to, amount).tx;
+    let simulation = client
+        .simulate_bundle(vec![mint, transfer.clone()])
+        .await
+        .unwrap();
+    assert_eq!(simulation.results.len(), 2);
+    assert!(simulation.is_success());
+    assert_eq!(
+        simulation.gas_used,
+        simulation
+            .results
+            .iter()
+            .map(|result| result.gas_used())
+            .sum::<u64>()
+    );
+    assert_eq!(simulation.profit, ethers::types::I256::zero());
+
+    // Nothing in the bundle was committed.
+    let balance = arbiter_token.balance_of(to).call().await.unwrap();
+    assert_eq!(balance, ethers::types::U256::zero());
+    let simulation = client.simulate_bundle(vec![transfer]).await.unwrap();
+    assert!(!simulation.is_success());
+}
+
+#[tokio::test]
+async fn call_with_overrides() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+
+    // `totalSupply` lives in slot 2 of the solmate `ERC20`.
+    let mut state = ethers::types::spoof::state();
+    state.account(arbiter_token.address()).store(
+        ethers::types::H256::from_low_u64_be(2),
+        ethers::types::H256::from_low_u64_be(1337),
+    );
+    let tx = arbiter_token.total_supply().tx;
+    let output = client.call_with_overrides(&tx, &state).await.unwrap();
+    assert_eq!(U256::from_big_endian(&output), U256::from(1337));
+
+    // The overrides never touch the actual worldstate.
+    let total_supply = arbiter_token.total_supply().call().await.unwrap();
+    assert_eq!(total_supply, U256::zero());
+}
+
 #[tokio::test]
 async fn transact() {
     let (_environment, client) = startup_user_controlled().unwrap();
@@ -176,6 +294,72 @@ async fn filter_address() {
     };
 }
 
+#[tokio::test]
+async fn subscribe_logs() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let mut subscription = client
+        .subscribe_logs(&Filter::new().address(arbiter_token.address()))
+        .await
+        .unwrap();
+    let approval = arbiter_token.approve(
+        client.default_sender().unwrap(),
+        ethers::types::U256::from(TEST_APPROVAL_AMOUNT),
+    );
+    approval.send().await.unwrap().await.unwrap();
+    let event = subscription.next().await.unwrap();
+    assert_eq!(event.address, arbiter_token.address());
+    let approval_filter_output = ApprovalFilter::decode_log(&event.into()).unwrap();
+    assert_eq!(
+        approval_filter_output.amount,
+        ethers::types::U256::from(TEST_APPROVAL_AMOUNT)
+    );
+}
+
+#[tokio::test]
+async fn subscribe_blocks() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let mut subscription = client.subscribe_blocks().await.unwrap();
+    client.update_block(69, 420).unwrap();
+    let block = subscription.next().await.unwrap();
+    assert_eq!(block.number, Some(ethers::types::U64::from(69)));
+    assert_eq!(block.timestamp, ethers::types::U256::from(420));
+}
+
+#[tokio::test]
+async fn watch_blocks() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let mut block_watcher = client.watch_blocks().await.unwrap();
+    client.update_block(69, 420).unwrap();
+    let block_hash = block_watcher.next().await.unwrap();
+    let mut block_hasher = Sha256::new();
+    block_hasher.update(69.to_string().as_bytes());
+    assert_eq!(
+        block_hash,
+        ethers::types::H256::from_slice(&block_hasher.finalize())
+    );
+}
+
+#[tokio::test]
+async fn watch_pending_transactions() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let mut pending_watcher = client.watch_pending_transactions().await.unwrap();
+    let receipt = arbiter_token
+        .approve(
+            client.default_sender().unwrap(),
+            ethers::types::U256::from(TEST_APPROVAL_AMOUNT),
+        )
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    let transaction_hash = pending_watcher.next().await.unwrap();
+    assert_eq!(transaction_hash, receipt.transaction_hash);
+}
+
 #[tokio::test]
 async fn filter_topics() {
     let (_environment, client) = startup_user_controlled().unwrap();
@@ -245,6 +429,36 @@ async fn get_block_number() {
     assert_eq!(block_number.as_u64(), 0_u64)
 }
 
+#[tokio::test]
+async fn get_chainid() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    assert_eq!(client.get_chainid().await.unwrap(), 1.into());
+
+    let environment = builder::EnvironmentBuilder::new().chain_id(10).build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    assert_eq!(client.get_chainid().await.unwrap(), 10.into());
+    assert_eq!(client.provider().get_chainid().await.unwrap(), 10.into());
+
+    // The domain separator of the token is computed with the `CHAINID` opcode.
+    let arbiter_token = deploy_arbx(client).await.unwrap();
+    let expected = ethers::utils::keccak256(ethers::abi::encode(&[
+        ethers::abi::Token::FixedBytes(
+            ethers::utils::keccak256(
+                "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)",
+            )
+            .to_vec(),
+        ),
+        ethers::abi::Token::FixedBytes(ethers::utils::keccak256(ARBITER_TOKEN_X_NAME).to_vec()),
+        ethers::abi::Token::FixedBytes(ethers::utils::keccak256("1").to_vec()),
+        ethers::abi::Token::Uint(10.into()),
+        ethers::abi::Token::Address(arbiter_token.address()),
+    ]));
+    assert_eq!(
+        arbiter_token.domain_separator().call().await.unwrap(),
+        expected
+    );
+}
+
 #[tokio::test]
 async fn get_block_timestamp() {
     let (_environment, client) = startup_user_controlled().unwrap();
@@ -274,6 +488,71 @@ async fn deal() {
     assert_eq!(balance.unwrap(), 1.into());
 }
 
+#[tokio::test]
+async fn deal_token() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let holder = Address::from_low_u64_be(1337);
+    client
+        .apply_cheatcode(Cheatcodes::DealToken {
+            token: arbiter_token.address(),
+            holder,
+            amount: TEST_MINT_AMOUNT.into(),
+        })
+        .await
+        .unwrap();
+    let balance = arbiter_token.balance_of(holder).call().await.unwrap();
+    assert_eq!(balance, TEST_MINT_AMOUNT.into());
+    let balance = arbiter_token
+        .balance_of(client.address())
+        .call()
+        .await
+        .unwrap();
+    assert_eq!(balance, 0.into());
+
+    // An account without a balance mapping has no slot to write to.
+    let result = client
+        .apply_cheatcode(Cheatcodes::DealToken {
+            token: client.address(),
+            holder,
+            amount: TEST_MINT_AMOUNT.into(),
+        })
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn set_nonce() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    client
+        .apply_cheatcode(Cheatcodes::SetNonce {
+            address: client.address(),
+            nonce: 5,
+        })
+        .await
+        .unwrap();
+    let nonce = client
+        .get_transaction_count(client.address(), None)
+        .await
+        .unwrap();
+    assert_eq!(nonce, 5.into());
+
+    // The next deployment lands where a deployment with that nonce would.
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    assert_eq!(
+        arbiter_token.address(),
+        ethers::utils::get_contract_address(client.address(), 5)
+    );
+
+    let result = client
+        .apply_cheatcode(Cheatcodes::SetNonce {
+            address: Address::from_low_u64_be(1337),
+            nonce: 5,
+        })
+        .await;
+    assert!(result.is_err());
+}
+
 #[tokio::test]
 async fn deal_missing_account() {
     let (_environment, client) = startup_user_controlled().unwrap();
@@ -291,6 +570,99 @@ async fn deal_missing_account() {
     assert!(balance.is_err());
 }
 
+#[tokio::test]
+async fn warp_and_roll() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    client.update_block(1, 12).unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+
+    client
+        .apply_cheatcode(Cheatcodes::Warp {
+            timestamp: 1000.into(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(client.get_block_timestamp().await.unwrap(), 1000.into());
+    assert_eq!(client.get_block_number().await.unwrap(), 1.into());
+
+    client
+        .apply_cheatcode(Cheatcodes::Roll {
+            block_number: 50.into(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(client.get_block_number().await.unwrap(), 50.into());
+    assert_eq!(client.get_block_timestamp().await.unwrap(), 1000.into());
+
+    // The gas used by the block so far is kept across the jump.
+    let receipt = arbiter_token
+        .mint(client.address(), TEST_MINT_AMOUNT.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(receipt.block_number, Some(50.into()));
+    assert!(receipt.cumulative_gas_used > receipt.gas_used.unwrap());
+}
+
+#[tokio::test]
+async fn set_block_fields() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let base_fee = ethers::types::U256::from(7);
+    let coinbase = Address::from_low_u64_be(0xc0ffee);
+    let prev_randao = ethers::types::H256::from_low_u64_be(42);
+    client
+        .apply_cheatcode(Cheatcodes::SetBaseFee { base_fee })
+        .await
+        .unwrap();
+    client
+        .apply_cheatcode(Cheatcodes::SetCoinbase { coinbase })
+        .await
+        .unwrap();
+    client
+        .apply_cheatcode(Cheatcodes::SetPrevRandao { prev_randao })
+        .await
+        .unwrap();
+
+    // Transactions below the base fee are rejected, so the client has to pay.
+    client.set_gas_price(base_fee).await.unwrap();
+    client
+        .apply_cheatcode(Cheatcodes::Deal {
+            address: client.address(),
+            amount: ethers::types::U256::exp10(30),
+        })
+        .await
+        .unwrap();
+
+    // Each deployment returns the word pushed by its first opcode as its code:
+    // `BASEFEE`, `COINBASE`, and `PREVRANDAO` respectively.
+    let deployments = [0x48_u8, 0x41, 0x44]
+        .into_iter()
+        .map(|opcode| {
+            ethers::types::TransactionRequest::new()
+                .data(vec![opcode, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3])
+                .into()
+        })
+        .collect::<Vec<TypedTransaction>>();
+    let results = client.send_batch(deployments).await.unwrap();
+    let expected = [
+        ethers::types::H256::from_uint(&base_fee),
+        ethers::types::H256::from(coinbase),
+        prev_randao,
+    ];
+    for (result, expected) in results.iter().zip(expected) {
+        let output = result.output().unwrap();
+        assert_eq!(ethers::types::H256::from_slice(output), expected);
+    }
+
+    // Calls pay no gas, so they are not held to the base fee.
+    let name = arbiter_token.name().call().await.unwrap();
+    assert_eq!(name, TEST_ARG_NAME);
+}
+
 #[tokio::test]
 async fn set_gas_price() {
     let (_environment, client) = startup_user_controlled().unwrap();
@@ -359,6 +731,110 @@ async fn inner_nonce_environment() {
     assert_eq!(inner.address(), client.address());
 }
 
+#[tokio::test]
+async fn caching_middleware() {
+    let environment = builder::EnvironmentBuilder::new()
+        .record_instructions()
+        .build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    let caching = Arc::new(CachingMiddleware::new(client.clone()));
+    let cached_token = ArbiterToken::new(arbiter_token.address(), caching.clone());
+    let to = Address::from_str(TEST_MINT_TO).unwrap();
+    let calls = || {
+        environment
+            .recorded_instructions()
+            .into_iter()
+            .filter(|kind| *kind == InstructionKind::Call)
+            .count()
+    };
+
+    let before = calls();
+    for _ in 0..3 {
+        assert_eq!(cached_token.balance_of(to).call().await.unwrap(), 0.into());
+    }
+    assert_eq!(calls(), before + 1);
+    assert_eq!(caching.hits(), 2);
+
+    // A transaction from any client clears the cache.
+    arbiter_token
+        .mint(to, TEST_MINT_AMOUNT.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    assert_eq!(
+        cached_token.balance_of(to).call().await.unwrap(),
+        TEST_MINT_AMOUNT.into()
+    );
+    assert_eq!(calls(), before + 2);
+
+    // So does a new block.
+    client.update_block(1, 12).unwrap();
+    cached_token.balance_of(to).call().await.unwrap();
+    assert_eq!(calls(), before + 3);
+    assert_eq!(caching.hits(), 2);
+}
+
+#[tokio::test]
+async fn differential_middleware() {
+    let environment = builder::EnvironmentBuilder::new()
+        .record_state_diffs()
+        .build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    // Another `Environment` with a client at the same address stands in for the
+    // reference node.
+    let reference_environment = builder::EnvironmentBuilder::new().build();
+    let reference =
+        RevmMiddleware::new(&reference_environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let differential = Arc::new(DifferentialMiddleware::new(
+        client.clone(),
+        reference.clone(),
+    ));
+    let arbiter_token = ArbiterToken::deploy(
+        differential.clone(),
+        (
+            ARBITER_TOKEN_X_NAME.to_string(),
+            ARBITER_TOKEN_X_SYMBOL.to_string(),
+            ARBITER_TOKEN_X_DECIMALS,
+        ),
+    )
+    .unwrap()
+    .send()
+    .await
+    .unwrap();
+    let to = Address::from_str(TEST_MINT_TO).unwrap();
+    arbiter_token
+        .mint(to, TEST_MINT_AMOUNT.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    assert_eq!(differential.divergences(), vec![]);
+
+    // A transaction only the reference node executed makes the nonces diverge.
+    deploy_arby(reference.clone()).await.unwrap();
+    arbiter_token
+        .mint(to, TEST_MINT_AMOUNT.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    let divergences = differential.divergences();
+    assert_eq!(divergences.len(), 1);
+    assert_eq!(
+        divergences[0].kind,
+        DivergenceKind::Nonce {
+            account: client.address(),
+            environment: 3,
+            reference: 4.into(),
+        }
+    );
+}
+
 #[tokio::test]
 async fn fill_transaction() {
     let (_environment, client) = startup_user_controlled().unwrap();
@@ -445,6 +921,56 @@ async fn test_cheatcodes_store() {
     assert_eq!(storage, random_value);
 }
 
+#[tokio::test]
+async fn test_cheatcodes_storage() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+    arbiter_token
+        .mint(
+            client.address(),
+            ethers::types::U256::from(TEST_MINT_AMOUNT),
+        )
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    let slots = match client
+        .apply_cheatcode(Cheatcodes::Storage {
+            account: arbiter_token.address(),
+            offset: 0,
+            limit: None,
+        })
+        .await
+        .unwrap()
+    {
+        CheatcodesReturn::Storage { slots } => slots,
+        _ => panic!("Expected CheatcodesReturn::Storage"),
+    };
+    // The name, symbol, total supply, balance, and admin are populated.
+    assert!(!slots.is_empty());
+    assert!(slots.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    assert!(slots
+        .iter()
+        .any(|(_, value)| *value == revm::primitives::U256::from(TEST_MINT_AMOUNT)));
+
+    // Paginating returns the same slots.
+    let page = match client
+        .apply_cheatcode(Cheatcodes::Storage {
+            account: arbiter_token.address(),
+            offset: 1,
+            limit: Some(2),
+        })
+        .await
+        .unwrap()
+    {
+        CheatcodesReturn::Storage { slots } => slots,
+        _ => panic!("Expected CheatcodesReturn::Storage"),
+    };
+    assert_eq!(page[..], slots[1..3]);
+}
+
 #[tokio::test]
 async fn unimplemented_middleware_instruction() {
     let (_environment, client) = startup_user_controlled().unwrap();
@@ -462,3 +988,249 @@ async fn unimplemented_middleware_instruction() {
         panic!("Expected RevmMiddlewareError::Provider");
     }
 }
+
+#[tokio::test]
+async fn get_code_and_selectors() {
+    use ethers::contract::EthCall;
+
+    use crate::bytecode::*;
+
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+
+    let code = client
+        .get_code(arbiter_token.address(), None)
+        .await
+        .unwrap();
+    assert!(!code.is_empty());
+    assert_eq!(
+        client.get_code(client.address(), None).await.unwrap(),
+        ethers::types::Bytes::default()
+    );
+
+    let opcodes = disassemble(&code);
+    assert_eq!(opcodes[0].name, Some("PUSH1"));
+    let jump_destinations = jump_destinations(&code);
+    let selectors = function_selectors(&code);
+    let mint = selectors
+        .iter()
+        .find(|entry| entry.selector == MintCall::selector())
+        .unwrap();
+    assert!(jump_destinations.contains(&mint.jump_destination));
+    assert!(selectors
+        .iter()
+        .any(|entry| entry.selector == TransferCall::selector()));
+}
+
+#[tokio::test]
+async fn expect_revert_with() {
+    use ethers::contract::EthError;
+
+    use crate::middleware::revert::{ExpectedRevert, RevertError};
+
+    let (environment, client) = startup_user_controlled().unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+
+    // Only the admin can mint.
+    let other = RevmMiddleware::new(&environment, Some("other")).unwrap();
+    let mint = ArbiterToken::new(arbiter_token.address(), other.clone())
+        .mint(other.address(), 1000u64.into());
+    let revert = client
+        .expect_revert_with("Only admin can call this function", mint.send())
+        .await
+        .unwrap();
+    assert_eq!(
+        revert,
+        RevertError::Reason("Only admin can call this function".to_string())
+    );
+
+    // Transferring more than the balance underflows.
+    let transfer = arbiter_token.transfer(other.address(), 1u64.into());
+    let revert = client
+        .expect_revert_with(ExpectedRevert::Panic(0x11), transfer.send())
+        .await
+        .unwrap();
+    assert_eq!(
+        revert.to_string(),
+        "panicked with code 0x11 (arithmetic overflow or underflow)"
+    );
+
+    // Actions that succeed or revert differently are reported.
+    let mint = arbiter_token.mint(client.address(), 1000u64.into());
+    assert!(matches!(
+        client
+            .expect_revert_with(ExpectedRevert::Any, mint.send())
+            .await,
+        Err(crate::middleware::errors::RevmMiddlewareError::UnexpectedOutcome(_))
+    ));
+    let mint = ArbiterToken::new(arbiter_token.address(), other.clone())
+        .mint(other.address(), 1000u64.into());
+    assert!(client
+        .expect_revert_with("Some other reason", mint.send())
+        .await
+        .is_err());
+
+    // Custom errors are decoded when their ABI is given.
+    let revert = RevertError::decode(&OOB::selector(), Some(&ARBITERMATH_ABI));
+    assert_eq!(revert.to_string(), "reverted with OOB()");
+    assert!(revert.matches(&OOB::selector().into()));
+    assert!(matches!(
+        RevertError::decode(&OOB::selector(), None),
+        RevertError::Unknown(_)
+    ));
+}
+
+#[tokio::test]
+async fn decode_reverts_with_registered_abi() {
+    use ethers::contract::EthError;
+
+    use crate::middleware::{errors::RevmMiddlewareError, revert::RevertError};
+
+    let (environment, client) = startup_user_controlled().unwrap();
+
+    // A contract whose runtime code reverts with `OOB()` on every call.
+    let mut runtime = vec![0x63];
+    runtime.extend(OOB::selector());
+    runtime.extend([
+        0x60, 0xe0, 0x1b, 0x60, 0x00, 0x52, 0x60, 0x04, 0x60, 0x00, 0xfd,
+    ]);
+    let mut init_code = vec![
+        0x60, 0x10, 0x60, 0x0c, 0x60, 0x00, 0x39, 0x60, 0x10, 0x60, 0x00, 0xf3,
+    ];
+    init_code.extend(runtime);
+    let deploy = ethers::types::TransactionRequest::new().data(init_code);
+    let reverter = client
+        .send_transaction(deploy, None)
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap()
+        .contract_address
+        .unwrap();
+    let tx: TypedTransaction = ethers::types::TransactionRequest::new()
+        .to(reverter)
+        .data(vec![0x01])
+        .into();
+
+    // Without a registered ABI the custom error is left undecoded.
+    let error = client.call(&tx, None).await.unwrap_err();
+    assert!(matches!(
+        error,
+        RevmMiddlewareError::ExecutionRevert {
+            decoded: RevertError::Unknown(_),
+            ..
+        }
+    ));
+
+    // Once the ABI is registered, calls and transactions decode it.
+    client.register_abi(reverter, ARBITERMATH_ABI.clone());
+    assert!(environment.abi_registry().get(reverter).is_some());
+    let error = client.call(&tx, None).await.unwrap_err();
+    assert!(error.to_string().ends_with("reverted with OOB()"));
+    let error = client.send_transaction(tx, None).await.unwrap_err();
+    match error {
+        RevmMiddlewareError::ExecutionRevert { decoded, .. } => {
+            assert_eq!(
+                decoded,
+                RevertError::Custom {
+                    name: "OOB".to_string(),
+                    selector: OOB::selector(),
+                    args: vec![],
+                }
+            );
+        }
+        error => panic!("expected a revert, got {}", error),
+    }
+}
+
+#[tokio::test]
+async fn render_with_registered_abis() {
+    use crate::environment::trace::CallTracer;
+
+    let tracer = CallTracer::new();
+    let environment = EnvironmentBuilder::new()
+        .with_inspector(tracer.clone())
+        .build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await.unwrap();
+
+    // Deploying a known binding registers it under its name.
+    let registry = environment.abi_registry();
+    assert_eq!(
+        registry.name(arbiter_token.address()),
+        Some("ArbiterToken".to_string())
+    );
+
+    let receipt = arbiter_token
+        .mint(client.address(), 1000u64.into())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    let receiver = ethers::utils::to_checksum(&client.address(), None);
+    let rendered = registry.render_receipt(&receipt);
+    assert!(rendered.contains(&format!(
+        "::Transfer(from: 0x0000000000000000000000000000000000000000, to: {}, amount: 1000)",
+        receiver
+    )));
+
+    let trace = tracer.last().unwrap();
+    assert_eq!(trace.len(), 1);
+    assert!(trace[0].success);
+    let rendered = registry.render_trace(&trace);
+    assert!(rendered.contains(&format!("::mint(receiver: {}, amount: 1000)", receiver)));
+    assert!(rendered.ends_with("└─ ← (true)\n"));
+}
+
+#[tokio::test]
+async fn deploy_create2() {
+    let environment = builder::EnvironmentBuilder::new()
+        .create2_deployer()
+        .build();
+    let client = RevmMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let salt = [7u8; 32];
+    let args = (
+        TEST_ARG_NAME.to_string(),
+        TEST_ARG_SYMBOL.to_string(),
+        TEST_ARG_DECIMALS,
+    );
+
+    let address = client
+        .deploy_create2(salt, ARBITERTOKEN_BYTECODE.clone(), args.clone())
+        .await
+        .unwrap();
+    let init_code = [
+        ARBITERTOKEN_BYTECODE.to_vec(),
+        ethers::abi::encode(&ethers::abi::Tokenize::into_tokens(args.clone())),
+    ]
+    .concat();
+    assert_eq!(
+        address,
+        ethers::utils::get_create2_address(builder::CREATE2_DEPLOYER, salt, init_code)
+    );
+    let arbiter_token = ArbiterToken::new(address, client.clone());
+    assert_eq!(arbiter_token.name().call().await.unwrap(), TEST_ARG_NAME);
+
+    // The same contract cannot be deployed twice with the same salt.
+    assert!(client
+        .deploy_create2(salt, ARBITERTOKEN_BYTECODE.clone(), args)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn deploy_create2_needs_deployer() {
+    let (_environment, client) = startup_user_controlled().unwrap();
+    let result = client
+        .deploy_create2([0u8; 32], ARBITERTOKEN_BYTECODE.clone(), ())
+        .await;
+    assert!(matches!(
+        result,
+        Err(crate::middleware::errors::RevmMiddlewareError::MissingData(
+            _
+        ))
+    ));
+}
@@ -4,9 +4,11 @@
 // mod interaction;
 mod clients;
 mod contracts;
+#[cfg(feature = "data-collection")]
 mod data_output;
 mod derives;
 mod environment_control;
+mod metrics;
 mod middleware_instructions;
 
 use std::{str::FromStr, sync::Arc};
@@ -4,17 +4,53 @@ use std::{
     fs::{write, File},
     io,
     io::{BufRead, BufReader},
-    path::Path,
+    path::{Path, PathBuf},
     process::Command,
 };
 
+use ethers::utils::{hex, keccak256};
+
+/// Which contracts `arbiter bind` keeps bindings for.
+///
+/// The patterns are globs where `*` matches any run of characters and `?` any
+/// single character. They are matched against the module names of the
+/// bindings, and a pattern written as a contract name, e.g., `UniswapV2*`, is
+/// turned into a module name the same way. The `shared_types` module is
+/// always kept.
+#[derive(Debug, Default)]
+pub(crate) struct BindFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl BindFilter {
+    /// Keeps the contracts that match any of the `include` patterns, or all
+    /// of them if there are none, unless they match any of the `exclude`
+    /// patterns.
+    pub(crate) fn new(include: &[String], exclude: &[String]) -> Self {
+        Self {
+            include: include.iter().map(|p| camel_to_snake_case(p)).collect(),
+            exclude: exclude.iter().map(|p| camel_to_snake_case(p)).collect(),
+        }
+    }
+
+    fn keeps(&self, module: &str) -> bool {
+        let matches = |pattern: &String| glob_matches(pattern.as_bytes(), module.as_bytes());
+        module == "shared_types"
+            || ((self.include.is_empty() || self.include.iter().any(matches))
+                && !self.exclude.iter().any(matches))
+    }
+}
+
 /// Runs the `forge` command-line tool to generate bindings.
 ///
 /// This function attempts to execute the external command `forge` with the
 /// provided arguments to generate necessary bindings. The bindings are stored
 /// in the `arbiter/src/bindings/` directory, and existing bindings will be
 /// overwritten. The function wraps the forge command to generate bindings as a
-/// module to a specific destination.
+/// module to a specific destination. Only the bindings of the contracts kept
+/// by the `filter` are left, and the `mod.rs` of each directory is updated to
+/// declare exactly those.
 ///
 /// # Returns
 ///
@@ -23,7 +59,7 @@ use std::{
 ///   error in generating the bindings. This can also include if the `forge`
 ///   tool is not installed.
 
-pub(crate) fn forge_bind() -> std::io::Result<()> {
+pub(crate) fn forge_bind(filter: &BindFilter) -> std::io::Result<()> {
     println!("Generating bindings for project contracts...");
     let output = Command::new("forge")
         .arg("bind")
@@ -34,7 +70,8 @@ pub(crate) fn forge_bind() -> std::io::Result<()> {
         .arg("--module")
         .arg("--overwrite")
         .output()?;
-    let project_contracts = collect_contract_list(Path::new("contracts"))?;
+    let mut project_contracts = collect_contract_list(Path::new("contracts"))?;
+    project_contracts.retain(|contract| filter.keeps(contract));
     if output.status.success() {
         let output_str = String::from_utf8_lossy(&output.stdout);
         println!("Command output: {}", output_str);
@@ -52,13 +89,115 @@ pub(crate) fn forge_bind() -> std::io::Result<()> {
     remove_unneeded_contracts(src_binding_dir, project_contracts)?;
 
     let lib_dir = Path::new("lib");
-    let (output_path, sub_module_contracts) = bindings_for_submodules(lib_dir)?;
+    let (output_path, mut sub_module_contracts) = bindings_for_submodules(lib_dir)?;
+    sub_module_contracts.retain(|contract| filter.keeps(contract));
     println!("submodule contracts: {:?}", sub_module_contracts);
     remove_unneeded_contracts(Path::new(&output_path), sub_module_contracts)?;
 
     Ok(())
 }
 
+/// Writes `src/artifacts/generated.rs` from the contracts compiled into
+/// `out/` that have a binding in `src/bindings/`. Each artifact records the
+/// size and `keccak256` hash of the contract's init and deployed bytecode so
+/// that `arbiter-core` can check the bytecode embedded in its bindings.
+///
+/// This is meant to be run in the `arbiter-core` directory after
+/// [`forge_bind`].
+pub(crate) fn write_artifacts() -> io::Result<()> {
+    println!("Generating artifacts for embedded contracts...");
+    let bindings = fs::read_to_string("src/bindings/mod.rs")?;
+    let modules = bindings
+        .lines()
+        .filter_map(|line| {
+            line.trim()
+                .strip_prefix("pub mod ")
+                .and_then(|s| s.strip_suffix(';'))
+        })
+        .filter(|module| *module != "shared_types")
+        .collect::<Vec<_>>();
+    let compiled = collect_compiled_contracts(Path::new("out"))?;
+
+    let mut artifacts = Vec::new();
+    for module in modules {
+        let Some((name, path)) = compiled
+            .iter()
+            .find(|(name, _)| name.to_lowercase() == module.replace('_', ""))
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "No compiled contract in `out/` for the binding `{}`, run `forge build` first.",
+                    module
+                ),
+            ));
+        };
+        let compiled: serde_json::Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let bytecode = decode_bytecode(&compiled["bytecode"]["object"], name)?;
+        let deployed_bytecode = decode_bytecode(&compiled["deployedBytecode"]["object"], name)?;
+        artifacts.push(render_artifact(module, name, &bytecode, &deployed_bytecode));
+    }
+
+    let generated = format!(
+        "//! This file is generated by `arbiter bind --artifacts`. Do not edit it by\n//! hand.\n\nuse super::Artifact;\nuse crate::bindings::*;\n\n/// The contracts embedded in `arbiter-core`.\npub static ARTIFACTS: [Artifact; {}] = [\n{}];\n",
+        artifacts.len(),
+        artifacts.concat()
+    );
+    write(Path::new("src/artifacts/generated.rs"), generated)?;
+    println!(
+        "Wrote {} artifacts to src/artifacts/generated.rs",
+        artifacts.len()
+    );
+    Ok(())
+}
+
+/// Collects the name and path of every contract compiled into `out_dir`,
+/// sorted by name.
+fn collect_compiled_contracts(out_dir: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut contracts = Vec::new();
+    for entry in fs::read_dir(out_dir)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .map_or(false, |extension| extension == "json")
+            {
+                let name = path.file_stem().unwrap().to_str().unwrap().to_string();
+                contracts.push((name, path));
+            }
+        }
+    }
+    contracts.sort();
+    Ok(contracts)
+}
+
+fn decode_bytecode(object: &serde_json::Value, name: &str) -> io::Result<Vec<u8>> {
+    object
+        .as_str()
+        .and_then(|object| hex::decode(object.trim_start_matches("0x")).ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("The compiled `{}` has no bytecode.", name),
+            )
+        })
+}
+
+fn render_artifact(module: &str, name: &str, bytecode: &[u8], deployed_bytecode: &[u8]) -> String {
+    let constant = name.to_uppercase();
+    format!(
+        "    Artifact {{\n        name: \"{name}\",\n        bytecode: &{module}::{constant}_BYTECODE,\n        deployed_bytecode: &{module}::{constant}_DEPLOYED_BYTECODE,\n        abi: &{module}::{constant}_ABI,\n        bytecode_size: {},\n        bytecode_hash: \"0x{}\",\n        deployed_bytecode_size: {},\n        deployed_bytecode_hash:\n            \"0x{}\",\n    }},\n",
+        bytecode.len(),
+        hex::encode(keccak256(bytecode)),
+        deployed_bytecode.len(),
+        hex::encode(keccak256(deployed_bytecode)),
+    )
+}
+
 fn bindings_for_submodules(dir: &Path) -> io::Result<(String, Vec<String>)> {
     let mut contracts_to_generate = Vec::new(); // to keep track of contracts we're generating bindings for
     let mut output_path = String::new();
@@ -175,14 +314,25 @@ fn remove_unneeded_contracts(
     Ok(())
 }
 
+/// Updates the `mod.rs` in `bindings_path` to declare exactly the bindings of
+/// `contracts_to_keep`. Declarations of other contracts are removed and the
+/// bindings that are in the directory but not declared yet are added, so the
+/// output of `forge bind` does not have to be wired up by hand. A missing
+/// `mod.rs` is created.
 fn update_mod_file(bindings_path: &Path, contracts_to_keep: Vec<String>) -> io::Result<()> {
     let mod_path = bindings_path.join("mod.rs");
+    if !mod_path.exists() {
+        write(
+            &mod_path,
+            "#![allow(clippy::all)]\n//! This module contains abigen! generated bindings for solidity contracts.\n//! This is autogenerated code.\n//! Do not manually edit these files.\n",
+        )?;
+    }
 
     // Open the file and read its contents
     let file = File::open(&mod_path)?;
     let reader = BufReader::new(file);
 
-    let lines: Vec<String> = reader
+    let mut lines: Vec<String> = reader
         .lines()
         .map_while(Result::ok)
         .filter(|line| {
@@ -205,12 +355,47 @@ fn update_mod_file(bindings_path: &Path, contracts_to_keep: Vec<String>) -> io::
         })
         .collect();
 
+    // Declare the bindings that were generated but are not declared yet
+    let mut undeclared = Vec::new();
+    for entry in fs::read_dir(bindings_path)? {
+        let path = entry?.path();
+        let Some(module) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let declaration = format!("pub mod {};", module);
+        if path
+            .extension()
+            .map_or(false, |extension| extension == "rs")
+            && module != "mod"
+            && contracts_to_keep.iter().any(|contract| contract == module)
+            && !lines.iter().any(|line| line.trim() == declaration)
+        {
+            undeclared.push(declaration);
+        }
+    }
+    undeclared.sort();
+    lines.extend(undeclared);
+
     // Write the new lines back to the mod.rs
     write(&mod_path, lines.join("\n"))?;
 
     Ok(())
 }
 
+/// Whether `name` matches the glob `pattern`, where `*` matches any run of
+/// characters and `?` any single character.
+fn glob_matches(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some((b'*', rest)), _) => {
+            glob_matches(rest, name) || (!name.is_empty() && glob_matches(pattern, &name[1..]))
+        }
+        (Some((b'?', rest)), Some((_, name_rest))) => glob_matches(rest, name_rest),
+        (Some((p, rest)), Some((n, name_rest))) => p == n && glob_matches(rest, name_rest),
+        (Some(_), None) => false,
+    }
+}
+
 fn camel_to_snake_case(s: &str) -> String {
     let mut snake_case = String::new();
     let chars: Vec<char> = s.chars().collect();
@@ -314,4 +499,49 @@ mod tests {
         // Temp dir (and the mock mod.rs file inside it) will be automatically
         // cleaned up after going out of scope.
     }
+
+    #[test]
+    fn test_update_mod_file_declares_new_bindings() {
+        let dir = tempdir().expect("Failed to create temporary directory");
+        fs::write(dir.path().join("example_contract.rs"), "").expect("Failed to write file");
+        fs::write(dir.path().join("skipped_contract.rs"), "").expect("Failed to write file");
+
+        // There is no mod.rs yet, so one is created.
+        let contracts_to_keep = vec!["shared_types".to_owned(), "example_contract".to_owned()];
+        update_mod_file(dir.path(), contracts_to_keep.clone()).expect("Failed to update mod file");
+        update_mod_file(dir.path(), contracts_to_keep).expect("Failed to update mod file");
+
+        let content = fs::read_to_string(dir.path().join("mod.rs")).unwrap();
+        assert!(content.starts_with("#![allow(clippy::all)]"));
+        assert_eq!(content.matches("pub mod example_contract;").count(), 1);
+        assert!(!content.contains("pub mod skipped_contract;"));
+    }
+
+    #[test]
+    fn test_bind_filter() {
+        let all = BindFilter::default();
+        assert!(all.keeps("liquid_exchange"));
+
+        let filter = BindFilter::new(
+            &["Uniswap*".to_owned(), "arbiter_?oken".to_owned()],
+            &["uniswap_v3*".to_owned()],
+        );
+        assert!(filter.keeps("uniswap_v2_pair"));
+        assert!(filter.keeps("arbiter_token"));
+        assert!(filter.keeps("shared_types"));
+        assert!(!filter.keeps("uniswap_v3_pool"));
+        assert!(!filter.keeps("liquid_exchange"));
+    }
+
+    #[test]
+    fn test_render_artifact() {
+        let rendered = render_artifact("liquid_exchange", "LiquidExchange", &[], &[0x00]);
+        assert!(rendered.contains("name: \"LiquidExchange\""));
+        assert!(rendered.contains("bytecode: &liquid_exchange::LIQUIDEXCHANGE_BYTECODE"));
+        assert!(rendered.contains("abi: &liquid_exchange::LIQUIDEXCHANGE_ABI"));
+        assert!(rendered.contains(
+            "bytecode_hash: \"0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470\""
+        ));
+        assert!(rendered.contains("deployed_bytecode_size: 1,"));
+    }
 }
@@ -6,6 +6,8 @@ use super::*;
 pub(crate) struct Artifacts {
     #[serde(rename = "storageLayout")]
     pub(crate) storage_layout: StorageLayout,
+    #[serde(default)]
+    pub(crate) abi: Option<Abi>,
     // TODO: Add more here if we need them.
 }
 
@@ -47,106 +49,128 @@ pub(crate) enum StorageType {
     },
 }
 
-pub(crate) fn digest_artifacts(path: &str) -> Result<Artifacts, ArbiterError> {
+/// The contents of an artifacts file. This is either the artifacts `forge`
+/// writes out for a single contract or the standard JSON output of `solc`,
+/// which can hold many contracts.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub(crate) enum ArtifactsFile {
+    Forge(Artifacts),
+    Solc {
+        contracts: HashMap<String, HashMap<String, SolcContract>>,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct SolcContract {
+    #[serde(rename = "storageLayout")]
+    pub(crate) storage_layout: Option<StorageLayout>,
+    #[serde(default)]
+    pub(crate) abi: Option<Abi>,
+}
+
+/// Reads the storage layout from the artifacts at `path`. If the artifacts
+/// hold more than one contract, `contract_name` picks out which one to use.
+pub(crate) fn digest_artifacts(
+    path: &str,
+    contract_name: Option<&str>,
+) -> Result<Artifacts, ArbiterError> {
     // Read the file to a string
     let data = fs::read_to_string(path)?;
-    let json_data = serde_json::from_str(&data)?;
+    let artifacts_file: ArtifactsFile = serde_json::from_str(&data)?;
 
-    Ok(json_data)
+    match artifacts_file {
+        ArtifactsFile::Forge(artifacts) => Ok(artifacts),
+        ArtifactsFile::Solc { contracts } => {
+            let mut layouts = contracts
+                .into_iter()
+                .flat_map(|(file, contracts)| {
+                    contracts.into_iter().filter_map(move |(name, contract)| {
+                        let abi = contract.abi;
+                        contract
+                            .storage_layout
+                            .map(|layout| (format!("{}:{}", file, name), name, layout, abi))
+                    })
+                })
+                .filter(|(qualified_name, name, ..)| match contract_name {
+                    Some(contract_name) => {
+                        name.as_str() == contract_name || qualified_name.as_str() == contract_name
+                    }
+                    None => true,
+                })
+                .collect::<Vec<_>>();
+            match layouts.len() {
+                1 => {
+                    let (_, _, storage_layout, abi) = layouts.remove(0);
+                    Ok(Artifacts {
+                        storage_layout,
+                        abi,
+                    })
+                }
+                0 => Err(ArbiterError::ArtifactsError(format!(
+                    "No storage layout found in {}. Make sure `storageLayout` is part of the `outputSelection`.",
+                    path
+                ))),
+                _ => Err(ArbiterError::ArtifactsError(format!(
+                    "Found more than one contract in {}. Set `contract_name` to pick one.",
+                    path
+                ))),
+            }
+        }
+    }
+}
+
+/// Reads the ABI from the artifacts at `path`, which are either the artifacts
+/// `forge` writes out for a contract or a bare ABI.
+pub(crate) fn digest_abi(path: &str) -> Result<Abi, ArbiterError> {
+    let data = fs::read_to_string(path)?;
+    let mut value: serde_json::Value = serde_json::from_str(&data)?;
+    let abi = match value.get_mut("abi") {
+        Some(abi) => abi.take(),
+        None => value,
+    };
+    serde_json::from_value(abi)
+        .map_err(|e| ArbiterError::ArtifactsError(format!("No ABI found in {}: {}", path, e)))
 }
 
 pub(crate) fn create_storage_layout(
     contract_data: &ContractMetadata,
     storage_layout: StorageLayout,
-    db: &mut CacheDB<EmptyDB>,
-    ethers_db: &mut EthersDB<Provider<Http>>,
+    db: &mut CacheDB<ExternalDb>,
+    forked_db: &ForkedDb,
 ) -> Result<(), ArbiterError> {
+    let address: revm::primitives::Address = contract_data.address.to_fixed_bytes().into();
+    let mut fetch = |slot: revm::primitives::U256| -> Result<(), ArbiterError> {
+        let storage = forked_db
+            .storage_ref(address, slot)
+            .map_err(|e| ArbiterError::DBError(format!("Failed to fetch storage: {}", e)))?;
+        db.insert_account_storage(address, slot, storage)
+            .map_err(|e| ArbiterError::DBError(format!("{:?}", e)))
+    };
+
     for storage_item in storage_layout.storage {
-        // The unwraps here should not fail.
-        let label = storage_item.label;
-        let slot = storage_item.slot;
-        let slot_bytes =
-            revm::primitives::U256::from_limbs(U256::from_str_radix(slot.as_str(), 10).unwrap().0);
-        let storage = ethers_db
-            .storage(contract_data.address.to_fixed_bytes().into(), slot_bytes)
-            .unwrap();
-        db.insert_account_storage(
-            contract_data.address.to_fixed_bytes().into(),
-            slot_bytes,
-            storage,
-        )
-        .unwrap();
-        match storage_layout.types.get(&storage_item.type_).unwrap() {
-            StorageType::Simple {
-                encoding: _,
-                label: _,
-                number_of_bytes: _,
-            } => {
-                // Already got the storage slot above, so continue.
-                continue;
-            }
-            StorageType::Mapping {
-                encoding: _encoding,
-                key,
-                value,
-                label: _label,
-                number_of_bytes: _number_of_bytes,
-            } => {
-                // Catch the case where the value of a map is a map. We don't handle that yet.
-                if let StorageType::Mapping { .. } =
-                    storage_layout.types.get(&value.to_string()).unwrap()
-                {
-                    println!(
-                        "Only handling one map deep for now. A map of a map was found and ignored."
-                    );
-                    continue;
-                }
-                // We got a one-deep mapping, so we need to get the keys and values from the
-                // config and properly pad everything to get the storage slot.
-                let key_bytes = match storage_layout.types.get(&key.to_string()).unwrap() {
-                    StorageType::Simple {
-                        encoding: _,
-                        label: _,
-                        number_of_bytes,
-                    } => number_of_bytes.parse::<usize>().unwrap(),
-                    StorageType::Mapping { .. } => {
-                        println!(
-                            "Only handling one map deep for now. A map of a map was found and ignored."
-                        );
-                        continue;
-                    }
-                };
-                if let StorageType::Mapping { .. } =
-                    storage_layout.types.get(&value.to_string()).unwrap()
-                {
-                    println!(
-                        "Only handling one map deep for now. A map of a map was found and ignored."
-                    );
-                    continue;
+        let base_slot = revm::primitives::U256::from_str_radix(&storage_item.slot, 10)
+            .map_err(|e| ArbiterError::ArtifactsError(e.to_string()))?;
+        let storage_type = get_type(&storage_layout.types, &storage_item.type_)?;
+        match storage_type {
+            StorageType::Simple { .. } => {
+                for offset in 0..slot_count(storage_type) {
+                    fetch(base_slot + revm::primitives::U256::from(offset))?;
                 }
-
-                if let Some(keys) = contract_data.mappings.get(&label) {
-                    for key in keys {
-                        let mut padded_key_bytes = vec![0; 32 - key_bytes];
-                        let key_bytes = hex::decode(key).unwrap();
-                        padded_key_bytes.extend(key_bytes.clone());
-                        let to_hash: Vec<u8> = padded_key_bytes
-                            .into_iter()
-                            .chain(slot_bytes.to_be_bytes_vec())
-                            .collect();
-                        let slot_to_get = keccak256(to_hash);
-                        let storage = ethers_db
-                            .storage(
-                                contract_data.address.to_fixed_bytes().into(),
-                                revm::primitives::U256::from_be_bytes(slot_to_get),
-                            )
-                            .unwrap();
-                        db.insert_account_storage(
-                            contract_data.address.to_fixed_bytes().into(),
-                            revm::primitives::U256::from_be_bytes(slot_to_get),
-                            storage,
-                        )
-                        .unwrap();
+            }
+            StorageType::Mapping { .. } => {
+                // Mappings are only pulled in for the keys listed in the config. Keys of
+                // nested mappings are separated by commas, e.g., `"0xowner,0xspender"`.
+                if let Some(key_paths) = contract_data.mappings.get(&storage_item.label) {
+                    for key_path in key_paths {
+                        for slot in resolve_mapping_slots(
+                            key_path,
+                            base_slot,
+                            &storage_item.type_,
+                            &storage_layout.types,
+                        )? {
+                            fetch(slot)?;
+                        }
                     }
                 }
             }
@@ -154,3 +178,139 @@ pub(crate) fn create_storage_layout(
     }
     Ok(())
 }
+
+/// Walks the (possibly nested) mapping at `base_slot` using the
+/// comma-separated keys in `key_path` and returns the slots holding the value
+/// that is found.
+pub(crate) fn resolve_mapping_slots(
+    key_path: &str,
+    base_slot: revm::primitives::U256,
+    type_id: &str,
+    types: &HashMap<String, StorageType>,
+) -> Result<Vec<revm::primitives::U256>, ArbiterError> {
+    let mut slot = base_slot;
+    let mut type_id = type_id;
+    for key in key_path.split(',') {
+        match get_type(types, type_id)? {
+            StorageType::Mapping {
+                key: key_type,
+                value,
+                ..
+            } => {
+                let encoded_key = encode_key(key.trim(), get_type(types, key_type)?)?;
+                slot = mapping_slot(&encoded_key, slot);
+                type_id = value;
+            }
+            StorageType::Simple { .. } => {
+                return Err(ArbiterError::ArtifactsError(format!(
+                    "Too many keys given in `{}`.",
+                    key_path
+                )))
+            }
+        }
+    }
+    let value_type = get_type(types, type_id)?;
+    if let StorageType::Mapping { .. } = value_type {
+        return Err(ArbiterError::ArtifactsError(format!(
+            "Not enough keys given in `{}` to reach a value.",
+            key_path
+        )));
+    }
+    Ok((0..slot_count(value_type))
+        .map(|offset| slot + revm::primitives::U256::from(offset))
+        .collect())
+}
+
+/// Computes the storage slot of `mapping[key]` for a mapping stored at `slot`
+/// where `encoded_key` is the key encoded with [`encode_key`].
+pub(crate) fn mapping_slot(
+    encoded_key: &[u8],
+    slot: revm::primitives::U256,
+) -> revm::primitives::U256 {
+    let to_hash: Vec<u8> = encoded_key
+        .iter()
+        .copied()
+        .chain(slot.to_be_bytes_vec())
+        .collect();
+    revm::primitives::U256::from_be_bytes(keccak256(to_hash))
+}
+
+/// Encodes a mapping key from the config the way Solidity does when hashing it
+/// into a slot. Value types are padded to 32 bytes whereas `string` and `bytes`
+/// keys are used as is.
+pub(crate) fn encode_key(key: &str, key_type: &StorageType) -> Result<Vec<u8>, ArbiterError> {
+    let (encoding, label) = match key_type {
+        StorageType::Simple {
+            encoding, label, ..
+        } => (encoding.as_str(), label.as_str()),
+        StorageType::Mapping { .. } => {
+            return Err(ArbiterError::ArtifactsError(
+                "A mapping cannot be used as a mapping key.".to_string(),
+            ))
+        }
+    };
+    let decode = |key: &str| {
+        hex::decode(key)
+            .map_err(|e| ArbiterError::ArtifactsError(format!("Invalid hex key `{}`: {}", key, e)))
+    };
+
+    if encoding == "bytes" {
+        return match label {
+            "string" => Ok(key.as_bytes().to_vec()),
+            _ => decode(key),
+        };
+    }
+    if !key.starts_with("0x") && (label.starts_with("uint") || label.starts_with("int")) {
+        let value = if label.starts_with("int") {
+            ethers::types::I256::from_dec_str(key)
+                .map_err(|e| ArbiterError::ArtifactsError(e.to_string()))?
+                .into_raw()
+        } else {
+            U256::from_dec_str(key).map_err(|e| ArbiterError::ArtifactsError(e.to_string()))?
+        };
+        let mut encoded = [0_u8; 32];
+        value.to_big_endian(&mut encoded);
+        return Ok(encoded.to_vec());
+    }
+
+    let key_bytes = decode(key)?;
+    if key_bytes.len() > 32 {
+        return Err(ArbiterError::ArtifactsError(format!(
+            "Key `{}` is longer than 32 bytes.",
+            key
+        )));
+    }
+    let padding = vec![0; 32 - key_bytes.len()];
+    // Fixed size byte arrays are left aligned while all other value types are
+    // right aligned.
+    if label.starts_with("bytes") {
+        Ok(key_bytes.into_iter().chain(padding).collect())
+    } else {
+        Ok(padding.into_iter().chain(key_bytes).collect())
+    }
+}
+
+/// The number of slots a value of the given type takes up in storage.
+fn slot_count(storage_type: &StorageType) -> usize {
+    match storage_type {
+        StorageType::Simple {
+            number_of_bytes, ..
+        } => number_of_bytes
+            .parse::<usize>()
+            .map(|bytes| ((bytes + 31) / 32).max(1))
+            .unwrap_or(1),
+        StorageType::Mapping { .. } => 1,
+    }
+}
+
+fn get_type<'a>(
+    types: &'a HashMap<String, StorageType>,
+    type_id: &str,
+) -> Result<&'a StorageType, ArbiterError> {
+    types
+        .get(type_id)
+        .ok_or(ArbiterError::ArtifactsError(format!(
+            "Type `{}` is missing from the storage layout.",
+            type_id
+        )))
+}
@@ -1,25 +1,41 @@
 #![warn(missing_docs)]
 
-use std::{collections::HashMap, env, fs, io::Write, path::Path, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use arbiter_core::environment::fork::*;
 use config::{Config, ConfigError};
 use ethers::{
-    providers::{Http, Provider},
-    types::{Address, BlockId, BlockNumber, U256},
+    abi::Abi,
+    providers::{Http, HttpRateLimitRetryPolicy, Provider, RetryClient, RetryClientBuilder},
+    types::{Address, U256},
     utils::{hex, keccak256},
 };
 use revm::{
-    db::{ethersdb::EthersDB, CacheDB, EmptyDB},
-    Database,
+    db::CacheDB,
+    primitives::{AccountInfo, ExecutionResult, Output, TransactTo},
+    DatabaseRef, EVM,
 };
 use serde::{Deserialize, Serialize};
 
 use super::*;
 
 pub(crate) mod digest;
+pub(crate) mod proxy;
 #[cfg(test)]
 mod tests;
+pub(crate) mod token;
+pub(crate) mod update;
+pub(crate) mod verify;
+
+/// How many times a failed request to the node is retried when the config
+/// does not say otherwise.
+const DEFAULT_RETRIES: u32 = 5;
 
 /// A `ForkConfig` is a d
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -28,8 +44,27 @@ pub(crate) struct ForkConfig {
     output_filename: Option<String>,
     provider: String,
     block_number: u64,
+    /// Additional block numbers to fork at, keyed by the label of the
+    /// snapshot they are written to.
+    #[serde(default)]
+    snapshots: HashMap<String, u64>,
     #[serde(rename = "contracts")]
     contracts_meta: HashMap<String, ContractMetadata>,
+    /// View calls that `arbiter fork verify` replays against both the node and
+    /// the fork file to check that the fork captured enough state.
+    #[serde(default)]
+    verify: Vec<verify::ViewCall>,
+    /// How many times a request to the node that failed, e.g., because it
+    /// timed out or was rate limited, is retried.
+    #[serde(default)]
+    retries: Option<u32>,
+    /// The directory state fetched from the node is cached in, so that forking
+    /// the same block again does not hit the node.
+    #[serde(default)]
+    cache_directory: Option<String>,
+    /// How the `cache_directory` is used. This is set on the command line.
+    #[serde(skip)]
+    cache_policy: CachePolicy,
 }
 
 impl ForkConfig {
@@ -56,46 +91,166 @@ impl ForkConfig {
         Ok(fork_config)
     }
 
-    /// Digests the config file and takes in an `EthersDB` so that the data can
-    /// be fetched from the blockchain.
+    /// Overrides the node, the block, the path the fork is written to, and the
+    /// number of retries given in the config with the ones given on the
+    /// command line.
+    pub(crate) fn override_with(
+        &mut self,
+        rpc_url: Option<String>,
+        block_number: Option<u64>,
+        output: Option<String>,
+        retries: Option<u32>,
+    ) {
+        if let Some(rpc_url) = rpc_url {
+            self.provider = rpc_url;
+        }
+        if let Some(block_number) = block_number {
+            self.block_number = block_number;
+        }
+        if let Some(output) = output {
+            let output = Path::new(&output);
+            if let Some(directory) = output.parent() {
+                self.output_directory = Some(directory.to_string_lossy().into_owned());
+            }
+            if let Some(filename) = output.file_name() {
+                self.output_filename = Some(filename.to_string_lossy().into_owned());
+            }
+        }
+        if retries.is_some() {
+            self.retries = retries;
+        }
+    }
+
+    /// Sets how the cache directory is used, along with the directory itself
+    /// in place of the `cache_directory` in the config. Going offline or
+    /// refreshing the cache needs a cache directory.
+    pub(crate) fn use_cache(
+        &mut self,
+        cache_directory: Option<String>,
+        policy: CachePolicy,
+    ) -> Result<(), ArbiterError> {
+        if cache_directory.is_some() {
+            self.cache_directory = cache_directory;
+        }
+        if policy != CachePolicy::Reuse && self.cache_directory.is_none() {
+            return Err(ArbiterError::ConfigError(ConfigError::Message(
+                "`--offline` and `--refresh` need a `cache_directory` in the config or `--cache-dir`."
+                    .to_string(),
+            )));
+        }
+        self.cache_policy = policy;
+        Ok(())
+    }
+
+    /// Digests the config file and spawns a [`ForkedDb`] so that the data can
+    /// be fetched from the blockchain at the given `block_number`.
     /// Once all the `AccountInfo` for the contracts are fetched, we digest the
     /// contract artifacts to get the storage layout.
-    pub(crate) fn digest_config(&self) -> Result<CacheDB<EmptyDB>, ArbiterError> {
-        // Spawn the `EthersDB` and the `CacheDB` we will write to.
-        let ethers_db = &mut self.spawn_ethers_db()?;
-        let mut db = CacheDB::new(EmptyDB::default());
-        for contract_data in self.contracts_meta.values() {
+    ///
+    /// Contracts that turn out to be EIP-1967 proxies have their
+    /// implementation forked along with them. The returned metadata records
+    /// the proxies that were found and the ABIs of the contracts.
+    pub(crate) fn digest_config(
+        &self,
+        block_number: u64,
+    ) -> Result<(CacheDB<ExternalDb>, HashMap<String, ContractMetadata>), ArbiterError> {
+        // Spawn the `ForkedDb` we fetch with and the `CacheDB` we will write to.
+        let forked_db = self.spawn_forked_db(block_number)?;
+        let mut db = CacheDB::new(ExternalDb::default());
+        let mut contracts_meta = self.contracts_meta.clone();
+        let contracts = contracts_meta.len();
+        for (index, (name, contract_data)) in contracts_meta.iter_mut().enumerate() {
             let address = contract_data.address;
-            let info = ethers_db
-                .basic(address.to_fixed_bytes().into())
-                .map_err(|_| {
-                    ArbiterError::DBError(
-                        "Failed to fetch account info with
-                EthersDB."
-                            .to_string(),
-                    )
-                })?
+            println!(
+                "[{}/{}] Forking contract `{}` at {:?} at block {}.",
+                index + 1,
+                contracts,
+                name,
+                address,
+                block_number
+            );
+            let info = forked_db
+                .basic_ref(address.to_fixed_bytes().into())
+                .map_err(|e| ArbiterError::DBError(format!("Failed to fetch account info: {}", e)))?
                 .ok_or(ArbiterError::DBError(
-                    "Failed to fetch account info with EthersDB.".to_string(),
+                    "Failed to fetch account info.".to_string(),
                 ))?;
+            check_code_hash(name, contract_data, &info)?;
 
             db.insert_account_info(address.to_fixed_bytes().into(), info);
-            let artifacts = digest::digest_artifacts(contract_data.artifacts_path.as_str())?;
+            let artifacts = digest::digest_artifacts(
+                contract_data.artifacts_path.as_str(),
+                contract_data.contract_name.as_deref(),
+            )?;
             let storage_layout = artifacts.storage_layout;
+            contract_data.abi = artifacts.abi;
+
+            digest::create_storage_layout(contract_data, storage_layout, &mut db, &forked_db)?;
 
-            digest::create_storage_layout(contract_data, storage_layout, &mut db, ethers_db)?;
+            contract_data.proxy = proxy::resolve_proxy(address, &mut db, &forked_db)?;
+            match (
+                &mut contract_data.proxy,
+                &contract_data.proxy_artifacts_path,
+            ) {
+                (Some(proxy), Some(path)) => proxy.abi = Some(digest::digest_abi(path)?),
+                (None, Some(_)) => println!(
+                    "Contract `{}` has a `proxy_artifacts_path` but is not an EIP-1967 proxy.",
+                    name
+                ),
+                _ => {}
+            }
+            if let Some(proxy) = &contract_data.proxy {
+                println!(
+                    "Contract `{}` is a {:?} proxy. Forked its implementation at {:?}.",
+                    name, proxy.kind, proxy.implementation
+                );
+            }
+            contract_data.token = token::token_metadata(address, &forked_db)?;
+            if let Some(token) = &contract_data.token {
+                println!(
+                    "Contract `{}` is the ERC-20 token {} ({}) with {} decimals.",
+                    name, token.name, token.symbol, token.decimals
+                );
+            }
+            if let Some(scan) = contract_data.holder_scan {
+                let to_block = scan.to_block.unwrap_or(block_number);
+                let scanned =
+                    self.scanned_holders(address, scan.from_block, to_block, &forked_db)?;
+                println!(
+                    "Found {} holders of `{}` in blocks {} to {}.",
+                    scanned.len(),
+                    name,
+                    scan.from_block,
+                    to_block
+                );
+                let mut known: HashSet<Address> = contract_data.holders.iter().copied().collect();
+                contract_data
+                    .holders
+                    .extend(scanned.into_iter().filter(|holder| known.insert(*holder)));
+            }
+            if !contract_data.holders.is_empty() {
+                println!(
+                    "Forking the balances of {} holders of `{}`.",
+                    contract_data.holders.len(),
+                    name
+                );
+                token::fork_holders(
+                    address,
+                    &contract_data.holders,
+                    &contract_data.spenders,
+                    &mut db,
+                    &forked_db,
+                )?;
+            }
         }
-        Ok(db)
+        Ok((db, contracts_meta))
     }
 
     pub(crate) fn into_fork(self) -> Result<Fork, ArbiterError> {
         // Digest all of the contracts and their storage data listed in the fork config.
-        let db = self.digest_config()?;
+        let (db, contracts_meta) = self.digest_config(self.block_number)?;
 
-        Ok(Fork {
-            db,
-            contracts_meta: self.contracts_meta.clone(),
-        })
+        Ok(Fork { db, contracts_meta })
     }
 
     pub(crate) fn write_to_disk(self, overwrite: &bool) -> Result<(), ArbiterError> {
@@ -103,8 +258,7 @@ impl ForkConfig {
 
         // Check if a file at the output path already exists.
         let dir = self.output_directory.clone().unwrap();
-        let file_path = Path::new(&self.output_directory.clone().unwrap())
-            .join(self.output_filename.clone().unwrap());
+        let file_path = self.output_path();
         if file_path.try_exists().unwrap() && file_path.is_file() {
             if !overwrite {
                 // TODO: We should allow for an overwrite flag here.
@@ -116,21 +270,19 @@ impl ForkConfig {
                 fs::remove_file(&file_path).unwrap();
             }
         }
-        let fork = self.into_fork()?;
-        let mut raw = HashMap::new();
-        for (address, db_account) in fork.db.accounts {
-            let info = db_account.info;
-            let mut storage = HashMap::new();
-            for key in db_account.storage.keys() {
-                let recast_key = key.to_string();
-                let recast_value = db_account.storage.get(key).unwrap().to_string();
-                storage.insert(recast_key, recast_value);
-            }
-            raw.insert(Address::from(address.into_array()), (info, storage));
+        let mut snapshots = HashMap::new();
+        for (label, block_number) in self.snapshots.iter() {
+            println!("Fetching snapshot `{}` at block {}.", label, block_number);
+            let (db, _) = self.digest_config(*block_number)?;
+            snapshots.insert(label.clone(), to_raw(db));
         }
+        let block_number = self.block_number;
+        let fork = self.into_fork()?;
         let disk_data = DiskData {
             meta: fork.contracts_meta,
-            raw,
+            raw: to_raw(fork.db),
+            snapshots,
+            block_number: Some(block_number),
         };
 
         let json_data = serde_json::to_string(&disk_data)?;
@@ -142,17 +294,161 @@ impl ForkConfig {
         Ok(())
     }
 
-    fn spawn_ethers_db(&self) -> Result<EthersDB<Provider<Http>>, ArbiterError> {
-        let ethers_db = EthersDB::new(
-            Arc::new(
-                Provider::<Http>::try_from(self.provider.clone())
-                    .expect("could not instantiate HTTP Provider"),
-            ),
-            Some(BlockId::Number(BlockNumber::Number(
-                self.block_number.into(),
-            ))),
+    /// Writes the metadata digested for each contract, i.e., its ABI and the
+    /// proxy or token it was found to be, next to where the fork is written,
+    /// without writing the fork itself.
+    pub(crate) fn write_digest(self, overwrite: &bool) -> Result<(), ArbiterError> {
+        let file_path = self.output_path().with_extension("digest.json");
+        if file_path.is_file() && !overwrite {
+            return Err(ArbiterError::DBError(format!(
+                "A digest already exists at {:?}. Please use the `--overwrite` flag, delete it, or change the output path.",
+                file_path
+            )));
+        }
+        let (_, contracts_meta) = self.digest_config(self.block_number)?;
+        fs::create_dir_all(self.output_directory.as_deref().unwrap_or("./"))?;
+        fs::write(&file_path, serde_json::to_string_pretty(&contracts_meta)?)?;
+        println!("Wrote the contract digest to {:?}.", file_path);
+        Ok(())
+    }
+
+    /// The path the fork is written to.
+    pub(crate) fn output_path(&self) -> PathBuf {
+        Path::new(self.output_directory.as_deref().unwrap_or("./"))
+            .join(self.output_filename.as_deref().unwrap_or("output.json"))
+    }
+
+    /// Spawns the [`ForkedDb`] state is fetched from the node with at
+    /// `block_number`. Requests that time out or are rate limited are retried
+    /// with a backoff, and fetched state is cached in the cache directory if
+    /// there is one.
+    fn spawn_forked_db(&self, block_number: u64) -> Result<ForkedDb, ArbiterError> {
+        let forked_db = ForkedDb::with_retries(
+            &self.provider,
+            block_number,
+            self.retries.unwrap_or(DEFAULT_RETRIES),
         )
-        .unwrap();
-        Ok(ethers_db)
+        .map_err(|e| ArbiterError::DBError(e.to_string()))?;
+        match &self.cache_directory {
+            Some(directory) => forked_db
+                .cache_dir(directory, self.cache_policy)
+                .map_err(|e| ArbiterError::DBError(e.to_string())),
+            None => Ok(forked_db),
+        }
+    }
+
+    /// Finds the holders of the token at `address` in the `Transfer` logs of
+    /// the blocks from `from_block` to `to_block`. With a cache directory, the
+    /// holders are kept in it so the logs are only scanned once.
+    fn scanned_holders(
+        &self,
+        address: Address,
+        from_block: u64,
+        to_block: u64,
+        forked_db: &ForkedDb,
+    ) -> Result<Vec<Address>, ArbiterError> {
+        let cached = match (&self.cache_directory, forked_db.chain_id()) {
+            (Some(directory), Some(chain_id)) => Some(
+                Path::new(directory)
+                    .join(chain_id.to_string())
+                    .join("holders")
+                    .join(format!("{:?}-{}-{}.json", address, from_block, to_block)),
+            ),
+            _ => None,
+        };
+        if let Some(path) = &cached {
+            if path.is_file() && self.cache_policy != CachePolicy::Refresh {
+                return Ok(serde_json::from_str(&fs::read_to_string(path)?)?);
+            }
+        }
+        if self.cache_policy == CachePolicy::Offline {
+            return Err(ArbiterError::DBError(format!(
+                "The holders of {:?} in blocks {} to {} are not cached and the fork is offline.",
+                address, from_block, to_block
+            )));
+        }
+        let holders = token::scan_holders(address, from_block, to_block, &self.spawn_provider()?)?;
+        if let Some(path) = cached {
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, serde_json::to_string(&holders)?)?;
+        }
+        Ok(holders)
+    }
+
+    /// Connects to the node, retrying requests that time out or are rate
+    /// limited as many times as the config says.
+    fn spawn_provider(&self) -> Result<Provider<RetryClient<Http>>, ArbiterError> {
+        let http = self
+            .provider
+            .parse::<Http>()
+            .map_err(|e| ArbiterError::DBError(format!("Invalid RPC URL: {}", e)))?;
+        let retries = self.retries.unwrap_or(DEFAULT_RETRIES);
+        let client = RetryClientBuilder::default()
+            .rate_limit_retries(retries)
+            .timeout_retries(retries)
+            .initial_backoff(Duration::from_millis(500))
+            .build(http, Box::<HttpRateLimitRetryPolicy>::default());
+        Ok(Provider::new(client))
+    }
+}
+
+/// Fails if a code hash is pinned for the contract `name` and the code in the
+/// fetched `info` does not have that hash.
+fn check_code_hash(
+    name: &str,
+    contract_data: &ContractMetadata,
+    info: &AccountInfo,
+) -> Result<(), ArbiterError> {
+    match contract_data.code_hash {
+        Some(expected) if expected.0 != info.code_hash.0 => Err(ArbiterError::CodeHashMismatch(
+            name.to_string(),
+            expected,
+            info.code_hash.0.into(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Calls the contract at `to` with `data` against the state `forked_db` is
+/// pinned to, without changing it. Returns the output of the call if it
+/// succeeded along with the state the call touched.
+fn call_remote(
+    to: Address,
+    data: Vec<u8>,
+    forked_db: &ForkedDb,
+) -> Result<(Option<Vec<u8>>, CacheDB<ExternalDb>), ArbiterError> {
+    let mut evm = EVM::new();
+    evm.database(CacheDB::new(ExternalDb::Forked(forked_db.clone())));
+    evm.env.block.gas_limit = revm::primitives::U256::MAX;
+    evm.env.tx.transact_to = TransactTo::Call(to.to_fixed_bytes().into());
+    evm.env.tx.data = data.into();
+    let result = evm
+        .transact()
+        .map_err(|e| ArbiterError::DBError(format!("{:?}", e)))?
+        .result;
+    let output = match result {
+        ExecutionResult::Success {
+            output: Output::Call(output),
+            ..
+        } => Some(output.to_vec()),
+        _ => None,
+    };
+    Ok((output, evm.db.take().unwrap()))
+}
+
+/// Converts the accounts in a [`CacheDB`] into the raw format that is written
+/// to disk in [`DiskData`].
+fn to_raw(db: CacheDB<ExternalDb>) -> RawState {
+    let mut raw = HashMap::new();
+    for (address, db_account) in db.accounts {
+        let info = db_account.info;
+        let mut storage = HashMap::new();
+        for key in db_account.storage.keys() {
+            let recast_key = key.to_string();
+            let recast_value = db_account.storage.get(key).unwrap().to_string();
+            storage.insert(recast_key, recast_value);
+        }
+        raw.insert(Address::from(address.into_array()), (info, storage));
     }
+    raw
 }
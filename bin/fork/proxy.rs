@@ -0,0 +1,115 @@
+#![warn(missing_docs)]
+
+use revm::db::AccountState;
+
+use super::*;
+
+/// The slot EIP-1967 proxies keep the address of their implementation in,
+/// i.e., `keccak256("eip1967.proxy.implementation") - 1`.
+pub(crate) const IMPLEMENTATION_SLOT: &str =
+    "360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+
+/// The slot EIP-1967 beacon proxies keep the address of their beacon in,
+/// i.e., `keccak256("eip1967.proxy.beacon") - 1`.
+pub(crate) const BEACON_SLOT: &str =
+    "a3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50";
+
+/// The slot EIP-1967 proxies keep the address of their admin in, i.e.,
+/// `keccak256("eip1967.proxy.admin") - 1`.
+pub(crate) const ADMIN_SLOT: &str =
+    "b53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103";
+
+/// The selector of `implementation()`, which beacons answer with the address
+/// of the implementation.
+const IMPLEMENTATION_SELECTOR: [u8; 4] = [0x5c, 0x60, 0xda, 0x1b];
+
+/// Detects whether the contract at `address` is an EIP-1967 proxy. If it is,
+/// the slots of the proxy, the code of its implementation, and the state its
+/// beacon needs to answer with the implementation are forked into `db`.
+pub(crate) fn resolve_proxy(
+    address: Address,
+    db: &mut CacheDB<ExternalDb>,
+    forked_db: &ForkedDb,
+) -> Result<Option<ProxyMetadata>, ArbiterError> {
+    let recast_address: revm::primitives::Address = address.to_fixed_bytes().into();
+    let mut read_slot = |slot: &str| -> Result<Option<Address>, ArbiterError> {
+        let slot = revm::primitives::U256::from_str_radix(slot, 16)
+            .map_err(|e| ArbiterError::DBError(e.to_string()))?;
+        let value = forked_db
+            .storage_ref(recast_address, slot)
+            .map_err(|e| ArbiterError::DBError(format!("Failed to fetch storage: {}", e)))?;
+        if value == revm::primitives::U256::ZERO {
+            return Ok(None);
+        }
+        db.insert_account_storage(recast_address, slot, value)
+            .map_err(|e| ArbiterError::DBError(format!("{:?}", e)))?;
+        Ok(Some(slot_to_address(value)))
+    };
+    let implementation = read_slot(IMPLEMENTATION_SLOT)?;
+    let beacon = read_slot(BEACON_SLOT)?;
+    let admin = read_slot(ADMIN_SLOT)?;
+
+    let (kind, implementation) = match (beacon, implementation) {
+        (Some(beacon), _) => (
+            ProxyKind::Beacon,
+            beacon_implementation(beacon, db, forked_db)?,
+        ),
+        (None, Some(implementation)) if admin.is_some() => (ProxyKind::Transparent, implementation),
+        (None, Some(implementation)) => (ProxyKind::Uups, implementation),
+        (None, None) => return Ok(None),
+    };
+    let info = forked_db
+        .basic_ref(implementation.to_fixed_bytes().into())
+        .map_err(|e| ArbiterError::DBError(format!("Failed to fetch account info: {}", e)))?
+        .ok_or(ArbiterError::DBError(format!(
+            "The implementation {:?} of the proxy at {:?} does not exist.",
+            implementation, address
+        )))?;
+    db.insert_account_info(implementation.to_fixed_bytes().into(), info);
+
+    Ok(Some(ProxyMetadata {
+        kind,
+        implementation,
+        beacon,
+        admin,
+        abi: None,
+    }))
+}
+
+/// Asks the `beacon` for its implementation by calling `implementation()` on
+/// it. Whatever state the call touches is forked into `db` so that the proxy
+/// gets the same answer in a simulation.
+fn beacon_implementation(
+    beacon: Address,
+    db: &mut CacheDB<ExternalDb>,
+    forked_db: &ForkedDb,
+) -> Result<Address, ArbiterError> {
+    let (output, touched) = call_remote(beacon, IMPLEMENTATION_SELECTOR.to_vec(), forked_db)?;
+    let implementation = match output {
+        Some(output) if output.len() == 32 => Address::from_slice(&output[12..]),
+        _ => {
+            return Err(ArbiterError::DBError(format!(
+                "The beacon at {:?} did not return an implementation.",
+                beacon
+            )))
+        }
+    };
+    for (address, account) in touched.accounts {
+        if address == revm::primitives::Address::ZERO
+            || matches!(account.account_state, AccountState::NotExisting)
+        {
+            continue;
+        }
+        db.insert_account_info(address, account.info);
+        for (slot, value) in account.storage {
+            db.insert_account_storage(address, slot, value)
+                .map_err(|e| ArbiterError::DBError(format!("{:?}", e)))?;
+        }
+    }
+    Ok(implementation)
+}
+
+/// The address held in the lower 20 bytes of a storage slot.
+pub(crate) fn slot_to_address(value: revm::primitives::U256) -> Address {
+    Address::from_slice(&value.to_be_bytes::<32>()[12..])
+}
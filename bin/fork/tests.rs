@@ -1,4 +1,5 @@
 use arbiter_core::environment::fork::Fork;
+use ethers::types::H256;
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 
 use super::*;
@@ -52,3 +53,281 @@ fn read_in() {
     });
     fs::remove_file(PATH_TO_DISK_STORAGE).unwrap();
 }
+
+#[test]
+fn resolve_mapping_slots() {
+    let artifacts = digest::digest_artifacts("example_fork/WETH.json", None).unwrap();
+    let types = artifacts.storage_layout.types;
+
+    let balance_of = digest::resolve_mapping_slots(
+        "0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045",
+        revm::primitives::U256::from(3),
+        "t_mapping(t_address,t_uint256)",
+        &types,
+    )
+    .unwrap();
+    assert_eq!(
+        balance_of,
+        vec![revm::primitives::U256::from_str_radix(
+            "3a988d762a24303c37d08f1543db6143453b579691d5c20fed39629ff1334cca",
+            16
+        )
+        .unwrap()]
+    );
+
+    let allowance = digest::resolve_mapping_slots(
+        "0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045, 0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2",
+        revm::primitives::U256::from(4),
+        "t_mapping(t_address,t_mapping(t_address,t_uint256))",
+        &types,
+    )
+    .unwrap();
+    assert_eq!(
+        allowance,
+        vec![revm::primitives::U256::from_str_radix(
+            "81c73bca26f0f5035e5641f79b632216fd7e2c241d148c4b46f3113072df671b",
+            16
+        )
+        .unwrap()]
+    );
+
+    // A nested mapping needs a key for every level.
+    assert!(digest::resolve_mapping_slots(
+        "0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045",
+        revm::primitives::U256::from(4),
+        "t_mapping(t_address,t_mapping(t_address,t_uint256))",
+        &types,
+    )
+    .is_err());
+}
+
+#[test]
+fn check_code_hash() {
+    let code = revm::primitives::Bytecode::new_raw(vec![0x60, 0x00, 0x60, 0x00, 0xf3].into());
+    let info = AccountInfo {
+        code_hash: code.hash_slow(),
+        code: Some(code),
+        ..Default::default()
+    };
+    let mut contract_data = ContractMetadata {
+        address: Address::zero(),
+        artifacts_path: "example_fork/WETH.json".to_string(),
+        contract_name: None,
+        mappings: HashMap::new(),
+        code_hash: None,
+        proxy_artifacts_path: None,
+        abi: None,
+        proxy: None,
+        token: None,
+        holders: vec![],
+        spenders: vec![],
+        holder_scan: None,
+    };
+    // Nothing is checked unless a code hash is pinned.
+    assert!(super::check_code_hash("weth", &contract_data, &info).is_ok());
+
+    contract_data.code_hash = Some(info.code_hash.0.into());
+    assert!(super::check_code_hash("weth", &contract_data, &info).is_ok());
+
+    contract_data.code_hash = Some(ethers::types::H256::from_low_u64_be(1));
+    assert!(matches!(
+        super::check_code_hash("weth", &contract_data, &info),
+        Err(ArbiterError::CodeHashMismatch(name, ..)) if name == "weth"
+    ));
+}
+
+#[test]
+fn eip1967_slots() {
+    for (slot, label) in [
+        (proxy::IMPLEMENTATION_SLOT, "eip1967.proxy.implementation"),
+        (proxy::BEACON_SLOT, "eip1967.proxy.beacon"),
+        (proxy::ADMIN_SLOT, "eip1967.proxy.admin"),
+    ] {
+        let expected = U256::from(keccak256(label)) - U256::one();
+        assert_eq!(U256::from_str_radix(slot, 16).unwrap(), expected);
+    }
+
+    let value = revm::primitives::U256::from_str_radix(
+        "000000000000000000000000c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2",
+        16,
+    )
+    .unwrap();
+    assert_eq!(
+        proxy::slot_to_address(value),
+        "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2"
+            .parse::<Address>()
+            .unwrap()
+    );
+}
+
+#[test]
+fn register_proxy_abis() {
+    let abi = digest::digest_abi("example_fork/WETH.json").unwrap();
+    let proxy_address = Address::from_low_u64_be(1);
+    let implementation = Address::from_low_u64_be(2);
+    let mut proxy_abi = Abi::default();
+    proxy_abi.errors.insert(
+        "Unauthorized".to_string(),
+        vec![ethers::abi::ethabi::AbiError {
+            name: "Unauthorized".to_string(),
+            inputs: vec![],
+        }],
+    );
+    let contract_data = ContractMetadata {
+        address: proxy_address,
+        artifacts_path: "example_fork/WETH.json".to_string(),
+        contract_name: None,
+        mappings: HashMap::new(),
+        code_hash: None,
+        proxy_artifacts_path: None,
+        abi: Some(abi.clone()),
+        proxy: Some(ProxyMetadata {
+            kind: ProxyKind::Uups,
+            implementation,
+            beacon: None,
+            admin: None,
+            abi: Some(proxy_abi),
+        }),
+        token: None,
+        holders: vec![],
+        spenders: vec![],
+        holder_scan: None,
+    };
+    let fork = Fork {
+        db: CacheDB::new(ExternalDb::default()),
+        contracts_meta: HashMap::from([("weth".to_string(), contract_data)]),
+    };
+    let registry = arbiter_core::environment::abi_registry::AbiRegistry::default();
+    fork.register_abis(&registry);
+
+    // The implementation is registered with its own ABI and the proxy with
+    // both.
+    assert_eq!(registry.get(implementation), Some(abi.clone()));
+    let merged = registry.get(proxy_address).unwrap();
+    assert_eq!(merged.functions, abi.functions);
+    assert!(merged.errors.contains_key("Unauthorized"));
+}
+
+#[test]
+fn decode_token_metadata() {
+    let string = ethers::abi::encode(&[ethers::abi::Token::String("Wrapped Ether".to_string())]);
+    assert_eq!(
+        token::decode_string(&string),
+        Some("Wrapped Ether".to_string())
+    );
+    // Some older tokens return a `bytes32`.
+    let mut bytes32 = b"MKR".to_vec();
+    bytes32.resize(32, 0);
+    assert_eq!(token::decode_string(&bytes32), Some("MKR".to_string()));
+    assert_eq!(token::decode_string(&[0; 32]), None);
+
+    let decimals = ethers::abi::encode(&[ethers::abi::Token::Uint(U256::from(18))]);
+    assert_eq!(token::decode_decimals(&decimals), Some(18));
+    let too_many = ethers::abi::encode(&[ethers::abi::Token::Uint(U256::from(256))]);
+    assert_eq!(token::decode_decimals(&too_many), None);
+}
+
+#[test]
+fn replay_view_call_against_fork() {
+    let view_call = verify::ViewCall {
+        contract: "weth".to_string(),
+        function: "balanceOf(address) returns (uint256)".to_string(),
+        args: vec!["0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045".to_string()],
+    };
+    let (function, data) = view_call.encode().unwrap();
+    assert_eq!(function.name, "balanceOf");
+    assert_eq!(data[..4], [0x70, 0xa0, 0x82, 0x31]);
+
+    let fork = Fork::from_disk("example_fork/fork_into_test.json").unwrap();
+    let to = view_call.address(&fork.contracts_meta).unwrap();
+    let output = verify::call_fork(&fork, to, data, 18228556)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        U256::from_big_endian(&output),
+        U256::from_dec_str("34890707020710109111").unwrap()
+    );
+
+    // Calls with the wrong number of arguments or an unknown contract fail.
+    let view_call = verify::ViewCall {
+        args: vec![],
+        ..view_call
+    };
+    assert!(view_call.encode().is_err());
+    let view_call = verify::ViewCall {
+        contract: "dai".to_string(),
+        ..view_call
+    };
+    assert!(view_call.address(&fork.contracts_meta).is_err());
+}
+
+#[test]
+fn merge_updated_raw_state() {
+    let proxy = Address::from_low_u64_be(1);
+    let implementation = Address::from_low_u64_be(2);
+    let proxy_info = AccountInfo {
+        nonce: 1,
+        ..Default::default()
+    };
+    let mut raw = RawState::from([(
+        proxy,
+        (
+            proxy_info.clone(),
+            Storage::from([("0".to_string(), "1".to_string())]),
+        ),
+    )]);
+    // Resolving a proxy again only touches its slots and forks the new
+    // implementation.
+    let resolved = RawState::from([
+        (
+            proxy,
+            (
+                AccountInfo::default(),
+                Storage::from([("1".to_string(), "2".to_string())]),
+            ),
+        ),
+        (implementation, (AccountInfo::default(), Storage::new())),
+    ]);
+    update::merge_raw(&mut raw, resolved);
+
+    assert_eq!(raw.len(), 2);
+    assert_eq!(raw[&proxy].0, proxy_info);
+    assert_eq!(raw[&proxy].1.len(), 2);
+    assert!(raw.contains_key(&implementation));
+}
+
+#[test]
+fn holders_from_transfer_logs() {
+    let transfer = H256::from(keccak256("Transfer(address,address,uint256)"));
+    let alice = Address::from_low_u64_be(1);
+    let bob = Address::from_low_u64_be(2);
+    let log = |topics: Vec<H256>| ethers::types::Log {
+        topics,
+        ..Default::default()
+    };
+    let logs = [
+        // A mint to alice.
+        log(vec![transfer, H256::zero(), alice.into()]),
+        log(vec![transfer, alice.into(), bob.into()]),
+        log(vec![transfer, bob.into(), alice.into()]),
+        // An ERC-721 transfer, which also indexes the id of the token.
+        log(vec![
+            transfer,
+            alice.into(),
+            Address::from_low_u64_be(3).into(),
+            H256::from_low_u64_be(7),
+        ]),
+    ];
+    assert_eq!(token::holders_from_logs(&logs), vec![alice, bob]);
+}
+
+#[test]
+fn offline_needs_cache_directory() {
+    let mut fork_config = ForkConfig::new(FORK_CONFIG_PATH).unwrap();
+    assert!(fork_config.use_cache(None, CachePolicy::Reuse).is_ok());
+    assert!(fork_config.use_cache(None, CachePolicy::Offline).is_err());
+    assert!(fork_config
+        .use_cache(Some("example_fork/cache".to_string()), CachePolicy::Offline)
+        .is_ok());
+    assert_eq!(fork_config.cache_policy, CachePolicy::Offline);
+}
@@ -0,0 +1,177 @@
+#![warn(missing_docs)]
+
+use ethers::{
+    abi::{decode, encode, ParamType, Token},
+    providers::Middleware,
+    types::{Filter, Log, H256},
+};
+
+use super::*;
+
+/// The selector of `name()`.
+const NAME_SELECTOR: [u8; 4] = [0x06, 0xfd, 0xde, 0x03];
+
+/// The selector of `symbol()`.
+const SYMBOL_SELECTOR: [u8; 4] = [0x95, 0xd8, 0x9b, 0x41];
+
+/// The selector of `decimals()`.
+const DECIMALS_SELECTOR: [u8; 4] = [0x31, 0x3c, 0xe5, 0x67];
+
+/// The selector of `balanceOf(address)`.
+const BALANCE_OF_SELECTOR: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+
+/// The selector of `allowance(address,address)`.
+const ALLOWANCE_SELECTOR: [u8; 4] = [0xdd, 0x62, 0xed, 0x3e];
+
+/// The most blocks whose logs are asked for in a single request when scanning
+/// for holders, which keeps the requests within the limits of most nodes.
+const LOG_SCAN_CHUNK: u64 = 2_000;
+
+/// Asks the contract at `address` for its ERC-20 metadata. The contract is
+/// taken to be a token if it answers both `symbol()` and `decimals()`.
+pub(crate) fn token_metadata(
+    address: Address,
+    forked_db: &ForkedDb,
+) -> Result<Option<TokenMetadata>, ArbiterError> {
+    let call = |selector: [u8; 4]| -> Result<Option<Vec<u8>>, ArbiterError> {
+        Ok(call_remote(address, selector.to_vec(), forked_db)?.0)
+    };
+    let Some(decimals) = call(DECIMALS_SELECTOR)?.and_then(|output| decode_decimals(&output))
+    else {
+        return Ok(None);
+    };
+    let Some(symbol) = call(SYMBOL_SELECTOR)?.and_then(|output| decode_string(&output)) else {
+        return Ok(None);
+    };
+    let name = call(NAME_SELECTOR)?
+        .and_then(|output| decode_string(&output))
+        .unwrap_or_else(|| symbol.clone());
+    Ok(Some(TokenMetadata {
+        name,
+        symbol,
+        decimals,
+    }))
+}
+
+/// Decodes the output of `name()` or `symbol()`, which is a `string` for most
+/// tokens and a `bytes32` for some older ones such as MKR.
+pub(crate) fn decode_string(output: &[u8]) -> Option<String> {
+    if let Ok(mut tokens) = decode(&[ParamType::String], output) {
+        if let Some(Token::String(string)) = tokens.pop() {
+            return Some(string);
+        }
+    }
+    if output.len() != 32 {
+        return None;
+    }
+    let end = output.iter().position(|byte| *byte == 0).unwrap_or(32);
+    String::from_utf8(output[..end].to_vec())
+        .ok()
+        .filter(|string| !string.is_empty())
+}
+
+/// Decodes the output of `decimals()`.
+pub(crate) fn decode_decimals(output: &[u8]) -> Option<u8> {
+    match decode(&[ParamType::Uint(8)], output).ok()?.pop()? {
+        Token::Uint(decimals) if decimals <= U256::from(u8::MAX) => Some(decimals.as_u32() as u8),
+        _ => None,
+    }
+}
+
+/// Forks the balances of the `holders` of the token at `address`, and the
+/// allowances they have given each of the `spenders`, into `db`. The slots
+/// are found by calling `balanceOf` and `allowance` and keeping the storage
+/// the calls read, so they are found whatever the layout of the token, e.g.,
+/// behind a proxy.
+pub(crate) fn fork_holders(
+    address: Address,
+    holders: &[Address],
+    spenders: &[Address],
+    db: &mut CacheDB<ExternalDb>,
+    forked_db: &ForkedDb,
+) -> Result<(), ArbiterError> {
+    for holder in holders {
+        let mut data = BALANCE_OF_SELECTOR.to_vec();
+        data.extend(encode(&[Token::Address(*holder)]));
+        merge_storage(call_remote(address, data, forked_db)?.1, db)?;
+        for spender in spenders {
+            let mut data = ALLOWANCE_SELECTOR.to_vec();
+            data.extend(encode(&[Token::Address(*holder), Token::Address(*spender)]));
+            merge_storage(call_remote(address, data, forked_db)?.1, db)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies the storage read by a call into `db`, along with the accounts it
+/// belongs to if they are not in `db` yet.
+fn merge_storage(
+    touched: CacheDB<ExternalDb>,
+    db: &mut CacheDB<ExternalDb>,
+) -> Result<(), ArbiterError> {
+    for (address, account) in touched.accounts {
+        if account.storage.is_empty() {
+            continue;
+        }
+        if !db.accounts.contains_key(&address) {
+            db.insert_account_info(address, account.info);
+        }
+        for (slot, value) in account.storage {
+            db.insert_account_storage(address, slot, value)
+                .map_err(|e| ArbiterError::DBError(format!("{:?}", e)))?;
+        }
+    }
+    Ok(())
+}
+
+/// Scans the `Transfer` logs of the token at `address` in the blocks from
+/// `from_block` to `to_block` for its holders.
+pub(crate) fn scan_holders<M: Middleware>(
+    address: Address,
+    from_block: u64,
+    to_block: u64,
+    provider: &M,
+) -> Result<Vec<Address>, ArbiterError> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let topic = H256::from(keccak256("Transfer(address,address,uint256)"));
+    let mut logs = vec![];
+    let mut start = from_block;
+    while start <= to_block {
+        let end = to_block.min(start.saturating_add(LOG_SCAN_CHUNK - 1));
+        let filter = Filter::new()
+            .address(address)
+            .topic0(topic)
+            .from_block(start)
+            .to_block(end);
+        logs.extend(
+            runtime
+                .block_on(provider.get_logs(&filter))
+                .map_err(|e| ArbiterError::DBError(e.to_string()))?,
+        );
+        start = end + 1;
+    }
+    Ok(holders_from_logs(&logs))
+}
+
+/// Collects the senders and recipients of the `Transfer` logs of a token in
+/// the order they first appear. Mints and burns leave out the zero address.
+pub(crate) fn holders_from_logs(logs: &[Log]) -> Vec<Address> {
+    let mut holders = vec![];
+    let mut seen = HashSet::new();
+    for log in logs {
+        // Both the sender and the recipient are indexed in an ERC-20
+        // `Transfer`, unlike in an ERC-721 one, which also indexes the id.
+        if log.topics.len() != 3 {
+            continue;
+        }
+        for topic in &log.topics[1..] {
+            let holder = Address::from(*topic);
+            if !holder.is_zero() && seen.insert(holder) {
+                holders.push(holder);
+            }
+        }
+    }
+    holders
+}
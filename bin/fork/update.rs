@@ -0,0 +1,112 @@
+#![warn(missing_docs)]
+
+use super::*;
+
+impl ForkConfig {
+    /// Moves the fork written out for this config to `block_number`, or to the
+    /// block in the config if none is given, without digesting the config
+    /// again. Only the accounts and storage slots that
+    /// are already in the fork are fetched, and the ones that changed are
+    /// rewritten. Proxies are resolved again so that an upgraded proxy has
+    /// its new implementation forked along with it. Snapshots are left as
+    /// they are.
+    pub(crate) fn update(&self, block_number: Option<u64>) -> Result<(), ArbiterError> {
+        let block_number = block_number.unwrap_or(self.block_number);
+        let file_path = self.output_path();
+        if !file_path.is_file() {
+            return Err(ArbiterError::DBError(format!(
+                "No fork file at {:?} to update. Run `arbiter fork` first.",
+                file_path
+            )));
+        }
+        let mut disk_data: DiskData = serde_json::from_str(&fs::read_to_string(&file_path)?)?;
+        if disk_data.block_number == Some(block_number) {
+            println!("Fork is already at block {}.", block_number);
+            return Ok(());
+        }
+
+        let forked_db = self.spawn_forked_db(block_number)?;
+        let mut changed_accounts = 0;
+        let mut changed_slots = 0;
+        for (address, (info, storage)) in disk_data.raw.iter_mut() {
+            let recast_address: revm::primitives::Address = address.to_fixed_bytes().into();
+            let fetched = forked_db
+                .basic_ref(recast_address)
+                .map_err(|e| ArbiterError::DBError(format!("Failed to fetch account info: {}", e)))?
+                .unwrap_or_default();
+            if fetched.balance != info.balance
+                || fetched.nonce != info.nonce
+                || fetched.code_hash != info.code_hash
+            {
+                changed_accounts += 1;
+                *info = fetched;
+            }
+            for (slot, value) in storage.iter_mut() {
+                let recast_slot = revm::primitives::U256::from_str_radix(slot, 10)
+                    .map_err(|e| ArbiterError::DBError(e.to_string()))?;
+                let fetched = forked_db
+                    .storage_ref(recast_address, recast_slot)
+                    .map_err(|e| ArbiterError::DBError(format!("Failed to fetch storage: {}", e)))?
+                    .to_string();
+                if *value != fetched {
+                    changed_slots += 1;
+                    *value = fetched;
+                }
+            }
+        }
+
+        // A proxy may have been upgraded, in which case its new implementation is not
+        // in the fork yet.
+        let mut db = CacheDB::new(ExternalDb::default());
+        for (name, contract_data) in disk_data.meta.iter_mut() {
+            if contract_data.proxy.is_none() {
+                continue;
+            }
+            let proxy = proxy::resolve_proxy(contract_data.address, &mut db, &forked_db)?;
+            if let (Some(old), Some(new)) = (&contract_data.proxy, &proxy) {
+                if old.implementation != new.implementation {
+                    println!(
+                        "Proxy `{}` was upgraded from {:?} to {:?}. Point its `artifacts_path` at the new implementation if its ABI changed.",
+                        name, old.implementation, new.implementation
+                    );
+                }
+            }
+            contract_data.proxy = proxy.map(|proxy| ProxyMetadata {
+                abi: contract_data
+                    .proxy
+                    .as_ref()
+                    .and_then(|proxy| proxy.abi.clone()),
+                ..proxy
+            });
+        }
+        merge_raw(&mut disk_data.raw, to_raw(db));
+
+        println!(
+            "Updated fork from block {} to block {}: {} accounts and {} storage slots changed.",
+            disk_data
+                .block_number
+                .map(|block_number| block_number.to_string())
+                .unwrap_or_else(|| "unknown".to_string()),
+            block_number,
+            changed_accounts,
+            changed_slots
+        );
+        disk_data.block_number = Some(block_number);
+        fs::write(&file_path, serde_json::to_string(&disk_data)?)?;
+        Ok(())
+    }
+}
+
+/// Merges the accounts in `other` into `raw`. Storage slots in `other` take
+/// precedence while the account info in `raw` is only filled in for accounts
+/// that are new to it.
+pub(crate) fn merge_raw(raw: &mut RawState, other: RawState) {
+    for (address, (info, storage)) in other {
+        match raw.get_mut(&address) {
+            Some((_, existing)) => existing.extend(storage),
+            None => {
+                raw.insert(address, (info, storage));
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+#![warn(missing_docs)]
+
+use ethers::abi::{
+    ethabi::token::{LenientTokenizer, Tokenizer},
+    AbiParser, Function,
+};
+
+use super::*;
+
+/// A view call listed under `[[verify]]` in a fork config that is replayed
+/// against both the node and the fork file by `arbiter fork verify`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct ViewCall {
+    /// The label of a contract in the config or an address.
+    pub(crate) contract: String,
+
+    /// The signature of the function to call, e.g., `balanceOf(address)` or
+    /// `getReserves() returns (uint112, uint112, uint32)`. Return types are
+    /// only used to render the results.
+    pub(crate) function: String,
+
+    /// The arguments of the call, written the way `cast` takes them.
+    #[serde(default)]
+    pub(crate) args: Vec<String>,
+}
+
+impl ViewCall {
+    /// Parses the function of the call and encodes its arguments into
+    /// calldata.
+    pub(crate) fn encode(&self) -> Result<(Function, Vec<u8>), ArbiterError> {
+        let function = AbiParser::default()
+            .parse_function(&self.function)
+            .map_err(|e| ArbiterError::VerifyError(format!("`{}`: {}", self.function, e)))?;
+        if function.inputs.len() != self.args.len() {
+            return Err(ArbiterError::VerifyError(format!(
+                "`{}` takes {} arguments but {} were given.",
+                self.function,
+                function.inputs.len(),
+                self.args.len()
+            )));
+        }
+        let tokens = function
+            .inputs
+            .iter()
+            .zip(&self.args)
+            .map(|(input, arg)| LenientTokenizer::tokenize(&input.kind, arg))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ArbiterError::VerifyError(format!("`{}`: {}", self.function, e)))?;
+        let data = function
+            .encode_input(&tokens)
+            .map_err(|e| ArbiterError::VerifyError(format!("`{}`: {}", self.function, e)))?;
+        Ok((function, data))
+    }
+
+    /// The address of the contract that is called, looking its label up in
+    /// `contracts_meta`.
+    pub(crate) fn address(
+        &self,
+        contracts_meta: &HashMap<String, ContractMetadata>,
+    ) -> Result<Address, ArbiterError> {
+        match contracts_meta.get(&self.contract) {
+            Some(contract_data) => Ok(contract_data.address),
+            None => self.contract.parse().map_err(|_| {
+                ArbiterError::VerifyError(format!(
+                    "`{}` is neither a contract in the config nor an address.",
+                    self.contract
+                ))
+            }),
+        }
+    }
+}
+
+impl ForkConfig {
+    /// Replays the view calls listed under `[[verify]]` against the node at
+    /// the config's block and against the fork file written out for it, and
+    /// reports every call whose results differ.
+    pub(crate) fn verify(&self) -> Result<(), ArbiterError> {
+        if self.verify.is_empty() {
+            return Err(ArbiterError::VerifyError(
+                "No view calls are listed under `[[verify]]` in the config.".to_string(),
+            ));
+        }
+        let file_path = self.output_path();
+        if !file_path.is_file() {
+            return Err(ArbiterError::VerifyError(format!(
+                "No fork file at {:?}. Run `arbiter fork` first.",
+                file_path
+            )));
+        }
+        let fork = Fork::from_disk(file_path.to_str().unwrap())
+            .map_err(|e| ArbiterError::VerifyError(e.to_string()))?;
+        let forked_db = ForkedDb::new(&self.provider, self.block_number)
+            .map_err(|e| ArbiterError::DBError(e.to_string()))?;
+
+        let mut mismatches = 0;
+        for view_call in &self.verify {
+            let (function, data) = view_call.encode()?;
+            let to = view_call.address(&fork.contracts_meta)?;
+            let live = call_remote(to, data.clone(), &forked_db)?.0;
+            let captured = call_fork(&fork, to, data, self.block_number)?;
+            let label = format!(
+                "{}.{}({})",
+                view_call.contract,
+                function.name,
+                view_call.args.join(", ")
+            );
+            if live == captured {
+                println!("ok       {} = {}", label, render_output(&function, &live));
+            } else {
+                mismatches += 1;
+                println!("MISMATCH {}", label);
+                println!("    node: {}", render_output(&function, &live));
+                println!("    fork: {}", render_output(&function, &captured));
+            }
+        }
+        match mismatches {
+            0 => {
+                println!(
+                    "All {} view calls match the node at block {}.",
+                    self.verify.len(),
+                    self.block_number
+                );
+                Ok(())
+            }
+            _ => Err(ArbiterError::VerifyError(format!(
+                "{} of {} view calls differ from the node. The fork may be missing storage, e.g., mapping keys or a proxy's implementation.",
+                mismatches,
+                self.verify.len()
+            ))),
+        }
+    }
+}
+
+/// Calls the contract at `to` with `data` against the state in the `fork`.
+/// Returns the output of the call if it succeeded.
+pub(crate) fn call_fork(
+    fork: &Fork,
+    to: Address,
+    data: Vec<u8>,
+    block_number: u64,
+) -> Result<Option<Vec<u8>>, ArbiterError> {
+    let mut evm = EVM::new();
+    evm.database(fork.db.clone());
+    evm.env.block.gas_limit = revm::primitives::U256::MAX;
+    evm.env.block.number = revm::primitives::U256::from(block_number);
+    evm.env.tx.transact_to = TransactTo::Call(to.to_fixed_bytes().into());
+    evm.env.tx.data = data.into();
+    let result = evm
+        .transact()
+        .map_err(|e| ArbiterError::DBError(format!("{:?}", e)))?
+        .result;
+    Ok(match result {
+        ExecutionResult::Success {
+            output: Output::Call(output),
+            ..
+        } => Some(output.to_vec()),
+        _ => None,
+    })
+}
+
+/// Renders the output of a call, decoded with the return types of the
+/// `function` if it has any.
+fn render_output(function: &Function, output: &Option<Vec<u8>>) -> String {
+    let Some(output) = output else {
+        return "reverted".to_string();
+    };
+    match function.decode_output(output) {
+        Ok(tokens) if !function.outputs.is_empty() => format!(
+            "({})",
+            tokens
+                .iter()
+                .map(|token| token.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        _ => format!("0x{}", hex::encode(output)),
+    }
+}
@@ -2,12 +2,19 @@ use std::{env, fs, io, process::Command};
 
 use crate::ArbiterError;
 
+/// The repository `arbiter init` fetches templates from unless another one is
+/// given.
+pub(crate) const DEFAULT_TEMPLATE_REPOSITORY: &str =
+    "https://github.com/primitivefinance/arbiter-template.git";
+
 /// Initializes a new Arbiter project from a template.
 ///
 /// This function does the following:
-/// 1. Clones the `arbiter-template` from GitHub into a new directory named
-///    after the provided project name.
-/// Template link is here https://github.com/primitivefinance/arbiter-template
+/// 1. Clones the template from a git repository into a new directory named
+///    after the provided project name. Each named template is a branch of
+///    the repository, and its default branch is used if no template is named.
+///    The default repository is
+///    https://github.com/primitivefinance/arbiter-template
 /// 2. Changes the current directory to the cloned project.
 /// 3. Executes the `forge install` command.
 ///
@@ -19,6 +26,8 @@ use crate::ArbiterError;
 /// * `name` - The name of the new project. This will also be the name of the
 ///   directory
 /// where the project is initialized.
+/// * `template` - The name of the template, e.g., `uniswap-arbitrage`.
+/// * `repository` - The git repository the template is fetched from.
 ///
 /// # Returns
 ///
@@ -26,21 +35,27 @@ use crate::ArbiterError;
 /// initialization. Failure can be due to reasons like:
 /// - Network issues or repository being unavailable leading to git clone
 ///   failure.
+/// - The template not being a branch of the repository.
 /// - The `forge install` command failing.
 
-pub(crate) fn init_project(name: &str) -> io::Result<()> {
-    let status = Command::new("git")
-        .arg("clone")
-        .arg("https://github.com/primitivefinance/arbiter-template.git")
-        .arg(name)
-        .status()?;
+pub(crate) fn init_project(name: &str, template: Option<&str>, repository: &str) -> io::Result<()> {
+    let mut clone = Command::new("git");
+    clone.arg("clone");
+    if let Some(template) = template {
+        clone.arg("--branch").arg(template).arg("--single-branch");
+    }
+    let status = clone.arg(repository).arg(name).status()?;
 
     if !status.success() {
-        println!("Failed to clone the repository.");
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            "Failed to clone the repository.",
-        ));
+        let message = match template {
+            Some(template) => format!(
+                "Failed to clone the template `{}` from {}, is it a branch of the repository?",
+                template, repository
+            ),
+            None => format!("Failed to clone the template from {}.", repository),
+        };
+        println!("{}", message);
+        return Err(io::Error::new(io::ErrorKind::Other, message));
     }
 
     env::set_current_dir(name)?;
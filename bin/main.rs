@@ -12,11 +12,13 @@
 //!   simulations.
 //! - Contract Bindings: Generate necessary bindings for interfacing with
 //!   different contracts.
+//! - Simulation Runs: Run a simulation declared in a TOML config.
 //!
 //!
 //! This CLI leverages the power of Rust's type system to
 //! offer fast and reliable operations, ensuring data integrity and ease of use.
 
+use arbiter_core::environment::fork::CachePolicy;
 use clap::{command, CommandFactory, Parser, Subcommand};
 use thiserror::Error;
 
@@ -25,6 +27,7 @@ use crate::fork::ForkConfig;
 mod bind;
 mod fork;
 mod init;
+mod run;
 
 /// Represents command-line arguments passed to the `Arbiter` tool.
 #[derive(Parser)]
@@ -63,13 +66,45 @@ pub enum ArbiterError {
     /// Indicates an error occurred with a database.
     #[error("Error with DB: {0}")]
     DBError(String),
+
+    /// Indicates an error occurred while reading contract artifacts or
+    /// resolving their storage layout.
+    #[error("Error with artifacts: {0}")]
+    ArtifactsError(String),
+
+    /// Indicates that the code fetched for a contract does not match the code
+    /// hash pinned for it in the fork config.
+    #[error("Code hash mismatch for contract `{0}`: expected {1:?} but fetched {2:?}. The contract may have changed since the config was written.")]
+    CodeHashMismatch(String, ethers::types::H256, ethers::types::H256),
+
+    /// Indicates that the view calls replayed by `arbiter fork verify` could
+    /// not be made or did not match between the node and the fork.
+    #[error("Error verifying fork: {0}")]
+    VerifyError(String),
+
+    /// Indicates that a simulation run by `arbiter run` failed.
+    #[error("Error running simulation: {0}")]
+    SimulationError(String),
 }
 
 /// Defines available subcommands for the `Arbiter` tool.
 #[derive(Subcommand)]
 enum Commands {
     /// Represents the `Bind` subcommand.
-    Bind,
+    Bind {
+        /// Flag to also regenerate the artifacts of the contracts embedded in
+        /// `arbiter-core` from the compiled contracts in `out/`.
+        #[clap(long)]
+        artifacts: bool,
+        /// Only keeps the bindings of the contracts matching one of these glob
+        /// patterns, e.g., `UniswapV2*`. Can be given more than once.
+        #[clap(long)]
+        include: Vec<String>,
+        /// Drops the bindings of the contracts matching one of these glob
+        /// patterns. Can be given more than once.
+        #[clap(long)]
+        exclude: Vec<String>,
+    },
 
     /// Represents the `Init` subcommand to initialize a simulation.
     Init {
@@ -79,14 +114,91 @@ enum Commands {
         /// Flag to indicate if git should be skipped.
         #[clap(long)]
         no_git: bool,
+        /// The template to initialize the simulation from, i.e., a branch of
+        /// the template repository such as `uniswap-arbitrage`. Defaults to
+        /// the default branch.
+        #[clap(long)]
+        template: Option<String>,
+        /// The git repository the template is fetched from.
+        #[clap(long, default_value = init::DEFAULT_TEMPLATE_REPOSITORY)]
+        template_repository: String,
+    },
+
+    /// Represents the `Run` subcommand to run a simulation declared in a
+    /// config.
+    Run {
+        /// The name of the config file that declares the simulation.
+        #[clap(index = 1)]
+        config_path: String,
     },
 
+    /// Represents the `Fork` subcommand to write out a fork of a network.
+    #[command(args_conflicts_with_subcommands = true)]
     Fork {
         /// The name of the config file used to configure the fork.
         #[clap(index = 1)]
-        fork_config_path: String,
+        fork_config_path: Option<String>,
         #[clap(long)]
         overwrite: bool,
+        /// The RPC URL of the node to fork from, in place of the `provider`
+        /// in the config.
+        #[clap(long)]
+        rpc_url: Option<String>,
+        /// The block to fork at, in place of the `block_number` in the
+        /// config.
+        #[clap(long)]
+        block: Option<u64>,
+        /// The path to write the fork to, in place of the
+        /// `output_directory` and `output_filename` in the config.
+        #[clap(long)]
+        output: Option<String>,
+        /// Only writes the metadata digested for each contract, e.g., its ABI
+        /// and whether it is a proxy or a token, instead of the fork.
+        #[clap(long)]
+        digest: bool,
+        /// How many times a request to the node that times out or is rate
+        /// limited is retried.
+        #[clap(long)]
+        retries: Option<u32>,
+        /// The directory state fetched from the node is cached in, in place
+        /// of the `cache_directory` in the config.
+        #[clap(long)]
+        cache_dir: Option<String>,
+        /// Only uses state from the cache directory and fails instead of
+        /// asking the node for anything that is missing.
+        #[clap(long, conflicts_with = "refresh")]
+        offline: bool,
+        /// Fetches all state from the node again and replaces what the cache
+        /// directory holds.
+        #[clap(long)]
+        refresh: bool,
+        /// Defines the fork subcommand to execute.
+        #[command(subcommand)]
+        command: Option<ForkCommands>,
+    },
+}
+
+/// Defines available subcommands for the `Fork` subcommand.
+#[derive(Subcommand)]
+enum ForkCommands {
+    /// Replays the view calls listed under `[[verify]]` in a fork config
+    /// against both the node and the fork written out for it.
+    Verify {
+        /// The name of the config file used to configure the fork.
+        #[clap(index = 1)]
+        fork_config_path: String,
+    },
+
+    /// Moves an existing fork to a newer block by fetching only the accounts
+    /// and storage slots it already holds.
+    Update {
+        /// The name of the config file used to configure the fork.
+        #[clap(index = 1)]
+        fork_config_path: String,
+        /// The block to move the fork to. Defaults to the `block_number` in
+        /// the config.
+        #[clap(long)]
+        block: Option<u64>,
     },
 }
 
@@ -106,24 +218,83 @@ fn main() -> Result<(), ArbiterError> {
         Some(Commands::Init {
             simulation_name,
             no_git,
+            template,
+            template_repository,
         }) => {
             println!("Initializing Arbiter project...");
-            init::init_project(simulation_name)?;
+            init::init_project(simulation_name, template.as_deref(), template_repository)?;
             if *no_git {
                 init::remove_git()?;
             }
         }
-        Some(Commands::Bind) => {
+        Some(Commands::Bind {
+            artifacts,
+            include,
+            exclude,
+        }) => {
             println!("Generating bindings...");
-            bind::forge_bind()?;
+            bind::forge_bind(&bind::BindFilter::new(include, exclude))?;
+            if *artifacts {
+                bind::write_artifacts()?;
+            }
+        }
+        Some(Commands::Run { config_path }) => {
+            println!("Running simulation...");
+            run::RunConfig::new(config_path)?.run()?;
         }
         Some(Commands::Fork {
-            fork_config_path,
+            command: Some(ForkCommands::Verify { fork_config_path }),
+            ..
+        }) => {
+            println!("Verifying fork...");
+            let fork_config = ForkConfig::new(fork_config_path)?;
+            fork_config.verify()?;
+        }
+        Some(Commands::Fork {
+            command:
+                Some(ForkCommands::Update {
+                    fork_config_path,
+                    block,
+                }),
+            ..
+        }) => {
+            println!("Updating fork...");
+            let fork_config = ForkConfig::new(fork_config_path)?;
+            fork_config.update(*block)?;
+        }
+        Some(Commands::Fork {
+            fork_config_path: Some(fork_config_path),
             overwrite,
+            rpc_url,
+            block,
+            output,
+            digest,
+            retries,
+            cache_dir,
+            offline,
+            refresh,
+            command: None,
         }) => {
             println!("Forking...");
-            let fork_config = ForkConfig::new(fork_config_path)?;
-            fork_config.write_to_disk(overwrite)?;
+            let mut fork_config = ForkConfig::new(fork_config_path)?;
+            fork_config.override_with(rpc_url.clone(), *block, output.clone(), *retries);
+            let policy = match (*offline, *refresh) {
+                (true, _) => CachePolicy::Offline,
+                (_, true) => CachePolicy::Refresh,
+                _ => CachePolicy::Reuse,
+            };
+            fork_config.use_cache(cache_dir.clone(), policy)?;
+            if *digest {
+                fork_config.write_digest(overwrite)?;
+            } else {
+                fork_config.write_to_disk(overwrite)?;
+            }
+        }
+        Some(Commands::Fork { .. }) => {
+            Args::command()
+                .find_subcommand_mut("fork")
+                .unwrap()
+                .print_long_help()?;
         }
         None => Args::command().print_long_help()?,
     }
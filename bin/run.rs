@@ -0,0 +1,474 @@
+//! Runs a simulation declared in a TOML config with `arbiter run`.
+//!
+//! A config sets up the
+//! [`Environment`](arbiter_core::environment::Environment), funds the agents,
+//! deploys contracts from their compiled artifacts, and then has the agents
+//! take turns calling those contracts:
+//!
+//! ```toml
+//! results_directory = "results"
+//!
+//! [environment]
+//! block_settings = "UserControlled"
+//! gas_settings = "UserControlled"
+//! seed = 7
+//!
+//! # Accounts set up before the first block, with hex balances and storage.
+//! [environment.genesis."0x00000000000000000000000000000000000000aa"]
+//! balance = "0xde0b6b3a7640000"
+//!
+//! [[agents]]
+//! name = "admin"
+//! balance = "1000000000000000000"
+//!
+//! [[agents.actions]]
+//! contract = "token"
+//! function = "mint"
+//! args = ["trader", "1000"]
+//! repeat = 2
+//!
+//! [[agents]]
+//! name = "trader"
+//!
+//! [[deployments]]
+//! name = "token"
+//! artifact = "out/ArbiterToken.sol/ArbiterToken.json"
+//! deployer = "admin"
+//! args = ["Arbiter Token", "ARBT", "18"]
+//! ```
+//!
+//! Arguments are parsed as the types of the ABI, and an argument naming an
+//! agent or a deployment is replaced with its address. In each round, every
+//! agent takes its next action, in the order the agents are listed, until all
+//! of them are out of actions. The addresses of the agents and deployments and
+//! a record of every transaction are written to the results directory.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use arbiter_core::{
+    environment::{
+        builder::{EnvironmentBuilder, EnvironmentParameters},
+        cheatcodes::Cheatcodes,
+    },
+    middleware::RevmMiddleware,
+    runner::SimulationRunner,
+};
+use ethers::{
+    abi::{
+        token::{LenientTokenizer, Tokenizer},
+        Abi, Param, Token,
+    },
+    providers::Middleware,
+    types::{Address, TransactionRequest, H256, U256},
+    utils::hex,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::ArbiterError;
+
+/// A simulation declared in a TOML config.
+#[derive(Debug, Deserialize)]
+pub(crate) struct RunConfig {
+    /// The parameters the environment is built with.
+    environment: EnvironmentParameters,
+
+    /// Where the results are written to.
+    #[serde(default = "default_results_directory")]
+    results_directory: String,
+
+    /// The agents, in the order they take their turns.
+    #[serde(default)]
+    agents: Vec<AgentConfig>,
+
+    /// The contracts deployed before the agents act, in order.
+    #[serde(default)]
+    deployments: Vec<DeploymentConfig>,
+}
+
+fn default_results_directory() -> String {
+    "results".to_string()
+}
+
+/// An agent with its own client.
+#[derive(Debug, Deserialize)]
+struct AgentConfig {
+    /// The name of the agent, which also seeds the address of its client.
+    name: String,
+
+    /// The ether balance in wei the agent starts with.
+    #[serde(default)]
+    balance: Option<String>,
+
+    /// The calls the agent makes, one per round.
+    #[serde(default)]
+    actions: Vec<ActionConfig>,
+}
+
+/// A contract deployed from a compiled artifact.
+#[derive(Debug, Deserialize)]
+struct DeploymentConfig {
+    /// The name the contract is referred to by.
+    name: String,
+
+    /// The path of the artifact `forge build` wrote for the contract.
+    artifact: String,
+
+    /// The name of the agent that deploys the contract.
+    deployer: String,
+
+    /// The arguments of the constructor.
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+/// A call of a deployed contract.
+#[derive(Debug, Deserialize)]
+struct ActionConfig {
+    /// The name of the deployment to call.
+    contract: String,
+
+    /// The name of the function to call.
+    function: String,
+
+    /// The arguments of the function.
+    #[serde(default)]
+    args: Vec<String>,
+
+    /// How many rounds in a row the call is made.
+    #[serde(default = "default_repeat")]
+    repeat: usize,
+}
+
+fn default_repeat() -> usize {
+    1
+}
+
+/// What happened to a transaction of an agent.
+#[derive(Debug, Serialize)]
+struct TransactionRecord {
+    round: usize,
+    agent: String,
+    contract: String,
+    function: String,
+    transaction_hash: Option<H256>,
+    block_number: Option<u64>,
+    gas_used: Option<U256>,
+    error: Option<String>,
+}
+
+impl RunConfig {
+    /// Reads the config at `path`.
+    pub(crate) fn new(path: &str) -> Result<Self, ArbiterError> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Runs the simulation and writes its results.
+    pub(crate) fn run(self) -> Result<(), ArbiterError> {
+        SimulationRunner::new([self])
+            .threads(1)
+            .run(|config| async move { config.simulate().await })
+            .pop()
+            .unwrap()
+            .map_err(ArbiterError::SimulationError)
+    }
+
+    async fn simulate(self) -> Result<(), String> {
+        let parameters = self.environment;
+        let mut builder = EnvironmentBuilder::new()
+            .block_settings(parameters.block_settings)
+            .gas_settings(parameters.gas_settings);
+        if let Some(label) = parameters.label {
+            builder = builder.label(label);
+        }
+        if let Some(block_gas_limit) = parameters.block_gas_limit {
+            builder = builder.block_gas_limit(block_gas_limit);
+        }
+        if let Some(seed) = parameters.seed {
+            builder = builder.seed(seed);
+        }
+        if let Some(l1_fee) = parameters.l1_fee {
+            builder = builder.l1_fee(l1_fee);
+        }
+        if let Some(spec_id) = parameters.spec_id {
+            builder = builder.spec_id(spec_id);
+        }
+        if let Some(chain_id) = parameters.chain_id {
+            builder = builder.chain_id(chain_id);
+        }
+        builder = builder.with_genesis(parameters.genesis);
+        let environment = builder.build();
+
+        let mut clients = HashMap::new();
+        let mut addresses = HashMap::new();
+        for agent in &self.agents {
+            let client = RevmMiddleware::new(&environment, Some(agent.name.as_str()))
+                .map_err(|e| e.to_string())?;
+            if let Some(balance) = &agent.balance {
+                let amount = U256::from_dec_str(balance)
+                    .map_err(|e| format!("Invalid balance for agent `{}`: {}", agent.name, e))?;
+                client
+                    .apply_cheatcode(Cheatcodes::Deal {
+                        address: client.address(),
+                        amount,
+                    })
+                    .await
+                    .map_err(|e| e.to_string())?;
+            }
+            addresses.insert(agent.name.clone(), client.address());
+            clients.insert(agent.name.clone(), client);
+        }
+
+        let mut contracts = HashMap::new();
+        for deployment in &self.deployments {
+            let (abi, bytecode) = read_artifact(Path::new(&deployment.artifact))?;
+            let client = clients.get(&deployment.deployer).ok_or(format!(
+                "The deployer `{}` of `{}` is not an agent.",
+                deployment.deployer, deployment.name
+            ))?;
+            let mut data = bytecode;
+            if let Some(constructor) = abi.constructor() {
+                let tokens = tokenize(&constructor.inputs, &deployment.args, &addresses)?;
+                data = constructor
+                    .encode_input(data, &tokens)
+                    .map_err(|e| e.to_string())?;
+            }
+            let receipt = client
+                .send_transaction(TransactionRequest::new().data(data), None)
+                .await
+                .map_err(|e| format!("Failed to deploy `{}`: {}", deployment.name, e))?
+                .await
+                .map_err(|e| e.to_string())?
+                .ok_or(format!("Failed to deploy `{}`.", deployment.name))?;
+            let address = receipt
+                .contract_address
+                .ok_or(format!("Failed to deploy `{}`.", deployment.name))?;
+            addresses.insert(deployment.name.clone(), address);
+            contracts.insert(deployment.name.clone(), (address, abi));
+        }
+
+        let schedules = self
+            .agents
+            .iter()
+            .map(|agent| {
+                agent
+                    .actions
+                    .iter()
+                    .flat_map(|action| std::iter::repeat(action).take(action.repeat))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let rounds = schedules.iter().map(Vec::len).max().unwrap_or_default();
+        let mut records = Vec::new();
+        for round in 0..rounds {
+            for (agent, schedule) in self.agents.iter().zip(&schedules) {
+                let Some(action) = schedule.get(round) else {
+                    continue;
+                };
+                let (address, abi) = contracts.get(&action.contract).ok_or(format!(
+                    "The contract `{}` called by `{}` is not deployed.",
+                    action.contract, agent.name
+                ))?;
+                let function = abi.function(&action.function).map_err(|e| {
+                    format!(
+                        "`{}` has no function `{}`: {}",
+                        action.contract, action.function, e
+                    )
+                })?;
+                let tokens = tokenize(&function.inputs, &action.args, &addresses)?;
+                let data = function.encode_input(&tokens).map_err(|e| e.to_string())?;
+                let tx = TransactionRequest::new().to(*address).data(data);
+                let mut record = TransactionRecord {
+                    round,
+                    agent: agent.name.clone(),
+                    contract: action.contract.clone(),
+                    function: action.function.clone(),
+                    transaction_hash: None,
+                    block_number: None,
+                    gas_used: None,
+                    error: None,
+                };
+                match clients[&agent.name].send_transaction(tx, None).await {
+                    Ok(pending_tx) => match pending_tx.await {
+                        Ok(Some(receipt)) => {
+                            record.transaction_hash = Some(receipt.transaction_hash);
+                            record.block_number = receipt.block_number.map(|n| n.as_u64());
+                            record.gas_used = receipt.gas_used;
+                        }
+                        Ok(None) => record.error = Some("No receipt was returned.".to_string()),
+                        Err(e) => record.error = Some(e.to_string()),
+                    },
+                    Err(e) => record.error = Some(e.to_string()),
+                }
+                records.push(record);
+            }
+        }
+
+        let results_directory = Path::new(&self.results_directory);
+        let write = |name: &str, value: serde_json::Value| {
+            fs::create_dir_all(results_directory)
+                .and_then(|_| {
+                    fs::write(
+                        results_directory.join(name),
+                        serde_json::to_string_pretty(&value)?,
+                    )
+                })
+                .map_err(|e| e.to_string())
+        };
+        write(
+            "addresses.json",
+            serde_json::to_value(&addresses).map_err(|e| e.to_string())?,
+        )?;
+        write(
+            "transactions.json",
+            serde_json::to_value(&records).map_err(|e| e.to_string())?,
+        )?;
+        println!(
+            "Wrote the results of {} transactions to {}",
+            records.len(),
+            results_directory.display()
+        );
+        Ok(())
+    }
+}
+
+/// Reads the ABI and the bytecode from an artifact written by `forge build`.
+fn read_artifact(path: &Path) -> Result<(Abi, Vec<u8>), String> {
+    let artifact: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read the artifact {}: {}", path.display(), e))?,
+    )
+    .map_err(|e| e.to_string())?;
+    let abi = serde_json::from_value(artifact["abi"].clone()).map_err(|e| e.to_string())?;
+    let bytecode = artifact["bytecode"]["object"]
+        .as_str()
+        .and_then(|object| hex::decode(object.trim_start_matches("0x")).ok())
+        .ok_or(format!("The artifact {} has no bytecode.", path.display()))?;
+    Ok((abi, bytecode))
+}
+
+/// Parses `args` as the types of the `params`, replacing the names of agents
+/// and deployments with their addresses.
+fn tokenize(
+    params: &[Param],
+    args: &[String],
+    addresses: &HashMap<String, Address>,
+) -> Result<Vec<Token>, String> {
+    if params.len() != args.len() {
+        return Err(format!(
+            "Expected {} arguments but got {}: {:?}",
+            params.len(),
+            args.len(),
+            args
+        ));
+    }
+    params
+        .iter()
+        .zip(args)
+        .map(|(param, arg)| {
+            let arg = match addresses.get(arg) {
+                Some(address) => format!("{:?}", address),
+                None => arg.clone(),
+            };
+            LenientTokenizer::tokenize(&param.kind, &arg)
+                .map_err(|e| format!("Invalid argument `{}` for `{}`: {}", arg, param.name, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    /// A contract with a `set(uint256)` function that stores its argument.
+    const SET_ARTIFACT: &str = r#"{
+        "abi": [{
+            "type": "function",
+            "name": "set",
+            "inputs": [{ "name": "value", "type": "uint256" }],
+            "outputs": [],
+            "stateMutability": "nonpayable"
+        }],
+        "bytecode": { "object": "0x600780600b6000396000f360043560005500" }
+    }"#;
+
+    #[test]
+    fn run_config() {
+        let dir = tempdir().unwrap();
+        let artifact = dir.path().join("Set.json");
+        fs::write(&artifact, SET_ARTIFACT).unwrap();
+        let results = dir.path().join("results");
+        let config = format!(
+            r#"
+            results_directory = "{}"
+
+            [environment]
+            block_settings = "UserControlled"
+            gas_settings = "UserControlled"
+
+            [[agents]]
+            name = "setter"
+            balance = "1000"
+
+            [[agents.actions]]
+            contract = "set"
+            function = "set"
+            args = ["42"]
+            repeat = 2
+
+            [[deployments]]
+            name = "set"
+            artifact = "{}"
+            deployer = "setter"
+            "#,
+            results.display(),
+            artifact.display()
+        );
+        let config: RunConfig = toml::from_str(&config).unwrap();
+        config.run().unwrap();
+
+        let addresses: HashMap<String, Address> =
+            serde_json::from_str(&fs::read_to_string(results.join("addresses.json")).unwrap())
+                .unwrap();
+        assert!(addresses.contains_key("setter"));
+        assert!(addresses.contains_key("set"));
+        let transactions: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(results.join("transactions.json")).unwrap())
+                .unwrap();
+        let transactions = transactions.as_array().unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert!(transactions.iter().all(|record| record["error"].is_null()));
+    }
+
+    #[test]
+    fn tokenize_names() {
+        let addresses = HashMap::from([("alice".to_string(), Address::repeat_byte(1))]);
+        let params = vec![
+            Param {
+                name: "to".to_string(),
+                kind: ethers::abi::ParamType::Address,
+                internal_type: None,
+            },
+            Param {
+                name: "amount".to_string(),
+                kind: ethers::abi::ParamType::Uint(256),
+                internal_type: None,
+            },
+        ];
+        let tokens = tokenize(
+            &params,
+            &["alice".to_string(), "1000".to_string()],
+            &addresses,
+        )
+        .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Address(Address::repeat_byte(1)),
+                Token::Uint(1000.into())
+            ]
+        );
+        assert!(tokenize(&params, &["alice".to_string()], &addresses).is_err());
+    }
+}